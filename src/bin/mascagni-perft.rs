@@ -0,0 +1,40 @@
+//! `mascagni-perft DEPTH [FEN]`
+//! Counts the positions DEPTH plies from the starting position or the given FEN, move by move,
+//! as move generators are usually checked against each other.
+
+use std::process::exit;
+use std::time::Instant;
+
+use mascagni::{tools::divide, Board, START_FEN};
+
+/// Exit code for bad arguments, as in the main binary
+const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "mascagni-perft DEPTH [FEN]";
+
+/// Prints what went wrong and the usage line, and exits with [`EXIT_USAGE`]
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("Usage: {}", USAGE);
+    exit(EXIT_USAGE)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(depth) = args.first() else { usage_error("Expected a depth") };
+    let Ok(depth) = depth.parse::<usize>() else { usage_error(&format!("Invalid depth: {}", depth)) };
+    let fen = if args.len() > 1 { args[1..].join(" ") } else { START_FEN.to_string() };
+    let mut board = match Board::new(fen) {
+        Ok(board) => board,
+        Err(e) => usage_error(&format!("Invalid fen: {}", e)),
+    };
+    let start = Instant::now();
+    let moves = divide(&mut board, depth);
+    let elapsed = start.elapsed();
+    for (mv, nodes) in &moves {
+        println!("{}: {}", mv, nodes);
+    }
+    let nodes: u64 = moves.iter().map(|(_, nodes)| nodes).sum();
+    println!("\nNodes: {}", nodes);
+    println!("Time: {} ms ({} nps)", elapsed.as_millis(), (nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64);
+}
@@ -0,0 +1,63 @@
+//! `mascagni-play [FEN]`
+//! Plays a game against the engine on the terminal, from the starting position or the given FEN,
+//! after asking which side the user takes and how strongly the engine should play. The game is
+//! appended to `games.pgn` at the end.
+
+use std::io::{stdin, stdout, BufRead, Write};
+use std::process::exit;
+
+use mascagni::game::{self, Game};
+use mascagni::{Board, Color, START_FEN};
+
+/// Asks a question until the answer is one `parse` accepts. An empty answer takes the default.
+fn ask<T>(question: &str, default: T, parse: impl Fn(&str) -> Option<T>) -> T {
+    loop {
+        print!("{} ", question);
+        stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+            exit(0)
+        }
+        match line.trim() {
+            "" => return default,
+            answer => match parse(answer) {
+                Some(value) => return value,
+                None => println!("Please answer again."),
+            },
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let fen = if args.is_empty() { START_FEN.to_string() } else { args.join(" ") };
+    let board = match Board::new(fen) {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("Invalid fen: {}", e);
+            exit(2)
+        }
+    };
+    let player = ask("Play White or Black? [w]", Color::White, |answer| match answer {
+        "w" | "white" => Some(Color::White),
+        "b" | "black" => Some(Color::Black),
+        _ => None,
+    });
+    let question = format!("Engine level, {} to {}? [{}]", game::MIN_LEVEL, game::MAX_LEVEL, game::DEFAULT_LEVEL);
+    let level = ask(&question, game::DEFAULT_LEVEL, |answer| {
+        answer.parse().ok().filter(|level| (game::MIN_LEVEL..=game::MAX_LEVEL).contains(level))
+    });
+    println!("\n{}\n", game::COMMANDS);
+    let mut game = Game::new(board, player, level);
+    if let Err(e) = game.run(stdin().lock(), &mut stdout()) {
+        eprintln!("{}", e);
+        exit(1)
+    }
+    if !game.moves().is_empty() {
+        if let Err(e) = game.save_pgn(game::DEFAULT_PGN_FILE) {
+            eprintln!("Could not write {}: {}", game::DEFAULT_PGN_FILE, e);
+            exit(1)
+        }
+        println!("Game saved to {}", game::DEFAULT_PGN_FILE);
+    }
+}
@@ -1,17 +1,30 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use zobrist::ZOBRIST;
 
-use crate::types::{Bitboard, BlackKingside, BlackQueenside, Castling, CastlingKind, Color, File, FullMove, MoveList, MoveType, Piece, Rank, Square, WhiteKingside, WhiteQueenside};
+use crate::eval::nnue::{Network, NnueState};
+use crate::types::{Bitboard, BlackKingside, BlackQueenside, Castling, CastlingKind, Color, FullMove, MoveList, MoveType, Piece, Rank, Square, WhiteKingside, WhiteQueenside};
 
 use self::movegen::{black_pawn_advances, generate_bishop_attacks, generate_queen_attacks, generate_rook_attacks, white_pawn_advances, StandardBitboards};
 
-use self::parser::FenParseErr;
+pub use self::parser::FenParseErr;
 
 mod parser;
 mod movegen;
 mod makemove;
 mod zobrist;
+mod chess960;
+mod variant;
+mod antichess;
+mod crazyhouse;
+mod atomic;
+
+pub use self::chess960::{chess960_fen, dfrc_fen};
+pub use self::crazyhouse::POCKET_PIECES;
+pub use self::variant::{GameResult, Variant};
+
+/// FEN of the standard starting position
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 /// Basic state of the board
 /// This does not include all of the board data. It includes zobrist hashes for pawns, minor, and
@@ -27,58 +40,80 @@ struct BoardState {
     non_pawn_keys: [u64; Color::NUM],
     en_passant: Square,
     halfmove_clock: u8,
+    /// Halfmoves since the last null move; the search's null moves are not played in the game,
+    /// so a position before one cannot repeat after it
+    plies_from_null: u16,
     castling: Castling,
     fullmove_number: u16,
+    /// Checks given by each side, in Three-check
+    checks_given: [u8; Color::NUM],
+    /// Pieces in each side's pocket, in Crazyhouse
+    pockets: [[u8; Piece::NUM]; Color::NUM],
+    /// Pieces which were pawns before they promoted, in Crazyhouse
+    promoted: Bitboard,
+    /// Pieces blown up by the capture on the last turn, in Atomic
+    explosion: atomic::Explosion,
 }
 
 #[derive(Default, Copy, Clone)]
-pub struct Check {
-    pub checking_piece: Square,
-    pub piece: Piece,
-    pub king_square: Square,
+pub(crate) struct Check {
+    pub(crate) checking_piece: Square,
+    pub(crate) piece: Piece,
+    pub(crate) king_square: Square,
     /// The squares that a piece can enter to block this check (including captures)
-    pub block_space: Bitboard,
+    pub(crate) block_space: Bitboard,
 }
 
 #[derive(Default, Copy, Clone)]
-pub struct CheckState {
-    pub check_space: Bitboard,
+pub(crate) struct CheckState {
+    pub(crate) check_space: Bitboard,
     /// TODO: Potentially change this to a slice
-    pub checks: [Check; 2],
+    pub(crate) checks: [Check; 2],
 }
 
 #[derive(Clone)]
 pub struct Board {
     // basic board state
     pub side_to_move: Color,
+    variant: Variant,
     state: BoardState,
     state_stack: Vec<BoardState>,
-    pub move_stack: Vec<FullMove>,
+    move_stack: Vec<FullMove>,
     // pieces / mailboxes
     colors: [Bitboard; Color::NUM],
     pieces: [Bitboard; Piece::NUM],
     mailbox: [Piece; Square::NUM],
     standard_bitboards: StandardBitboards,
     // calculated board state
-    pub checking_state: CheckState,
+    checking_state: CheckState,
     pinning_state: [Bitboard; Square::NUM],
-    pub legal_moves: MoveList,
+    legal_moves: MoveList,
+    // evaluation
+    /// NNUE accumulators, present when a network has been attached with `set_network`
+    nnue: Option<Box<NnueState>>,
 }
 
 
 impl Board {
     /// Create a new board from a given fen
+    ///
+    /// ```
+    /// use mascagni::{Board, START_FEN};
+    ///
+    /// let board = Board::new(START_FEN.to_string()).unwrap();
+    /// assert_eq!(board.legal_moves().len, 20);
+    /// assert!(Board::new("not a fen".to_string()).is_err());
+    /// ```
     pub fn new(fen: String) -> Result<Self, FenParseErr> {
-        match Self::from_str(&fen) {
-            Ok(mut board) => {
-                board.analyze_board();
-                Ok(board)
-            }
-            Err(err) => {
-                println!("There was an error: {}", err);
-                Err(err)
-            }
-        }
+        Self::new_variant(fen, Variant::Standard)
+    }
+
+    /// Create a new board for a variant from a given fen
+    pub fn new_variant(fen: String, variant: Variant) -> Result<Self, FenParseErr> {
+        let mut board = Self::from_str(&fen)?;
+        board.variant = variant;
+        board.analyze_board();
+        Ok(board)
     }
 
     /// Generates the zobrist hash key for the current position
@@ -101,6 +136,14 @@ impl Board {
             hash ^= ZOBRIST.side_to_move;
         }
         hash ^= ZOBRIST.castling[self.state.castling];
+        for color in [Color::White, Color::Black] {
+            hash ^= ZOBRIST.checks[color][self.state.checks_given[color].min(3) as usize];
+            for piece in POCKET_PIECES {
+                for count in 0..self.state.pockets[color][piece] {
+                    hash ^= ZOBRIST.pockets[color][piece][count as usize & 15];
+                }
+            }
+        }
         hash
     }
 
@@ -154,6 +197,11 @@ impl Board {
         hashes
     }
 
+    /// Every square with a piece on it
+    pub fn occupied(&self) -> Bitboard {
+        self.colors[Color::White] | self.colors[Color::Black]
+    }
+
     /// Get pieces of specific type and color
     pub fn pieces_of(&self, piece: Piece, color: Color) -> Bitboard {
         self.pieces[piece] & self.colors[color]
@@ -162,50 +210,206 @@ impl Board {
 
     /// Should be run on board creation and after each move. This function generates legal moves
     /// and updates other basic state about the board.
-    pub fn analyze_board(&mut self) {
+    pub(crate) fn analyze_board(&mut self) {
         // TODO: update hash
-        self.checking_state = self.calculate_check_state();
-        self.pinning_state = self.calculate_pin_state();
+        if self.variant == Variant::Antichess {
+            // there are no checks or pins, and there may not even be a king
+            self.checking_state = CheckState::default();
+            self.pinning_state = [Bitboard::universal(); Square::NUM];
+        } else if self.variant == Variant::Atomic {
+            // the moves are tried out one by one instead, and a king next to the other one or
+            // blown up is never in check
+            self.checking_state = match self.is_atomic_check() {
+                true => self.calculate_check_state(),
+                false => CheckState::default(),
+            };
+            self.pinning_state = [Bitboard::universal(); Square::NUM];
+        } else {
+            self.checking_state = self.calculate_check_state();
+            self.pinning_state = self.calculate_pin_state();
+        }
         self.legal_moves = self.generate_legal_moves();
     }
 
     /// determine if the current side to move is checkmated.
     pub fn is_checkmate(&self) -> bool {
-        if self.legal_moves.len == 0 && self.checking_state.checks[0].checking_piece != Square::None {
-            true
-        } else {
-            false
-        }
+        self.legal_moves.len == 0 && self.checking_state.checks[0].checking_piece != Square::None && self.variant_outcome().is_none()
     }
 
     /// determine if the current position is a stalemate
     pub fn is_stalemate(&self) -> bool {
-        if self.legal_moves.len == 0 && self.checking_state.checks[0].checking_piece == Square::None {
-            true
-        } else {
-            false
-        }
+        self.legal_moves.len == 0 && self.checking_state.checks[0].checking_piece == Square::None && self.variant_outcome().is_none()
     }
 
     pub fn is_check(&self) -> bool {
         self.checking_state.checks[0].checking_piece != Square::None
     }
 
+    /// The legal moves of the side to move
+    pub fn legal_moves(&self) -> &MoveList {
+        &self.legal_moves
+    }
+
+    /// The moves made on this board, oldest first, which `undo_move` takes back
+    pub fn move_stack(&self) -> &[FullMove] {
+        &self.move_stack
+    }
+
+    /// Number of halfmoves since the last capture or pawn move (for the fifty-move rule)
+    pub fn halfmove_clock(&self) -> u8 {
+        self.state.halfmove_clock
+    }
+
+    /// Number of the current move, starting at 1 and counted up after each Black move
+    pub fn fullmove_number(&self) -> u16 {
+        self.state.fullmove_number
+    }
+
+    /// The square a pawn can capture onto en passant, or `Square::None`
+    pub fn en_passant(&self) -> Square {
+        self.state.en_passant
+    }
+
+    pub fn castling(&self) -> Castling {
+        self.state.castling
+    }
+
+    /// Whether the current position has occurred before. Only positions since the last capture or
+    /// pawn move, with the same side to move, can repeat. The search scores this first recurrence
+    /// as a draw, the game only ends on the third, see [`Board::is_threefold_repetition`].
+    pub fn is_repetition(&self) -> bool {
+        self.earlier_occurrences().next().is_some()
+    }
+
+    /// Whether the current position has occurred at least twice before, which draws the game
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.earlier_occurrences().nth(1).is_some()
+    }
+
+    fn earlier_occurrences(&self) -> impl Iterator<Item = &BoardState> {
+        self.state_stack
+            .iter()
+            .rev()
+            .take(usize::from(self.state.halfmove_clock).min(usize::from(self.state.plies_from_null)))
+            .skip(1)
+            .step_by(2)
+            .filter(|state| state.hash_key == self.state.hash_key)
+    }
+
+    /// A hundred halfmoves without a capture or pawn move, unless the last of them mated
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.state.halfmove_clock >= 100 && !self.is_checkmate()
+    }
+
+    /// Neither side can possibly mate: bare kings, a single minor piece, or only bishops which
+    /// all stand on squares of the same colour. Only in standard chess, since the variants have
+    /// other ways to win.
+    pub fn is_insufficient_material(&self) -> bool {
+        if self.variant != Variant::Standard {
+            return false
+        }
+        if !(self.pieces[Piece::Pawn] | self.pieces[Piece::Rook] | self.pieces[Piece::Queen]).is_empty() {
+            return false
+        }
+        let knights = self.pieces[Piece::Knight];
+        let bishops = self.pieces[Piece::Bishop];
+        if (knights | bishops).count() <= 1 {
+            return true
+        }
+        let light_squares = bishops.filter(|square| (square.rank() as u8 + square.file() as u8) % 2 == 1).count();
+        knights.is_empty() && (light_squares == 0 || light_squares == bishops.count())
+    }
+
+    /// Whether the game is drawn by any rule: stalemate, threefold repetition, the fifty-move
+    /// rule, insufficient material or a rule of the variant
+    pub fn is_draw(&self) -> bool {
+        self.is_stalemate()
+            || self.is_threefold_repetition()
+            || self.is_fifty_move_draw()
+            || self.is_insufficient_material()
+            || self.variant_outcome() == Some(GameResult::Draw)
+    }
+
     pub fn piece_on(&self, square: Square) -> Piece {
         self.mailbox[square]
     }
 
-    pub fn add_piece(&mut self, square: Square, color: Color, piece: Piece) {
-        // TODO: Update mailbox?
+    /// The colour of the piece on a square, `None` if it is empty
+    pub fn color_on(&self, square: Square) -> Option<Color> {
+        [Color::White, Color::Black]
+            .into_iter()
+            .find(|&color| !(self.colors[color] & Bitboard::from(square)).is_empty())
+    }
+
+    pub(crate) fn add_piece(&mut self, square: Square, color: Color, piece: Piece) {
         self.pieces[piece].set(square);
         self.colors[color].set(square);
         self.mailbox[square] = piece;
+        self.toggle_piece_keys(square, color, piece);
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.add_piece(square, color, piece);
+        }
     }
 
-    pub fn remove_piece(&mut self, square: Square, color: Color, piece: Piece) {
+    pub(crate) fn remove_piece(&mut self, square: Square, color: Color, piece: Piece) {
         self.pieces[piece].clear(square);
         self.colors[color].clear(square);
         self.mailbox[square] = Piece::None;
+        self.toggle_piece_keys(square, color, piece);
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.remove_piece(square, color, piece);
+        }
+    }
+
+    /// Adds or removes a piece from the zobrist keys (XOR undoes itself)
+    fn toggle_piece_keys(&mut self, square: Square, color: Color, piece: Piece) {
+        let key = ZOBRIST.pieces[color][piece][square];
+        self.state.hash_key ^= key;
+        if piece == Piece::Pawn {
+            self.state.pawn_key ^= key;
+        }
+    }
+
+    /// Zobrist hash of the current position
+    pub fn hash(&self) -> u64 {
+        self.state.hash_key
+    }
+
+    /// Attaches a network to the board and builds its accumulators from scratch. From then on,
+    /// the accumulators are kept up to date by `make_move` and `undo_move`, and built again when a
+    /// move made before is taken back.
+    pub fn set_network(&mut self, network: Arc<Network>) {
+        let mut nnue = NnueState::new(network);
+        self.refresh_accumulators(&mut nnue);
+        self.nnue = Some(Box::new(nnue));
+    }
+
+    /// Builds the current accumulators from the pieces on the board
+    fn refresh_accumulators(&self, nnue: &mut NnueState) {
+        nnue.clear();
+        for square in self.colors[Color::White] | self.colors[Color::Black] {
+            let color = if (self.colors[Color::White] & Bitboard::from(square)).is_empty() {
+                Color::Black
+            } else {
+                Color::White
+            };
+            nnue.add_piece(square, color, self.mailbox[square]);
+        }
+    }
+
+    /// Goes back to counting material
+    pub fn remove_network(&mut self) {
+        self.nnue = None;
+    }
+
+    /// The attached network and its accumulators, if any
+    pub fn nnue(&self) -> Option<&NnueState> {
+        self.nnue.as_deref()
+    }
+
+    /// Evaluates the position with the attached network, from the side to move's point of view.
+    pub fn nnue_evaluate(&self) -> Option<i32> {
+        self.nnue.as_ref().map(|nnue| nnue.evaluate(self.side_to_move))
     }
 
     pub fn our(&self, piece: Piece) -> Bitboard {
@@ -220,22 +424,14 @@ impl Board {
     /// position. For any square `sq`, we can retrieve the bitboard `b` at `sq`'s index. If one of
     /// our pieces is on `sq`, then its moves are, at most, restricted to the squares that are set
     /// on `b`. Note that `b` is naive as to the type of piece on `sq`.
-    /// example:
-    /// ```rust
-    /// let sq = Square::E4;
-    /// // (assume one of our pieces is on E4)
-    /// let pins = self.calculate_pin_state();
-    /// let pins_for_sq = pins[sq];
-    /// // The piece on E4 cannot legally move away from the pins_for_sq bitboard
-    /// ```
-    pub fn calculate_pin_state(&self) -> [Bitboard; Square::NUM] {
+    pub(crate) fn calculate_pin_state(&self) -> [Bitboard; Square::NUM] {
         let their_sliders = self.their(Piece::Rook)
             | self.their(Piece::Bishop)
             | self.their(Piece::Queen);
         let our_king_bb = self.our(Piece::King);
         let our_king_square = our_king_bb.lsb();
         let universal_bitboard = Bitboard::universal();
-        let mut pin_spaces: [Bitboard; Square::NUM] = [universal_bitboard.clone(); Square::NUM];
+        let mut pin_spaces: [Bitboard; Square::NUM] = [universal_bitboard; Square::NUM];
         for square in their_sliders {
             let attack_space = match self.mailbox[square] {
                 Piece::Bishop => generate_bishop_attacks(&square, &our_king_bb),
@@ -250,7 +446,7 @@ impl Board {
             if our_betweeners.count() == 1 && their_betweeners.count() == 0 {
                 // there is exactly one piece between their slider and our king
                 // and that piece is of our color
-                let mut pin_bb = between_space.clone();
+                let mut pin_bb = between_space;
                 pin_bb.set(square);
                 let lsb = our_betweeners.lsb();
                 pin_spaces[lsb] = pin_bb;
@@ -260,13 +456,13 @@ impl Board {
     }
 
     /// Get the check state for the current position
-    pub fn calculate_check_state(&self) -> CheckState {
+    pub(crate) fn calculate_check_state(&self) -> CheckState {
         let our_nonkings = self.colors[self.side_to_move] ^ self.pieces[Piece::King];
         let our_king = self.our(Piece::King);
 
         // This occupancy bitboard contains everything except our kings
         let occupancy_bitboard = self.colors[!self.side_to_move] | our_nonkings;
-        let their_pieces = self.colors[!self.side_to_move].clone();
+        let their_pieces = self.colors[!self.side_to_move];
         let mut answer = CheckState::default();
         for square in their_pieces {
             let sq_bb = Bitboard::from(square);
@@ -309,7 +505,7 @@ impl Board {
         answer
     }
 
-    pub fn generate_king_moves(&self, check_state: &CheckState) -> Bitboard {
+    pub(crate) fn generate_king_moves(&self, check_state: &CheckState) -> Bitboard {
         let our_king = self.our(Piece::King);
         let mut bb = Bitboard(0);
         for king_square in our_king {
@@ -321,7 +517,26 @@ impl Board {
         bb
     }
 
-    pub fn generate_legal_moves(&self) -> MoveList {
+    /// The legal moves under the rules of the board's variant
+    pub(crate) fn generate_legal_moves(&self) -> MoveList {
+        if self.variant == Variant::Antichess {
+            return self.generate_antichess_moves()
+        }
+        if self.variant_game_over() {
+            return MoveList::default()
+        }
+        if self.variant == Variant::Atomic {
+            return self.generate_atomic_moves()
+        }
+        let mut mvs = self.generate_standard_moves();
+        self.restrict_variant_moves(&mut mvs);
+        if self.variant == Variant::Crazyhouse {
+            self.add_drops(&mut mvs);
+        }
+        mvs
+    }
+
+    fn generate_standard_moves(&self) -> MoveList {
         // First, let's find out if we are in check.
         let mut mvs = MoveList::default();
         let check_state = self.checking_state;
@@ -360,11 +575,9 @@ impl Board {
                 Piece::Queen => generate_queen_attacks(&square, &full_occupancy) & !our_pieces,
                 Piece::Knight => self.standard_bitboards.knight_attacks[square] & !our_pieces,
                 Piece::Pawn => {
-                    let en_passant_bb = match self.state.en_passant {
-                        Square::None => Bitboard(0),
-                        _ => Bitboard::from(self.state.en_passant),
-                    };
-                    let captures = self.standard_bitboards.pawn_captures[self.side_to_move][square] & (their_pieces | en_passant_bb);
+                    // en passant is handled separately, since neither pins nor block spaces
+                    // describe it correctly
+                    let captures = self.standard_bitboards.pawn_captures[self.side_to_move][square] & their_pieces;
 
                     // TODO: Define pawn advances
                     let advances = match self.side_to_move {
@@ -408,12 +621,8 @@ impl Board {
                                 mvs.add(square, target, MoveType::PromotionCaptureToKnight);
                             }
                         } else if !is_advance {
-                            if target == self.state.en_passant {
-                                mvs.add(square, target, MoveType::EnPassant)
-                            } else {
-                                mvs.add(square, target, MoveType::Capture)
-                            }
-                        } else if target == square.shift(16) || target == square.shift(-16) {
+                            mvs.add(square, target, MoveType::Capture)
+                        } else if (target as i8 - square as i8).abs() == 16 {
                             mvs.add(square, target, MoveType::DoublePawnPush);
                         } else {
                             mvs.add(square, target, MoveType::Quiet);
@@ -432,6 +641,7 @@ impl Board {
                 };
             }
         }
+        self.generate_en_passant(&mut mvs);
         if self.side_to_move == Color::White {
             self.generate_castling::<WhiteKingside>(&mut mvs);
             self.generate_castling::<WhiteQueenside>(&mut mvs);
@@ -442,63 +652,85 @@ impl Board {
         mvs
     }
 
-    pub fn generate_castling<Kind: CastlingKind>(&self, list: &mut MoveList) {
-        if !self.state.castling.is_allowed::<Kind>() {
-            // lost castling rights
+    /// Adds the legal en passant captures. Since en passant removes two pieces from the same rank,
+    /// it can expose our king in ways that the pin state does not describe, so each capture is
+    /// checked by looking for attacks on our king in the position after the capture.
+    pub(crate) fn generate_en_passant(&self, list: &mut MoveList) {
+        let target = self.state.en_passant;
+        if target == Square::None {
             return
         }
-        let full_occupancies = self.colors[self.side_to_move] | self.colors[!self.side_to_move];
-        let path_mask: Bitboard = self.standard_bitboards.between[Kind::CASTLING_MOVE.start()][Kind::ROOK_START_SQUARE];
-        if !((path_mask & full_occupancies).is_empty()) {
-            // cant castle because there is a piece between the king and the rooks
-            return
-        }
-        // if check_space includes the check_squares, we can't do it
-        for sq in Kind::CHECK_SQUARES {
-            let bb = Bitboard::from(sq);
-            if !((self.checking_state.check_space & bb).is_empty()) {
-                return
+        let captured_square = match self.side_to_move {
+            Color::White => target.shift(-8),
+            Color::Black => target.shift(8),
+        };
+        let king_square = self.our(Piece::King).lsb();
+        // our pawns that could capture onto the target are those a pawn of theirs on the target
+        // square would attack
+        let attackers = self.standard_bitboards.pawn_captures[!self.side_to_move][target] & self.our(Piece::Pawn);
+        for start in attackers {
+            let occupancy = (self.colors[Color::White] | self.colors[Color::Black])
+                ^ Bitboard::from(start)
+                ^ Bitboard::from(captured_square)
+                | Bitboard::from(target);
+            let remaining = self.colors[!self.side_to_move] ^ Bitboard::from(captured_square);
+            let diagonal = (self.pieces[Piece::Bishop] | self.pieces[Piece::Queen]) & remaining;
+            let straight = (self.pieces[Piece::Rook] | self.pieces[Piece::Queen]) & remaining;
+            let checked = !(generate_bishop_attacks(&king_square, &occupancy) & diagonal).is_empty()
+                || !(generate_rook_attacks(&king_square, &occupancy) & straight).is_empty()
+                || !(self.standard_bitboards.knight_attacks[king_square] & self.pieces[Piece::Knight] & remaining).is_empty()
+                || !(self.standard_bitboards.pawn_captures[self.side_to_move][king_square] & self.pieces[Piece::Pawn] & remaining).is_empty();
+            if !checked {
+                list.add(start, target, MoveType::EnPassant);
             }
         }
-        list.push(Kind::CASTLING_MOVE);
     }
 
-    pub fn pretty_print(&self) {
-        let mut cur_square = Square::A1;
-        let mut lines: [String; 8] = [(); 8].map(|_| String::new());
-        let mut current_line = 0;
-
-        while cur_square <= Square::H8 {
-            let piece = self.mailbox[cur_square];
-            let mut new_char: String = match piece {
-                Piece::King => "k",
-                Piece::Queen => "q",
-                Piece::Knight => "n",
-                Piece::Bishop => "b",
-                Piece::Rook => "r",
-                Piece::Pawn => "p",
-                _ => "-",
-            }.to_owned();
-
-            let is_white = !(self.colors[Color::White] & Bitboard::from(cur_square)).is_empty();
-
-            new_char = match is_white {
-                true => new_char.to_uppercase(),
-                false => new_char
-            };
-
-            lines[current_line] = lines[current_line].to_owned() + &new_char;
+    /// Adds the castling move of a kind, if the right is still there and it is legal. As in
+    /// Chess960, the king and rook may start on any square of the back rank: every square
+    /// between them and their targets must be empty, except for the two of them, and no square
+    /// the king passes through (both ends included) may be attacked.
+    pub(crate) fn generate_castling<Kind: CastlingKind>(&self, list: &mut MoveList) {
+        let Some(king_path) = self.castling_path::<Kind>() else { return };
+        let castling = self.state.castling;
+        let king = castling.king_square::<Kind>();
+        let rook = castling.rook_square::<Kind>();
+        let others = self.occupied() ^ Bitboard::from(king) ^ Bitboard::from(rook);
+        // if check_space includes a square the king passes, we can't do it
+        if !((king_path | Bitboard::from(king)) & self.checking_state.check_space).is_empty() {
+            return
+        }
+        // the rook may have been shielding the king's target from a rook or queen on the rank
+        let occupancy = others | Bitboard::from(Kind::KING_TARGET) | Bitboard::from(Kind::ROOK_TARGET);
+        let straight = (self.pieces[Piece::Rook] | self.pieces[Piece::Queen]) & self.colors[!Kind::COLOR];
+        if !(generate_rook_attacks(&Kind::KING_TARGET, &occupancy) & straight).is_empty() {
+            return
+        }
+        list.add(king, rook, Kind::MOVE_TYPE);
+    }
 
-            if cur_square.file() == File::H {
-                current_line += 1;
-            }
-            cur_square = cur_square.shift(1);
+    /// The squares the king passes through, its target included, when castling of a kind, if the
+    /// right is still there and nothing but the king and rook stands where either goes
+    fn castling_path<Kind: CastlingKind>(&self) -> Option<Bitboard> {
+        let castling = self.state.castling;
+        if !castling.is_allowed::<Kind>() {
+            // lost castling rights
+            return None
         }
-        lines.reverse();
-        println!("{}", lines
-            .map(|l| l.split("").collect::<Vec<&str>>().join(" "))
-            .join("\n")
-        );
+        let king = castling.king_square::<Kind>();
+        let rook = castling.rook_square::<Kind>();
+        let between = &self.standard_bitboards.between;
+        let others = self.occupied() ^ Bitboard::from(king) ^ Bitboard::from(rook);
+        let king_path = between[king][Kind::KING_TARGET] | Bitboard::from(Kind::KING_TARGET);
+        let rook_path = between[rook][Kind::ROOK_TARGET] | Bitboard::from(Kind::ROOK_TARGET);
+        // cant castle if there is a piece in the way
+        ((king_path | rook_path | between[king][rook]) & others).is_empty().then_some(king_path)
+    }
+
+    /// Prints the board as plain text from White's side, see [`crate::render::BoardRenderer`]
+    /// for more
+    pub fn pretty_print(&self) {
+        println!("{}", crate::render::BoardRenderer::new().render(self));
     }
 }
 
@@ -506,6 +738,7 @@ impl Default for Board {
     fn default() -> Self {
         Self {
             side_to_move: Color::White,
+            variant: Variant::Standard,
             pieces: [Bitboard::default(); Piece::NUM],
             colors: [Bitboard::default(); Color::NUM],
             state_stack: vec![],
@@ -516,6 +749,7 @@ impl Default for Board {
             checking_state: CheckState::default(),
             pinning_state: [Bitboard(u64::MAX); Square::NUM],
             legal_moves: MoveList::default(),
+            nnue: None,
         }
     }
 }
@@ -0,0 +1,149 @@
+//! Move generation for Antichess (losing chess). There is no check: the king is an ordinary
+//! piece which can be captured, pawns may also promote to a king, and there is no castling.
+//! Whenever a capture is possible, some capture must be played. Without checks or pins to
+//! account for, every move a piece can make is legal, so the generator is much simpler than the
+//! standard one. Atomic starts from the same moves and checks each of them afterwards.
+
+use super::movegen::{black_pawn_advances, generate_bishop_attacks, generate_queen_attacks, generate_rook_attacks, white_pawn_advances};
+use super::Board;
+use crate::types::{Bitboard, Color, MoveList, MoveType, Piece, Rank, Square};
+
+const PROMOTIONS: [MoveType; 5] = [
+    MoveType::PromotionToQueen,
+    MoveType::PromotionToRook,
+    MoveType::PromotionToBishop,
+    MoveType::PromotionToKnight,
+    MoveType::PromotionToKing,
+];
+
+const CAPTURE_PROMOTIONS: [MoveType; 5] = [
+    MoveType::PromotionCaptureToQueen,
+    MoveType::PromotionCaptureToRook,
+    MoveType::PromotionCaptureToBishop,
+    MoveType::PromotionCaptureToKnight,
+    MoveType::PromotionCaptureToKing,
+];
+
+impl Board {
+    /// The moves of Antichess: only the captures if there are any, every move otherwise
+    pub(super) fn generate_antichess_moves(&self) -> MoveList {
+        let mut list = self.generate_pseudo_legal_moves(&PROMOTIONS, &CAPTURE_PROMOTIONS);
+        if list.moves[..list.len].iter().any(|mv| mv.is_capture()) {
+            list.retain(|mv| mv.is_capture());
+        }
+        list
+    }
+
+    /// Every move of every piece, other than castling, without a thought for the king. Pawns
+    /// promote to the pieces whose move types are given.
+    pub(super) fn generate_pseudo_legal_moves(&self, promotions: &[MoveType], capture_promotions: &[MoveType]) -> MoveList {
+        let mut list = MoveList::default();
+        let ours = self.colors[self.side_to_move];
+        let theirs = self.colors[!self.side_to_move];
+        let occupancy = ours | theirs;
+        let promotion_rank = if self.side_to_move == Color::White { Rank::R8 } else { Rank::R1 };
+        for start in ours {
+            let targets = match self.mailbox[start] {
+                Piece::Pawn => {
+                    let captures = self.standard_bitboards.pawn_captures[self.side_to_move][start] & theirs;
+                    let advances = match self.side_to_move {
+                        Color::White => white_pawn_advances(&start, &occupancy),
+                        Color::Black => black_pawn_advances(&start, &occupancy),
+                    };
+                    for target in captures | advances {
+                        let capture = !(captures & Bitboard::from(target)).is_empty();
+                        if target.rank() == promotion_rank {
+                            let kinds = if capture { capture_promotions } else { promotions };
+                            kinds.iter().for_each(|&kind| list.add(start, target, kind));
+                        } else if capture {
+                            list.add(start, target, MoveType::Capture);
+                        } else if (target as i8 - start as i8).abs() == 16 {
+                            list.add(start, target, MoveType::DoublePawnPush);
+                        } else {
+                            list.add(start, target, MoveType::Quiet);
+                        }
+                    }
+                    continue
+                }
+                Piece::Knight => self.standard_bitboards.knight_attacks[start],
+                Piece::Bishop => generate_bishop_attacks(&start, &occupancy),
+                Piece::Rook => generate_rook_attacks(&start, &occupancy),
+                Piece::Queen => generate_queen_attacks(&start, &occupancy),
+                Piece::King => self.standard_bitboards.king_attacks[start],
+                Piece::None => Bitboard::default(),
+            } & !ours;
+            list.add_many(start, targets & theirs, MoveType::Capture);
+            list.add_many(start, targets & !theirs, MoveType::Quiet);
+        }
+        if self.state.en_passant != Square::None {
+            let attackers = self.standard_bitboards.pawn_captures[!self.side_to_move][self.state.en_passant] & self.our(Piece::Pawn);
+            for start in attackers {
+                list.add(start, self.state.en_passant, MoveType::EnPassant);
+            }
+        }
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{GameResult, Variant};
+
+    fn antichess(fen: &str) -> Board {
+        Board::new_variant(fen.to_string(), Variant::Antichess).unwrap()
+    }
+
+    fn moves(board: &Board) -> Vec<String> {
+        let mut moves: Vec<String> = board.legal_moves.moves[..board.legal_moves.len].iter().map(|mv| mv.to_string()).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn captures_are_forced() {
+        // the king may be taken, and may take, and nothing else may move while a capture is on
+        let board = antichess("8/8/8/3k4/4P3/8/8/R7 w - - 0 1");
+        assert_eq!(moves(&board), ["e4d5"]);
+        let board = antichess("8/8/8/3k4/4P3/8/8/R7 b - - 0 1");
+        assert_eq!(moves(&board), ["d5e4"]);
+        // no check: the king may walk next to an enemy rook, and pinned pieces may move
+        let board = antichess("8/8/8/8/8/8/1r6/K7 w - - 0 1");
+        assert_eq!(moves(&board), ["a1b2"]);
+        let board = antichess("8/8/8/8/8/3k4/8/K1R5 w - - 0 1");
+        assert_eq!(moves(&board).len(), 3 + 13);
+        assert!(!board.is_check());
+    }
+
+    #[test]
+    fn promotions() {
+        let mut board = antichess("8/4P3/8/8/8/8/8/k7 w - - 0 1");
+        assert_eq!(moves(&board), ["e7e8b", "e7e8k", "e7e8n", "e7e8q", "e7e8r"]);
+        let mv = board.legal_moves.moves[..board.legal_moves.len].iter().copied().find(|mv| mv.to_string() == "e7e8k").unwrap();
+        assert!(!mv.is_capture() && mv.is_promotion());
+        board.make_move(mv);
+        assert!(board.piece_on(Square::E8) == Piece::King);
+        assert_eq!(board.fen(), "4K3/8/8/8/8/8/8/k7 b - - 0 1");
+        board.undo_move();
+        assert!(board.piece_on(Square::E7) == Piece::Pawn);
+    }
+
+    #[test]
+    fn outcomes() {
+        // losing every piece wins
+        let mut board = antichess("8/8/8/8/8/8/1p6/R7 b - - 0 1");
+        let mv = board.legal_moves.moves[0];
+        assert_eq!(mv.to_string(), "b2a1q");
+        assert_eq!(board.legal_moves.len, 5);
+        board.make_move(mv);
+        assert!(board.variant_outcome() == Some(GameResult::Win(Color::White)));
+        assert!(board.outcome() == Some(GameResult::Win(Color::White)));
+        // so does having no move
+        let board = antichess("8/8/8/8/8/p7/P7/8 w - - 0 1");
+        assert!(board.variant_outcome() == Some(GameResult::Win(Color::White)));
+        assert!(!board.is_stalemate() && !board.is_checkmate());
+        // bare kings are not a draw
+        let board = antichess("8/8/8/3k4/8/8/8/K7 w - - 0 1");
+        assert!(board.outcome().is_none());
+    }
+}
@@ -0,0 +1,247 @@
+//! Atomic chess: a capture is an explosion which takes off the board the captured piece, the
+//! capturing piece and every piece other than a pawn on the eight squares around. A move which
+//! blows up your own king is illegal, and blowing up the other king wins at once, whatever else
+//! the move does. Since a king cannot capture, and taking a king next to your own would blow
+//! both up, kings may stand next to each other, and a king next to the other one is never in
+//! check.
+
+use super::movegen::{generate_bishop_attacks, generate_rook_attacks};
+use super::{zobrist::ZOBRIST, Board};
+use crate::types::{
+    Bitboard, BlackKingside, BlackQueenside, CastlingKind, Color, Move, MoveList, MoveType, Piece, Square, WhiteKingside,
+    WhiteQueenside,
+};
+
+const PROMOTIONS: [MoveType; 4] = [
+    MoveType::PromotionToQueen,
+    MoveType::PromotionToRook,
+    MoveType::PromotionToBishop,
+    MoveType::PromotionToKnight,
+];
+
+const CAPTURE_PROMOTIONS: [MoveType; 4] = [
+    MoveType::PromotionCaptureToQueen,
+    MoveType::PromotionCaptureToRook,
+    MoveType::PromotionCaptureToBishop,
+    MoveType::PromotionCaptureToKnight,
+];
+
+/// The pieces around a capture which an explosion took off the board, so that `undo_move` can
+/// put them back. The captured and capturing pieces are not among them.
+#[derive(Default, Copy, Clone)]
+pub(super) struct Explosion {
+    squares: Bitboard,
+    /// Which of the squares held white pieces
+    white: Bitboard,
+    /// The pieces, in the order of their squares
+    pieces: [Piece; 8],
+}
+
+/// Just the pieces of a position, to try a move out on
+#[derive(Clone, Copy)]
+struct Pieces {
+    pieces: [Bitboard; Piece::NUM],
+    colors: [Bitboard; Color::NUM],
+}
+
+impl Pieces {
+    fn of(&self, piece: Piece, color: Color) -> Bitboard {
+        self.pieces[piece] & self.colors[color]
+    }
+
+    fn remove(&mut self, squares: Bitboard) {
+        self.pieces.iter_mut().chain(self.colors.iter_mut()).for_each(|bitboard| *bitboard &= !squares);
+    }
+
+    fn put(&mut self, square: Square, color: Color, piece: Piece) {
+        self.pieces[piece].set(square);
+        self.colors[color].set(square);
+    }
+}
+
+impl Board {
+    /// The moves which do not leave our king blown up or in check
+    pub(super) fn generate_atomic_moves(&self) -> MoveList {
+        let mut list = self.generate_pseudo_legal_moves(&PROMOTIONS, &CAPTURE_PROMOTIONS);
+        if self.side_to_move == Color::White {
+            self.add_atomic_castling::<WhiteKingside>(&mut list);
+            self.add_atomic_castling::<WhiteQueenside>(&mut list);
+        } else {
+            self.add_atomic_castling::<BlackKingside>(&mut list);
+            self.add_atomic_castling::<BlackQueenside>(&mut list);
+        }
+        list.retain(|mv| self.is_atomic_legal(mv));
+        list
+    }
+
+    /// Whether the side to move is in check, with the rules of Atomic
+    pub(super) fn is_atomic_check(&self) -> bool {
+        self.atomic_check(&self.current_pieces(), self.side_to_move)
+    }
+
+    /// The squares an explosion on `square` takes off the board, besides the capturing piece and
+    /// the one captured
+    fn blast(&self, square: Square) -> Bitboard {
+        self.standard_bitboards.king_attacks[square] & self.occupied() & !self.pieces[Piece::Pawn]
+    }
+
+    /// Blows up the pieces around a capture on `target`, the capturing piece (which now stands
+    /// there) included, and keeps the others in the state for `undo_move`
+    pub(super) fn explode(&mut self, target: Square) {
+        let mut explosion = Explosion::default();
+        let blast = self.blast(target);
+        for (i, square) in blast.enumerate() {
+            let piece = self.piece_on(square);
+            let color = if (self.colors[Color::White] & Bitboard::from(square)).is_empty() { Color::Black } else { Color::White };
+            if color == Color::White {
+                explosion.white.set(square);
+            }
+            explosion.pieces[i] = piece;
+            self.remove_piece(square, color, piece);
+        }
+        explosion.squares = blast;
+        self.remove_piece(target, self.side_to_move, self.piece_on(target));
+        // a rook or king blown up on its starting square takes its castling rights along
+        self.state.hash_key ^= ZOBRIST.castling[self.state.castling];
+        for square in blast {
+            self.state.castling.update(square, square);
+        }
+        self.state.hash_key ^= ZOBRIST.castling[self.state.castling];
+        self.state.explosion = explosion;
+    }
+
+    /// Puts back the pieces of the explosion of the last move, and the capturing `piece` on
+    /// `target`, where the rest of `undo_move` expects it to be. The side to move must already be
+    /// the side which captured.
+    pub(super) fn undo_explosion(&mut self, target: Square, piece: Piece) {
+        let explosion = self.state.explosion;
+        for (i, square) in explosion.squares.enumerate() {
+            let color = if (explosion.white & Bitboard::from(square)).is_empty() { Color::Black } else { Color::White };
+            self.add_piece(square, color, explosion.pieces[i]);
+        }
+        self.add_piece(target, self.side_to_move, piece);
+    }
+
+    fn current_pieces(&self) -> Pieces {
+        Pieces { pieces: self.pieces, colors: self.colors }
+    }
+
+    /// Whether the king of `color` is in check in `position`. A king next to the other king is
+    /// not, and neither is one whose opponent has no king left.
+    fn atomic_check(&self, position: &Pieces, color: Color) -> bool {
+        let king = position.of(Piece::King, color);
+        let their_king = position.of(Piece::King, !color);
+        if king.is_empty() || their_king.is_empty() {
+            return false
+        }
+        let square = king.lsb();
+        if !(self.standard_bitboards.king_attacks[square] & their_king).is_empty() {
+            return false
+        }
+        let occupancy = position.colors[Color::White] | position.colors[Color::Black];
+        let them = !color;
+        let diagonal = position.of(Piece::Bishop, them) | position.of(Piece::Queen, them);
+        let straight = position.of(Piece::Rook, them) | position.of(Piece::Queen, them);
+        !(self.standard_bitboards.knight_attacks[square] & position.of(Piece::Knight, them)).is_empty()
+            || !(self.standard_bitboards.pawn_captures[color][square] & position.of(Piece::Pawn, them)).is_empty()
+            || !(generate_bishop_attacks(&square, &occupancy) & diagonal).is_empty()
+            || !(generate_rook_attacks(&square, &occupancy) & straight).is_empty()
+    }
+
+    /// Tries the move out: it must not blow up our king, and unless it blows up theirs, it must
+    /// not leave ours in check
+    fn is_atomic_legal(&self, mv: Move) -> bool {
+        let us = self.side_to_move;
+        let (start, target) = (mv.start(), mv.target());
+        let mut after = self.current_pieces();
+        if mv.is_castling() {
+            let (king_target, rook_target) = mv.castling_targets();
+            after.remove(Bitboard::from(start) | Bitboard::from(target));
+            after.put(king_target, us, Piece::King);
+            after.put(rook_target, us, Piece::Rook);
+        } else if mv.is_capture() {
+            let captured = if mv.is_en_passant() {
+                Square::from_rank_file(start.rank() as u8, target.file() as u8)
+            } else {
+                target
+            };
+            after.remove(Bitboard::from(start) | Bitboard::from(captured) | Bitboard::from(target) | self.blast(target));
+        } else {
+            let placed = if mv.is_promotion() { mv.promo_piece() } else { self.piece_on(start) };
+            after.remove(Bitboard::from(start));
+            after.put(target, us, placed);
+        }
+        if after.of(Piece::King, us).is_empty() {
+            return false
+        }
+        !self.atomic_check(&after, us)
+    }
+
+    /// Adds the castling move of a kind if the right is there and the way is clear. Only the
+    /// final position is checked later on, so here no square the king stands on or passes
+    /// through may be in check, with the rook already moved.
+    fn add_atomic_castling<Kind: CastlingKind>(&self, list: &mut MoveList) {
+        let Some(king_path) = self.castling_path::<Kind>() else { return };
+        let king = self.state.castling.king_square::<Kind>();
+        let rook = self.state.castling.rook_square::<Kind>();
+        let mut base = self.current_pieces();
+        base.remove(Bitboard::from(king) | Bitboard::from(rook));
+        base.put(Kind::ROOK_TARGET, Kind::COLOR, Piece::Rook);
+        let attacked = (king_path | Bitboard::from(king)).any(|square| {
+            let mut position = base;
+            position.put(square, Kind::COLOR, Piece::King);
+            self.atomic_check(&position, Kind::COLOR)
+        });
+        if !attacked {
+            list.add(king, rook, Kind::MOVE_TYPE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{GameResult, Variant};
+    use crate::uci::parse_uci_move;
+
+    fn atomic(fen: &str) -> Board {
+        Board::new_variant(fen.to_string(), Variant::Atomic).unwrap()
+    }
+
+    #[test]
+    fn explosions() {
+        // Nxd7 blows up the knight, the bishop, the queen, the king and the knight on c6, but not
+        // the pawns, and Black's castling rights go with the king
+        let mut board = atomic("r2qk2r/pppb1ppp/2n5/4N3/8/8/PPPP1PPP/R1BQK2R w KQkq - 0 1");
+        let hash = board.hash();
+        let fen = board.fen();
+        board.make_move(parse_uci_move(&board, "e5d7").unwrap());
+        assert_eq!(board.fen(), "r6r/ppp2ppp/8/8/8/8/PPPP1PPP/R1BQK2R b KQ - 0 1");
+        assert!(board.variant_outcome() == Some(GameResult::Win(Color::White)));
+        assert!(board.outcome() == Some(GameResult::Win(Color::White)));
+        assert_eq!(board.legal_moves.len, 0);
+        assert_eq!(board.hash(), board.generate_hash_key());
+        board.undo_move();
+        assert_eq!(board.fen(), fen);
+        assert_eq!(board.hash(), hash);
+    }
+
+    #[test]
+    fn kings() {
+        // neither a king nor a piece next to it may capture
+        let board = atomic("4k3/8/8/8/8/8/3n4/3RK3 w - - 0 1");
+        assert!(parse_uci_move(&board, "e1d2").is_none() && parse_uci_move(&board, "d1d2").is_none());
+        // kings side by side: the rook on a1 gives no check, and the white king may step along
+        // the first rank next to the black king
+        let board = atomic("8/8/8/8/8/8/4k3/r3K3 w - - 0 1");
+        assert!(!board.is_check());
+        assert!(parse_uci_move(&board, "e1d1").is_some() && parse_uci_move(&board, "e1f1").is_some());
+        // but away from it, the rook's line is out of bounds
+        let board = atomic("8/8/8/8/8/6k1/8/r3K3 w - - 0 1");
+        assert!(board.is_check());
+        assert!(parse_uci_move(&board, "e1f1").is_none() && parse_uci_move(&board, "e1f2").is_some());
+        // blowing up their king wins even when ours is left in check
+        let board = atomic("7k/6p1/8/8/8/8/6Q1/r3K3 w - - 0 1");
+        assert!(parse_uci_move(&board, "g2g7").is_some() && parse_uci_move(&board, "g2g4").is_none());
+    }
+}
@@ -0,0 +1,95 @@
+//! Chess960 (Fischer Random) starting positions. Each of the 960 back ranks has a number in the
+//! standard scheme, in which 518 is the standard starting position.
+
+use crate::types::Piece;
+
+/// The squares the two knights take among the five left after the bishops and queen, by the
+/// number which the index leaves for them
+const KNIGHTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+/// The back rank of a Chess960 position, from the a file to the h file. The index (0 to 959) is
+/// taken apart into the light-squared bishop's file, the dark-squared bishop's, the queen's
+/// among the empty squares and the knights', and the king goes between the rooks on the three
+/// squares left.
+pub fn back_rank(index: usize) -> [Piece; 8] {
+    assert!(index < 960, "Chess960 positions are numbered 0 to 959");
+    let mut rank = [Piece::None; 8];
+    let mut n = index;
+    rank[n % 4 * 2 + 1] = Piece::Bishop;
+    n /= 4;
+    rank[n % 4 * 2] = Piece::Bishop;
+    n /= 4;
+    let empty = |rank: &[Piece; 8]| (0..8).filter(|&file| rank[file] == Piece::None).collect::<Vec<_>>();
+    rank[empty(&rank)[n % 6]] = Piece::Queen;
+    n /= 6;
+    let files = empty(&rank);
+    let (first, second) = KNIGHTS[n];
+    rank[files[first]] = Piece::Knight;
+    rank[files[second]] = Piece::Knight;
+    for (file, piece) in empty(&rank).into_iter().zip([Piece::Rook, Piece::King, Piece::Rook]) {
+        rank[file] = piece;
+    }
+    rank
+}
+
+fn letter(piece: Piece) -> char {
+    match piece {
+        Piece::Queen => 'q',
+        Piece::Rook => 'r',
+        Piece::Bishop => 'b',
+        Piece::Knight => 'n',
+        _ => 'k',
+    }
+}
+
+/// The starting position with these back ranks, each side able to castle with both rooks. The
+/// castling field names the files of the rooks (Shredder-FEN), since X-FEN's `KQkq` only fits
+/// when the back ranks are the standard one.
+pub fn start_fen(white: &[Piece; 8], black: &[Piece; 8]) -> String {
+    let white_rank: String = white.iter().map(|&piece| letter(piece).to_ascii_uppercase()).collect();
+    let black_rank: String = black.iter().map(|&piece| letter(piece)).collect();
+    let rooks = |rank: &[Piece; 8], base: u8| -> String {
+        // the kingside rook first, as in `KQkq`
+        (0..8u8).rev().filter(|&file| rank[file as usize] == Piece::Rook).map(|file| (base + file) as char).collect()
+    };
+    format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{} - 0 1", black_rank, white_rank, rooks(white, b'A'), rooks(black, b'a'))
+}
+
+/// The Chess960 starting position with this number
+pub fn chess960_fen(index: usize) -> String {
+    let rank = back_rank(index);
+    start_fen(&rank, &rank)
+}
+
+/// A Double Fischer Random starting position, where White's and Black's back ranks are drawn
+/// independently: `white` and `black` are each a Chess960 number, giving 960 × 960 positions.
+pub fn dfrc_fen(white: usize, black: usize) -> String {
+    start_fen(&back_rank(white), &back_rank(black))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    #[test]
+    fn numbering() {
+        assert_eq!(chess960_fen(518), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1");
+        assert!(chess960_fen(0).starts_with("bbqnnrkr/"));
+        assert!(chess960_fen(959).starts_with("rkrnnqbb/"));
+        // every position is different, with the king between the rooks and bishops on both colours
+        let mut ranks: Vec<String> = (0..960).map(|index| chess960_fen(index)[..8].to_string()).collect();
+        ranks.sort();
+        ranks.dedup();
+        assert_eq!(ranks.len(), 960);
+        for index in [0, 100, 518, 959] {
+            let board = Board::new(chess960_fen(index)).unwrap();
+            assert_eq!(board.legal_moves.len, 20);
+            assert!(!board.castling().is_empty());
+        }
+        assert_eq!(dfrc_fen(518, 518), chess960_fen(518));
+        let board = Board::new(dfrc_fen(0, 959)).unwrap();
+        assert_eq!(board.fen(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KFcq - 0 1");
+        assert_eq!(board.legal_moves.len, 20);
+    }
+}
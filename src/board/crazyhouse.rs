@@ -0,0 +1,158 @@
+//! Crazyhouse: a captured piece changes sides and goes into the capturer's pocket, and instead of
+//! moving, a player may drop a piece from their pocket onto any empty square. Pawns may not be
+//! dropped on the first or last rank. A piece which was promoted goes back into the pocket as a
+//! pawn, so the board keeps track of which pieces were promoted.
+
+use super::{zobrist::ZOBRIST, Board};
+use crate::types::{Bitboard, Color, Move, MoveList, Piece, Rank, Square};
+
+/// The pieces which can be in a pocket, in the order a FEN lists them
+pub const POCKET_PIECES: [Piece; 5] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn];
+
+impl Board {
+    /// How many pieces of a kind `color` has in their pocket
+    pub fn pocket(&self, color: Color, piece: Piece) -> u8 {
+        self.state.pockets[color][piece]
+    }
+
+    /// Whether the piece on `square` is a promoted pawn
+    pub fn is_promoted(&self, square: Square) -> bool {
+        !(self.state.promoted & Bitboard::from(square)).is_empty()
+    }
+
+    pub(super) fn add_to_pocket(&mut self, color: Color, piece: Piece) {
+        let count = &mut self.state.pockets[color][piece];
+        // the n-th piece of a kind in a pocket has its own key, so the key covers the count
+        self.state.hash_key ^= ZOBRIST.pockets[color][piece][*count as usize & 15];
+        *count += 1;
+    }
+
+    pub(super) fn take_from_pocket(&mut self, color: Color, piece: Piece) {
+        let count = &mut self.state.pockets[color][piece];
+        *count -= 1;
+        self.state.hash_key ^= ZOBRIST.pockets[color][piece][*count as usize & 15];
+    }
+
+    /// Pockets a piece just captured on `square` for the side to move, as a pawn if it had been
+    /// promoted
+    pub(super) fn pocket_capture(&mut self, square: Square, piece: Piece) {
+        let piece = if self.is_promoted(square) {
+            self.state.promoted.clear(square);
+            Piece::Pawn
+        } else {
+            piece
+        };
+        self.add_to_pocket(self.side_to_move, piece);
+    }
+
+    /// Follows a promoted piece to its new square, or marks the piece a pawn promoted to
+    pub(super) fn move_promoted(&mut self, mv: Move) {
+        if self.is_promoted(mv.start()) {
+            self.state.promoted.clear(mv.start());
+            self.state.promoted.set(mv.target());
+        } else if mv.is_promotion() {
+            self.state.promoted.set(mv.target());
+        }
+    }
+
+    /// Adds the drops of the side to move. In check, a drop can only block: a double check or a
+    /// contact check leaves no drop at all.
+    pub(super) fn add_drops(&self, list: &mut MoveList) {
+        let mut targets = !self.occupied();
+        let checks = &self.checking_state.checks;
+        if checks[1].checking_piece != Square::None {
+            return
+        }
+        if checks[0].checking_piece != Square::None {
+            targets &= checks[0].block_space;
+        }
+        for piece in POCKET_PIECES {
+            if self.pocket(self.side_to_move, piece) == 0 {
+                continue
+            }
+            let targets = match piece {
+                Piece::Pawn => targets & !(Bitboard::rank(Rank::R1) | Bitboard::rank(Rank::R8)),
+                _ => targets,
+            };
+            for target in targets {
+                list.push(Move::new_drop(piece, target));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Variant;
+    use crate::uci::parse_uci_move;
+
+    fn crazyhouse(fen: &str) -> Board {
+        Board::new_variant(fen.to_string(), Variant::Crazyhouse).unwrap()
+    }
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for mv in moves {
+            let mv = parse_uci_move(board, mv).unwrap();
+            board.make_move(mv);
+        }
+    }
+
+    #[test]
+    fn captures_fill_the_pocket() {
+        let mut board = crazyhouse(Variant::Crazyhouse.start_fen());
+        let hash = board.hash();
+        play(&mut board, &["e2e4", "d7d5", "e4d5"]);
+        assert_eq!(board.pocket(Color::White, Piece::Pawn), 1);
+        assert_eq!(board.fen(), "rnbqkbnr/ppp1pppp/8/3P4/8/8/PPPP1PPP/RNBQKBNR[P] b KQkq - 0 2");
+        play(&mut board, &["d8d5", "P@e4"]);
+        assert_eq!(board.pocket(Color::White, Piece::Pawn), 0);
+        assert_eq!(board.pocket(Color::Black, Piece::Pawn), 1);
+        assert!(board.piece_on(Square::E4) == Piece::Pawn);
+        assert_eq!(board.hash(), board.generate_hash_key());
+        for _ in 0..5 {
+            board.undo_move();
+        }
+        assert_eq!(board.hash(), hash);
+        assert_eq!(board.fen(), Variant::Crazyhouse.start_fen());
+    }
+
+    #[test]
+    fn promoted_pieces_return_as_pawns() {
+        let mut board = crazyhouse("r3k3/1P6/8/8/8/8/8/4K3[] w - - 0 1");
+        play(&mut board, &["b7a8q"]);
+        assert!(board.is_promoted(Square::A8));
+        assert_eq!(board.pocket(Color::White, Piece::Rook), 1);
+        assert_eq!(board.fen(), "Q~3k3/8/8/8/8/8/8/4K3[R] b - - 0 1");
+        play(&mut board, &["e8d7", "a8a4"]);
+        assert!(board.is_promoted(Square::A4) && !board.is_promoted(Square::A8));
+        // the queen is taken back as a pawn
+        let mut board = crazyhouse("4k3/8/8/8/Q~7/8/8/r3K3[] b - - 0 1");
+        play(&mut board, &["a1a4"]);
+        assert_eq!(board.pocket(Color::Black, Piece::Pawn), 1);
+        assert_eq!(board.pocket(Color::Black, Piece::Queen), 0);
+        assert!(!board.is_promoted(Square::A4));
+        board.undo_move();
+        assert!(board.is_promoted(Square::A4));
+    }
+
+    #[test]
+    fn drops() {
+        // five kinds of piece on 62 empty squares, but no pawns on the first and last ranks
+        let board = crazyhouse("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1");
+        let drops = board.legal_moves.moves[..board.legal_moves.len].iter().filter(|mv| mv.is_drop()).count();
+        assert_eq!(drops, 4 * 62 + 48);
+        // a drop may block a check, but not a contact check or a double check
+        let board = crazyhouse("4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1");
+        let mut drops: Vec<String> = board.legal_moves.moves[..board.legal_moves.len].iter().filter(|mv| mv.is_drop()).map(|mv| mv.to_string()).collect();
+        drops.sort();
+        assert_eq!(drops, ["N@b1", "N@c1", "N@d1"]);
+        let board = crazyhouse("4k3/8/8/8/8/8/3p4/4K3[N] w - - 0 1");
+        assert!(!board.legal_moves.moves[..board.legal_moves.len].iter().any(|mv| mv.is_drop()));
+        // pockets and promoted pieces read back the same, with the pocket as a ninth rank too
+        let board = crazyhouse("4k3/8/8/8/8/8/8/4K2N~/QPbb w - - 0 1");
+        assert_eq!(board.fen(), "4k3/8/8/8/8/8/8/4K2N~[QPbb] w - - 0 1");
+        assert!(board.is_promoted(Square::H1));
+        assert_ne!(board.hash(), crazyhouse("4k3/8/8/8/8/8/8/4K2N~[QPb] w - - 0 1").hash());
+    }
+}
@@ -1,25 +1,44 @@
+use crate::eval::nnue::NnueState;
 use crate::types::{Color, FullMove, Move, MoveType, Piece, Square};
 
-use super::Board;
+use super::{zobrist::ZOBRIST, Board, Variant};
 
 impl Board {
+    /// Plays a legal move, which must come from `legal_moves`, and generates the moves of the
+    /// new position. `undo_move` takes it back.
+    ///
+    /// ```
+    /// use mascagni::{uci::parse_uci_move, Board, START_FEN};
+    ///
+    /// let mut board = Board::new(START_FEN.to_string()).unwrap();
+    /// let hash = board.hash();
+    /// board.make_move(parse_uci_move(&board, "e2e4").unwrap());
+    /// assert_eq!(board.fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+    /// board.undo_move();
+    /// assert_eq!(board.hash(), hash);
+    /// ```
     pub fn make_move(&mut self, mv: Move) {
         self.state_stack.push(self.state);
         let start = mv.start();
         let target = mv.target();
-        let piece = self.piece_on(start);
+        // a drop keeps the piece it puts down where a move has its start square
+        let piece = if mv.is_drop() { mv.drop_piece() } else { self.piece_on(start) };
 
         if piece == Piece::None {
             // this is no good
             return
         }
 
-        if start == target {
+        if start == target && !mv.is_drop() {
             // this is no good
             return
         }
 
 
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.accumulators.push();
+        }
+
         let kind = mv.kind();
 
         //
@@ -43,39 +62,30 @@ impl Board {
             captured_piece = self.piece_on(capture_square);
 
             self.remove_piece(capture_square, !self.side_to_move, captured_piece);
+            if self.variant == Variant::Crazyhouse {
+                self.pocket_capture(capture_square, captured_piece);
+            }
         }
 
-        // remove the moved piece from its own square
-        self.remove_piece(start, self.side_to_move, piece);
-
-        // place the moved piece (or promoted piece) on target square.
-        let placed_piece: Piece = match kind {
-            MoveType::PromotionToKnight => Piece::Knight,
-            MoveType::PromotionToBishop => Piece::Bishop,
-            MoveType::PromotionToRook => Piece::Rook,
-            MoveType::PromotionToQueen => Piece::Queen,
-            MoveType::PromotionCaptureToKnight => Piece::Knight,
-            MoveType::PromotionCaptureToBishop => Piece::Bishop,
-            MoveType::PromotionCaptureToRook => Piece::Rook,
-            MoveType::PromotionCaptureToQueen => Piece::Queen,
-            _ => piece,
-        };
-        self.add_piece(target, self.side_to_move, placed_piece);
-
-        // Do castling
-        if mv.is_castling() {
-            // TODO: edit this to make compatible with chess 960
-            let rook_square = match kind {
-                MoveType::KingsideCastle => start.shift(3),
-                MoveType::QueensideCastle => start.shift(-4),
-                _ => Square::None,
-            };
-            // remove the relevant rook
-            self.remove_piece(rook_square, self.side_to_move, Piece::Rook);
-
-            // add rook to the square between king's start and target squares.
-            let between_square = self.standard_bitboards.between[start][target].lsb();
-            self.add_piece(between_square, self.side_to_move, Piece::Rook);
+        if mv.is_drop() {
+            self.take_from_pocket(self.side_to_move, piece);
+            self.add_piece(target, self.side_to_move, piece);
+        } else if mv.is_castling() {
+            // the king takes its own rook: both are lifted before either is put down, since in
+            // Chess960 one may land where the other stood
+            let (king_target, rook_target) = mv.castling_targets();
+            self.remove_piece(start, self.side_to_move, Piece::King);
+            self.remove_piece(target, self.side_to_move, Piece::Rook);
+            self.add_piece(king_target, self.side_to_move, Piece::King);
+            self.add_piece(rook_target, self.side_to_move, Piece::Rook);
+        } else {
+            if self.variant == Variant::Crazyhouse {
+                self.move_promoted(mv);
+            }
+            self.move_piece(mv, piece);
+            if self.variant == Variant::Atomic && mv.is_capture() {
+                self.explode(target);
+            }
         }
 
         //
@@ -92,84 +102,342 @@ impl Board {
         } else {
             self.state.halfmove_clock += 1;
         }
+        self.state.plies_from_null = self.state.plies_from_null.saturating_add(1);
 
         // set en passant square
+        self.clear_en_passant();
         if kind == MoveType::DoublePawnPush {
             self.state.en_passant = self.standard_bitboards.between[start][target].lsb();
-        } else {
-            self.state.en_passant = Square::None;
+            self.state.hash_key ^= ZOBRIST.en_passant[self.state.en_passant];
         }
 
-        // Set castling rights
-        self.state.castling.update(start, target);
+        // Set castling rights (a drop lands on an empty square, which cannot take any away)
+        if !mv.is_drop() {
+            self.state.hash_key ^= ZOBRIST.castling[self.state.castling];
+            self.state.castling.update(start, target);
+            self.state.hash_key ^= ZOBRIST.castling[self.state.castling];
+        }
 
         // change side_to_move
         self.side_to_move = !self.side_to_move;
+        self.state.hash_key ^= ZOBRIST.side_to_move;
+        if self.side_to_move == Color::White {
+            self.state.fullmove_number += 1;
+        }
 
         // Update the move stack
         self.move_stack.push(full_move);
 
-        // TODO: update the zobrist hashes
-
+        if self.variant == Variant::ThreeCheck {
+            self.count_check();
+        }
         self.analyze_board()
     }
 
+    /// Counts a check by the side which just moved, before the moves are generated since the
+    /// third check ends the game
+    fn count_check(&mut self) {
+        self.checking_state = self.calculate_check_state();
+        if self.is_check() {
+            let color = !self.side_to_move;
+            let checks = self.state.checks_given[color].min(3) as usize;
+            self.state.hash_key ^= ZOBRIST.checks[color][checks] ^ ZOBRIST.checks[color][(checks + 1).min(3)];
+            self.state.checks_given[color] += 1;
+        }
+    }
+
+    /// Moves a piece other than a castling king, promoting it if need be
+    fn move_piece(&mut self, mv: Move, piece: Piece) {
+        // remove the moved piece from its own square
+        self.remove_piece(mv.start(), self.side_to_move, piece);
+
+        // place the moved piece (or promoted piece) on target square.
+        let placed_piece: Piece = match mv.kind() {
+            MoveType::PromotionToKnight => Piece::Knight,
+            MoveType::PromotionToBishop => Piece::Bishop,
+            MoveType::PromotionToRook => Piece::Rook,
+            MoveType::PromotionToQueen => Piece::Queen,
+            MoveType::PromotionCaptureToKnight => Piece::Knight,
+            MoveType::PromotionCaptureToBishop => Piece::Bishop,
+            MoveType::PromotionCaptureToRook => Piece::Rook,
+            MoveType::PromotionCaptureToQueen => Piece::Queen,
+            MoveType::PromotionToKing => Piece::King,
+            MoveType::PromotionCaptureToKing => Piece::King,
+            _ => piece,
+        };
+        self.add_piece(mv.target(), self.side_to_move, placed_piece);
+    }
+
+    /// Passes the turn to the other side without moving (used by null move pruning). The en
+    /// passant square is cleared, since the right to capture en passant is lost.
+    pub fn make_null_move(&mut self) {
+        self.state_stack.push(self.state);
+        self.clear_en_passant();
+        self.state.captured_piece = Piece::None;
+        self.state.halfmove_clock += 1;
+        self.state.plies_from_null = 0;
+        self.side_to_move = !self.side_to_move;
+        self.state.hash_key ^= ZOBRIST.side_to_move;
+        self.analyze_board();
+    }
+
+    /// Takes back a move made with `make_null_move`
+    pub fn undo_null_move(&mut self) {
+        if let Some(state) = self.state_stack.pop() {
+            self.state = state;
+            self.side_to_move = !self.side_to_move;
+            self.analyze_board();
+        }
+    }
+
+    fn clear_en_passant(&mut self) {
+        if self.state.en_passant != Square::None {
+            self.state.hash_key ^= ZOBRIST.en_passant[self.state.en_passant];
+            self.state.en_passant = Square::None;
+        }
+    }
+
     pub fn undo_move(&mut self) {
-        let full_move = self.move_stack.pop();
-        match full_move {
-            Some(fm) => {
-                let start = fm.inner_move.start();
-                let target = fm.inner_move.target();
-                // the piece that was moved
-                let moved_piece = fm.piece;
-                // the piece AFTER it was moved
-                let placed_piece = if fm.inner_move.is_promotion() {
-                    fm.inner_move.promo_piece()
-                } else {
-                    moved_piece
+        if let Some(fm) = self.move_stack.pop() {
+            // The accumulators of the previous ply are still on the stack, so there is no need to
+            // update them while we put the pieces back.
+            let nnue = self.nnue.take();
+            let start = fm.inner_move.start();
+            let target = fm.inner_move.target();
+            // the piece that was moved
+            let moved_piece = fm.piece;
+            // the piece AFTER it was moved
+            let placed_piece = if fm.inner_move.is_promotion() {
+                fm.inner_move.promo_piece()
+            } else {
+                moved_piece
+            };
+            self.side_to_move = !self.side_to_move;
+            if fm.inner_move.is_drop() {
+                // the pocket comes back with the state
+                self.remove_piece(target, self.side_to_move, moved_piece);
+                self.restore_state(nnue);
+                return
+            }
+            if fm.inner_move.is_castling() {
+                let (king_target, rook_target) = fm.inner_move.castling_targets();
+                self.remove_piece(king_target, self.side_to_move, Piece::King);
+                self.remove_piece(rook_target, self.side_to_move, Piece::Rook);
+                self.add_piece(start, self.side_to_move, Piece::King);
+                self.add_piece(target, self.side_to_move, Piece::Rook);
+                self.restore_state(nnue);
+                return
+            }
+            if self.variant == Variant::Atomic && fm.inner_move.is_capture() {
+                self.undo_explosion(target, placed_piece);
+            }
+            // remove the piece that was placed
+            self.remove_piece(target, self.side_to_move, placed_piece);
+            // replace the piece that was captured
+            if fm.inner_move.is_capture() {
+                let captured_piece = fm.captured;
+                let captured_piece_square = match fm.inner_move.is_en_passant() {
+                    true => {
+                        if self.side_to_move == Color::White {
+                            target.shift(-8)
+                        } else {
+                            target.shift(8)
+                        }
+                    },
+                    false => target,
                 };
-                self.side_to_move = !self.side_to_move;
-                // remove the piece that was placed
-                self.remove_piece(target, self.side_to_move, placed_piece);
-                // replace the piece that was captured
-                if fm.inner_move.is_capture() {
-                    let captured_piece = fm.captured;
-                    let captured_piece_square = match fm.inner_move.is_en_passant() {
-                        true => {
-                            if self.side_to_move == Color::White {
-                                target.shift(-8)
-                            } else {
-                                target.shift(8)
-                            }
-                        },
-                        false => target,
-                    };
-                    self.add_piece(captured_piece_square, !self.side_to_move, captured_piece);
-                }
-                if fm.inner_move.is_castling() {
-                    let (rook_start, rook_end) = get_rook_move_for_king_target(target);
-                    self.remove_piece(rook_end, self.side_to_move, Piece::Rook);
-                    self.add_piece(rook_start, self.side_to_move, Piece::Rook);
-                }
-                // re-add the piece that was moved
-                self.add_piece(start, self.side_to_move, moved_piece);
-                self.state = self.state_stack.pop().unwrap();
-                self.analyze_board();
-            },
-            None => {
-            },
+                self.add_piece(captured_piece_square, !self.side_to_move, captured_piece);
+            }
+            // re-add the piece that was moved
+            self.add_piece(start, self.side_to_move, moved_piece);
+            self.restore_state(nnue);
+        }
+    }
+
+    /// Finishes taking back a move once the pieces are back in place
+    fn restore_state(&mut self, mut nnue: Option<Box<NnueState>>) {
+        self.state = self.state_stack.pop().unwrap();
+        if let Some(nnue) = nnue.as_mut() {
+            // the network was attached after this move was made, so its accumulators have to be
+            // built again
+            if !nnue.accumulators.pop() {
+                self.refresh_accumulators(nnue);
+            }
         }
+        self.nnue = nnue;
+        self.analyze_board();
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::board::{Board, START_FEN};
+    use crate::types::{MoveType, Piece, Square};
+    use crate::uci::parse_uci_move;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for mv in moves {
+            let mv = parse_uci_move(board, mv).unwrap();
+            board.make_move(mv);
+        }
+    }
+
+    #[test]
+    fn incremental_hash_matches_full_hash() {
+        let mut board = Board::new(KIWIPETE.to_string()).unwrap();
+        let root_hash = board.hash();
+        for i in 0..board.legal_moves.len {
+            board.make_move(board.legal_moves.moves[i]);
+            assert_eq!(board.hash(), board.generate_hash_key());
+            for j in 0..board.legal_moves.len {
+                board.make_move(board.legal_moves.moves[j]);
+                assert_eq!(board.hash(), board.generate_hash_key());
+                board.undo_move();
+            }
+            board.undo_move();
+        }
+        assert_eq!(board.hash(), root_hash);
+    }
+
+    #[test]
+    fn double_pushes() {
+        // pawns about to promote, and on their starting ranks, for both sides
+        for fen in ["4k3/1P4p1/8/8/8/8/1P4p1/4K3 w - - 0 1", "4k3/1P4p1/8/8/8/8/1P4p1/4K3 b - - 0 1"] {
+            let board = Board::new(fen.to_string()).unwrap();
+            for mv in board.legal_moves().as_slice() {
+                let two_squares = (mv.target() as i8 - mv.start() as i8).abs() == 16;
+                assert_eq!(mv.kind() == MoveType::DoublePawnPush, two_squares, "{} in {}", mv, fen);
+            }
+        }
+        let board = Board::new(START_FEN.to_string()).unwrap();
+        assert!(parse_uci_move(&board, "e2e4").unwrap().kind() == MoveType::DoublePawnPush);
+        assert!(parse_uci_move(&board, "e2e3").unwrap().kind() == MoveType::Quiet);
+    }
+
+    #[test]
+    fn transpositions_share_a_hash() {
+        let mut a = Board::new(START_FEN.to_string()).unwrap();
+        let mut b = Board::new(START_FEN.to_string()).unwrap();
+        play(&mut a, &["g1f3", "g8f6", "b1c3"]);
+        play(&mut b, &["b1c3", "g8f6", "g1f3"]);
+        assert_eq!(a.hash(), b.hash());
+        // same pieces, but the en passant square differs
+        play(&mut a, &["e7e5"]);
+        play(&mut b, &["e7e6", "a2a3", "e6e5"]);
+        assert_ne!(a.hash(), b.hash());
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // the king already stands on its target, and only the rook moves
+        let mut board = Board::new("4k3/8/8/8/8/8/8/6KR w H - 0 1".to_string()).unwrap();
+        let hash = board.hash();
+        let castle = parse_uci_move(&board, "g1h1").unwrap();
+        assert!(castle.is_castling());
+        assert_eq!(castle.to_uci(true), "g1h1");
+        board.make_move(castle);
+        assert!(board.piece_on(Square::G1) == Piece::King && board.piece_on(Square::F1) == Piece::Rook);
+        assert!(board.castling().is_empty());
+        assert_eq!(board.hash(), board.generate_hash_key());
+        board.undo_move();
+        assert!(board.piece_on(Square::H1) == Piece::Rook);
+        assert_eq!(board.hash(), hash);
+
+        // the rook on b1 shields c1, where the king would stay, from the rook on a1
+        let board = Board::new("4k3/8/8/8/8/8/8/rRK5 w B - 0 1".to_string()).unwrap();
+        assert!(!board.legal_moves.moves[..board.legal_moves.len].iter().any(|mv| mv.is_castling()));
+
+        // the king and rook may swap squares
+        let mut board = Board::new("4k3/8/8/8/8/8/8/5KR1 w G - 0 1".to_string()).unwrap();
+        play(&mut board, &["f1g1"]);
+        assert!(board.piece_on(Square::G1) == Piece::King && board.piece_on(Square::F1) == Piece::Rook);
+    }
+
+    #[test]
+    fn castling_fields() {
+        // X-FEN and Shredder-FEN give the same rights
+        let standard = Board::new(KIWIPETE.to_string()).unwrap();
+        let shredder = Board::new(KIWIPETE.replace("KQkq", "HAha")).unwrap();
+        assert_eq!(standard.hash(), shredder.hash());
+        assert_eq!(shredder.castling().to_fen(false), "KQkq");
+        assert_eq!(shredder.castling().to_fen(true), "HAha");
+        // with two rooks on one side, `K` means the outer one
+        let board = Board::new("4k3/8/8/8/8/8/8/R2K1RR1 w KA - 0 1".to_string()).unwrap();
+        assert_eq!(board.castling().to_fen(false), "GQ");
+        let board = Board::new("4k3/8/8/8/8/8/8/R2K1RR1 w FA - 0 1".to_string()).unwrap();
+        assert_eq!(board.castling().to_fen(false), "FQ");
+        assert!(Board::new("4k3/8/8/8/8/8/8/R3K3 w X - 0 1".to_string()).is_err());
+    }
+
+    #[test]
+    fn null_move() {
+        let mut board = Board::new(START_FEN.to_string()).unwrap();
+        play(&mut board, &["e2e4"]);
+        let hash = board.hash();
+        board.make_null_move();
+        assert!(board.side_to_move == crate::types::Color::White);
+        assert_eq!(board.hash(), board.generate_hash_key());
+        assert_ne!(board.hash(), hash);
+        // white can move again, and may not capture en passant
+        assert!(parse_uci_move(&board, "d2d4").is_some());
+        board.undo_null_move();
+        assert_eq!(board.hash(), hash);
+        assert!(board.side_to_move == crate::types::Color::Black);
+    }
+
+    #[test]
+    fn null_move_ends_the_repetition_window() {
+        let mut board = Board::new(START_FEN.to_string()).unwrap();
+        play(&mut board, &["g1f3", "g8f6", "f3g1"]);
+        let hash = board.hash();
+        board.make_null_move();
+        play(&mut board, &["g1f3"]);
+        board.make_null_move();
+        play(&mut board, &["f3g1"]);
+        // the same position, but only by way of the null moves
+        assert_eq!(board.hash(), hash);
+        assert!(!board.is_repetition());
+        play(&mut board, &["f6g8", "g1f3", "g8f6", "f3g1"]);
+        assert!(board.is_repetition());
+    }
+
+    #[test]
+    fn repetition() {
+        let mut board = Board::new(START_FEN.to_string()).unwrap();
+        play(&mut board, &["g1f3", "g8f6", "f3g1"]);
+        assert!(!board.is_repetition());
+        play(&mut board, &["f6g8"]);
+        assert!(board.is_repetition());
+        // the game goes on until the position comes up a third time
+        assert!(!board.is_threefold_repetition());
+        assert!(!board.is_draw());
+        play(&mut board, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert!(board.is_threefold_repetition());
+        assert!(board.is_draw());
+        // a pawn move makes the earlier positions unreachable
+        play(&mut board, &["e2e4", "g8f6", "g1f3", "f6g8", "f3g1"]);
+        assert!(!board.is_repetition());
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let drawn = ["8/8/4k3/8/8/3K4/8/8 w - - 0 1", "8/8/4k3/8/8/3KN3/8/8 w - - 0 1", "8/2b5/4k3/8/8/3KB3/8/8 w - - 0 1"];
+        for fen in drawn {
+            assert!(Board::new(fen.to_string()).unwrap().is_insufficient_material(), "{}", fen);
+        }
+        // bishops on opposite colours, two knights and a pawn can all still mate
+        let playable = ["8/3b4/4k3/8/8/3KB3/8/8 w - - 0 1", "8/8/4k3/8/8/3KNN2/8/8 w - - 0 1", "8/8/4k3/8/8/3K4/4P3/8 w - - 0 1"];
+        for fen in playable {
+            assert!(!Board::new(fen.to_string()).unwrap().is_insufficient_material(), "{}", fen);
+        }
+    }
 
-/// Returns (rook_start, rook_end) square for a given castle move
-const fn get_rook_move_for_king_target(king_target: Square) -> (Square, Square) {
-    match king_target {
-        Square::G1 => (Square::H1, Square::F1),
-        Square::C1 => (Square::A1, Square::D1),
-        Square::G8 => (Square::H8, Square::F8),
-        Square::C8 => (Square::A8, Square::D8),
-        _ => panic!("Not a valid king target for castling.")
+    #[test]
+    fn fifty_move_rule() {
+        let board = Board::new("8/8/4k3/8/8/3K4/4R3/8 w - - 100 80".to_string()).unwrap();
+        assert!(board.is_fifty_move_draw());
+        assert!(board.is_draw());
     }
 }
@@ -90,7 +90,7 @@ pub fn generate_king_moves(square: Square) -> Bitboard {
 pub fn sliding_attack(square: &Square, occupancy: &Bitboard, offset: i8) -> Bitboard {
     let oc = *occupancy;
     let mut dir_bitboard = Bitboard::default();
-    let mut current_square = square.clone();
+    let mut current_square = *square;
     while safe_destination(&current_square, offset) {
         current_square = current_square.shift(offset);
         let cur_bb = Bitboard::from(current_square);
@@ -103,9 +103,12 @@ pub fn sliding_attack(square: &Square, occupancy: &Bitboard, offset: i8) -> Bitb
 }
 
 pub fn safe_destination(square: &Square, step: i8) -> bool {
-    let to: Square = square.shift(step);
-    let is_valid_square: bool = to >= Square::A1 && to <= Square::H8;
-    is_valid_square && distance(square, &to) <= 2
+    let to = *square as i8 + step;
+    // check the range before building a square, since out-of-range values are not valid squares
+    if !(0..Square::NUM as i8).contains(&to) {
+        return false
+    }
+    distance(square, &Square::new(to as u8)) <= 2
 }
 
 /// Returns the rook moves, given a square and occupancy
@@ -158,19 +161,15 @@ pub fn generate_queen_attacks(square: &Square, occupancy: &Bitboard) -> Bitboard
 }
 
 fn white_pawn_captures(square: &Square) -> Bitboard {
-    let sq = square.clone();
-    let bb: u64 = 1 << sq as u8;
-    let answer: u64 = 0
-        | (bb & !A_FILE) << 7
+    let bb: u64 = 1 << *square as u8;
+    let answer: u64 = (bb & !A_FILE) << 7
         | (bb & !H_FILE) << 9;
     Bitboard(answer)
 }
 
 fn black_pawn_captures(square: &Square) -> Bitboard {
-    let sq = square.clone();
-    let bb: u64 = 1 << sq as u8;
-    let answer: u64 = 0
-        | (bb & !A_FILE) >> 9
+    let bb: u64 = 1 << *square as u8;
+    let answer: u64 = (bb & !A_FILE) >> 9
         | (bb & !H_FILE) >> 7;
     Bitboard(answer)
 }
@@ -178,8 +177,8 @@ fn black_pawn_captures(square: &Square) -> Bitboard {
 /// Takes an attack generator function and returns a map from squares to attack bitboards
 pub fn create_map<T: Fn(Square) -> Bitboard>(generator: T) -> [Bitboard; Square::NUM] {
     let mut map: [Bitboard; Square::NUM] = [Bitboard(0); Square::NUM];
-    for i in 0..Square::NUM {
-        map[i as usize] = generator(Square::new(i as u8));
+    for (i, bb) in map.iter_mut().enumerate() {
+        *bb = generator(Square::new(i as u8));
     }
     map
 }
@@ -209,7 +208,7 @@ pub fn white_pawn_advances(square: &Square, occupancy: &Bitboard) -> Bitboard {
             }
         }
     }
-    return bb
+    bb
 }
 
 pub fn black_pawn_advances(square: &Square, occupancy: &Bitboard) -> Bitboard {
@@ -238,7 +237,7 @@ pub fn black_pawn_advances(square: &Square, occupancy: &Bitboard) -> Bitboard {
             bb.set(advance_square);
         }
     }
-    return bb
+    bb
 }
 
 impl StandardBitboards {
@@ -248,8 +247,8 @@ impl StandardBitboards {
                 create_map(|square| white_pawn_captures(&square)),
                 create_map(|square| black_pawn_captures(&square))
             ],
-            knight_attacks: create_map(|square| generate_knight_moves(square)),
-            king_attacks: create_map(|square| generate_king_moves(square)),
+            knight_attacks: create_map(generate_knight_moves),
+            king_attacks: create_map(generate_king_moves),
             between: generate_betweeners(),
         }
     }
@@ -262,8 +261,8 @@ impl Default for StandardBitboards {
                 create_map(|square| white_pawn_captures(&square)),
                 create_map(|square| black_pawn_captures(&square))
             ],
-            knight_attacks: create_map(|square| generate_knight_moves(square)),
-            king_attacks: create_map(|square| generate_king_moves(square)),
+            knight_attacks: create_map(generate_knight_moves),
+            king_attacks: create_map(generate_king_moves),
             between: generate_betweeners(),
         }
     }
@@ -284,6 +283,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn safe_destination_stays_on_the_board() {
+        assert!(safe_destination(&Square::E4, 8));
+        assert!(safe_destination(&Square::G7, 9));
+        // off the top or bottom of the board
+        assert!(!safe_destination(&Square::E8, 8));
+        assert!(!safe_destination(&Square::H8, 9));
+        assert!(!safe_destination(&Square::A1, -9));
+        assert!(!safe_destination(&Square::B2, -16));
+        // wrapping around to the other side
+        assert!(!safe_destination(&Square::H1, 1));
+        assert!(!safe_destination(&Square::A4, 7));
+    }
+
     #[test]
     fn rook() {
         let tweeners = generate_betweeners();
@@ -291,4 +304,15 @@ mod tests {
         hopefully_b1.pretty_print();
         assert_eq!(hopefully_b1, Bitboard::from(Square::F8) | Bitboard::from(Square::G8));
     }
+
+    #[test]
+    fn pin_state() {
+        // the bishop on a5 pins the knight on d2 to the king on e1
+        let board = crate::board::Board::new("4k3/8/8/b7/8/8/3N4/4K3 w - - 0 1".to_string()).unwrap();
+        let pins = board.calculate_pin_state();
+        // the knight cannot leave the squares between the bishop and the king, the bishop's
+        // included
+        assert_eq!(pins[Square::D2].count(), 4);
+        assert_eq!(pins[Square::E4].count(), 64);
+    }
 }
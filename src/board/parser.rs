@@ -1,7 +1,7 @@
 use core::fmt;
 use std::str::FromStr;
-use super::Board;
-use crate::types::{Castling, Color, Square};
+use super::{Board, Variant, POCKET_PIECES};
+use crate::types::{Bitboard, Castling, Color, Piece, Rank, Square};
 
 #[derive(Debug)]
 pub enum FenParseErr {
@@ -10,6 +10,9 @@ pub enum FenParseErr {
     InvalidPieceType,
     InvalidColor,
     InvalidEnPassant,
+    InvalidCastling,
+    InvalidChecks,
+    InvalidPocket,
 }
 
 impl fmt::Display for FenParseErr {
@@ -19,6 +22,9 @@ impl fmt::Display for FenParseErr {
             FenParseErr::InvalidColor => "Invalid Color",
             FenParseErr::InvalidPieceType => "Invalid Piece Type",
             FenParseErr::InvalidEnPassant => "Invalid En Passant",
+            FenParseErr::InvalidCastling => "Invalid Castling",
+            FenParseErr::InvalidChecks => "Invalid Checks",
+            FenParseErr::InvalidPocket => "Invalid Pocket",
         };
         write!(f, "{}", disp)
     }
@@ -31,8 +37,24 @@ impl FromStr for Board {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut board = Self::default();
         let mut elements = s.split_whitespace();
-        let rows = elements.next().ok_or(FenParseErr::MissingData)?.split('/');
+        let placement = elements.next().ok_or(FenParseErr::MissingData)?;
+        // Crazyhouse pockets follow the placement, either in brackets (`...R[Qb]`) or as a
+        // ninth rank (`.../R/Qb`)
+        let (placement, pocket) = match placement.split_once('[') {
+            Some((placement, pocket)) => (placement, pocket.strip_suffix(']').ok_or(FenParseErr::InvalidPocket)?),
+            None if placement.matches('/').count() == 8 => placement.rsplit_once('/').unwrap(),
+            None => (placement, ""),
+        };
+        for symbol in pocket.chars() {
+            let piece: Piece = symbol.try_into().map_err(|()| FenParseErr::InvalidPocket)?;
+            let color = if symbol.is_uppercase() { Color::White } else { Color::Black };
+            if piece == Piece::King || board.state.pockets[color][piece] >= 16 {
+                return Err(FenParseErr::InvalidPocket)
+            }
+            board.state.pockets[color][piece] += 1;
+        }
 
+        let rows = placement.split('/');
         for (rank, row) in rows.rev().enumerate() {
             let mut file = 0;
             for symbol in row.chars() {
@@ -40,6 +62,13 @@ impl FromStr for Board {
                     file += skip as u8;
                     continue;
                 }
+                if symbol == '~' {
+                    // the piece before was promoted
+                    if file > 0 {
+                        board.state.promoted.set(Square::from_rank_file(rank as u8, file - 1));
+                    }
+                    continue;
+                }
                 let piece = symbol.try_into().map_err(|()| FenParseErr::InvalidPieceType)?;
                 let color = if symbol.is_uppercase() { Color::White } else { Color::Black };
                 let square = Square::from_rank_file(rank as u8, file);
@@ -55,10 +84,21 @@ impl FromStr for Board {
             _ => return Err(FenParseErr::InvalidColor),
         };
 
-        board.state.castling = Castling::from(elements.next().unwrap());
+        board.state.castling = board.parse_castling(elements.next().ok_or(FenParseErr::MissingData)?)?;
         board.state.en_passant = elements.next().unwrap().try_into().map_err(|()| FenParseErr::InvalidEnPassant)?;
-        board.state.halfmove_clock = elements.next().unwrap().parse::<u8>().unwrap();
-        board.state.fullmove_number = elements.next().unwrap().parse::<u16>().unwrap();
+        // EPD records stop after the en passant square, so the clocks may be missing. Three-check
+        // positions end with the checks given by each side, `+N+M`.
+        let (checks, mut clocks): (Vec<&str>, Vec<&str>) = elements.partition(|field| field.starts_with('+'));
+        clocks.resize(2, "");
+        board.state.halfmove_clock = clocks[0].parse().unwrap_or(0);
+        board.state.fullmove_number = clocks[1].parse().unwrap_or(1);
+        if let Some(checks) = checks.first() {
+            let mut counts = checks.split('+').skip(1).map(|count| count.parse::<u8>());
+            match (counts.next(), counts.next()) {
+                (Some(Ok(white)), Some(Ok(black))) if white <= 3 && black <= 3 => board.state.checks_given = [white, black],
+                _ => return Err(FenParseErr::InvalidChecks),
+            }
+        }
 
         // hash keys
         board.state.hash_key = board.generate_hash_key();
@@ -70,3 +110,109 @@ impl FromStr for Board {
         Ok(board)
     }
 }
+
+impl Board {
+    /// The position as a FEN. Castling rights with a rook in the corner are written as `KQkq`,
+    /// and the others by the file of their rook, which any Chess960-aware reader understands.
+    /// Three-check positions end with the checks given, `+N+M`, and Crazyhouse positions have
+    /// the pockets after the placement.
+    pub fn fen(&self) -> String {
+        let fen = format!("{} {} {}", self.epd(), self.state.halfmove_clock, self.state.fullmove_number);
+        match self.variant {
+            Variant::ThreeCheck => format!("{} +{}+{}", fen, self.state.checks_given[0], self.state.checks_given[1]),
+            _ => fen,
+        }
+    }
+
+    /// The position as the first four fields of an EPD record, which are a FEN without the
+    /// halfmove clock and fullmove number
+    pub fn epd(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let square = Square::from_rank_file(rank, file);
+                let piece = self.piece_on(square);
+                if piece == Piece::None {
+                    empty += 1;
+                    continue
+                }
+                if empty > 0 {
+                    placement.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                let symbol = match piece {
+                    Piece::Pawn => 'p',
+                    Piece::Knight => 'n',
+                    Piece::Bishop => 'b',
+                    Piece::Rook => 'r',
+                    Piece::Queen => 'q',
+                    _ => 'k',
+                };
+                let black = (self.colors[Color::White] & Bitboard::from(square)).is_empty();
+                placement.push(if black { symbol } else { symbol.to_ascii_uppercase() });
+                if self.is_promoted(square) {
+                    placement.push('~');
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+        if self.variant == Variant::Crazyhouse {
+            placement.push('[');
+            for color in [Color::White, Color::Black] {
+                for piece in POCKET_PIECES {
+                    let symbol = match piece {
+                        Piece::Queen => 'q',
+                        Piece::Rook => 'r',
+                        Piece::Bishop => 'b',
+                        Piece::Knight => 'n',
+                        _ => 'p',
+                    };
+                    let symbol = if color == Color::White { symbol.to_ascii_uppercase() } else { symbol };
+                    (0..self.pocket(color, piece)).for_each(|_| placement.push(symbol));
+                }
+            }
+            placement.push(']');
+        }
+        let side = if self.side_to_move == Color::White { "w" } else { "b" };
+        let en_passant = if self.state.en_passant == Square::None {
+            "-".to_string()
+        } else {
+            self.state.en_passant.to_string()
+        };
+        format!("{} {} {} {}", placement, side, self.state.castling.to_fen(false), en_passant)
+    }
+
+    /// Reads the castling field of a FEN. Besides `KQkq`, which castle with the outermost rook on
+    /// each side (X-FEN), a right may name the file of its rook (Shredder-FEN, `HAha`), as
+    /// Chess960 positions need when a side has two rooks on one side of the king. Rights whose
+    /// king or rook is not on the back rank are dropped.
+    fn parse_castling(&self, field: &str) -> Result<Castling, FenParseErr> {
+        let mut castling = Castling::default();
+        for symbol in field.chars().filter(|&symbol| symbol != '-') {
+            let color = if symbol.is_ascii_uppercase() { Color::White } else { Color::Black };
+            let back_rank = Bitboard::rank(if color == Color::White { Rank::R1 } else { Rank::R8 });
+            let king = self.pieces_of(Piece::King, color) & back_rank;
+            if king.is_empty() {
+                continue
+            }
+            let king_file = king.lsb() as u8 & 7;
+            let mut rook_files = (self.pieces_of(Piece::Rook, color) & back_rank).map(|square| square as u8 & 7);
+            let rook_file = match symbol.to_ascii_uppercase() {
+                'K' => rook_files.filter(|&file| file > king_file).max(),
+                'Q' => rook_files.filter(|&file| file < king_file).min(),
+                file @ 'A'..='H' => rook_files.find(|&rook_file| rook_file == file as u8 - b'A'),
+                _ => return Err(FenParseErr::InvalidCastling),
+            };
+            if let Some(rook_file) = rook_file {
+                castling.add(color, king_file, rook_file);
+            }
+        }
+        Ok(castling)
+    }
+}
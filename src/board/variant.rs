@@ -0,0 +1,327 @@
+//! Chess variants. The board plays standard chess unless it is given a variant, which changes how
+//! the game can be won, which moves are legal and what the FEN holds.
+
+use std::fmt;
+
+use super::movegen::{generate_bishop_attacks, generate_rook_attacks};
+use super::Board;
+use crate::types::{Bitboard, Color, Move, MoveList, Piece, Rank, Square};
+
+/// The rules a board plays by
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// Bringing your king to one of the four centre squares wins
+    KingOfTheHill,
+    /// Giving check for the third time wins. The checks given by each side are part of the
+    /// position, written at the end of the FEN as `+N+M` (White's first).
+    ThreeCheck,
+    /// Both sides start on the first two ranks, without pawns, and the first king to reach the
+    /// eighth rank wins. Moves which give check are illegal. If White gets there first, Black has
+    /// one more move to draw by getting there too.
+    RacingKings,
+    /// Losing chess: captures are compulsory, the king is an ordinary piece, and losing all your
+    /// pieces, or having no move, wins
+    Antichess,
+    /// Captured pieces go into the capturer's pocket, from which they can be dropped back on the
+    /// board as a move. The pockets are part of the position, written in the FEN after the
+    /// placement as `[QRbn]`, and promoted pieces are marked with a `~`.
+    Crazyhouse,
+    /// Captures explode, taking every piece but the pawns around the capture square off the board
+    /// along with the capturing piece. Blowing up the other king wins.
+    Atomic,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 7] = [
+        Variant::Standard,
+        Variant::KingOfTheHill,
+        Variant::ThreeCheck,
+        Variant::RacingKings,
+        Variant::Antichess,
+        Variant::Crazyhouse,
+        Variant::Atomic,
+    ];
+
+    /// The name of the variant in `UCI_Variant`
+    pub const fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::ThreeCheck => "3check",
+            Variant::RacingKings => "racingkings",
+            Variant::Antichess => "antichess",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Atomic => "atomic",
+        }
+    }
+
+    pub const fn start_fen(self) -> &'static str {
+        match self {
+            Variant::RacingKings => "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+            Variant::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0",
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Variant::Crazyhouse => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            _ => super::START_FEN,
+        }
+    }
+}
+
+impl TryFrom<&str> for Variant {
+    type Error = ();
+
+    /// Reads the `UCI_Variant` names, and a few other common spellings
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name.to_ascii_lowercase().replace(['-', ' ', '_'], "").as_str() {
+            "chess" | "standard" | "normal" => Ok(Variant::Standard),
+            "kingofthehill" | "koth" => Ok(Variant::KingOfTheHill),
+            "3check" | "threecheck" => Ok(Variant::ThreeCheck),
+            "racingkings" => Ok(Variant::RacingKings),
+            "antichess" | "losers" | "giveaway" => Ok(Variant::Antichess),
+            "crazyhouse" | "zh" => Ok(Variant::Crazyhouse),
+            "atomic" => Ok(Variant::Atomic),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a game ended
+#[derive(Clone, Copy, PartialEq)]
+pub enum GameResult {
+    Win(Color),
+    Draw,
+}
+
+impl fmt::Display for GameResult {
+    /// The result as PGN writes it: `1-0`, `0-1` or `1/2-1/2`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            GameResult::Win(Color::White) => "1-0",
+            GameResult::Win(Color::Black) => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The squares a king wins on in King of the Hill
+const HILL: [Square; 4] = [Square::D4, Square::E4, Square::D5, Square::E5];
+
+/// Checks which win a game of Three-check
+const WINNING_CHECKS: u8 = 3;
+
+impl Board {
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// The result of the game if it is over by a rule of the variant, rather than by checkmate,
+    /// stalemate or a draw rule of standard chess. The side to move never has legal moves then.
+    pub fn variant_outcome(&self) -> Option<GameResult> {
+        match self.variant {
+            Variant::Standard | Variant::Crazyhouse => None,
+            Variant::KingOfTheHill => [Color::White, Color::Black]
+                .into_iter()
+                .find(|&color| HILL.iter().any(|&square| !(self.pieces_of(Piece::King, color) & Bitboard::from(square)).is_empty()))
+                .map(GameResult::Win),
+            Variant::ThreeCheck => [Color::White, Color::Black]
+                .into_iter()
+                .find(|&color| self.state.checks_given[color] >= WINNING_CHECKS)
+                .map(GameResult::Win),
+            Variant::RacingKings => {
+                let goal = Bitboard::rank(Rank::R8);
+                let white = !(self.pieces_of(Piece::King, Color::White) & goal).is_empty();
+                let black = !(self.pieces_of(Piece::King, Color::Black) & goal).is_empty();
+                match (white, black) {
+                    (true, true) => Some(GameResult::Draw),
+                    (false, true) => Some(GameResult::Win(Color::Black)),
+                    // Black may still catch up, unless it has no move which does
+                    (true, false) if self.side_to_move == Color::Black && self.legal_moves.len > 0 => None,
+                    (true, false) => Some(GameResult::Win(Color::White)),
+                    (false, false) => None,
+                }
+            }
+            Variant::Antichess => (self.legal_moves.len == 0).then_some(GameResult::Win(self.side_to_move)),
+            Variant::Atomic => [Color::White, Color::Black]
+                .into_iter()
+                .find(|&color| self.pieces_of(Piece::King, color).is_empty())
+                .map(|color| GameResult::Win(!color)),
+        }
+    }
+
+    /// The result of the game, if it is over by any rule
+    pub fn outcome(&self) -> Option<GameResult> {
+        if let Some(outcome) = self.variant_outcome() {
+            return Some(outcome)
+        }
+        if self.is_checkmate() {
+            return Some(GameResult::Win(!self.side_to_move))
+        }
+        self.is_draw().then_some(GameResult::Draw)
+    }
+
+    /// Whether the game is over by a rule of the variant, before any move is generated. In
+    /// Racing Kings, Black's last chance to catch up depends on its moves, which
+    /// `restrict_variant_moves` looks at.
+    pub(super) fn variant_game_over(&self) -> bool {
+        match self.variant {
+            // Antichess games end when the side to move has no move at all
+            Variant::Standard | Variant::Antichess | Variant::Crazyhouse => false,
+            Variant::RacingKings => {
+                let goal = Bitboard::rank(Rank::R8);
+                let black = !(self.pieces_of(Piece::King, Color::Black) & goal).is_empty();
+                let white = !(self.pieces_of(Piece::King, Color::White) & goal).is_empty();
+                black || (white && self.side_to_move == Color::White)
+            }
+            _ => self.variant_outcome().is_some(),
+        }
+    }
+
+    /// Takes out the moves which are legal in standard chess but not in the variant
+    pub(super) fn restrict_variant_moves(&self, list: &mut MoveList) {
+        if self.variant != Variant::RacingKings {
+            return
+        }
+        list.retain(|mv| !self.gives_check(mv));
+        let white_arrived = !(self.pieces_of(Piece::King, Color::White) & Bitboard::rank(Rank::R8)).is_empty();
+        if white_arrived {
+            // Black is to move, and the game goes on only if its king can reach the eighth rank too
+            let king = self.our(Piece::King);
+            let catches_up = list.moves[..list.len]
+                .iter()
+                .any(|mv| !(king & Bitboard::from(mv.start())).is_empty() && mv.target().rank() == Rank::R8);
+            if !catches_up {
+                list.len = 0;
+            }
+        }
+    }
+
+    /// Whether the move puts their king in check, looking at where our pieces stand after it
+    pub fn gives_check(&self, mv: Move) -> bool {
+        let their_king = self.their(Piece::King);
+        if their_king.is_empty() {
+            return false
+        }
+        let king_square = their_king.lsb();
+        let us = self.side_to_move;
+        let mut ours = [Bitboard::default(); Piece::NUM];
+        for (p, bitboard) in ours.iter_mut().enumerate() {
+            *bitboard = self.pieces[p] & self.colors[us];
+        }
+        let mut theirs = self.colors[!us];
+        let (start, target) = (mv.start(), mv.target());
+        if mv.is_castling() {
+            let (king_target, rook_target) = mv.castling_targets();
+            ours[Piece::King].clear(start);
+            ours[Piece::Rook].clear(target);
+            ours[Piece::King].set(king_target);
+            ours[Piece::Rook].set(rook_target);
+        } else if mv.is_drop() {
+            ours[mv.drop_piece()].set(target);
+        } else {
+            let piece = self.mailbox[start];
+            let placed = if mv.is_promotion() { mv.promo_piece() } else { piece };
+            ours[piece].clear(start);
+            ours[placed].set(target);
+            let captured = if mv.is_en_passant() {
+                Square::from_rank_file(start.rank() as u8, target.file() as u8)
+            } else {
+                target
+            };
+            theirs &= !Bitboard::from(captured);
+        }
+        let occupancy = ours.iter().fold(theirs, |occupancy, &bitboard| occupancy | bitboard);
+        let diagonal = ours[Piece::Bishop] | ours[Piece::Queen];
+        let straight = ours[Piece::Rook] | ours[Piece::Queen];
+        let bitboards = &self.standard_bitboards;
+        !(bitboards.knight_attacks[king_square] & ours[Piece::Knight]).is_empty()
+            || !(bitboards.pawn_captures[!us][king_square] & ours[Piece::Pawn]).is_empty()
+            || !(bitboards.king_attacks[king_square] & ours[Piece::King]).is_empty()
+            || !(generate_bishop_attacks(&king_square, &occupancy) & diagonal).is_empty()
+            || !(generate_rook_attacks(&king_square, &occupancy) & straight).is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant_board(fen: &str, variant: Variant) -> Board {
+        Board::new_variant(fen.to_string(), variant).unwrap()
+    }
+
+    #[test]
+    fn names() {
+        for variant in Variant::ALL {
+            assert_eq!(Variant::try_from(variant.name()), Ok(variant));
+            let board = variant_board(variant.start_fen(), variant);
+            assert!(board.legal_moves.len > 0 && board.variant_outcome().is_none());
+        }
+        assert_eq!(Variant::try_from("King of the Hill"), Ok(Variant::KingOfTheHill));
+        assert!(Variant::try_from("shogi").is_err());
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        let mut board = variant_board("4k3/8/8/8/8/3K4/8/8 w - - 0 1", Variant::KingOfTheHill);
+        let mv = board.legal_moves.moves[..board.legal_moves.len].iter().copied().find(|mv| mv.to_string() == "d3e4").unwrap();
+        board.make_move(mv);
+        assert!(board.variant_outcome() == Some(GameResult::Win(Color::White)));
+        assert_eq!(board.legal_moves.len, 0);
+        assert!(!board.is_checkmate() && !board.is_stalemate() && !board.is_draw());
+        board.undo_move();
+        assert!(board.variant_outcome().is_none());
+        // bare kings are not a draw, since either can still walk to the centre
+        assert!(!board.is_insufficient_material());
+    }
+
+    #[test]
+    fn three_check() {
+        let mut board = variant_board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2+0", Variant::ThreeCheck);
+        assert_eq!(board.state.checks_given, [2, 0]);
+        let hash = board.hash();
+        let mv = board.legal_moves.moves[..board.legal_moves.len].iter().copied().find(|mv| mv.to_string() == "a1a8").unwrap();
+        board.make_move(mv);
+        assert_eq!(board.state.checks_given, [3, 0]);
+        assert!(board.variant_outcome() == Some(GameResult::Win(Color::White)));
+        assert_eq!(board.legal_moves.len, 0);
+        assert!(!board.is_checkmate());
+        assert_eq!(board.fen(), "R3k3/8/8/8/8/8/8/4K3 b - - 1 1 +3+0");
+        board.undo_move();
+        assert_eq!(board.state.checks_given, [2, 0]);
+        assert_eq!(board.hash(), hash);
+        // the same position with other counters is another position
+        let other = Board::new_variant("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +1+0".to_string(), Variant::ThreeCheck).unwrap();
+        assert_ne!(other.hash(), hash);
+    }
+
+    #[test]
+    fn racing_kings() {
+        // no move may give check: the rook cannot go to the seventh rank or the f file
+        let board = variant_board("8/5k2/8/8/8/8/8/R6K w - - 0 1", Variant::RacingKings);
+        assert!(board.legal_moves.moves[..board.legal_moves.len].iter().all(|mv| !board.gives_check(*mv)));
+        assert!(!board.legal_moves.moves[..board.legal_moves.len].iter().any(|mv| mv.to_string() == "a1a7" || mv.to_string() == "a1f1"));
+
+        // White got there first, but Black can still draw by getting there too
+        let mut board = variant_board("6K1/1k6/8/8/8/8/8/8 b - - 0 1", Variant::RacingKings);
+        assert!(board.variant_outcome().is_none());
+        let mv = board.legal_moves.moves[..board.legal_moves.len].iter().copied().find(|mv| mv.to_string() == "b7b8").unwrap();
+        board.make_move(mv);
+        assert!(board.variant_outcome() == Some(GameResult::Draw));
+        assert_eq!(board.legal_moves.len, 0);
+
+        // too far behind
+        let board = variant_board("6K1/8/1k6/8/8/8/8/8 b - - 0 1", Variant::RacingKings);
+        assert!(board.variant_outcome() == Some(GameResult::Win(Color::White)));
+        assert_eq!(board.legal_moves.len, 0);
+        let board = variant_board("1k6/8/8/6K1/8/8/8/8 w - - 0 1", Variant::RacingKings);
+        assert!(board.variant_outcome() == Some(GameResult::Win(Color::Black)));
+    }
+}
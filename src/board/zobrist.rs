@@ -10,11 +10,15 @@ pub struct Zobrist {
     pub castling: [u64; 16],
     /// probably include if black and not if white?
     pub side_to_move: u64,
+    /// Checks given by each color so far in Three-check (zero to three)
+    pub checks: [[u64; 4]; Color::NUM],
+    /// The n-th piece of each kind in each color's pocket in Crazyhouse (kings never get there)
+    pub pockets: [[[u64; 16]; Piece::NUM - 1]; Color::NUM],
 }
 
 // init the zobrist numbers here
-pub const ZOBRIST: Zobrist = {
-    let mut zobrist = [0; 849];
+pub static ZOBRIST: Zobrist = {
+    let mut zobrist = [0; 1017];
     let len = zobrist.len();
     let mut i = 0;
     // This initial number was randomly generated by the rand package
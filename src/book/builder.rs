@@ -0,0 +1,109 @@
+//! Builds a Polyglot book from the games of PGN files.
+
+use std::collections::HashMap;
+
+use crate::pgn::PgnGame;
+
+use super::{polyglot_key, polyglot_move, Book, BookEntry};
+
+/// Plies of each game used when no limit is given
+pub const DEFAULT_MAX_PLY: usize = 20;
+/// Games a move must be played in when no minimum is given
+pub const DEFAULT_MIN_GAMES: u32 = 1;
+
+pub struct BookBuilder {
+    /// Only the first this many plies of each game go into the book
+    max_ply: usize,
+    /// A move is only kept if it was played in at least this many games
+    min_games: u32,
+    /// How many games played each move, by position key and move
+    counts: HashMap<(u64, u16), u32>,
+    games: usize,
+}
+
+impl BookBuilder {
+    pub fn new(max_ply: usize, min_games: u32) -> Self {
+        Self {
+            max_ply,
+            min_games: min_games.max(1),
+            counts: HashMap::new(),
+            games: 0,
+        }
+    }
+
+    /// Adds the moves of a game, up to the ply limit or the first illegal move. Returns false if
+    /// the game could not be read at all.
+    pub fn add_game(&mut self, game: &PgnGame) -> bool {
+        let Some((mut board, moves)) = game.replay() else { return false };
+        // a position can repeat within a game, but the game only counts once
+        let mut seen = vec![];
+        for mv in moves.into_iter().take(self.max_ply) {
+            let entry = (polyglot_key(&board), polyglot_move(mv));
+            if !seen.contains(&entry) {
+                seen.push(entry);
+                *self.counts.entry(entry).or_default() += 1;
+            }
+            board.make_move(mv);
+        }
+        self.games += 1;
+        true
+    }
+
+    /// The number of games added so far
+    pub fn games(&self) -> usize {
+        self.games
+    }
+
+    /// The book of every move played in enough games, weighted by how often it was played. The
+    /// weights of a position are scaled down together if they do not fit in 16 bits.
+    pub fn build(&self) -> Book {
+        let mut positions: HashMap<u64, Vec<(u16, u32)>> = HashMap::new();
+        for (&(key, mv), &count) in &self.counts {
+            if count >= self.min_games {
+                positions.entry(key).or_default().push((mv, count));
+            }
+        }
+        let mut entries = Vec::with_capacity(self.counts.len());
+        for (key, mut moves) in positions {
+            // the most played move first
+            moves.sort_by_key(|&(mv, count)| (u32::MAX - count, mv));
+            let most = moves[0].1 as u64;
+            for (mv, count) in moves {
+                let weight = if most > u16::MAX as u64 {
+                    (count as u64 * u16::MAX as u64 / most).max(1)
+                } else {
+                    count as u64
+                };
+                entries.push(BookEntry { key, mv, weight: weight as u16, learn: 0 });
+            }
+        }
+        // sorting by key keeps the order of the moves within a position
+        entries.sort_by_key(|entry| entry.key);
+        Book::from_entries(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, START_FEN};
+    use crate::pgn::parse_pgn;
+
+    #[test]
+    fn builds_from_games() {
+        let games = parse_pgn("1. e4 e5 2. Nf3 * 1. e4 c5 2. Nf3 * 1. d4 d5 * 1. e4 e5 2. Bc4 *");
+        let mut builder = BookBuilder::new(2, 2);
+        for game in &games {
+            assert!(builder.add_game(game));
+        }
+        assert_eq!(builder.games(), 4);
+        let book = builder.build();
+        let board = Board::new(START_FEN.to_string()).unwrap();
+        // d4 was played only once, and the third ply is past the limit
+        let moves: Vec<(String, u16)> = book.moves(&board).iter().map(|(mv, w)| (mv.to_string(), *w)).collect();
+        assert_eq!(moves, [("e2e4".to_string(), 3)]);
+        assert_eq!(book.len(), 2);
+        let book = Book::from_bytes(&book.to_bytes()).unwrap();
+        assert_eq!(book.pick(&board, 12345).unwrap().to_string(), "e2e4");
+    }
+}
@@ -0,0 +1,270 @@
+//! Opening books in the Polyglot `.bin` format.
+//!
+//! A book is a list of 16 byte entries sorted by position key, all numbers big-endian:
+//!
+//! | bytes  | content                                                      |
+//! |--------|--------------------------------------------------------------|
+//! | 0..8   | key of the position                                          |
+//! | 8..10  | move: to file (3 bits), to rank (3), from file (3), from rank (3), promotion (3) |
+//! | 10..12 | weight, how often the move should be played                  |
+//! | 12..16 | learn, unused                                                |
+//!
+//! The key is a Zobrist hash built from the fixed random numbers in [`keys`], so it differs from
+//! [`Board::hash`]. Castling is written as the king taking its own rook (`e1h1` for `e1g1`), and
+//! promotions count from 1 for a knight to 4 for a queen.
+
+use std::{fmt, fs, io, path::Path};
+
+use crate::board::Board;
+use crate::types::{BlackKingside, BlackQueenside, Color, Move, Piece, Square, WhiteKingside, WhiteQueenside};
+
+mod builder;
+mod keys;
+
+pub use builder::{BookBuilder, DEFAULT_MAX_PLY, DEFAULT_MIN_GAMES};
+
+#[derive(Debug)]
+pub enum BookLoadErr {
+    Io(io::Error),
+    /// The file is not made of whole entries
+    WrongLength(usize),
+}
+
+impl fmt::Display for BookLoadErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookLoadErr::Io(err) => write!(f, "Could not read book: {}", err),
+            BookLoadErr::WrongLength(len) => write!(f, "A book of {} bytes is not made of 16 byte entries", len),
+        }
+    }
+}
+
+impl From<io::Error> for BookLoadErr {
+    fn from(err: io::Error) -> Self {
+        BookLoadErr::Io(err)
+    }
+}
+
+/// The Polyglot key of a position
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for color in [Color::White, Color::Black] {
+        for p in 0..Piece::NUM {
+            let piece = Piece::new(p);
+            // black pawn, white pawn, black knight, ...
+            let kind = 2 * p + (color == Color::White) as usize;
+            for square in board.pieces_of(piece, color) {
+                key ^= keys::RANDOM64[keys::PIECES + 64 * kind + square as usize];
+            }
+        }
+    }
+    let castling = board.castling();
+    let rights = [
+        castling.is_allowed::<WhiteKingside>(),
+        castling.is_allowed::<WhiteQueenside>(),
+        castling.is_allowed::<BlackKingside>(),
+        castling.is_allowed::<BlackQueenside>(),
+    ];
+    for (i, _) in rights.iter().enumerate().filter(|(_, &allowed)| allowed) {
+        key ^= keys::RANDOM64[keys::CASTLING + i];
+    }
+    // the en passant file only counts if a pawn could actually take en passant
+    let en_passant = board.en_passant();
+    if en_passant != Square::None {
+        let file = en_passant as usize % 8;
+        // the rank of the pawn which just moved, where the capturing pawns must stand
+        let rank = if board.side_to_move == Color::White { 4 } else { 3 };
+        let can_capture = board
+            .pieces_of(Piece::Pawn, board.side_to_move)
+            .any(|square| square as usize / 8 == rank && (square as usize % 8).abs_diff(file) == 1);
+        if can_capture {
+            key ^= keys::RANDOM64[keys::EN_PASSANT + file];
+        }
+    }
+    if board.side_to_move == Color::White {
+        key ^= keys::RANDOM64[keys::TURN];
+    }
+    key
+}
+
+/// A move encoded the Polyglot way
+pub fn polyglot_move(mv: Move) -> u16 {
+    // castling moves are encoded as the king taking its rook already
+    let target = mv.target();
+    let promotion = match mv.promo_piece() {
+        Piece::Knight => 1,
+        Piece::Bishop => 2,
+        Piece::Rook => 3,
+        Piece::Queen => 4,
+        _ => 0,
+    };
+    target as u16 | (mv.start() as u16) << 6 | promotion << 12
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    pub const SIZE: usize = 16;
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Self {
+        // stable, so that the moves of a position keep their order
+        entries.sort_by_key(|entry| entry.key);
+        Self { entries }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookLoadErr> {
+        if !bytes.len().is_multiple_of(BookEntry::SIZE) {
+            return Err(BookLoadErr::WrongLength(bytes.len()))
+        }
+        Ok(Self::from_entries(bytes.chunks_exact(BookEntry::SIZE).map(BookEntry::from_bytes).collect()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, BookLoadErr> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(|entry| entry.to_bytes()).collect()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The legal book moves of the position and their weights, in the book's order. Entries whose
+    /// move is not legal (from a key collision or a broken book) are left out.
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let legal = board.legal_moves().as_slice();
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                let mv = legal.iter().find(|&&mv| polyglot_move(mv) == entry.mv)?;
+                Some((*mv, entry.weight))
+            })
+            .collect()
+    }
+
+    /// Picks a book move at random, each with a chance proportional to its weight. `random` is any
+    /// random number. Moves with no weight are never played.
+    pub fn pick(&self, board: &Board, random: u64) -> Option<Move> {
+        let moves = self.moves(board);
+        let total: u64 = moves.iter().map(|&(_, weight)| weight as u64).sum();
+        if total == 0 {
+            return None
+        }
+        let mut roll = random % total;
+        for (mv, weight) in moves {
+            if roll < weight as u64 {
+                return Some(mv)
+            }
+            roll -= weight as u64;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uci::parse_uci_move;
+
+    /// Plays the moves from the start and returns the Polyglot key
+    fn key_after(moves: &[&str]) -> u64 {
+        let mut board = Board::new(crate::board::START_FEN.to_string()).unwrap();
+        for mv in moves {
+            let mv = parse_uci_move(&board, mv).unwrap();
+            board.make_move(mv);
+        }
+        polyglot_key(&board)
+    }
+
+    #[test]
+    fn keys_match_the_specification() {
+        // the test positions given with the format
+        assert_eq!(key_after(&[]), 0x463b96181691fc9c);
+        assert_eq!(key_after(&["e2e4"]), 0x823c9b50fd114196);
+        assert_eq!(key_after(&["e2e4", "d7d5"]), 0x0756b94461c50fb0);
+        assert_eq!(key_after(&["e2e4", "d7d5", "e4e5"]), 0x662fafb965db29d4);
+        assert_eq!(key_after(&["e2e4", "d7d5", "e4e5", "f7f5"]), 0x22a48b5a8e47ff78);
+        assert_eq!(key_after(&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"]), 0x652a607ca3f242c1);
+        assert_eq!(key_after(&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"]), 0x00fdd303c946bdd9);
+        assert_eq!(key_after(&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"]), 0x3c8123ea7b067637);
+        assert_eq!(key_after(&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"]), 0x5c3f9b829b279560);
+    }
+
+    #[test]
+    fn castling_is_king_takes_rook() {
+        let board = Board::new("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".to_string()).unwrap();
+        let encode = |s| polyglot_move(parse_uci_move(&board, s).unwrap());
+        // e1h1: to h1 (7), from e1 (4)
+        assert_eq!(encode("e1g1"), 7 | 4 << 6);
+        assert_eq!(encode("e1c1"), 4 << 6);
+        let board = Board::new("8/1P2k3/8/8/8/8/8/4K3 w - - 0 1".to_string()).unwrap();
+        // b7b8q: to b8 (57), from b7 (49), queen
+        assert_eq!(polyglot_move(parse_uci_move(&board, "b7b8q").unwrap()), 57 | 49 << 6 | 4 << 12);
+    }
+
+    #[test]
+    fn probe_and_pick() {
+        let board = Board::new(crate::board::START_FEN.to_string()).unwrap();
+        let key = polyglot_key(&board);
+        let entry = |mv: &str, weight| BookEntry {
+            key,
+            mv: polyglot_move(parse_uci_move(&board, mv).unwrap()),
+            weight,
+            learn: 0,
+        };
+        let other = BookEntry { key: key ^ 1, mv: 0, weight: 1, learn: 0 };
+        let book = Book::from_entries(vec![other, entry("e2e4", 3), entry("d2d4", 1), entry("g1f3", 0)]);
+        let book = Book::from_bytes(&book.to_bytes()).unwrap();
+        assert_eq!(book.len(), 4);
+        let moves: Vec<String> = book.moves(&board).iter().map(|(mv, _)| mv.to_string()).collect();
+        assert_eq!(moves, ["e2e4", "d2d4", "g1f3"]);
+        // rolls 0..3 give e4, 3 gives d4, and the weightless move never comes up
+        let picks: Vec<String> = (0..8).map(|roll| book.pick(&board, roll).unwrap().to_string()).collect();
+        assert_eq!(picks, ["e2e4", "e2e4", "e2e4", "d2d4", "e2e4", "e2e4", "e2e4", "d2d4"]);
+        assert!(Book::from_bytes(&[0; 17]).is_err());
+    }
+}
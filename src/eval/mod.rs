@@ -0,0 +1,49 @@
+//! Static evaluation of positions.
+
+use crate::{board::{Board, Variant, POCKET_PIECES}, types::{Color, Piece}};
+
+pub(crate) mod nnue;
+mod simd;
+
+pub use self::nnue::{Network, NetworkLoadErr, NnueState, INPUTS};
+
+/// Centipawn values of each piece, indexed by [`Piece`]
+pub const PIECE_VALUES: [i32; Piece::NUM] = [100, 320, 330, 500, 900, 0];
+
+/// Evaluates the position in centipawns from the point of view of the side to move.
+/// If the board has a network attached, the network is used. Otherwise, we just count material.
+pub fn evaluate(board: &Board) -> i32 {
+    if board.variant() == Variant::Antichess {
+        // the networks know nothing of losing chess, where having fewer pieces is what counts
+        let count = |color| (0..Piece::NUM).map(|p| board.pieces_of(Piece::new(p), color).count() as i32).sum::<i32>();
+        return (count(!board.side_to_move) - count(board.side_to_move)) * PIECE_VALUES[Piece::Pawn]
+    }
+    match board.nnue_evaluate() {
+        // the networks only see the board, not what is in the pockets
+        Some(score) => score + pocket_material(board),
+        None => material(board),
+    }
+}
+
+/// Material balance from the point of view of the side to move
+pub fn material(board: &Board) -> i32 {
+    let mut score = 0;
+    for p in 0..Piece::NUM {
+        let piece = Piece::new(p);
+        let white = board.pieces_of(piece, Color::White).count() as i32;
+        let black = board.pieces_of(piece, Color::Black).count() as i32;
+        score += (white - black) * PIECE_VALUES[piece];
+    }
+    match board.side_to_move {
+        Color::White => score + pocket_material(board),
+        Color::Black => -score + pocket_material(board),
+    }
+}
+
+/// Balance of the pieces in the Crazyhouse pockets, from the point of view of the side to move
+fn pocket_material(board: &Board) -> i32 {
+    POCKET_PIECES
+        .into_iter()
+        .map(|piece| (board.pocket(board.side_to_move, piece) as i32 - board.pocket(!board.side_to_move, piece) as i32) * PIECE_VALUES[piece])
+        .sum()
+}
@@ -0,0 +1,519 @@
+//! Efficiently updatable neural network (NNUE) evaluation.
+//!
+//! The network is a simple perspective net: `768 -> N -> 1`, where each side gets its own
+//! accumulator of `N` neurons fed by the same feature transformer. The accumulators are updated
+//! incrementally as pieces are added to or removed from the board, and a copy is pushed for every
+//! ply so that `undo_move` can simply pop back to the previous one.
+//!
+//! # File format
+//!
+//! All values are little-endian.
+//!
+//! | Field            | Type                | Notes                                   |
+//! |------------------|---------------------|-----------------------------------------|
+//! | magic            | `[u8; 8]`           | `b"MSCNNUE\0"`                          |
+//! | version          | `u32`               | currently `1`                           |
+//! | hidden size `N`  | `u32`               | must be a non-zero multiple of 16       |
+//! | feature weights  | `i16 * 768 * N`     | feature-major: all `N` weights of feature 0, then feature 1, ... |
+//! | feature biases   | `i16 * N`           |                                         |
+//! | output weights   | `i16 * 2N`          | first `N` for the side to move, then `N` for the other side |
+//! | output bias      | `i32`               |                                         |
+//!
+//! A feature index is computed relative to a perspective:
+//! `index = side * 384 + piece * 64 + square`, where `side` is `0` for the perspective's own pieces
+//! and `1` for the opponent's, `piece` is the [`Piece`] discriminant and `square` is flipped
+//! vertically (`square ^ 56`) for black's perspective.
+//!
+//! Feature weights and biases are quantized by [`QA`], output weights by [`QB`], and the output
+//! bias by `QA * QB`. The hidden layer activation is a clipped ReLU to `[0, QA]`.
+
+use std::{fmt, fs, io, path::Path, sync::Arc};
+
+use crate::types::{Color, Piece, Square};
+
+use super::simd;
+
+/// Number of input features (2 sides * 6 pieces * 64 squares)
+pub const INPUTS: usize = 768;
+/// Quantization of the feature transformer
+pub const QA: i32 = 255;
+/// Quantization of the output layer
+pub const QB: i32 = 64;
+/// Scales the network output to centipawns
+pub const SCALE: i32 = 400;
+
+const MAGIC: &[u8; 8] = b"MSCNNUE\0";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 16;
+
+/// The net bundled into the binary when the `embedded-net` feature is enabled.
+#[cfg(feature = "embedded-net")]
+pub const EMBEDDED_NET: &[u8] = include_bytes!("../../nets/default.nnue");
+
+#[derive(Debug)]
+pub enum NetworkLoadErr {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// The hidden layer size is zero or not a multiple of 16
+    InvalidHiddenSize(u32),
+    /// The file does not have the number of bytes its header promises
+    WrongLength { expected: usize, found: usize },
+}
+
+impl fmt::Display for NetworkLoadErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkLoadErr::Io(err) => write!(f, "Could not read network: {}", err),
+            NetworkLoadErr::BadMagic => write!(f, "Not a network file"),
+            NetworkLoadErr::UnsupportedVersion(v) => write!(f, "Unsupported network version {}", v),
+            NetworkLoadErr::InvalidHiddenSize(n) => write!(f, "Invalid hidden layer size {}", n),
+            NetworkLoadErr::WrongLength { expected, found } => {
+                write!(f, "Expected {} bytes of network data, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for NetworkLoadErr {
+    fn from(err: io::Error) -> Self {
+        NetworkLoadErr::Io(err)
+    }
+}
+
+/// A quantized `768 -> N -> 1` perspective network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    /// Parses a network from the bytes of a network file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NetworkLoadErr> {
+        if bytes.len() < HEADER_LEN {
+            return Err(NetworkLoadErr::WrongLength { expected: HEADER_LEN, found: bytes.len() })
+        }
+        if &bytes[0..8] != MAGIC {
+            return Err(NetworkLoadErr::BadMagic)
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(NetworkLoadErr::UnsupportedVersion(version))
+        }
+        let hidden_raw = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+        if hidden_raw == 0 || hidden_raw % 16 != 0 {
+            return Err(NetworkLoadErr::InvalidHiddenSize(hidden_raw))
+        }
+        let hidden = hidden_raw as usize;
+        let expected = Self::file_len(hidden);
+        if bytes.len() != expected {
+            return Err(NetworkLoadErr::WrongLength { expected, found: bytes.len() })
+        }
+
+        let mut cursor = HEADER_LEN;
+        let mut read_i16s = |count: usize| -> Vec<i16> {
+            let values = bytes[cursor..cursor + count * 2]
+                .chunks_exact(2)
+                .map(|c| i16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            cursor += count * 2;
+            values
+        };
+        let feature_weights = read_i16s(INPUTS * hidden);
+        let feature_biases = read_i16s(hidden);
+        let output_weights = read_i16s(2 * hidden);
+        let output_bias = i32::from_le_bytes(bytes[expected - 4..].try_into().unwrap());
+
+        Ok(Self {
+            hidden,
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    /// Loads a network file from disk.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, NetworkLoadErr> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// The network bundled with the binary, if it was built with the `embedded-net` feature.
+    pub fn embedded() -> Option<Self> {
+        #[cfg(feature = "embedded-net")]
+        {
+            Self::from_bytes(EMBEDDED_NET).ok()
+        }
+        #[cfg(not(feature = "embedded-net"))]
+        {
+            None
+        }
+    }
+
+    /// Serializes the network to the file format described in the module docs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::file_len(self.hidden));
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden as u32).to_le_bytes());
+        for values in [&self.feature_weights, &self.feature_biases, &self.output_weights] {
+            for v in values.iter() {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// A hand-built network which only counts material. It is what `nets/default.nnue` contains
+    /// until a trained network replaces it, and is handy for checking the inference code.
+    ///
+    /// Neuron `p` counts the perspective's own pieces of type `p`, neuron `8 + p` counts the
+    /// opponent's, and the output layer weighs the counts by [`super::PIECE_VALUES`].
+    pub fn material() -> Self {
+        const HIDDEN: usize = 16;
+        const PER_PIECE: i16 = 16;
+        let mut feature_weights = vec![0; INPUTS * HIDDEN];
+        let mut output_weights = vec![0; 2 * HIDDEN];
+        for p in 0..Piece::NUM {
+            for square in 0..Square::NUM {
+                feature_weights[(p * 64 + square) * HIDDEN + p] = PER_PIECE;
+                feature_weights[(384 + p * 64 + square) * HIDDEN + 8 + p] = PER_PIECE;
+            }
+            // Both accumulators see the material, so each contributes half of the score.
+            let value = super::PIECE_VALUES[p] * QA * QB / (2 * PER_PIECE as i32 * SCALE);
+            output_weights[p] = value as i16;
+            output_weights[8 + p] = -value as i16;
+            output_weights[HIDDEN + p] = -value as i16;
+            output_weights[HIDDEN + 8 + p] = value as i16;
+        }
+        Self {
+            hidden: HIDDEN,
+            feature_weights,
+            feature_biases: vec![0; HIDDEN],
+            output_weights,
+            output_bias: 0,
+        }
+    }
+
+    /// A network which adds up piece-square tables, as written by the Texel tuner.
+    /// `tables[piece][square]` is the value in centipawns of a piece of that type on that square,
+    /// for White; Black's pieces use the mirrored square. `tempo` goes to the side to move.
+    ///
+    /// There is a neuron for each feature, so that its accumulator only ever holds the feature's
+    /// own weight, and the output layer reads the side to move's accumulator alone. Each value is
+    /// split between the feature weight and the output weight, keeping the error under a
+    /// centipawn for values up to a few thousand.
+    pub fn piece_square(tables: &[[i32; Square::NUM]; Piece::NUM], tempo: i32) -> Self {
+        const HIDDEN: usize = INPUTS;
+        let mut feature_weights = vec![0; INPUTS * HIDDEN];
+        let mut output_weights = vec![0; 2 * HIDDEN];
+        for feature in 0..INPUTS {
+            // a piece of the side to move, or one of the other side's, which sits on the mirrored
+            // square of its own tables
+            let (piece, square) = (feature % 384 / 64, feature % 64);
+            let value = match feature < 384 {
+                true => tables[piece][square],
+                false => -tables[piece][square ^ 56],
+            };
+            // weight * output must come to this, with the weight at most QA
+            let product = value as i64 * (QA * QB) as i64 / SCALE as i64;
+            let output = (product.abs() + QA as i64 - 1) / QA as i64 * product.signum();
+            if output != 0 {
+                feature_weights[feature * HIDDEN + feature] = (product as f64 / output as f64).round() as i16;
+                output_weights[feature] = output as i16;
+            }
+        }
+        Self {
+            hidden: HIDDEN,
+            feature_weights,
+            feature_biases: vec![0; HIDDEN],
+            output_weights,
+            output_bias: tempo * QA * QB / SCALE,
+        }
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    const fn file_len(hidden: usize) -> usize {
+        HEADER_LEN + 2 * (INPUTS * hidden + hidden + 2 * hidden) + 4
+    }
+
+    /// The feature-transformer column for the given feature
+    fn feature(&self, index: usize) -> &[i16] {
+        &self.feature_weights[index * self.hidden..(index + 1) * self.hidden]
+    }
+
+    /// Runs the output layer on a pair of accumulators, returning centipawns for the side whose
+    /// accumulator is `us`.
+    pub fn output(&self, us: &[i16], them: &[i16]) -> i32 {
+        let (our_weights, their_weights) = self.output_weights.split_at(self.hidden);
+        let sum = simd::crelu_dot(us, our_weights, QA as i16)
+            + simd::crelu_dot(them, their_weights, QA as i16);
+        (sum + self.output_bias) * SCALE / (QA * QB)
+    }
+}
+
+/// The feature index of a piece as seen from `perspective`
+pub fn feature_index(perspective: Color, color: Color, piece: Piece, square: Square) -> usize {
+    let (side, square) = match perspective {
+        Color::White => (color as usize, square as usize),
+        Color::Black => ((!color) as usize, square as usize ^ 56),
+    };
+    side * 384 + piece as usize * 64 + square
+}
+
+/// Stack of accumulator pairs, one pair per ply. The accumulators live in one flat buffer so that
+/// pushing a ply does not allocate once the buffer has grown.
+#[derive(Clone)]
+pub struct AccumulatorStack {
+    hidden: usize,
+    /// Each ply takes `2 * hidden` values: white's perspective followed by black's
+    data: Vec<i16>,
+    ply: usize,
+}
+
+impl AccumulatorStack {
+    fn new(hidden: usize) -> Self {
+        Self {
+            hidden,
+            data: vec![0; 2 * hidden],
+            ply: 0,
+        }
+    }
+
+    fn current_range(&self) -> std::ops::Range<usize> {
+        let width = 2 * self.hidden;
+        self.ply * width..(self.ply + 1) * width
+    }
+
+    /// The accumulator of the current ply for the given perspective
+    pub fn current(&self, perspective: Color) -> &[i16] {
+        let start = self.current_range().start + perspective as usize * self.hidden;
+        &self.data[start..start + self.hidden]
+    }
+
+    fn current_mut(&mut self) -> &mut [i16] {
+        let range = self.current_range();
+        &mut self.data[range]
+    }
+
+    /// Copies the current accumulators into a new ply
+    pub fn push(&mut self) {
+        let range = self.current_range();
+        if self.data.len() < range.end + range.len() {
+            self.data.resize(range.end + range.len(), 0);
+        }
+        self.data.copy_within(range.clone(), range.end);
+        self.ply += 1;
+    }
+
+    /// Returns to the accumulators of the previous ply. Returns false, leaving the stack as it is,
+    /// when there is none: the caller must then rebuild the current accumulators.
+    #[must_use]
+    pub fn pop(&mut self) -> bool {
+        if self.ply == 0 {
+            return false
+        }
+        self.ply -= 1;
+        true
+    }
+}
+
+/// A network together with the accumulators of the board it is attached to.
+#[derive(Clone)]
+pub struct NnueState {
+    pub network: Arc<Network>,
+    pub accumulators: AccumulatorStack,
+}
+
+impl NnueState {
+    pub fn new(network: Arc<Network>) -> Self {
+        let accumulators = AccumulatorStack::new(network.hidden);
+        let mut state = Self { network, accumulators };
+        state.clear();
+        state
+    }
+
+    /// Resets the current accumulators to the feature biases (an empty board)
+    pub fn clear(&mut self) {
+        let hidden = self.network.hidden;
+        let biases = &self.network.feature_biases;
+        let acc = self.accumulators.current_mut();
+        acc[..hidden].copy_from_slice(biases);
+        acc[hidden..].copy_from_slice(biases);
+    }
+
+    pub fn add_piece(&mut self, square: Square, color: Color, piece: Piece) {
+        self.update(square, color, piece, simd::add_assign);
+    }
+
+    pub fn remove_piece(&mut self, square: Square, color: Color, piece: Piece) {
+        self.update(square, color, piece, simd::sub_assign);
+    }
+
+    fn update(&mut self, square: Square, color: Color, piece: Piece, op: fn(&mut [i16], &[i16])) {
+        let hidden = self.network.hidden;
+        let acc = self.accumulators.current_mut();
+        let (white, black) = acc.split_at_mut(hidden);
+        op(white, self.network.feature(feature_index(Color::White, color, piece, square)));
+        op(black, self.network.feature(feature_index(Color::Black, color, piece, square)));
+    }
+
+    /// Evaluates the current accumulators for the side to move
+    pub fn evaluate(&self, side_to_move: Color) -> i32 {
+        self.network.output(
+            self.accumulators.current(side_to_move),
+            self.accumulators.current(!side_to_move),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn round_trip() {
+        let net = Network::material();
+        assert_eq!(Network::from_bytes(&net.to_bytes()).unwrap(), net);
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let bytes = Network::material().to_bytes();
+        assert!(matches!(
+            Network::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(NetworkLoadErr::WrongLength { .. })
+        ));
+    }
+
+    #[test]
+    fn default_net_is_material_net() {
+        let bytes = include_bytes!("../../nets/default.nnue");
+        assert_eq!(Network::from_bytes(bytes).unwrap(), Network::material());
+    }
+
+    #[test]
+    fn material_net_counts_material() {
+        let mut board = Board::new("4k3/8/8/8/8/8/8/3QK3 w - - 0 1".to_string()).unwrap();
+        board.set_network(Arc::new(Network::material()));
+        let score = board.nnue_evaluate().unwrap();
+        assert!((890..=900).contains(&score), "score was {}", score);
+    }
+
+    #[test]
+    fn piece_square_net_adds_up_tables() {
+        // material, with pawns worth more from their fourth rank on and an oversized queen
+        let mut tables = [[0; Square::NUM]; Piece::NUM];
+        for (piece, table) in tables.iter_mut().enumerate() {
+            for (square, value) in table.iter_mut().enumerate() {
+                *value = match piece {
+                    0 if square >= 24 => 125,
+                    4 => 2500,
+                    _ => super::super::PIECE_VALUES[piece],
+                };
+            }
+        }
+        let network = Arc::new(Network::piece_square(&tables, 15));
+        for (fen, expected) in [
+            ("4k3/8/8/3p4/4P3/8/8/3QK3 w - - 0 1", 2515),
+            ("4k3/8/8/3p4/4P3/8/8/3QK3 b - - 0 1", -2485),
+            ("4k3/8/8/8/8/3p4/4P3/4K3 b - - 0 1", 40),
+        ] {
+            let mut board = Board::new(fen.to_string()).unwrap();
+            board.set_network(network.clone());
+            let score = board.nnue_evaluate().unwrap();
+            assert!((score - expected).abs() <= 1, "{}: {}", fen, score);
+        }
+    }
+
+    /// A network with pseudo-random weights, so that every feature moves the accumulators
+    fn random_network() -> Network {
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % 201) as i16 - 100
+        };
+        let hidden = 32;
+        Network {
+            hidden,
+            feature_weights: (0..INPUTS * hidden).map(|_| next()).collect(),
+            feature_biases: (0..hidden).map(|_| next()).collect(),
+            output_weights: (0..2 * hidden).map(|_| next()).collect(),
+            output_bias: 1234,
+        }
+    }
+
+    fn assert_matches_refresh(board: &Board, network: &Arc<Network>) {
+        let mut refreshed = board.clone();
+        refreshed.set_network(network.clone());
+        for color in [Color::White, Color::Black] {
+            assert_eq!(
+                board.nnue().unwrap().accumulators.current(color),
+                refreshed.nnue().unwrap().accumulators.current(color),
+            );
+        }
+        assert_eq!(board.nnue_evaluate(), refreshed.nnue_evaluate());
+    }
+
+    #[test]
+    fn incremental_matches_refresh() {
+        let network = Arc::new(random_network());
+        let mut board = Board::new(KIWIPETE.to_string()).unwrap();
+        board.set_network(network.clone());
+        for i in 0..board.legal_moves().len {
+            let mv = board.legal_moves().moves[i];
+            board.make_move(mv);
+            assert_matches_refresh(&board, &network);
+            for j in 0..board.legal_moves().len {
+                let reply = board.legal_moves().moves[j];
+                board.make_move(reply);
+                assert_matches_refresh(&board, &network);
+                board.undo_move();
+            }
+            board.undo_move();
+        }
+        assert_matches_refresh(&board, &network);
+    }
+
+    #[test]
+    fn undo_past_the_attached_network() {
+        let network = Arc::new(random_network());
+        let mut board = Board::new(KIWIPETE.to_string()).unwrap();
+        for mv in ["e2a6", "b4c3", "d2c3"] {
+            board.make_move(crate::uci::parse_uci_move(&board, mv).unwrap());
+        }
+        board.set_network(network.clone());
+        for _ in 0..3 {
+            board.undo_move();
+            assert_matches_refresh(&board, &network);
+        }
+        assert_eq!(board.fen(), KIWIPETE);
+    }
+
+    #[test]
+    fn perspectives_are_symmetric() {
+        let network = Arc::new(random_network());
+        let mut white = Board::new("4k3/2p5/8/3n4/8/8/1P3B2/4K3 w - - 0 1".to_string()).unwrap();
+        let mut black = Board::new("4k3/1p3b2/8/8/3N4/8/2P5/4K3 b - - 0 1".to_string()).unwrap();
+        white.set_network(network.clone());
+        black.set_network(network);
+        assert_eq!(white.nnue_evaluate(), black.nnue_evaluate());
+    }
+}
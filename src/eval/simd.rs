@@ -0,0 +1,79 @@
+//! Integer kernels used by the network. On x86_64 the AVX2 versions are picked at runtime when the
+//! CPU supports them; everything else uses the scalar versions, which the compiler is free to
+//! auto-vectorize.
+
+/// `sum(clamp(acc[i], 0, max) * weights[i])`
+pub fn crelu_dot(acc: &[i16], weights: &[i16], max: i16) -> i32 {
+    debug_assert_eq!(acc.len(), weights.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: we just checked that the CPU supports AVX2
+            return unsafe { avx2::crelu_dot(acc, weights, max) }
+        }
+    }
+    scalar::crelu_dot(acc, weights, max)
+}
+
+/// `acc[i] += delta[i]`
+pub fn add_assign(acc: &mut [i16], delta: &[i16]) {
+    for (a, d) in acc.iter_mut().zip(delta) {
+        *a = a.wrapping_add(*d);
+    }
+}
+
+/// `acc[i] -= delta[i]`
+pub fn sub_assign(acc: &mut [i16], delta: &[i16]) {
+    for (a, d) in acc.iter_mut().zip(delta) {
+        *a = a.wrapping_sub(*d);
+    }
+}
+
+mod scalar {
+    pub fn crelu_dot(acc: &[i16], weights: &[i16], max: i16) -> i32 {
+        acc.iter()
+            .zip(weights)
+            .map(|(a, w)| (*a).clamp(0, max) as i32 * *w as i32)
+            .sum()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    /// # Safety
+    ///
+    /// The caller must ensure that the CPU supports AVX2
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn crelu_dot(acc: &[i16], weights: &[i16], max: i16) -> i32 {
+        const LANES: usize = 16;
+        let chunks = acc.len() / LANES;
+        let zero = _mm256_setzero_si256();
+        let ceiling = _mm256_set1_epi16(max);
+        let mut sum = _mm256_setzero_si256();
+        for i in 0..chunks {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i * LANES) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i * LANES) as *const __m256i);
+            let clipped = _mm256_min_epi16(_mm256_max_epi16(a, zero), ceiling);
+            // multiplies pairs of i16 and adds neighbours into i32 lanes
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, w));
+        }
+        let mut lanes = [0i32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+        let tail = chunks * LANES;
+        lanes.iter().sum::<i32>() + super::scalar::crelu_dot(&acc[tail..], &weights[tail..], max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simd_matches_scalar() {
+        let acc: Vec<i16> = (0..40).map(|i| (i * 37 % 600) as i16 - 150).collect();
+        let weights: Vec<i16> = (0..40).map(|i| (i * 91 % 300) as i16 - 140).collect();
+        assert_eq!(crelu_dot(&acc, &weights, 255), scalar::crelu_dot(&acc, &weights, 255));
+    }
+}
@@ -0,0 +1,359 @@
+//! A game against the engine in the terminal.
+//!
+//! The user plays one side, typing moves in standard algebraic notation or UCI, and the engine
+//! answers with a search limited by its level. Between moves, the user may also type one of the
+//! [`COMMANDS`]: take moves back, ask for a hint, turn the board around, resign or offer a draw.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, BufRead, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::board::{Board, GameResult, START_FEN};
+use crate::eval::nnue::Network;
+use crate::pgn::PgnGame;
+use crate::render::BoardRenderer;
+use crate::search::{time::{TimeLimit, TimeManager, WallClock}, Search, SearchLimits};
+use crate::types::{Color, Move};
+use crate::uci::{move_to_san, parse_san, parse_uci_move};
+
+pub const MIN_LEVEL: u8 = 1;
+pub const MAX_LEVEL: u8 = 10;
+pub const DEFAULT_LEVEL: u8 = 5;
+/// File the games are appended to when no other is given
+pub const DEFAULT_PGN_FILE: &str = "games.pgn";
+/// The engine accepts a draw when it thinks it is at least this far behind
+const DRAW_ACCEPT_SCORE: i32 = -50;
+
+pub const COMMANDS: &str = "Type a move (Nf3, e2e4), or one of:
+  undo    take back your last move and the engine's answer
+  hint    suggest a move
+  flip    turn the board around
+  fen     print the position as FEN
+  pgn     print the game so far as PGN
+  draw    offer a draw
+  resign  give up the game
+  quit    leave the game unfinished
+  help    print this list";
+
+/// Search limits of a level: level `n` searches `2n` plies deep, for at most `n` fifths of a
+/// second
+pub fn level_limits(level: u8) -> (SearchLimits, Duration) {
+    let level = level.clamp(MIN_LEVEL, MAX_LEVEL);
+    let limits = SearchLimits { depth: Some(2 * level as usize), ..Default::default() };
+    (limits, Duration::from_millis(200 * level as u64))
+}
+
+pub struct Game {
+    start: Board,
+    board: Board,
+    moves: Vec<Move>,
+    /// The side the user plays
+    player: Color,
+    level: u8,
+    renderer: BoardRenderer,
+    /// The side the board is shown from
+    orientation: Color,
+    /// How the game ended, and why
+    result: Option<(GameResult, String)>,
+}
+
+impl Game {
+    /// A game from `board`, with the user playing `player` against the engine at `level`. The
+    /// engine evaluates with the embedded network, if there is one.
+    pub fn new(mut board: Board, player: Color, level: u8) -> Self {
+        if let Some(network) = Network::embedded() {
+            board.set_network(Arc::new(network));
+        }
+        Self {
+            start: board.clone(),
+            board,
+            moves: vec![],
+            player,
+            level: level.clamp(MIN_LEVEL, MAX_LEVEL),
+            renderer: BoardRenderer::new(),
+            orientation: player,
+            result: None,
+        }
+    }
+
+    /// Draws the board with `renderer`, which may use colours or chess glyphs. The orientation
+    /// and the last move are set by the game.
+    pub fn with_renderer(mut self, renderer: BoardRenderer) -> Self {
+        self.renderer = renderer;
+        self
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// The result once the game is over, `None` while it goes on or if the user left it
+    pub fn result(&self) -> Option<GameResult> {
+        self.result.as_ref().map(|(result, _)| *result)
+    }
+
+    /// Plays the game out, reading the user's moves and commands from `input` until the game is
+    /// over, the user quits or the input ends
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        let mut lines = input.lines();
+        writeln!(out, "{}\n", self.diagram())?;
+        while self.result.is_none() {
+            if let Some(result) = self.board.outcome() {
+                self.result = Some((result, outcome_reason(&self.board, result)));
+                break
+            }
+            if self.board.side_to_move != self.player {
+                let Some((mv, _)) = self.search() else { break };
+                writeln!(out, "Mascagni plays {}\n", self.numbered_san(mv))?;
+                self.play(mv);
+                writeln!(out, "{}\n", self.diagram())?;
+                continue
+            }
+            write!(out, "Your move ({}): ", self.player)?;
+            out.flush()?;
+            let Some(line) = lines.next().transpose()? else { break };
+            writeln!(out)?;
+            if !self.handle(line.trim(), out)? {
+                break
+            }
+        }
+        match &self.result {
+            Some((result, reason)) => writeln!(out, "{} {{{}}}", result, reason),
+            None => writeln!(out, "Game left unfinished"),
+        }
+    }
+
+    /// Carries out a command or plays a move of the user. False once the user quits.
+    fn handle<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        match line {
+            "" => {}
+            "undo" => {
+                if self.undo() {
+                    writeln!(out, "{}\n", self.diagram())?;
+                } else {
+                    writeln!(out, "Nothing to take back")?;
+                }
+            }
+            "hint" => match self.search() {
+                Some((mv, _)) => writeln!(out, "Hint: {}", self.numbered_san(mv))?,
+                None => writeln!(out, "No move to suggest")?,
+            },
+            "flip" => {
+                self.orientation = !self.orientation;
+                writeln!(out, "{}\n", self.diagram())?;
+            }
+            "fen" => writeln!(out, "{}", self.board.fen())?,
+            "pgn" => write!(out, "{}", self.pgn())?,
+            "draw" => {
+                // the search is for the user's side, so the engine's view is the other way round
+                let accepted = self.search().is_some_and(|(_, score)| -score <= DRAW_ACCEPT_SCORE);
+                if accepted {
+                    self.result = Some((GameResult::Draw, "Draw agreed".to_string()));
+                } else {
+                    writeln!(out, "Mascagni declines the draw")?;
+                }
+            }
+            "resign" => self.result = Some((GameResult::Win(!self.player), format!("{} resigns", self.player))),
+            "quit" => return Ok(false),
+            "help" => writeln!(out, "{}", COMMANDS)?,
+            _ => match parse_san(&self.board, line).or_else(|| parse_uci_move(&self.board, line)) {
+                Some(mv) => {
+                    self.play(mv);
+                    writeln!(out, "{}\n", self.diagram())?;
+                }
+                None => writeln!(out, "Not a legal move or a command: {} (type help for the list)", line)?,
+            },
+        }
+        Ok(true)
+    }
+
+    fn diagram(&self) -> String {
+        let renderer = self.renderer.clone().with_orientation(self.orientation);
+        renderer.with_last_move(self.moves.last().copied()).render(&self.board)
+    }
+
+    fn play(&mut self, mv: Move) {
+        self.board.make_move(mv);
+        self.moves.push(mv);
+    }
+
+    /// Takes back moves until the user's last one is gone. False if the user has not moved yet.
+    fn undo(&mut self) -> bool {
+        // the engine moved first if the game started on its turn
+        let engine_first = self.start.side_to_move != self.player;
+        if self.moves.len() <= engine_first as usize {
+            return false
+        }
+        loop {
+            self.board.undo_move();
+            self.moves.pop();
+            if self.board.side_to_move == self.player {
+                return true
+            }
+        }
+    }
+
+    /// The engine's choice of move for the side to move, with its score from that side's point
+    /// of view
+    fn search(&self) -> Option<(Move, i32)> {
+        let (limits, move_time) = level_limits(self.level);
+        let time = TimeManager::new(TimeLimit::MoveTime(move_time), Duration::ZERO, Box::new(WallClock::start()));
+        let result = Search::new(self.board.clone(), limits, time, Arc::default()).run(|_| {});
+        result.best_move.map(|mv| (mv, result.score))
+    }
+
+    /// A move of the side to move as `12. Nf3` or `12... Nf6`
+    fn numbered_san(&self, mv: Move) -> String {
+        let dots = if self.board.side_to_move == Color::White { "." } else { "..." };
+        let san = move_to_san(&self.board, mv).unwrap_or_else(|| mv.to_string());
+        format!("{}{} {}", self.board.fullmove_number(), dots, san)
+    }
+
+    /// The game so far, with the date and the players in the tags
+    pub fn pgn(&self) -> PgnGame {
+        let engine = format!("Mascagni (level {})", self.level);
+        let (white, black) = match self.player {
+            Color::White => ("Player".to_string(), engine),
+            Color::Black => (engine, "Player".to_string()),
+        };
+        let result = self.result().map_or("*".to_string(), |result| result.to_string());
+        let mut tags = vec![
+            ("Event".to_string(), "Casual game".to_string()),
+            ("Site".to_string(), "?".to_string()),
+            ("Date".to_string(), today()),
+            ("Round".to_string(), "-".to_string()),
+            ("White".to_string(), white),
+            ("Black".to_string(), black),
+            ("Result".to_string(), result),
+        ];
+        if let Some((_, reason)) = &self.result {
+            tags.push(("Termination".to_string(), reason.clone()));
+        }
+        let fen = self.start.fen();
+        if fen != START_FEN {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), fen));
+        }
+        let mut board = self.start.clone();
+        let moves = self
+            .moves
+            .iter()
+            .map(|&mv| {
+                let san = move_to_san(&board, mv).unwrap_or_else(|| mv.to_string());
+                board.make_move(mv);
+                san
+            })
+            .collect();
+        PgnGame { tags, moves }
+    }
+
+    /// Appends the game to a PGN file
+    pub fn save_pgn<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", self.pgn())
+    }
+}
+
+/// Why a game is over, for a position where `outcome` is `Some(result)`
+pub(crate) fn outcome_reason(board: &Board, result: GameResult) -> String {
+    if board.is_checkmate() {
+        format!("{} mates", !board.side_to_move)
+    } else if let Some(GameResult::Win(color)) = board.variant_outcome() {
+        format!("{} wins by the rules of {}", color, board.variant())
+    } else if board.is_stalemate() {
+        "Stalemate".to_string()
+    } else if board.is_threefold_repetition() {
+        "Draw by repetition".to_string()
+    } else if board.is_fifty_move_draw() {
+        "Draw by the fifty-move rule".to_string()
+    } else if board.is_insufficient_material() {
+        "Draw by insufficient material".to_string()
+    } else {
+        match result {
+            GameResult::Win(color) => format!("{} wins", color),
+            GameResult::Draw => "Draw".to_string(),
+        }
+    }
+}
+
+/// Today's date as PGN writes it, `2024.03.17`
+pub(crate) fn today() -> String {
+    let Ok(elapsed) = SystemTime::now().duration_since(UNIX_EPOCH) else { return "????.??.??".to_string() };
+    // days to a civil date, after Howard Hinnant's `civil_from_days`
+    let days = (elapsed.as_secs() / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::parse_pgn;
+
+    fn play(fen: &str, player: Color, input: &str) -> (Game, String) {
+        let mut game = Game::new(Board::new(fen.to_string()).unwrap(), player, MIN_LEVEL);
+        let mut out = vec![];
+        game.run(input.as_bytes(), &mut out).unwrap();
+        (game, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn commands() {
+        let (game, out) = play(START_FEN, Color::White, "e4\nxyz\nundo\nundo\nNf3\nfen\nresign\n");
+        assert!(out.contains("Mascagni plays 1... "));
+        assert!(out.contains("Not a legal move or a command: xyz"));
+        assert!(out.contains("Nothing to take back"));
+        assert!(out.contains(&format!("{}\n", game.board().fen())));
+        assert!(game.result() == Some(GameResult::Win(Color::Black)));
+        assert_eq!(game.moves().len(), 2);
+        assert!(out.ends_with("0-1 {White resigns}\n"));
+        let pgn = parse_pgn(&game.pgn().to_string());
+        assert_eq!(pgn[0].moves[0], "Nf3");
+        assert_eq!(pgn[0].tag("White"), Some("Player"));
+        assert_eq!(pgn[0].tag("Result"), Some("0-1"));
+    }
+
+    #[test]
+    fn engine_mates() {
+        // the engine plays White and mates at once, before the user gets to move
+        let (game, out) = play("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", Color::Black, "");
+        assert_eq!(game.moves().len(), 1);
+        assert!(game.result() == Some(GameResult::Win(Color::White)));
+        assert!(out.contains("Mascagni plays 1. Ra8#"));
+        assert!(out.ends_with("1-0 {White mates}\n"));
+        let pgn = game.pgn();
+        assert_eq!(pgn.tag("FEN"), Some("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"));
+        assert_eq!(pgn.tag("Black"), Some("Player"));
+    }
+
+    #[test]
+    fn draw_reasons() {
+        // the fifty-move rule ends the game on a position seen only once before
+        let mut board = Board::new("8/8/4k3/8/8/3K4/R7/8 w - - 96 80".to_string()).unwrap();
+        for mv in ["d3c3", "e6d6", "c3d3", "d6e6"] {
+            board.make_move(parse_uci_move(&board, mv).unwrap());
+        }
+        assert!(board.outcome() == Some(GameResult::Draw));
+        assert_eq!(outcome_reason(&board, GameResult::Draw), "Draw by the fifty-move rule");
+        let mut board = Board::new(START_FEN.to_string()).unwrap();
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2) {
+            board.make_move(parse_uci_move(&board, mv).unwrap());
+        }
+        assert_eq!(outcome_reason(&board, GameResult::Draw), "Draw by repetition");
+    }
+}
@@ -0,0 +1,39 @@
+//! Mascagni, a chess engine.
+//!
+//! The library holds everything the engine is made of: the [`Board`] with its move generator
+//! and FEN support, the notations moves are read and written in ([`uci`]), the [`search`] and
+//! the [`eval`]uation, [`tools::perft`] and the other tools. The `mascagni` binary speaks UCI and
+//! runs the tools from the command line, `mascagni-play` plays a game in the terminal and
+//! `mascagni-perft` counts the positions a move generator has to get right.
+//!
+//! ```
+//! use mascagni::{uci::parse_san, Board};
+//!
+//! let mut board = Board::new(mascagni::START_FEN.to_string()).unwrap();
+//! let mv = parse_san(&board, "Nf3").unwrap();
+//! board.make_move(mv);
+//! assert_eq!(mv.to_string(), "g1f3");
+//! assert_eq!(board.fen(), "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1");
+//! assert_eq!(mascagni::tools::perft(&mut board, 2).nodes, 440);
+//! ```
+
+pub mod types;
+mod board;
+pub mod eval;
+pub mod search;
+pub mod uci;
+pub mod tools;
+mod solver;
+mod pgn;
+pub mod book;
+pub mod tablebase;
+pub mod game;
+pub mod render;
+pub mod tournament;
+mod xboard;
+
+pub use board::{chess960_fen, dfrc_fen, Board, FenParseErr, GameResult, Variant, POCKET_PIECES, START_FEN};
+pub use pgn::{parse_pgn, PgnGame};
+pub use solver::{Outcome, SolveResult, Solver};
+pub use types::{Color, Move, Piece, Square};
+pub use xboard::XBoardEngine;
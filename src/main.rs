@@ -1,69 +1,798 @@
-use std::process::exit;
+//! The `mascagni` binary: a UCI engine, which is what it runs without arguments (or an XBoard one,
+//! if the first command is `xboard`), and the tools which build its books, tables and opening
+//! suites.
+//!
+//! Every command exits with 0 when it succeeds, 1 when it fails (a file cannot be read, say) and
+//! [`EXIT_USAGE`] when its arguments are wrong.
 
-use board::Board;
-use uci::collect_algebraic_moves;
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{stdin, stdout, BufRead, Write},
+    process::exit,
+    str::FromStr,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
-use std::io::{stdin, stdout, Write};
-use crate::tools::perft;
+use mascagni::eval::{self, Network};
+use mascagni::game::{self, Game};
+use mascagni::render::{svg::{self, SvgRenderer}, BoardRenderer};
+use mascagni::search::{mate_in, Search, SearchLimits, TimeManager};
+use mascagni::types::{Bitboard, Color, Move, MoveType, Square};
+use mascagni::uci::{collect_algebraic_moves, line_to_san, Output, UciEngine};
+use mascagni::tournament::{self, Adjudication, Engine, EngineConfig, Match, RandomMover, Sprt, SprtDecision};
+use mascagni::{book, chess960_fen, parse_pgn, tablebase, tools, Board, Solver, XBoardEngine, START_FEN};
 
-mod types;
-mod board;
-mod uci;
-mod tools;
+/// Exit code of a command given arguments it does not understand
+const EXIT_USAGE: i32 = 2;
 
-fn main() {
-    // let mut s = String::new();
-    // print!("Paste a fen to start your game: ");
-    // let _ = stdout().flush();
-    // stdin().read_line(&mut s).expect("Did not enter a valid string.");
-    // if s == String::from("") {
-    //     s = String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-    // }
-    // if let Some('\n')=s.chars().next_back() {
-    //     s.pop();
-    // }
-    // if let Some('\r')=s.chars().next_back() {
-    //     s.pop();
-    // }
-
-    let mut stdout = stdout();
-
-    let board = Board::new(String::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"));
-
-    match board {
-        Ok(mut b) => {
-            let one_deep = perft::perft(&mut b, 1);
-            println!("one_deep: {}", one_deep);
-            while b.legal_moves.len > 0 {
-                let alg_moves = collect_algebraic_moves(&b);
-                b.pretty_print();
-                stdout.write_all(format!("\n{} to move: ", b.side_to_move.to_string()).as_bytes()).unwrap();
-                let mut new_st = String::new();
-                stdout.flush().unwrap();
-                stdin().read_line(&mut new_st).expect("Did not enter a valid string.");
-                let mut made_move = false;
-                for m in alg_moves.iter() {
-                    if m.algebraic.trim() == new_st.trim() {
-                        b.make_move(m.full_move.inner_move);
-                        made_move = true;
-                        break
-                    }
-                }
-                if !made_move {
-                    println!("Not a valid move!!!!");
-                }
+const USAGE: &str = "Usage: mascagni [COMMAND]
 
+Without a command, the engine speaks UCI on the standard input and output, or XBoard if the
+first command is `xboard`.
+
+Commands:
+  uci                                          Speak UCI
+  xboard                                       Speak XBoard (CECP version 2)
+  perft DEPTH [--fen FEN] [--divide]           Count the positions DEPTH plies away
+  bench [DEPTH]                                Search the bench positions
+  eval [--fen FEN] [--net FILE]                Evaluate a position
+  legal [--fen FEN]                            List the legal moves, in SAN and UCI
+  play [--fen FEN] [--engine-side white|black] [--level N] [--pgn FILE] [--unicode] [--colors]
+                                               Play a game against the engine
+  analyze [--fen FEN] [--depth D]              Search a position and print each iteration
+  diagram [--fen FEN] [--arrows e2e4,g1f3] [--highlight e4,d5] [--last-move e2e4] [--size N] [--flip] [--no-coordinates]
+                                               Print an SVG image of a position
+  match --engine CMD --engine CMD [--games N] [--openings FILE] [--tc BASE+INC] [--option NAME=VALUE]
+        [--resign CP[/MOVES]] [--draw CP[/MOVES]] [--sprt ELO0,ELO1] [--pgn FILE]
+                                               Play a match between two UCI engines
+  random-mover                                 Speak UCI, playing random moves
+  tune DATA [--out FILE] [--epochs N] [--rate R] [--k K] [--threads N]
+                                               Fit piece-square tables to game results
+  solve [--nodes N] [--side white|black] FEN   Prove a forced mate
+  book [--plies N] [--min-games N] OUT.bin GAMES.pgn...
+  tbgen [--out DIR] [ENDING...]
+  openings [--start standard|chess960|dfrc] [--count N] [--plies K] [--depth D] [--max-score CP] [--seed S]
+  chess960 INDEX
+  nnue-material [FILE]
+  help";
+
+/// Depth `analyze` searches to when none is given
+const DEFAULT_ANALYZE_DEPTH: usize = 10;
+
+/// The arguments of a command: `--name value` options, `--switch`es and the other arguments, in
+/// order
+struct CommandArgs {
+    options: Vec<(String, String)>,
+    switches: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl CommandArgs {
+    /// Sorts out `args`, given the names of the options which take a value and of the switches.
+    /// Any other argument starting with `--` is an error.
+    fn parse(args: &[String], options: &[&str], switches: &[&str]) -> Result<Self, String> {
+        let mut parsed = Self { options: vec![], switches: vec![], positional: vec![] };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if options.contains(&arg.as_str()) {
+                let Some(value) = args.next() else { return Err(format!("Missing value for {}", arg)) };
+                parsed.options.push((arg.clone(), value.clone()));
+            } else if switches.contains(&arg.as_str()) {
+                parsed.switches.push(arg.clone());
+            } else if arg.starts_with("--") {
+                return Err(format!("Unknown option: {}", arg))
+            } else {
+                parsed.positional.push(arg.clone());
             }
-            if b.is_checkmate() {
-                println!("{} wins by checkmate", (!b.side_to_move).to_string());
-            }
-            if b.is_stalemate() {
-                println!("stalemate!!");
+        }
+        Ok(parsed)
+    }
+
+    /// The value of an option, the last one if it was given more than once
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(option, _)| option == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of an option given more than once, in order
+    fn options_named(&self, name: &str) -> Vec<&str> {
+        self.options.iter().filter(|(option, _)| option == name).map(|(_, value)| value.as_str()).collect()
+    }
+
+    /// The value of an option parsed as a `T`, or `default` when it is not there
+    fn parsed_option<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.option(name) {
+            Some(value) => value.parse().map_err(|_| format!("Invalid value for {}: {}", name, value)),
+            None => Ok(default),
+        }
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|switch| switch == name)
+    }
+
+    /// The board set up from `--fen`, or the starting position
+    fn board(&self) -> Result<Board, String> {
+        let fen = self.option("--fen").unwrap_or(START_FEN);
+        Board::new(fen.to_string()).map_err(|e| format!("Invalid fen: {}", e))
+    }
+
+    /// Fails when there are arguments other than options and switches
+    fn no_positional(&self) -> Result<(), String> {
+        match self.positional.first() {
+            Some(arg) => Err(format!("Unexpected argument: {}", arg)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Prints what went wrong and the usage line of the command, and returns [`EXIT_USAGE`]
+fn usage_error(message: &str, usage: &str) -> i32 {
+    eprintln!("{}", message);
+    eprintln!("Usage: {}", usage);
+    EXIT_USAGE
+}
+
+/// `perft DEPTH [--fen FEN] [--divide]`
+/// Counts the positions DEPTH plies away from the starting position or the FEN, with `--divide`
+/// move by move.
+fn perft_command(args: &[String]) -> i32 {
+    const USAGE: &str = "perft DEPTH [--fen FEN] [--divide]";
+    let args = match CommandArgs::parse(args, &["--fen"], &["--divide"]) {
+        Ok(args) => args,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    let [depth] = args.positional.as_slice() else { return usage_error("Expected a depth", USAGE) };
+    let Ok(depth) = depth.parse::<usize>() else { return usage_error(&format!("Invalid depth: {}", depth), USAGE) };
+    let mut board = match args.board() {
+        Ok(board) => board,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    let start = Instant::now();
+    let nodes = if args.switch("--divide") {
+        let moves = tools::divide(&mut board, depth);
+        for (mv, nodes) in &moves {
+            println!("{}: {}", mv, nodes);
+        }
+        println!();
+        moves.iter().map(|(_, nodes)| nodes).sum()
+    } else {
+        tools::perft(&mut board, depth).nodes
+    };
+    let elapsed = start.elapsed();
+    println!("Nodes: {}", nodes);
+    println!("Time: {} ms ({} nps)", elapsed.as_millis(), (nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64);
+    0
+}
+
+/// `bench [DEPTH]`
+/// Searches the bench positions with the embedded network, if any, and prints the node count.
+fn bench_command(args: &[String]) -> i32 {
+    let depth = match args {
+        [] => tools::DEFAULT_BENCH_DEPTH,
+        [depth] => match depth.parse() {
+            Ok(depth) => depth,
+            Err(_) => return usage_error(&format!("Invalid depth: {}", depth), "bench [DEPTH]"),
+        },
+        _ => return usage_error("Too many arguments", "bench [DEPTH]"),
+    };
+    println!("{}", tools::bench(depth, Default::default(), Network::embedded().map(Arc::new)));
+    0
+}
+
+/// `eval [--fen FEN] [--net FILE]`
+/// Prints the evaluation of a position, by the network of `--net`, the embedded one or, without
+/// either, by counting material. The FEN may also be given without `--fen`.
+fn eval_command(args: &[String]) -> i32 {
+    const USAGE: &str = "eval [--fen FEN] [--net FILE]";
+    let args = match CommandArgs::parse(args, &["--fen", "--net"], &[]) {
+        Ok(args) => args,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    let network = match args.option("--net") {
+        Some(path) => match Network::load(path) {
+            Ok(net) => Some(net),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1
             }
         },
+        None => Network::embedded(),
+    };
+    let board = match args.positional.as_slice() {
+        [] => args.board(),
+        words => Board::new(words.join(" ")).map_err(|e| format!("Invalid fen: {}", e)),
+    };
+    let mut board = match board {
+        Ok(board) => board,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    board.pretty_print();
+    match network {
+        Some(network) => {
+            board.set_network(Arc::new(network));
+            if let Some(nnue) = board.nnue() {
+                println!("\nNetwork: {} -> {} -> 1", eval::INPUTS, nnue.network.hidden_size());
+            }
+            println!("NNUE evaluation: {} cp ({} to move)", eval::evaluate(&board), board.side_to_move);
+        }
+        None => println!("\nEvaluation: {} cp ({} to move, no network)", eval::evaluate(&board), board.side_to_move),
+    }
+    println!("Material: {} cp", eval::material(&board));
+    0
+}
+
+/// `legal [--fen FEN]`
+/// Lists the legal moves of a position, one per line, in standard algebraic notation and in UCI.
+fn legal_command(args: &[String]) -> i32 {
+    const USAGE: &str = "legal [--fen FEN]";
+    let board = match CommandArgs::parse(args, &["--fen"], &[]).and_then(|args| {
+        args.no_positional()?;
+        args.board()
+    }) {
+        Ok(board) => board,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    let mut out = stdout().lock();
+    for mv in collect_algebraic_moves(&board) {
+        // a closed pipe (`| head`) is no reason to fail
+        if writeln!(out, "{:<8} {}", mv.algebraic, mv.full_move.inner_move).is_err() {
+            break
+        }
+    }
+    0
+}
+
+/// `analyze [--fen FEN] [--depth D]`
+/// Searches a position to depth `D` with the embedded network, if any, printing the score and
+/// principal variation of every iteration, then the best move.
+fn analyze_command(args: &[String]) -> i32 {
+    const USAGE: &str = "analyze [--fen FEN] [--depth D]";
+    let (board, depth) = match CommandArgs::parse(args, &["--fen", "--depth"], &[]).and_then(|args| {
+        args.no_positional()?;
+        Ok((args.board()?, args.parsed_option("--depth", DEFAULT_ANALYZE_DEPTH)?))
+    }) {
+        Ok(parsed) => parsed,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    let limits = SearchLimits { depth: Some(depth), ..Default::default() };
+    let mut search = Search::new(board.clone(), limits, TimeManager::infinite(), Arc::default());
+    if let Some(network) = Network::embedded() {
+        search = search.with_network(Arc::new(network));
+    }
+    let result = search.run(|report| {
+        let score = match mate_in(report.score) {
+            Some(moves) => format!("mate {}", moves),
+            None => format!("cp {}", report.score),
+        };
+        println!(
+            "depth {:>2}  score {:<9} nodes {:>10}  nps {:>8}  pv {}",
+            report.depth,
+            score,
+            report.nodes,
+            report.nps(),
+            line_to_san(&board, &report.pv)
+        );
+    });
+    match result.best_move {
+        Some(mv) => println!("bestmove {}", mv),
+        None => println!("bestmove (none)"),
+    }
+    0
+}
+
+/// `play [--fen FEN] [--engine-side white|black] [--level N] [--pgn FILE] [--unicode] [--colors]`
+/// Plays a game in the terminal against the engine, which takes Black unless told otherwise, and
+/// appends it to the PGN file at the end. The board is drawn with chess glyphs and ANSI colours
+/// if asked to.
+fn play_command(args: &[String]) -> i32 {
+    const USAGE: &str = "play [--fen FEN] [--engine-side white|black] [--level N] [--pgn FILE] [--unicode] [--colors]";
+    let options = ["--fen", "--engine-side", "--level", "--pgn"];
+    let parsed = CommandArgs::parse(args, &options, &["--unicode", "--colors"]).and_then(|args| {
+        args.no_positional()?;
+        let engine = match args.option("--engine-side") {
+            None | Some("black") => Color::Black,
+            Some("white") => Color::White,
+            Some(side) => return Err(format!("Invalid side: {}", side)),
+        };
+        let level = args.parsed_option("--level", game::DEFAULT_LEVEL)?;
+        if !(game::MIN_LEVEL..=game::MAX_LEVEL).contains(&level) {
+            return Err(format!("The level goes from {} to {}", game::MIN_LEVEL, game::MAX_LEVEL))
+        }
+        let pgn = args.option("--pgn").unwrap_or(game::DEFAULT_PGN_FILE).to_string();
+        let renderer = BoardRenderer::new().with_unicode(args.switch("--unicode")).with_colors(args.switch("--colors"));
+        Ok((args.board()?, engine, level, pgn, renderer))
+    });
+    let (board, engine, level, pgn, renderer) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    let mut game = Game::new(board, !engine, level).with_renderer(renderer);
+    println!("{}\n", game::COMMANDS);
+    if let Err(e) = game.run(stdin().lock(), &mut stdout()) {
+        eprintln!("{}", e);
+        return 1
+    }
+    if game.moves().is_empty() {
+        return 0
+    }
+    match game.save_pgn(&pgn) {
+        Ok(()) => println!("Game saved to {}", pgn),
         Err(e) => {
-            println!("Error!!! {}", e);
-            exit(1)
-        },
+            eprintln!("Could not write {}: {}", pgn, e);
+            return 1
+        }
+    }
+    0
+}
+
+/// `diagram [--fen FEN] [--arrows e2e4,g1f3] [--highlight e4,d5] [--last-move e2e4] [--size N]
+/// [--flip] [--no-coordinates]`
+/// Prints an SVG image of a position.
+fn diagram_command(args: &[String]) -> i32 {
+    const USAGE: &str =
+        "diagram [--fen FEN] [--arrows e2e4,g1f3] [--highlight e4,d5] [--last-move e2e4] [--size N] [--flip] [--no-coordinates]";
+    /// Reads a comma-separated list of squares, or of pairs of squares written as `e2e4`
+    fn squares(list: &str) -> Result<Vec<Square>, String> {
+        let invalid = || format!("Invalid squares: {}", list);
+        let mut squares = vec![];
+        for item in list.split(',').filter(|item| !item.is_empty()) {
+            if !item.is_ascii() || item.len() % 2 != 0 {
+                return Err(invalid())
+            }
+            for i in (0..item.len()).step_by(2) {
+                squares.push(Square::try_from(&item[i..i + 2]).map_err(|_| invalid())?);
+            }
+        }
+        Ok(squares)
+    }
+    let options = ["--fen", "--arrows", "--highlight", "--last-move", "--size"];
+    let parsed = CommandArgs::parse(args, &options, &["--flip", "--no-coordinates"]).and_then(|args| {
+        args.no_positional()?;
+        let orientation = if args.switch("--flip") { Color::Black } else { Color::White };
+        let mut renderer = SvgRenderer::new()
+            .with_size(args.parsed_option("--size", svg::DEFAULT_SIZE)?)
+            .with_orientation(orientation)
+            .with_coordinates(!args.switch("--no-coordinates"));
+        let arrows = squares(args.option("--arrows").unwrap_or(""))?;
+        if arrows.len() % 2 != 0 {
+            return Err("An arrow needs two squares".to_string())
+        }
+        for arrow in arrows.chunks(2) {
+            renderer = renderer.with_arrow(arrow[0], arrow[1]);
+        }
+        let highlights = squares(args.option("--highlight").unwrap_or(""))?;
+        renderer = renderer.with_highlights(highlights.into_iter().fold(Bitboard::default(), |bb, sq| bb | Bitboard::from(sq)));
+        if let Some(last_move) = args.option("--last-move") {
+            let [from, to] = squares(last_move)?[..] else { return Err(format!("Invalid move: {}", last_move)) };
+            renderer = renderer.with_last_move(Some(Move::new(from, to, MoveType::Quiet)));
+        }
+        Ok((args.board()?, renderer))
+    });
+    let (board, renderer) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    print!("{}", renderer.render(&board));
+    0
+}
+
+/// `match --engine CMD --engine CMD [--games N] [--openings FILE] [--tc BASE+INC] [--option NAME=VALUE]
+/// [--resign CP[/MOVES]] [--draw CP[/MOVES]] [--sprt ELO0,ELO1] [--pgn FILE]`
+/// Plays a match between two UCI engines, each opening of the EPD or PGN file once with each
+/// colour, and prints the score after every game, then the Elo difference. `--option` is set in
+/// both engines. With `--sprt`, the match stops as soon as the test decides.
+fn match_command(args: &[String]) -> i32 {
+    const USAGE: &str = "match --engine CMD --engine CMD [--games N] [--openings FILE] [--tc BASE+INC] [--option NAME=VALUE] \
+                         [--resign CP[/MOVES]] [--draw CP[/MOVES]] [--sprt ELO0,ELO1] [--pgn FILE]";
+    /// `CP` or `CP/MOVES`
+    fn threshold(text: &str, default_moves: usize) -> Option<(i32, usize)> {
+        let (score, moves) = text.split_once('/').unwrap_or((text, ""));
+        let moves = if moves.is_empty() { default_moves } else { moves.parse().ok()? };
+        Some((score.parse().ok()?, moves))
+    }
+    let options = ["--engine", "--games", "--openings", "--tc", "--option", "--resign", "--draw", "--sprt", "--pgn"];
+    let parsed = CommandArgs::parse(args, &options, &[]).and_then(|args| {
+        args.no_positional()?;
+        let [first, second] = args.options_named("--engine")[..] else { return Err("Two engines are needed".to_string()) };
+        let mut configs = [EngineConfig::new(first), EngineConfig::new(second)];
+        for option in args.options_named("--option") {
+            let Some((name, value)) = option.split_once('=') else { return Err(format!("Invalid option: {}", option)) };
+            configs = configs.map(|config| config.with_option(name, value));
+        }
+        let games = args.parsed_option("--games", 2)?;
+        let time_control = args.parsed_option("--tc", "10+0.1".parse().unwrap())?;
+        let mut adjudication = Adjudication::default();
+        if let Some(text) = args.option("--resign") {
+            let (score, moves) = threshold(text, tournament::DEFAULT_RESIGN_MOVES).ok_or(format!("Invalid value for --resign: {}", text))?;
+            (adjudication.resign_score, adjudication.resign_moves) = (Some(score), moves);
+        }
+        if let Some(text) = args.option("--draw") {
+            let (score, moves) = threshold(text, tournament::DEFAULT_DRAW_MOVES).ok_or(format!("Invalid value for --draw: {}", text))?;
+            (adjudication.draw_score, adjudication.draw_moves) = (Some(score), moves);
+        }
+        let sprt = match args.option("--sprt") {
+            Some(text) => match text.split_once(',').map(|(elo0, elo1)| (elo0.parse(), elo1.parse())) {
+                Some((Ok(elo0), Ok(elo1))) => Some(Sprt::new(elo0, elo1)),
+                _ => return Err(format!("Invalid value for --sprt: {}", text)),
+            },
+            None => None,
+        };
+        let openings = args.option("--openings").map(String::from);
+        let pgn = args.option("--pgn").map(String::from);
+        Ok((configs, games, time_control, adjudication, sprt, openings, pgn))
+    });
+    let (configs, games, time_control, adjudication, sprt, openings, pgn) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => return usage_error(&message, USAGE),
+    };
+    let openings = match openings.map(tournament::load_openings).transpose() {
+        Ok(openings) => openings.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1
+        }
+    };
+    let mut pgn_file = match pgn.as_ref().map(|path| OpenOptions::new().create(true).append(true).open(path)).transpose() {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Could not open {}: {}", pgn.unwrap(), e);
+            return 1
+        }
     };
+    let mut engines = vec![];
+    for config in &configs {
+        match Engine::start(config) {
+            Ok(engine) => engines.push(engine),
+            Err(e) => {
+                eprintln!("Could not start {}: {}", config.command, e);
+                return 1
+            }
+        }
+    }
+    let engines: [Engine; 2] = engines.try_into().ok().unwrap();
+    let mut runner = Match::new(engines, games).with_openings(openings).with_time_control(time_control).with_adjudication(adjudication);
+    if let Some(sprt) = sprt {
+        runner = runner.with_sprt(sprt);
+    }
+    let [first, second] = runner.engine_names().map(String::from);
+    let mut write_error = None;
+    let result = runner.run(|game, tally| {
+        let (white, black) = if game.white == 0 { (&first, &second) } else { (&second, &first) };
+        println!("Game {}: {} vs {}: {} {{{}}}", game.round, white, black, game.result, game.reason);
+        println!("Score of {} vs {}: {} {}", first, second, tally, tally.games());
+        if let Some(file) = &mut pgn_file {
+            if let Err(e) = writeln!(file, "{}", game.pgn) {
+                write_error.get_or_insert(e);
+            }
+        }
+    });
+    let tally = match result {
+        Ok(tally) => tally,
+        Err(e) => {
+            eprintln!("The match stopped: {}", e);
+            return 1
+        }
+    };
+    let (elo, margin) = tally.elo();
+    println!("Elo difference: {:.1} +/- {:.1}", elo, margin);
+    if let Some(sprt) = sprt {
+        let (lower, upper) = sprt.bounds();
+        let decision = match sprt.decision(&tally) {
+            SprtDecision::AcceptH0 => "H0 accepted",
+            SprtDecision::AcceptH1 => "H1 accepted",
+            SprtDecision::Continue => "no decision",
+        };
+        println!(
+            "SPRT: llr {:.2} ({:.2}, {:.2}), elo0 {} elo1 {}: {}",
+            sprt.llr(&tally),
+            lower,
+            upper,
+            sprt.elo0,
+            sprt.elo1,
+            decision
+        );
+    }
+    if let Some(e) = write_error {
+        eprintln!("Could not write {}: {}", pgn.unwrap(), e);
+        return 1
+    }
+    0
+}
+
+/// `tune DATA [--out FILE] [--epochs N] [--rate R] [--k K] [--threads N]`
+/// Texel-tunes piece-square tables to the results in DATA, a file of FENs or EPD records with
+/// their game results, and saves them as a network which replaces the embedded one when given to
+/// the `EvalFile` option or `eval --net`.
+/// `K` is fitted to the material values unless given.
+fn tune_command(args: &[String]) -> i32 {
+    const USAGE: &str = "tune DATA [--out FILE] [--epochs N] [--rate R] [--k K] [--threads N]";
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let parsed = CommandArgs::parse(args, &["--out", "--epochs", "--rate", "--k", "--threads"], &[]).and_then(|args| {
+        let [data] = args.positional.as_slice() else { return Err("Expected a data file".to_string()) };
+        let out = args.option("--out").unwrap_or("tuned.nnue").to_string();
+        let epochs = args.parsed_option("--epochs", tools::DEFAULT_EPOCHS)?;
+        let rate = args.parsed_option("--rate", tools::DEFAULT_LEARNING_RATE)?;
+        let k = match args.option("--k") {
+            Some(_) => Some(args.parsed_option("--k", 0.0)?),
+            None => None,
+        };
+        let threads = args.parsed_option("--threads", cores)?.max(1);
+        Ok((data.clone(), out, epochs, rate, k, threads))
+    });
+    let (data, out, epochs, rate, k, threads) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => return usage_error(&message, USAGE),
+    };
+    let dataset = match tools::Dataset::load(&data, threads) {
+        Ok(dataset) => dataset,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1
+        }
+    };
+    if dataset.is_empty() {
+        eprintln!("No positions to tune on in {}", data);
+        return 1
+    }
+    println!("{} positions, {} skipped for being in check or over", dataset.len(), dataset.skipped());
+    let params = tools::EvalParams::default();
+    let k = k.unwrap_or_else(|| tools::fit_k(&dataset, &params, threads));
+    println!("K {:.3}, error {:.6}", k, tools::mean_error(&dataset, &params, k, threads));
+    let tuner = tools::Tuner::new(k, threads).with_epochs(epochs).with_learning_rate(rate);
+    let params = tuner.tune(&dataset, params, |epoch, params| {
+        if epoch % 50 == 0 || epoch == epochs {
+            println!("epoch {}: error {:.6}", epoch, tools::mean_error(&dataset, params, k, threads));
+        }
+    });
+    if let Err(e) = params.to_network().save(&out) {
+        eprintln!("Could not write {}: {}", out, e);
+        return 1
+    }
+    println!("Saved {}", out);
+    0
+}
+
+/// `solve [--nodes N] [--side white|black] FEN...`
+/// Tries to prove a forced mate for a side (by default the side to move) with the proof-number
+/// solver, and prints the main line.
+fn solve_command(args: &[String]) -> i32 {
+    const USAGE: &str = "solve [--nodes N] [--side white|black] FEN...";
+    let parsed = CommandArgs::parse(args, &["--nodes", "--side"], &[]).and_then(|args| {
+        let budget = args.parsed_option("--nodes", Solver::DEFAULT_NODE_BUDGET)?;
+        let side = match args.option("--side") {
+            None => None,
+            Some("white") => Some(Color::White),
+            Some("black") => Some(Color::Black),
+            Some(side) => return Err(format!("Invalid side: {}", side)),
+        };
+        if args.positional.is_empty() {
+            return Err("Expected a FEN".to_string())
+        }
+        let board = Board::new(args.positional.join(" ")).map_err(|e| format!("Invalid fen: {}", e))?;
+        Ok((board, side, budget))
+    });
+    let (board, side, budget) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => return usage_error(&message, USAGE),
+    };
+    let attacker = side.unwrap_or(board.side_to_move);
+    let result = Solver::new(board, attacker, budget).solve();
+    println!("Forced mate by {}: {}", attacker, result.outcome);
+    println!("Nodes: {}", result.nodes);
+    if !result.main_line.is_empty() {
+        let line: Vec<String> = result.main_line.iter().map(|mv| mv.to_string()).collect();
+        println!("Main line: {}", line.join(" "));
+    }
+    0
+}
+
+/// `book [--plies N] [--min-games N] OUT.bin GAMES.pgn...`
+/// Builds a Polyglot book from the first `N` plies of the games in the PGN files, keeping the
+/// moves played in at least `--min-games` games.
+fn book_command(args: &[String]) -> i32 {
+    const USAGE: &str = "book [--plies N] [--min-games N] OUT.bin GAMES.pgn...";
+    let parsed = CommandArgs::parse(args, &["--plies", "--min-games"], &[]).and_then(|args| {
+        let max_ply = args.parsed_option("--plies", book::DEFAULT_MAX_PLY)?;
+        let min_games = args.parsed_option("--min-games", book::DEFAULT_MIN_GAMES)?;
+        let [out, pgn_files @ ..] = args.positional.as_slice() else { return Err("Expected a book file".to_string()) };
+        Ok((max_ply, min_games, out.clone(), pgn_files.to_vec()))
+    });
+    let (max_ply, min_games, out, pgn_files) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => return usage_error(&message, USAGE),
+    };
+    let mut builder = book::BookBuilder::new(max_ply, min_games);
+    for path in &pgn_files {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Could not read {}: {}", path, e);
+                return 1
+            }
+        };
+        for game in parse_pgn(&text) {
+            if !builder.add_game(&game) {
+                eprintln!("Skipping a game with an invalid FEN tag in {}", path);
+            }
+        }
+    }
+    let book = builder.build();
+    if let Err(e) = book.save(&out) {
+        eprintln!("Could not write {}: {}", out, e);
+        return 1
+    }
+    println!("{} games, {} book entries written to {}", builder.games(), book.len(), out);
+    0
+}
+
+/// `tbgen [--out DIR] [ENDING...]`
+/// Generates distance-to-mate tables by retrograde analysis, by default every ending we can. The
+/// tables already in `DIR` are used for captures and promotions into their endings.
+fn tbgen_command(args: &[String]) -> i32 {
+    const USAGE: &str = "tbgen [--out DIR] [ENDING...]";
+    let args = match CommandArgs::parse(args, &["--out"], &[]) {
+        Ok(args) => args,
+        Err(e) => return usage_error(&e, USAGE),
+    };
+    let dir = std::path::PathBuf::from(args.option("--out").unwrap_or("."));
+    let names: Vec<&str> = match args.positional.as_slice() {
+        [] => tablebase::ENDINGS.to_vec(),
+        names => names.iter().map(String::as_str).collect(),
+    };
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        eprintln!("Could not create {}: {}", dir.display(), e);
+        return 1
+    }
+    let mut tables = tablebase::DtmTables::open(&dir).unwrap_or_default();
+    for name in names {
+        let start = std::time::Instant::now();
+        let table = match tablebase::generate(name, &tables) {
+            Ok(table) => table,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1
+            }
+        };
+        let path = dir.join(format!("{}.dtm", name));
+        if let Err(e) = table.save(&path) {
+            eprintln!("Could not write {}: {}", path.display(), e);
+            return 1
+        }
+        let (wins, losses, draws, longest) = table.stats();
+        println!(
+            "{}: {} wins, {} losses, {} draws, longest mate {} plies ({:.1}s)",
+            table.name(),
+            wins,
+            losses,
+            draws,
+            longest,
+            start.elapsed().as_secs_f64()
+        );
+        tables.insert(table);
+    }
+    0
+}
+
+/// `openings [--start standard|chess960|dfrc] [--count N] [--plies K] [--depth D] [--max-score CP]
+/// [--seed S]`
+/// Prints positions to start games from as EPD, one per line: `K` random moves are played from
+/// a start position, and positions a search of depth `D` scores beyond `CP` are skipped.
+fn openings_command(args: &[String]) -> i32 {
+    const USAGE: &str = "openings [--start standard|chess960|dfrc] [--count N] [--plies K] [--depth D] [--max-score CP] [--seed S]";
+    let options = ["--start", "--count", "--plies", "--depth", "--max-score", "--seed"];
+    let parsed = CommandArgs::parse(args, &options, &[]).and_then(|args| {
+        args.no_positional()?;
+        let start = match args.option("--start") {
+            Some(kind) => kind.try_into().map_err(|_| format!("Unknown start positions: {} (standard, chess960 or dfrc)", kind))?,
+            None => tools::StartPositions::Standard,
+        };
+        let count = args.parsed_option("--count", 1)?;
+        let plies = args.parsed_option("--plies", tools::DEFAULT_OPENING_PLIES)?;
+        let depth = args.parsed_option("--depth", tools::DEFAULT_OPENING_DEPTH)?;
+        let max_score = args.parsed_option("--max-score", tools::DEFAULT_MAX_SCORE)?;
+        let seed = args.parsed_option("--seed", 0x9E37_79B9_7F4A_7C15)?;
+        Ok((start, count, plies, depth, max_score, seed))
+    });
+    let (start, count, plies, depth, max_score, seed) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => return usage_error(&message, USAGE),
+    };
+    let mut generator = tools::OpeningGenerator::new(start, seed)
+        .with_plies(plies)
+        .with_depth(depth)
+        .with_max_score(max_score);
+    for _ in 0..count {
+        match generator.next_opening() {
+            Some(opening) => println!("{}", opening.to_epd()),
+            None => {
+                eprintln!("Could not find an open position {} plies in", plies);
+                return 1
+            }
+        }
+    }
+    0
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("uci") => {
+            UciEngine::new(Output::stdout()).run(stdin().lock());
+            exit(0)
+        }
+        Some("xboard") => {
+            XBoardEngine::new(Output::stdout()).run(stdin().lock());
+            exit(0)
+        }
+        Some("perft") => exit(perft_command(&args[1..])),
+        Some("bench") => exit(bench_command(&args[1..])),
+        Some("eval") => exit(eval_command(&args[1..])),
+        Some("legal") => exit(legal_command(&args[1..])),
+        Some("analyze") => exit(analyze_command(&args[1..])),
+        Some("play") => exit(play_command(&args[1..])),
+        Some("diagram") => exit(diagram_command(&args[1..])),
+        Some("match") => exit(match_command(&args[1..])),
+        Some("random-mover") => {
+            // a different game each time, from the clock
+            let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |elapsed| elapsed.as_nanos() as u64);
+            let lines = stdin().lock().lines().map_while(Result::ok);
+            match RandomMover::new(seed).run(lines, stdout()) {
+                Ok(()) => exit(0),
+                Err(_) => exit(1),
+            }
+        }
+        Some("tune") => exit(tune_command(&args[1..])),
+        Some("solve") => exit(solve_command(&args[1..])),
+        Some("book") => exit(book_command(&args[1..])),
+        Some("tbgen") => exit(tbgen_command(&args[1..])),
+        Some("openings") => exit(openings_command(&args[1..])),
+        Some("chess960") => {
+            // prints the FEN of a Chess960 starting position by its number
+            match args.get(1).and_then(|index| index.parse::<usize>().ok()).filter(|&index| index < 960) {
+                Some(index) => println!("{}", chess960_fen(index)),
+                None => {
+                    eprintln!("Usage: chess960 INDEX (0 to 959)");
+                    exit(EXIT_USAGE)
+                }
+            }
+            exit(0)
+        }
+        Some("nnue-material") => {
+            // writes the material-only network that ships as nets/default.nnue
+            let path = args.get(1).map(String::as_str).unwrap_or("nets/default.nnue");
+            if let Err(e) = Network::material().save(path) {
+                eprintln!("{}", e);
+                exit(1)
+            }
+            exit(0)
+        }
+        Some("help" | "--help" | "-h") => {
+            println!("{}", USAGE);
+            exit(0)
+        }
+        Some(command) => {
+            eprintln!("Unknown command: {}", command);
+            eprintln!("{}", USAGE);
+            exit(EXIT_USAGE)
+        }
+        None => {
+            // GUIs announce the protocol with their first command
+            let mut input = stdin().lock();
+            let mut first = String::new();
+            if input.read_line(&mut first).unwrap_or(0) == 0 {
+                exit(0)
+            }
+            if first.trim() == "xboard" {
+                XBoardEngine::new(Output::stdout()).run(input);
+            } else {
+                let mut engine = UciEngine::new(Output::stdout());
+                if engine.handle(&first) {
+                    engine.run(input);
+                }
+            }
+            exit(0)
+        }
+    }
 }
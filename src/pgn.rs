@@ -0,0 +1,274 @@
+//! Reading and writing games in PGN (portable game notation).
+//!
+//! Only what is needed to replay the main line of a game is kept: the tag pairs and the moves in
+//! standard algebraic notation. Comments, variations, move numbers and annotation glyphs are
+//! skipped.
+
+use std::fmt;
+
+use crate::board::{Board, START_FEN};
+use crate::types::{Color, Move};
+use crate::uci::parse_san;
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PgnGame {
+    /// The tag pairs, e.g. `("White", "Carlsen, Magnus")`
+    pub tags: Vec<(String, String)>,
+    /// The moves of the main line in standard algebraic notation
+    pub moves: Vec<String>,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from: the `FEN` tag if there is one, the standard starting
+    /// position otherwise. None if the `FEN` tag is invalid.
+    pub fn start_board(&self) -> Option<Board> {
+        let mut board = self.tag("FEN").unwrap_or(START_FEN).parse::<Board>().ok()?;
+        board.analyze_board();
+        Some(board)
+    }
+
+    /// The starting position and the moves of the game, up to the first one which is not legal
+    pub fn replay(&self) -> Option<(Board, Vec<Move>)> {
+        let start = self.start_board()?;
+        let mut board = start.clone();
+        let mut moves = vec![];
+        for san in &self.moves {
+            let Some(mv) = parse_san(&board, san) else { break };
+            board.make_move(mv);
+            moves.push(mv);
+        }
+        Some((start, moves))
+    }
+}
+
+/// Longest line of movetext we write
+const LINE_LENGTH: usize = 80;
+
+impl fmt::Display for PgnGame {
+    /// Writes the game as PGN: the tag pairs, then the numbered moves, wrapped at 80 columns and
+    /// followed by the `Result` tag (or `*`)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(f, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        writeln!(f)?;
+        let (mut number, mut white) = match self.start_board() {
+            Some(board) => (board.fullmove_number(), board.side_to_move == Color::White),
+            None => (1, true),
+        };
+        let mut tokens = vec![];
+        for (i, san) in self.moves.iter().enumerate() {
+            if white {
+                tokens.push(format!("{}.", number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", number));
+            }
+            tokens.push(san.clone());
+            if !white {
+                number += 1;
+            }
+            white = !white;
+        }
+        tokens.push(self.tag("Result").unwrap_or("*").to_string());
+        let mut length = 0;
+        for token in tokens {
+            if length > 0 && length + 1 + token.len() > LINE_LENGTH {
+                writeln!(f)?;
+                length = 0;
+            } else if length > 0 {
+                write!(f, " ")?;
+                length += 1;
+            }
+            write!(f, "{}", token)?;
+            length += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+/// Splits a PGN file into its games
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut reader = Reader::default();
+    for line in text.lines() {
+        reader.line(line);
+    }
+    reader.finish_game();
+    reader.games
+}
+
+#[derive(Default)]
+struct Reader {
+    games: Vec<PgnGame>,
+    game: PgnGame,
+    /// Inside a `{...}` comment, which may span lines
+    in_comment: bool,
+    /// How deeply nested in `(...)` variations we are
+    variation: usize,
+    token: String,
+}
+
+impl Reader {
+    fn line(&mut self, line: &str) {
+        let trimmed = line.trim();
+        if !self.in_comment && self.variation == 0 {
+            // lines starting with `%` are escaped
+            if trimmed.starts_with('%') {
+                return
+            }
+            if trimmed.starts_with('[') {
+                // a tag after the moves belongs to the next game
+                if !self.game.moves.is_empty() {
+                    self.finish_game();
+                }
+                if let Some(tag) = parse_tag(trimmed) {
+                    self.game.tags.push(tag);
+                }
+                return
+            }
+        }
+        for c in line.chars() {
+            if self.in_comment {
+                self.in_comment = c != '}';
+                continue
+            }
+            match c {
+                '{' => {
+                    self.end_token();
+                    self.in_comment = true;
+                }
+                // the rest of the line is a comment
+                ';' => break,
+                '(' => {
+                    self.end_token();
+                    self.variation += 1;
+                }
+                ')' => {
+                    self.end_token();
+                    self.variation = self.variation.saturating_sub(1);
+                }
+                c if c.is_whitespace() => self.end_token(),
+                c => self.token.push(c),
+            }
+        }
+        self.end_token();
+    }
+
+    fn end_token(&mut self) {
+        let token = std::mem::take(&mut self.token);
+        if token.is_empty() || self.variation > 0 || token.starts_with('$') {
+            return
+        }
+        if RESULTS.contains(&token.as_str()) {
+            self.finish_game();
+            return
+        }
+        // move numbers, `12.` or `12...`, may be glued to the move
+        let mv = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if !mv.is_empty() {
+            self.game.moves.push(mv.to_string());
+        }
+    }
+
+    fn finish_game(&mut self) {
+        let game = std::mem::take(&mut self.game);
+        if !game.tags.is_empty() || !game.moves.is_empty() {
+            self.games.push(game);
+        }
+        self.variation = 0;
+    }
+}
+
+/// `[Name "Value"]`
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.trim_end().strip_suffix(']')?;
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: &str = r#"[Event "Casual"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {the knight comes out; a comment} Nc6 (2... d6 3. d4) 3. Bb5 $1 a6
+4. Ba4 Nf6 5. O-O Be7 ; the closed Ruy Lopez
+6. Re1 b5 7. Bb3 d6 8. c3 O-O 1-0
+
+[Event "Second"]
+[FEN "4k3/P7/8/8/8/8/8/4K3 w - - 0 1"]
+
+1. a8=Q+ Kd7 2. Qb7+ *
+"#;
+
+    #[test]
+    fn reads_games() {
+        let games = parse_pgn(GAMES);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert_eq!(games[0].moves.len(), 16);
+        assert_eq!(games[0].moves[2..4], ["Nf3", "Nc6"]);
+        let (_, moves) = games[0].replay().unwrap();
+        assert_eq!(moves.len(), 16);
+        assert_eq!(moves[8].to_string(), "e1g1");
+
+        let (start, moves) = games[1].replay().unwrap();
+        assert!(start.piece_on(crate::types::Square::A7) == crate::types::Piece::Pawn);
+        assert_eq!(moves.iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), ["a7a8q", "e8d7", "a8b7"]);
+    }
+
+    #[test]
+    fn writes_games() {
+        let games = parse_pgn(GAMES);
+        assert_eq!(parse_pgn(&games.iter().map(|game| game.to_string()).collect::<String>()), games);
+        assert!(games[0].to_string().contains("\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6"));
+        assert!(games[0].to_string().ends_with("O-O 1-0\n"));
+        let game = PgnGame {
+            tags: vec![("FEN".to_string(), "4k3/8/8/8/8/8/8/R3K3 b - - 0 40".to_string())],
+            moves: vec!["Kd7".to_string(), "Ra7+".to_string()],
+        };
+        assert!(game.to_string().ends_with("\n\n40... Kd7 41. Ra7+ *\n"));
+    }
+
+    #[test]
+    fn san_disambiguation() {
+        let board = Board::new("4k3/8/8/8/8/8/4K3/R6R w - - 0 1".to_string()).unwrap();
+        // both rooks reach d1
+        assert_eq!(parse_san(&board, "Rd1"), None);
+        assert_eq!(parse_san(&board, "Rad1").unwrap().to_string(), "a1d1");
+        assert_eq!(parse_san(&board, "Rhd1").unwrap().to_string(), "h1d1");
+        assert_eq!(parse_san(&board, "Rh1d1").unwrap().to_string(), "h1d1");
+        assert_eq!(parse_san(&board, "Ra8+").unwrap().to_string(), "a1a8");
+        assert_eq!(parse_san(&board, "Kf1").unwrap().to_string(), "e2f1");
+        assert_eq!(parse_san(&board, "Kf4"), None);
+    }
+
+    #[test]
+    fn san_writing() {
+        use crate::uci::{line_to_san, move_to_san, parse_uci_move};
+
+        let board = Board::new("6k1/5ppp/8/8/8/8/5PPP/R3K2R w KQ - 0 30".to_string()).unwrap();
+        assert_eq!(move_to_san(&board, parse_uci_move(&board, "a1a8").unwrap()).as_deref(), Some("Ra8#"));
+        assert_eq!(move_to_san(&board, parse_san(&board, "O-O").unwrap()).as_deref(), Some("O-O"));
+        let mut line = vec![parse_san(&board, "Rd1").unwrap()];
+        let mut after = board.clone();
+        after.make_move(line[0]);
+        line.push(parse_san(&after, "h6").unwrap());
+        after.make_move(line[1]);
+        line.push(parse_san(&after, "Rd8+").unwrap());
+        assert_eq!(line_to_san(&board, &line), "30. Rd1 h6 31. Rd8+");
+        assert_eq!(line_to_san(&after, &line[2..]), "31. Rd8+");
+        after.undo_move();
+        assert_eq!(line_to_san(&after, &line[1..]), "30... h6 31. Rd8+");
+    }
+}
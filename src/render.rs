@@ -0,0 +1,303 @@
+//! Drawing boards and bitboards as text, for terminals and for debugging.
+//!
+//! Each square is three characters wide. Without colours, highlighted squares are marked with
+//! brackets around the piece: `[N]` for the squares of the last move, `{N}` for the selected
+//! piece, `(n)` for a piece it can take, `<K>` for a king in check, and empty squares the selected
+//! piece can reach, or which are in an overlay, show a `*`. With ANSI colours, they are drawn in
+//! their own background colour instead. [`svg`] draws the same diagrams as images.
+
+use crate::board::Board;
+use crate::types::{Bitboard, Color, Move, Piece, Square};
+
+pub mod svg;
+
+/// How a square stands out, from the weakest to the strongest: a square with several marks
+/// shows the strongest one
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Mark {
+    None,
+    LastMove,
+    Overlay,
+    Destination,
+    Selected,
+    Check,
+}
+
+impl Mark {
+    /// The 256-colour backgrounds of a light and a dark square with this mark
+    const fn backgrounds(self) -> (u8, u8) {
+        match self {
+            Mark::None => (223, 137),
+            Mark::LastMove => (187, 143),
+            Mark::Overlay => (153, 67),
+            Mark::Destination => (151, 107),
+            Mark::Selected => (117, 74),
+            Mark::Check => (210, 160),
+        }
+    }
+
+    /// The brackets around the piece when there are no colours
+    const fn brackets(self) -> (char, char) {
+        match self {
+            Mark::None | Mark::Overlay => (' ', ' '),
+            Mark::LastMove => ('[', ']'),
+            Mark::Destination => ('(', ')'),
+            Mark::Selected => ('{', '}'),
+            Mark::Check => ('<', '>'),
+        }
+    }
+}
+
+/// 256-colour foregrounds of the white and black pieces
+const PIECE_COLORS: [u8; Color::NUM] = [231, 16];
+
+/// Draws boards as text. The default is plain ASCII from White's side, with coordinates and the
+/// king in check marked.
+#[derive(Clone)]
+pub struct BoardRenderer {
+    unicode: bool,
+    colors: bool,
+    coordinates: bool,
+    orientation: Color,
+    show_check: bool,
+    last_move: Option<Move>,
+    selected: Option<Square>,
+    overlay: Bitboard,
+}
+
+impl Default for BoardRenderer {
+    fn default() -> Self {
+        Self {
+            unicode: false,
+            colors: false,
+            coordinates: true,
+            orientation: Color::White,
+            show_check: true,
+            last_move: None,
+            selected: None,
+            overlay: Bitboard::default(),
+        }
+    }
+}
+
+impl BoardRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Draws the pieces as chess glyphs (`♞`) rather than letters
+    pub fn with_unicode(mut self, unicode: bool) -> Self {
+        self.unicode = unicode;
+        self
+    }
+
+    /// Colours the squares and pieces with ANSI escape codes
+    pub fn with_colors(mut self, colors: bool) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Labels the ranks and files
+    pub fn with_coordinates(mut self, coordinates: bool) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+
+    /// Draws the board from the side of `color`, which has its first rank at the bottom
+    pub fn with_orientation(mut self, color: Color) -> Self {
+        self.orientation = color;
+        self
+    }
+
+    /// Marks the king of the side to move when it is in check
+    pub fn with_check(mut self, show_check: bool) -> Self {
+        self.show_check = show_check;
+        self
+    }
+
+    /// Marks the squares a move came from and went to
+    pub fn with_last_move(mut self, mv: Option<Move>) -> Self {
+        self.last_move = mv;
+        self
+    }
+
+    /// Marks a square and the squares its piece can legally move to
+    pub fn with_selected(mut self, square: Option<Square>) -> Self {
+        self.selected = square;
+        self
+    }
+
+    /// Marks a set of squares, such as the squares which block a check or a pin ray
+    pub fn with_overlay(mut self, overlay: Bitboard) -> Self {
+        self.overlay = overlay;
+        self
+    }
+
+    /// Draws a board, with the marks that apply to it
+    pub fn render(&self, board: &Board) -> String {
+        let mut marks = [Mark::None; Square::NUM];
+        let mut mark = |squares: Bitboard, mark: Mark| {
+            for square in squares {
+                marks[square] = marks[square].max(mark);
+            }
+        };
+        if let Some(mv) = self.last_move {
+            let start = if mv.is_drop() { Bitboard::default() } else { Bitboard::from(mv.start()) };
+            mark(start | Bitboard::from(mv.target()), Mark::LastMove);
+        }
+        mark(self.overlay, Mark::Overlay);
+        if let Some(selected) = self.selected {
+            let destinations = board.legal_moves().as_slice()
+                .iter()
+                .filter(|mv| !mv.is_drop() && mv.start() == selected)
+                .fold(Bitboard::default(), |squares, mv| squares | Bitboard::from(mv.target()));
+            mark(destinations, Mark::Destination);
+            mark(Bitboard::from(selected), Mark::Selected);
+        }
+        if self.show_check && board.is_check() {
+            mark(board.pieces_of(Piece::King, board.side_to_move), Mark::Check);
+        }
+        self.grid(|square| {
+            let symbol = match board.color_on(square) {
+                Some(color) => self.piece_symbol(board.piece_on(square), color),
+                None => self.empty_symbol(marks[square]),
+            };
+            (symbol, board.color_on(square), marks[square])
+        })
+    }
+
+    /// Draws the squares of a bitboard, as a debugging aid
+    pub fn render_bitboard(&self, bitboard: Bitboard) -> String {
+        self.grid(|square| {
+            let set = !(bitboard & Bitboard::from(square)).is_empty();
+            let mark = if set { Mark::Overlay } else { Mark::None };
+            let symbol = match (set, self.unicode) {
+                (true, true) => '●',
+                (true, false) => 'X',
+                (false, _) => self.empty_symbol(Mark::None),
+            };
+            (symbol, None, mark)
+        })
+    }
+
+    /// Lays out the squares, given the symbol of each one, the colour of the piece on it and its
+    /// mark
+    fn grid(&self, square: impl Fn(Square) -> (char, Option<Color>, Mark)) -> String {
+        let flipped = self.orientation == Color::Black;
+        let ranks: Vec<u8> = if flipped { (0..8).collect() } else { (0..8).rev().collect() };
+        let files: Vec<u8> = if flipped { (0..8).rev().collect() } else { (0..8).collect() };
+        let mut lines = vec![];
+        for &rank in &ranks {
+            let mut line = String::new();
+            if self.coordinates {
+                line.push_str(&format!("{} ", rank + 1));
+            }
+            for &file in &files {
+                let (symbol, color, mark) = square(Square::from_rank_file(rank, file));
+                if self.colors {
+                    let (light, dark) = mark.backgrounds();
+                    let background = if (rank + file) % 2 == 1 { light } else { dark };
+                    let foreground = PIECE_COLORS[color.unwrap_or(Color::Black)];
+                    line.push_str(&format!("\x1b[48;5;{};38;5;{}m {} \x1b[0m", background, foreground, symbol));
+                } else {
+                    // an empty square the selected piece can reach already shows a `*`
+                    let (left, right) = match (mark, color) {
+                        (Mark::Destination, None) => (' ', ' '),
+                        _ => mark.brackets(),
+                    };
+                    line.extend([left, symbol, right]);
+                }
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        if self.coordinates {
+            let labels: String = files.iter().map(|&file| format!(" {} ", (b'a' + file) as char)).collect();
+            lines.push(format!("  {}", labels.trim_end()));
+        }
+        lines.join("\n")
+    }
+
+    fn piece_symbol(&self, piece: Piece, color: Color) -> char {
+        const LETTERS: [char; 6] = ['p', 'n', 'b', 'r', 'q', 'k'];
+        const WHITE_GLYPHS: [char; 6] = ['♙', '♘', '♗', '♖', '♕', '♔'];
+        const BLACK_GLYPHS: [char; 6] = ['♟', '♞', '♝', '♜', '♛', '♚'];
+        let index = match piece {
+            Piece::Pawn => 0,
+            Piece::Knight => 1,
+            Piece::Bishop => 2,
+            Piece::Rook => 3,
+            Piece::Queen => 4,
+            Piece::King => 5,
+            Piece::None => return '?',
+        };
+        match (self.unicode, color) {
+            // the solid glyphs read best on coloured squares, where the colour tells the sides
+            // apart
+            (true, _) if self.colors => BLACK_GLYPHS[index],
+            (true, Color::White) => WHITE_GLYPHS[index],
+            (true, Color::Black) => BLACK_GLYPHS[index],
+            (false, Color::White) => LETTERS[index].to_ascii_uppercase(),
+            (false, Color::Black) => LETTERS[index],
+        }
+    }
+
+    fn empty_symbol(&self, mark: Mark) -> char {
+        match mark {
+            Mark::Destination | Mark::Overlay if !self.colors => '*',
+            _ if self.colors => ' ',
+            _ if self.unicode => '·',
+            _ => '.',
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::START_FEN;
+    use crate::uci::parse_uci_move;
+
+    #[test]
+    fn plain_boards() {
+        let mut board = Board::new(START_FEN.to_string()).unwrap();
+        let start = BoardRenderer::new().render(&board);
+        assert_eq!(start.lines().next(), Some("8  r  n  b  q  k  b  n  r"));
+        assert_eq!(start.lines().nth(5), Some("3  .  .  .  .  .  .  .  ."));
+        assert_eq!(start.lines().last(), Some("   a  b  c  d  e  f  g  h"));
+        let black = BoardRenderer::new().with_orientation(Color::Black).with_coordinates(false).render(&board);
+        assert_eq!(black.lines().next(), Some(" R  N  B  K  Q  B  N  R"));
+        assert_eq!(black.lines().count(), 8);
+
+        // the last move, the destinations of the knight on g1 and a king in check
+        let mv = parse_uci_move(&board, "e2e4").unwrap();
+        board.make_move(mv);
+        let text = BoardRenderer::new().with_last_move(Some(mv)).render(&board);
+        assert_eq!(text.lines().nth(4), Some("4  .  .  .  . [P] .  .  ."));
+        assert_eq!(text.lines().nth(6), Some("2  P  P  P  P [.] P  P  P"));
+        for uci in ["f7f6", "d1h5", "g7g6"] {
+            board.make_move(parse_uci_move(&board, uci).unwrap());
+        }
+        let text = BoardRenderer::new().with_selected(Some(Square::H5)).render(&board);
+        assert_eq!(text.lines().nth(2), Some("6  .  .  .  .  .  p (p) *"));
+        assert_eq!(text.lines().nth(3), Some("5  *  *  *  *  *  *  * {Q}"));
+        board.make_move(parse_uci_move(&board, "h5g6").unwrap());
+        let text = BoardRenderer::new().render(&board);
+        assert_eq!(text.lines().next(), Some("8  r  n  b  q <k> b  n  r"));
+    }
+
+    #[test]
+    fn overlays_and_colors() {
+        let board = Board::new("4k3/8/8/8/8/8/8/4K2R w K - 0 1".to_string()).unwrap();
+        let overlay = Bitboard::from(Square::F1) | Bitboard::from(Square::G1);
+        let text = BoardRenderer::new().with_overlay(overlay).render(&board);
+        assert_eq!(text.lines().nth(7), Some("1  .  .  .  .  K  *  *  R"));
+        let unicode = BoardRenderer::new().with_unicode(true).render(&board);
+        assert_eq!(unicode.lines().nth(7), Some("1  ·  ·  ·  ·  ♔  ·  ·  ♖"));
+        let bitboard = BoardRenderer::new().with_coordinates(false).render_bitboard(overlay);
+        assert_eq!(bitboard.lines().nth(7), Some(" .  .  .  .  .  X  X  ."));
+        let colored = BoardRenderer::new().with_colors(true).with_unicode(true).with_overlay(overlay).render(&board);
+        // h1 is a light square, f1 a light one in the overlay and g1 a dark one in the overlay
+        assert!(colored.contains("\x1b[48;5;223;38;5;231m ♜ \x1b[0m"));
+        assert!(colored.contains("\x1b[48;5;153;38;5;16m   \x1b[0m\x1b[48;5;67;38;5;16m   \x1b[0m"));
+    }
+}
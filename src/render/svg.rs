@@ -0,0 +1,303 @@
+//! Board diagrams as SVG images, for reports and puzzle sheets.
+//!
+//! The pieces are drawn as chess glyphs in a `<text>` element, so the image needs nothing but a
+//! font with the chess symbols, which every common one has.
+
+use std::fmt::Write;
+
+use crate::board::Board;
+use crate::types::{Bitboard, Color, Move, Piece, Square};
+
+const LIGHT_SQUARE: &str = "#f0d9b5";
+const DARK_SQUARE: &str = "#b58863";
+const LAST_MOVE: &str = "#cdd26a";
+const HIGHLIGHT: &str = "#5b9bd5";
+const CHECK: &str = "#e04040";
+const ARROW: &str = "#15781b";
+
+/// Width and height of the image when no size is given, in pixels
+pub const DEFAULT_SIZE: u32 = 400;
+
+/// Draws boards as SVG. The default is a 400 pixel board from White's side, with coordinates.
+#[derive(Clone)]
+pub struct SvgRenderer {
+    size: u32,
+    orientation: Color,
+    coordinates: bool,
+    last_move: Option<Move>,
+    highlights: Bitboard,
+    arrows: Vec<(Square, Square)>,
+}
+
+impl Default for SvgRenderer {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_SIZE,
+            orientation: Color::White,
+            coordinates: true,
+            last_move: None,
+            highlights: Bitboard::default(),
+            arrows: vec![],
+        }
+    }
+}
+
+impl SvgRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the width and height of the image, in pixels
+    pub fn with_size(mut self, size: u32) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Draws the board from the side of `color`, which has its first rank at the bottom
+    pub fn with_orientation(mut self, color: Color) -> Self {
+        self.orientation = color;
+        self
+    }
+
+    /// Labels the files along the bottom edge and the ranks along the left edge
+    pub fn with_coordinates(mut self, coordinates: bool) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+
+    /// Tints the squares a move came from and went to
+    pub fn with_last_move(mut self, mv: Option<Move>) -> Self {
+        self.last_move = mv;
+        self
+    }
+
+    /// Tints a set of squares
+    pub fn with_highlights(mut self, squares: Bitboard) -> Self {
+        self.highlights = squares;
+        self
+    }
+
+    /// Draws an arrow from one square to another, over the pieces
+    pub fn with_arrow(mut self, from: Square, to: Square) -> Self {
+        self.arrows.push((from, to));
+        self
+    }
+
+    /// Draws a board, with a king in check tinted red
+    pub fn render(&self, board: &Board) -> String {
+        let check = if board.is_check() { board.pieces_of(Piece::King, board.side_to_move) } else { Bitboard::default() };
+        let pieces = (0..Square::NUM as u8)
+            .map(Square::new)
+            .filter_map(|square| board.color_on(square).map(|color| (square, board.piece_on(square), color)))
+            .collect::<Vec<_>>();
+        self.draw(&pieces, check)
+    }
+
+    /// Draws the squares of a bitboard, tinted, on an empty board
+    pub fn render_bitboard(&self, bitboard: Bitboard) -> String {
+        self.clone().with_highlights(self.highlights | bitboard).draw(&[], Bitboard::default())
+    }
+
+    /// Width of a square
+    fn square_size(&self) -> f64 {
+        self.size as f64 / 8.0
+    }
+
+    /// Top left corner of a square in the image
+    fn corner(&self, square: Square) -> (f64, f64) {
+        let (rank, file) = (square as u8 / 8, square as u8 % 8);
+        let (column, row) = match self.orientation {
+            Color::White => (file, 7 - rank),
+            Color::Black => (7 - file, rank),
+        };
+        (column as f64 * self.square_size(), row as f64 * self.square_size())
+    }
+
+    fn center(&self, square: Square) -> (f64, f64) {
+        let (x, y) = self.corner(square);
+        (x + self.square_size() / 2.0, y + self.square_size() / 2.0)
+    }
+
+    fn draw(&self, pieces: &[(Square, Piece, Color)], check: Bitboard) -> String {
+        let size = self.square_size();
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#,
+            self.size
+        );
+        let _ = writeln!(
+            svg,
+            r#"<defs><marker id="arrowhead" viewBox="0 0 10 10" refX="0" refY="5" markerWidth="2.5" markerHeight="2.5" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="{}"/></marker></defs>"#,
+            ARROW
+        );
+        let last_move = match self.last_move {
+            Some(mv) if mv.is_drop() => Bitboard::from(mv.target()),
+            Some(mv) => Bitboard::from(mv.start()) | Bitboard::from(mv.target()),
+            None => Bitboard::default(),
+        };
+        for square in (0..Square::NUM as u8).map(Square::new) {
+            let (x, y) = self.corner(square);
+            let light = (square as u8 / 8 + square as u8 % 8) % 2 == 1;
+            let fill = if light { LIGHT_SQUARE } else { DARK_SQUARE };
+            let _ = writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, num(x), num(y), num(size), num(size), fill);
+            let bit = Bitboard::from(square);
+            for (squares, tint) in [(last_move, LAST_MOVE), (self.highlights, HIGHLIGHT), (check, CHECK)] {
+                if !(squares & bit).is_empty() {
+                    let _ = writeln!(
+                        svg,
+                        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="0.6"/>"#,
+                        num(x),
+                        num(y),
+                        num(size),
+                        num(size),
+                        tint
+                    );
+                }
+            }
+        }
+        if self.coordinates {
+            self.draw_coordinates(&mut svg);
+        }
+        for &(square, piece, color) in pieces {
+            let (x, y) = self.center(square);
+            let (fill, stroke) = match color {
+                Color::White => ("#ffffff", "#000000"),
+                Color::Black => ("#000000", "#000000"),
+            };
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central" fill="{}" stroke="{}" stroke-width="{}">{}</text>"#,
+                num(x),
+                num(y),
+                num(size * 0.8),
+                fill,
+                stroke,
+                num(size / 40.0),
+                glyph(piece)
+            );
+        }
+        for &(from, to) in &self.arrows {
+            self.draw_arrow(&mut svg, from, to);
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// File letters in the bottom right corner of the bottom row, rank numbers in the top left
+    /// corner of the left column, each in the colour of the other kind of square
+    fn draw_coordinates(&self, svg: &mut String) {
+        let size = self.square_size();
+        let font = size * 0.2;
+        for i in 0..8u8 {
+            let (bottom, left) = match self.orientation {
+                Color::White => (Square::from_rank_file(0, i), Square::from_rank_file(i, 0)),
+                Color::Black => (Square::from_rank_file(7, i), Square::from_rank_file(i, 7)),
+            };
+            for (square, label, right) in [(bottom, (b'a' + i) as char, true), (left, (b'1' + i) as char, false)] {
+                let (x, y) = self.corner(square);
+                let light = (square as u8 / 8 + square as u8 % 8) % 2 == 1;
+                let fill = if light { DARK_SQUARE } else { LIGHT_SQUARE };
+                let (x, y, anchor) = if right {
+                    (x + size - font * 0.3, y + size - font * 0.3, "end")
+                } else {
+                    (x + font * 0.3, y + font, "start")
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}" font-size="{}" font-family="sans-serif" text-anchor="{}" fill="{}">{}</text>"#,
+                    num(x),
+                    num(y),
+                    num(font),
+                    anchor,
+                    fill,
+                    label
+                );
+            }
+        }
+    }
+
+    /// An arrow from the centre of `from` whose head stops just short of the centre of `to`
+    fn draw_arrow(&self, svg: &mut String, from: Square, to: Square) {
+        let size = self.square_size();
+        let width = size * 0.16;
+        let (x1, y1) = self.center(from);
+        let (x2, y2) = self.center(to);
+        let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+        if length == 0.0 {
+            return
+        }
+        // the head is 2.5 line widths long and begins where the line ends
+        let shorten = (2.5 * width + size * 0.1).min(length);
+        let (x2, y2) = (x2 - (x2 - x1) * shorten / length, y2 - (y2 - y1) * shorten / length);
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}" stroke-opacity="0.8" stroke-linecap="round" marker-end="url(#arrowhead)"/>"#,
+            num(x1),
+            num(y1),
+            num(x2),
+            num(y2),
+            ARROW,
+            num(width)
+        );
+    }
+}
+
+/// The solid glyph of a piece, filled in the colour of its side
+fn glyph(piece: Piece) -> char {
+    match piece {
+        Piece::King => '♚',
+        Piece::Queen => '♛',
+        Piece::Rook => '♜',
+        Piece::Bishop => '♝',
+        Piece::Knight => '♞',
+        Piece::Pawn => '♟',
+        Piece::None => ' ',
+    }
+}
+
+/// A coordinate rounded to a tenth of a pixel, without a trailing `.0`
+fn num(x: f64) -> String {
+    ((x * 10.0).round() / 10.0).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::START_FEN;
+
+    #[test]
+    fn diagrams() {
+        let board = Board::new(START_FEN.to_string()).unwrap();
+        let svg = SvgRenderer::new().render(&board);
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="400" height="400""#));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect").count(), 64);
+        // 32 pieces and 16 coordinates
+        assert_eq!(svg.matches("<text").count(), 48);
+        // the white king on e1, bottom of the board from White's side and top from Black's
+        assert!(svg.contains(r##"<text x="225" y="375" font-size="40" text-anchor="middle" dominant-baseline="central" fill="#ffffff""##));
+        let flipped = SvgRenderer::new().with_orientation(Color::Black).with_coordinates(false).with_size(800).render(&board);
+        assert!(flipped.contains(r##"<text x="350" y="50" font-size="80" text-anchor="middle" dominant-baseline="central" fill="#ffffff""##));
+        assert_eq!(flipped.matches("<text").count(), 32);
+    }
+
+    #[test]
+    fn marks_and_arrows() {
+        let board = Board::new("4k3/8/8/8/8/8/8/4K2R w K - 0 1".to_string()).unwrap();
+        let svg = SvgRenderer::new()
+            .with_last_move(Some(Move::new(Square::H2, Square::H1, crate::types::MoveType::Quiet)))
+            .with_highlights(Bitboard::from(Square::F1))
+            .with_arrow(Square::E1, Square::G1)
+            .with_arrow(Square::H1, Square::H8)
+            .render(&board);
+        assert_eq!(svg.matches(LAST_MOVE).count(), 2);
+        assert_eq!(svg.matches(HIGHLIGHT).count(), 1);
+        assert_eq!(svg.matches("<line").count(), 2);
+        // the arrow up the h-file starts in the middle of h1
+        assert!(svg.contains(r#"<line x1="375" y1="375" x2="375" y2="50""#));
+        let bitboard = SvgRenderer::new().render_bitboard(Bitboard::from(Square::A1) | Bitboard::from(Square::H8));
+        assert_eq!(bitboard.matches(HIGHLIGHT).count(), 2);
+        assert!(!bitboard.contains("dominant-baseline"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Forced mate search, used for `go mate N`.
+//!
+//! Unlike the main search this is an exact AND/OR search: the attacker needs one move that mates
+//! against every defence, the defender one move that escapes. Nothing is pruned or reduced, so a
+//! mate that exists within the limit is always found, and mate lengths are tried from one move
+//! upwards so the first mate found is the shortest.
+
+use std::collections::HashMap;
+
+use crate::{board::GameResult, types::Move};
+
+use super::{Search, SearchReport, SearchResult, PvLine, MATE};
+
+/// What the mate search remembers between positions
+#[derive(Default)]
+struct MateTables {
+    /// Positions (by hash, attacker to move) with no mate within the given number of moves
+    refuted: HashMap<u64, usize>,
+    /// For each number of moves left, the attacker's move which mated last. It often mates
+    /// against the other defences too.
+    killers: HashMap<usize, Move>,
+    /// The same for the defender: the move which escaped last
+    escapes: HashMap<usize, Move>,
+}
+
+impl Search {
+    /// Looks for a mate in at most `moves` moves, reporting each mate length tried that finds a
+    /// mate. Returns an empty result if there is none (or the search was stopped first).
+    pub(super) fn run_mate<F: FnMut(&SearchReport)>(&mut self, moves: usize, mut report: F) -> SearchResult {
+        let mut result = SearchResult::default();
+        let mut tables = MateTables::default();
+        for n in 1..=moves.min(super::MAX_PLY / 2) {
+            let Some(line) = self.attack(n, &mut tables) else {
+                if self.stopped {
+                    break
+                }
+                continue
+            };
+            let depth = 2 * n - 1;
+            let score = MATE - line.len() as i32;
+            report(&SearchReport {
+                depth,
+                seldepth: line.len(),
+                multipv: 1,
+                score,
+                nodes: self.nodes,
+                time: self.time.elapsed(),
+                pv: line.clone(),
+            });
+            result = SearchResult {
+                best_move: Some(line[0]),
+                score,
+                depth,
+                nodes: self.nodes,
+                pv: line.clone(),
+                lines: vec![PvLine { mv: line[0], score, pv: line }],
+            };
+            break
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    /// The side to move looks for a mate in `n` moves. Returns the mating line.
+    fn attack(&mut self, n: usize, tables: &mut MateTables) -> Option<Vec<Move>> {
+        let hash = self.board.hash();
+        if tables.refuted.get(&hash).is_some_and(|&depth| depth >= n) || self.should_stop() {
+            return None
+        }
+        let legal = *self.board.legal_moves();
+        let mut moves = Vec::with_capacity(legal.len);
+        for &mv in &legal.moves[..legal.len] {
+            self.board.make_move(mv);
+            self.nodes += 1;
+            let check = self.board.is_check();
+            if n == 1 {
+                // the last move must mate on the spot
+                let mate = matches!(self.board.outcome(), Some(GameResult::Win(color)) if color != self.board.side_to_move);
+                self.board.undo_move();
+                if mate {
+                    tables.killers.insert(n, mv);
+                    return Some(vec![mv])
+                }
+                continue
+            }
+            self.board.undo_move();
+            moves.push((mv, check));
+        }
+        // the killer, then checks, then captures: forcing moves are the most likely to mate
+        let killer = tables.killers.get(&n).copied();
+        moves.sort_by_key(|&(mv, check)| (Some(mv) != killer, !check, !mv.is_capture()));
+        for (mv, _) in moves {
+            self.board.make_move(mv);
+            let line = self.defend(n, tables);
+            self.board.undo_move();
+            if let Some(mut line) = line {
+                tables.killers.insert(n, mv);
+                line.insert(0, mv);
+                return Some(line)
+            }
+            if self.stopped {
+                return None
+            }
+        }
+        if !self.stopped {
+            tables.refuted.insert(hash, n);
+        }
+        None
+    }
+
+    /// The defender replies to the attacker's move, the attacker having `n` moves including that
+    /// one. Returns the longest mate the attacker has against every defence.
+    fn defend(&mut self, n: usize, tables: &mut MateTables) -> Option<Vec<Move>> {
+        if self.board.legal_moves().len == 0 {
+            let lost = matches!(self.board.outcome(), Some(GameResult::Win(color)) if color != self.board.side_to_move);
+            return lost.then(Vec::new)
+        }
+        if self.board.halfmove_clock() >= 100 {
+            return None
+        }
+        let mut moves = *self.board.legal_moves();
+        let moves = &mut moves.moves[..moves.len];
+        if let Some(escape) = tables.escapes.get(&n).and_then(|escape| moves.iter().position(|mv| mv == escape)) {
+            moves.swap(0, escape);
+        }
+        let mut longest: Option<Vec<Move>> = None;
+        for &mut mv in moves {
+            self.board.make_move(mv);
+            self.nodes += 1;
+            let line = self.attack(n - 1, tables);
+            self.board.undo_move();
+            let Some(mut line) = line else {
+                if !self.stopped {
+                    tables.escapes.insert(n, mv);
+                }
+                return None
+            };
+            if longest.as_ref().is_none_or(|longest| line.len() + 1 > longest.len()) {
+                line.insert(0, mv);
+                longest = Some(line);
+            }
+        }
+        longest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::board::Board;
+    use crate::search::{time::TimeManager, SearchLimits, MAX_PLY};
+
+    use super::*;
+
+    /// Searches for a mate and checks that its line is legal, and ends in checkmate if it is a mate
+    fn solve(fen: &str, moves: usize) -> SearchResult {
+        let board = Board::new(fen.to_string()).unwrap();
+        let limits = SearchLimits { mate: Some(moves), ..Default::default() };
+        let result = Search::new(board.clone(), limits, TimeManager::infinite(), Arc::default()).run(|_| {});
+        let mut board = board;
+        for &mv in &result.pv {
+            assert!(board.legal_moves().as_slice().contains(&mv), "{} is illegal", mv);
+            board.make_move(mv);
+        }
+        if result.score >= MATE - MAX_PLY as i32 {
+            assert!(board.is_checkmate());
+        }
+        result
+    }
+
+    #[test]
+    fn mate_in_two() {
+        // Nf6+ gxf6 Bxf7#
+        let result = solve("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1", 2);
+        assert_eq!(result.score, MATE - 3);
+        assert_eq!(result.best_move.unwrap().to_string(), "d5f6");
+    }
+
+    #[test]
+    fn mate_in_three() {
+        let result = solve("3q1r1k/2p4p/1p1pBrp1/p2Pp3/2PnP3/5PP1/PP1Q2K1/5R1R w - - 1 1", 3);
+        assert_eq!(result.score, MATE - 5);
+        assert_eq!(result.best_move.unwrap().to_string(), "h1h7");
+        // the king walks into the open: Bc5+ Kxc5 Qb6+ Kd5 Qd6#
+        let result = solve("r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1", 3);
+        assert_eq!(result.score, MATE - 5);
+        assert_eq!(result.best_move.unwrap().to_string(), "f8c5");
+    }
+
+    #[test]
+    fn mate_in_four() {
+        let result = solve("r4r1k/1R1R2p1/7p/8/8/3Q1Ppq/P7/6K1 w - - 0 1", 4);
+        assert_eq!(result.score, MATE - 7);
+        assert_eq!(result.best_move.unwrap().to_string(), "d3h7");
+    }
+
+    #[test]
+    fn mate_in_five() {
+        let result = solve("7k/8/8/8/5K2/8/8/1R6 w - - 0 1", 5);
+        assert_eq!(result.score, MATE - 9);
+    }
+
+    #[test]
+    fn no_mate_within_the_limit() {
+        // mate in three, but not in two: a normal search picks the move instead
+        let result = solve("6k1/8/8/5K2/8/8/8/1R6 w - - 0 1", 2);
+        assert!(result.best_move.is_some());
+        assert!(result.score < MATE - 3);
+    }
+}
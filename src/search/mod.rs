@@ -0,0 +1,968 @@
+//! Iterative deepening alpha-beta search.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{board::{Board, GameResult, Variant}, eval::{evaluate, nnue::Network}, tablebase::{Dtm, DtmTables, Tablebases, Wdl}, types::{Move, MoveList, Piece}};
+
+use self::ordering::order_moves;
+use self::pruning::*;
+use self::tt::{score_from_tt, score_to_tt, Bound, TtEntry};
+
+mod mate;
+mod ordering;
+pub(crate) mod pruning;
+pub(crate) mod time;
+pub(crate) mod tt;
+
+pub use self::pruning::SearchOptions;
+pub use self::time::{Clock, SimulatedClock, TimeLimit, TimeManager, WallClock};
+pub use self::tt::{TranspositionTable, DEFAULT_HASH_MB};
+
+/// Score of being checkmated at the root. Mates further away score closer to zero.
+pub const MATE: i32 = 32_000;
+pub const INFINITY: i32 = 32_001;
+/// Deepest ply the search will ever reach
+pub const MAX_PLY: usize = 128;
+/// Score of a tablebase win at the root, below every mate score
+pub const TB_WIN: i32 = MATE - 2 * MAX_PLY as i32;
+
+/// Number of moves to mate for a mate score: positive when the side to move mates, negative when
+/// it is mated. `None` for other scores.
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score >= MATE - MAX_PLY as i32 {
+        Some((MATE - score + 1) / 2)
+    } else if score <= -MATE + MAX_PLY as i32 {
+        Some(-(MATE + score) / 2)
+    } else {
+        None
+    }
+}
+
+/// Score of a position where the side to move has no legal moves: mated (or beaten by a rule of
+/// the variant), drawn, or in Racing Kings already won
+fn terminal_score(board: &Board, ply: usize) -> i32 {
+    match board.outcome() {
+        Some(GameResult::Win(color)) if color == board.side_to_move => MATE - ply as i32,
+        Some(GameResult::Win(_)) => -MATE + ply as i32,
+        _ => 0,
+    }
+}
+
+/// Limits which are not about time
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    /// Look for a forced mate in this many moves. If there is none, the best move of a search to
+    /// the same depth is played.
+    pub mate: Option<usize>,
+}
+
+/// Summary of one line of a finished iteration, used for `info` output
+#[derive(Clone, Debug)]
+pub struct SearchReport {
+    pub depth: usize,
+    pub seldepth: usize,
+    /// Rank of this line, starting at 1
+    pub multipv: usize,
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<Move>,
+}
+
+impl SearchReport {
+    pub fn nps(&self) -> u64 {
+        let micros = self.time.as_micros().max(1) as u64;
+        self.nodes * 1_000_000 / micros
+    }
+}
+
+/// One of the lines found by a multi-PV search
+#[derive(Clone, Debug, PartialEq)]
+pub struct PvLine {
+    pub mv: Move,
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchResult {
+    /// `None` only if there are no legal moves
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: usize,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    /// Every line of the last completed iteration, best first. Only has more than one entry when
+    /// searching with [`Search::with_multipv`].
+    pub lines: Vec<PvLine>,
+}
+
+/// Searches the position and returns up to `lines` root moves ranked best first, each with its
+/// score and principal variation.
+pub fn multipv(board: &Board, lines: usize, limits: SearchLimits) -> Vec<(Move, i32, Vec<Move>)> {
+    Search::new(board.clone(), limits, TimeManager::infinite(), Arc::default())
+        .with_multipv(lines)
+        .run(|_| {})
+        .lines
+        .into_iter()
+        .map(|line| (line.mv, line.score, line.pv))
+        .collect()
+}
+
+/// Triangular principal variation table
+struct PvTable {
+    moves: Vec<[Move; MAX_PLY + 1]>,
+    len: [usize; MAX_PLY + 1],
+}
+
+impl PvTable {
+    fn new() -> Self {
+        Self {
+            moves: vec![[Move::default(); MAX_PLY + 1]; MAX_PLY + 1],
+            len: [0; MAX_PLY + 1],
+        }
+    }
+
+    fn clear(&mut self, ply: usize) {
+        self.len[ply] = ply;
+    }
+
+    /// Sets `mv` as the best move at `ply`, followed by the line found one ply deeper
+    fn update(&mut self, ply: usize, mv: Move) {
+        self.moves[ply][ply] = mv;
+        for i in ply + 1..self.len[ply + 1] {
+            self.moves[ply][i] = self.moves[ply + 1][i];
+        }
+        self.len[ply] = self.len[ply + 1].max(ply + 1);
+    }
+
+    fn line(&self) -> Vec<Move> {
+        self.moves[0][..self.len[0]].to_vec()
+    }
+}
+
+pub struct Search {
+    board: Board,
+    limits: SearchLimits,
+    time: TimeManager,
+    /// Set from outside (e.g. by the UCI `stop` command) to abort the search
+    stop: Arc<AtomicBool>,
+    stopped: bool,
+    nodes: u64,
+    seldepth: usize,
+    pv: PvTable,
+    /// The moves of the node being searched at each ply, in the order they are tried. The lists
+    /// are reused from node to node, so only the legal moves are copied into them.
+    move_lists: Vec<Vec<Move>>,
+    options: SearchOptions,
+    multipv: usize,
+    tt: Arc<TranspositionTable>,
+    threads: usize,
+    /// 0 for the main thread, which manages time and reports. Helper threads only fill the
+    /// transposition table.
+    thread_id: usize,
+    /// Nodes searched by each thread, published every `check_interval` nodes
+    thread_nodes: Arc<Vec<AtomicU64>>,
+    tablebases: Option<Arc<Tablebases>>,
+    /// Positions with at most this many pieces are looked up in the tablebases
+    tb_probe_limit: usize,
+    dtm_tables: Option<Arc<DtmTables>>,
+}
+
+/// Helper threads skip some iterations so that they are not all searching the same depth. Helper
+/// `i` uses entry `(i - 1) % 20`, and skips depth `d` when `(d + phase) / size` is odd.
+const SKIP_SIZE: [usize; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [usize; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+impl Search {
+    pub fn new(board: Board, limits: SearchLimits, time: TimeManager, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            board,
+            limits,
+            time,
+            stop,
+            stopped: false,
+            nodes: 0,
+            seldepth: 0,
+            pv: PvTable::new(),
+            move_lists: vec![Vec::new(); MAX_PLY + 1],
+            options: SearchOptions::default(),
+            multipv: 1,
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            threads: 1,
+            thread_id: 0,
+            thread_nodes: Arc::new(vec![AtomicU64::new(0)]),
+            tablebases: None,
+            tb_probe_limit: 0,
+            dtm_tables: None,
+        }
+    }
+
+    /// Uses a transposition table which outlives the search, e.g. one kept between moves
+    pub fn with_tt(mut self, tt: Arc<TranspositionTable>) -> Self {
+        self.tt = tt;
+        self
+    }
+
+    /// Searches with `threads` threads sharing the transposition table (lazy SMP). The calling
+    /// thread is the main one: only its result is used, and it alone reports and manages time.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self.thread_nodes = Arc::new((0..self.threads).map(|_| AtomicU64::new(0)).collect());
+        self
+    }
+
+    /// Evaluates with `network`. The helper threads search copies of the board, and so share it.
+    pub fn with_network(mut self, network: Arc<Network>) -> Self {
+        self.board.set_network(network);
+        self
+    }
+
+    /// Looks up positions with at most `probe_limit` pieces in the tablebases: the root moves are
+    /// narrowed down to those which keep the best result, and the search stops at positions
+    /// whose result is known.
+    pub fn with_tablebases(mut self, tablebases: Arc<Tablebases>, probe_limit: usize) -> Self {
+        self.tb_probe_limit = probe_limit.min(tablebases.max_pieces());
+        self.tablebases = Some(tablebases);
+        self
+    }
+
+    /// Looks up positions in the distance-to-mate tables we generate. Their scores are exact, so
+    /// the search stops there.
+    pub fn with_dtm_tables(mut self, tables: Arc<DtmTables>) -> Self {
+        self.dtm_tables = Some(tables);
+        self
+    }
+
+    /// Whether the current position may be looked up in the tablebases
+    fn can_probe_tablebases(&self) -> bool {
+        self.board.occupied().count() <= self.tb_probe_limit && self.board.castling().is_empty() && self.board.variant() == Variant::Standard
+    }
+
+    fn helper(&self, thread_id: usize, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            board: self.board.clone(),
+            limits: SearchLimits { depth: self.limits.depth, ..Default::default() },
+            time: TimeManager::infinite(),
+            stop,
+            stopped: false,
+            nodes: 0,
+            seldepth: 0,
+            pv: PvTable::new(),
+            move_lists: vec![Vec::new(); MAX_PLY + 1],
+            options: self.options,
+            multipv: 1,
+            tt: self.tt.clone(),
+            threads: 1,
+            thread_id,
+            thread_nodes: self.thread_nodes.clone(),
+            tablebases: self.tablebases.clone(),
+            tb_probe_limit: self.tb_probe_limit,
+            dtm_tables: self.dtm_tables.clone(),
+        }
+    }
+
+    fn publish_nodes(&self) {
+        self.thread_nodes[self.thread_id].store(self.nodes, Ordering::Relaxed);
+    }
+
+    /// Nodes searched by all threads so far
+    fn total_nodes(&self) -> u64 {
+        self.publish_nodes();
+        self.thread_nodes.iter().map(|nodes| nodes.load(Ordering::Relaxed)).sum()
+    }
+
+    fn skips_depth(&self, depth: usize) -> bool {
+        if self.thread_id == 0 {
+            return false
+        }
+        let i = (self.thread_id - 1) % SKIP_SIZE.len();
+        (depth + SKIP_PHASE[i]) / SKIP_SIZE[i] % 2 == 1
+    }
+
+    /// Selects which pruning, reduction and extension techniques to use
+    pub fn with_options(mut self, options: SearchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Searches the best `lines` root moves instead of just the best one. Each line after the
+    /// first is found by searching the root again without the moves of the previous lines.
+    pub fn with_multipv(mut self, lines: usize) -> Self {
+        self.multipv = lines.max(1);
+        self
+    }
+
+    /// Runs iterative deepening until a limit is hit, calling `report` for every line of every
+    /// completed iteration.
+    pub fn run<F: FnMut(&SearchReport)>(&mut self, mut report: F) -> SearchResult {
+        if let Some(moves) = self.limits.mate {
+            let result = self.run_mate(moves, &mut report);
+            if result.best_move.is_some() || self.stopped {
+                return result
+            }
+            let depth = (2 * moves).saturating_sub(1).max(1);
+            self.limits.depth = Some(self.limits.depth.map_or(depth, |limit| limit.min(depth)));
+        }
+        if self.threads == 1 {
+            let mut result = self.iterate(report);
+            result.nodes = self.total_nodes();
+            return result
+        }
+        // helpers are stopped once the main thread is done, whatever the reason
+        let helpers_stop = Arc::new(AtomicBool::new(false));
+        let mut helpers: Vec<Search> = (1..self.threads).map(|id| self.helper(id, helpers_stop.clone())).collect();
+        let mut result = thread::scope(|scope| {
+            for helper in &mut helpers {
+                scope.spawn(move || helper.iterate(|_| {}));
+            }
+            let result = self.iterate(report);
+            helpers_stop.store(true, Ordering::Relaxed);
+            result
+        });
+        result.nodes = self.total_nodes();
+        result
+    }
+
+    fn iterate<F: FnMut(&SearchReport)>(&mut self, mut report: F) -> SearchResult {
+        let mut result = SearchResult::default();
+        let mut root_moves = *self.board.legal_moves();
+        if root_moves.len == 0 {
+            result.score = terminal_score(&self.board, 0);
+            return result
+        }
+        if let Some(tablebases) = self.tablebases.clone().filter(|_| self.can_probe_tablebases()) {
+            if let Some(moves) = tablebases.root_moves(&mut self.board) {
+                root_moves = MoveList::default();
+                moves.into_iter().for_each(|mv| root_moves.push(mv));
+            }
+        }
+        result.best_move = Some(root_moves.moves[0]);
+        let multipv = self.multipv.min(root_moves.len);
+        let mut previous_lines: Vec<Move> = vec![];
+
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY).min(MAX_PLY);
+        for depth in 1..=max_depth {
+            if self.stop.load(Ordering::Relaxed) {
+                break
+            }
+            if self.skips_depth(depth) {
+                continue
+            }
+            self.seldepth = 0;
+            let nodes_before = self.nodes;
+            let mut lines = Vec::with_capacity(multipv);
+            let mut best_move_nodes = 0;
+            for pv_index in 0..multipv {
+                let previous_best = previous_lines.get(pv_index).copied();
+                let (score, nodes) = self.search_root(&mut root_moves.moves[..root_moves.len], pv_index, depth, previous_best);
+                if self.stopped {
+                    break
+                }
+                if pv_index == 0 {
+                    best_move_nodes = nodes;
+                }
+                lines.push(PvLine {
+                    mv: root_moves.moves[pv_index],
+                    score,
+                    pv: self.pv.line(),
+                });
+            }
+            if self.stopped {
+                // An interrupted iteration is still usable if its first move (the previous best)
+                // has been searched, but we keep it simple and trust the last full iteration.
+                break
+            }
+            // a later line can come out better than an earlier one, since the searches are
+            // separate
+            lines.sort_by_key(|line| -line.score);
+            previous_lines = lines.iter().map(|line| line.mv).collect();
+            let time = self.time.elapsed();
+            let total_nodes = self.total_nodes();
+            for (index, line) in lines.iter().enumerate() {
+                report(&SearchReport {
+                    depth,
+                    seldepth: self.seldepth,
+                    multipv: index + 1,
+                    score: line.score,
+                    nodes: total_nodes,
+                    time,
+                    pv: line.pv.clone(),
+                });
+            }
+
+            let best = &lines[0];
+            result.best_move = Some(best.mv);
+            result.score = best.score;
+            result.depth = depth;
+            result.pv = best.pv.clone();
+            result.nodes = self.nodes;
+            result.lines = lines.clone();
+
+            self.time.on_iteration(best.mv, best.score, best_move_nodes, self.nodes - nodes_before);
+            if root_moves.len == 1 && self.time.is_timed() && !self.time.is_pondering() {
+                // no point thinking about a forced move
+                break
+            }
+            if lines.iter().all(|line| line.score.abs() >= MATE - depth as i32) {
+                // we found mates within the horizon, deeper searches will not change them
+                break
+            }
+            if !self.time.should_start_iteration() {
+                break
+            }
+        }
+        result.nodes = self.nodes;
+        self.publish_nodes();
+        result
+    }
+
+    /// Searches the root moves `moves[first..]` and puts the best of them at `moves[first]`. The
+    /// moves before `first` belong to lines which were already found in this iteration. Returns
+    /// the score and the number of nodes spent on the best move.
+    fn search_root(&mut self, moves: &mut [Move], first: usize, depth: usize, previous_best: Option<Move>) -> (i32, u64) {
+        let moves = &mut moves[first..];
+        order_moves(&self.board, moves, previous_best);
+        self.pv.clear(0);
+        let mut alpha = -INFINITY;
+        let beta = INFINITY;
+        let mut best_index = 0;
+        let mut best_move_nodes = 0;
+        for (i, &mv) in moves.iter().enumerate() {
+            let nodes_before = self.nodes;
+            self.board.make_move(mv);
+            self.nodes += 1;
+            let depth = depth as i32;
+            let score = if i == 0 {
+                -self.negamax(depth - 1, 1, -beta, -alpha, true)
+            } else {
+                // the first move is expected to be best: try to prove the others are worse with
+                // a null window, and only search them fully if that fails
+                let score = -self.negamax(depth - 1, 1, -alpha - 1, -alpha, true);
+                if score > alpha {
+                    -self.negamax(depth - 1, 1, -beta, -alpha, true)
+                } else {
+                    score
+                }
+            };
+            self.board.undo_move();
+            if self.stopped {
+                break
+            }
+            if score > alpha {
+                alpha = score;
+                best_index = i;
+                best_move_nodes = self.nodes - nodes_before;
+                self.pv.update(0, mv);
+            }
+        }
+        // move the best move to the front, keeping the order of the others
+        moves[..=best_index].rotate_right(1);
+        (alpha, best_move_nodes)
+    }
+
+    /// Whether the search must be aborted. Only looks at the clock every `check_interval` nodes.
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true
+        }
+        if let Some(limit) = self.limits.nodes {
+            if self.nodes >= limit {
+                self.stopped = true;
+            }
+        }
+        if self.nodes.is_multiple_of(self.time.check_interval) {
+            self.publish_nodes();
+            if self.stop.load(Ordering::Relaxed) || self.time.hard_limit_reached() {
+                self.stopped = true;
+            }
+        }
+        self.stopped
+    }
+
+    /// Principal variation search. Nodes with a null window (`beta - alpha == 1`) are expected to
+    /// fail high or low, and are the only ones where we prune and reduce aggressively.
+    fn negamax(&mut self, mut depth: i32, ply: usize, mut alpha: i32, beta: i32, allow_null: bool) -> i32 {
+        self.pv.clear(ply);
+        if self.should_stop() {
+            return 0
+        }
+        let in_check = self.board.is_check();
+        if self.board.legal_moves().len == 0 {
+            return terminal_score(&self.board, ply)
+        }
+        if self.board.halfmove_clock() >= 100 {
+            return 0
+        }
+        // a line which returns to a position already reached can be repeated until it draws
+        if ply > 0 && self.board.is_repetition() {
+            return 0
+        }
+        if in_check && self.options.check_extensions {
+            depth += 1;
+        }
+        if depth <= 0 || ply >= MAX_PLY {
+            return self.quiescence(ply, alpha, beta)
+        }
+
+        // mate distance pruning: even mating right here cannot beat a shorter mate found already
+        alpha = alpha.max(-MATE + ply as i32);
+        let beta = beta.min(MATE - ply as i32 - 1);
+        if alpha >= beta {
+            return alpha
+        }
+
+        let options = self.options;
+        let pv_node = beta - alpha > 1;
+        let hash = self.board.hash();
+        let tt_entry = self.tt.probe(hash);
+        if let Some(entry) = tt_entry {
+            let score = score_from_tt(entry.score, ply);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            // PV nodes are searched anyway, to keep the principal variation intact
+            if !pv_node && entry.depth >= depth && usable {
+                return score
+            }
+        }
+
+        // Our own tables know the distance to mate, which is as good as searching to the end
+        if let Some(tables) = self.dtm_tables.as_ref().filter(|_| ply > 0) {
+            if let Some(dtm) = tables.probe(&self.board) {
+                let score = match dtm {
+                    Dtm::Win(plies) => MATE - ply as i32 - plies as i32,
+                    Dtm::Loss(plies) => -MATE + ply as i32 + plies as i32,
+                    Dtm::Draw => 0,
+                };
+                let depth = (depth + 6).min(MAX_PLY as i32);
+                self.tt.store(hash, TtEntry { mv: None, score: score_to_tt(score, ply), depth, bound: Bound::Exact });
+                return score
+            }
+        }
+
+        // Right after a capture or pawn move, the tablebases know the result. Wins and losses are
+        // only bounds, since the search may still find a faster mate. PV nodes are searched
+        // anyway, but their score is kept within the bound.
+        let mut tb_min = -INFINITY;
+        let mut tb_max = INFINITY;
+        if ply > 0 && self.board.halfmove_clock() == 0 && self.can_probe_tablebases() {
+            let tablebases = self.tablebases.clone().unwrap();
+            if let Some(wdl) = tablebases.probe_wdl(&mut self.board) {
+                let (score, bound) = match wdl {
+                    Wdl::Win => (TB_WIN - ply as i32, Bound::Lower),
+                    Wdl::Loss => (-TB_WIN + ply as i32, Bound::Upper),
+                    Wdl::CursedWin => (1, Bound::Exact),
+                    Wdl::BlessedLoss => (-1, Bound::Exact),
+                    Wdl::Draw => (0, Bound::Exact),
+                };
+                let usable = match bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if usable {
+                    let depth = (depth + 6).min(MAX_PLY as i32);
+                    self.tt.store(hash, TtEntry { mv: None, score: score_to_tt(score, ply), depth, bound });
+                    return score
+                }
+                if pv_node {
+                    match bound {
+                        Bound::Lower => {
+                            tb_min = score;
+                            alpha = alpha.max(score);
+                        }
+                        Bound::Upper => tb_max = score,
+                        Bound::Exact => {}
+                    }
+                }
+            }
+        }
+
+        if !pv_node && !in_check {
+            let eval = evaluate(&self.board);
+
+            // reverse futility pruning: we are so far ahead that a shallow search will not change it
+            if options.reverse_futility
+                && depth <= REVERSE_FUTILITY_MAX_DEPTH
+                && eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+            {
+                return eval
+            }
+
+            // razoring: we are so far behind that only tactics can save us
+            if options.razoring && depth <= RAZORING_MAX_DEPTH && eval + RAZORING_MARGIN * depth < alpha {
+                let score = self.quiescence(ply, alpha, beta);
+                if score < alpha {
+                    return score
+                }
+            }
+
+            // null move pruning: if passing still beats beta, a real move will too. Positions
+            // without pieces are skipped, since zugzwang is common there, and so is Antichess,
+            // where it is everywhere.
+            if options.null_move
+                && self.board.variant() != Variant::Antichess
+                && allow_null
+                && depth >= NULL_MOVE_MIN_DEPTH
+                && eval >= beta
+                && self.has_non_pawn_material()
+            {
+                self.board.make_null_move();
+                self.nodes += 1;
+                let reduced = depth - 1 - null_move_reduction(depth);
+                let score = -self.negamax(reduced, ply + 1, -beta, -beta + 1, false);
+                self.board.undo_null_move();
+                if self.stopped {
+                    return 0
+                }
+                if score >= beta {
+                    // don't trust mate scores from a null move search
+                    return if score >= MATE - MAX_PLY as i32 { beta } else { score }
+                }
+            }
+        }
+
+        let moves = &mut self.move_lists[ply];
+        moves.clear();
+        moves.extend_from_slice(self.board.legal_moves().as_slice());
+        order_moves(&self.board, moves, tt_entry.and_then(|entry| entry.mv));
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut moves_searched = 0;
+        let mut quiets_searched = 0;
+        for i in 0..self.move_lists[ply].len() {
+            let mv = self.move_lists[ply][i];
+            let quiet = !mv.is_capture() && !mv.is_promotion();
+
+            // late move pruning: quiet moves this late in the list are very unlikely to matter
+            if options.late_move_pruning
+                && !pv_node
+                && !in_check
+                && quiet
+                && depth <= LATE_MOVE_PRUNING_MAX_DEPTH
+                && best > -MATE + MAX_PLY as i32
+                && quiets_searched >= late_move_pruning_threshold(depth)
+            {
+                continue
+            }
+
+            self.board.make_move(mv);
+            self.nodes += 1;
+            let gives_check = self.board.is_check();
+            let score = if moves_searched == 0 {
+                -self.negamax(depth - 1, ply + 1, -beta, -alpha, true)
+            } else {
+                // late move reductions: search later quiet moves shallower, and only search them
+                // to full depth again if they turn out to be good
+                let reduction = if options.late_move_reductions
+                    && depth >= LMR_MIN_DEPTH
+                    && moves_searched >= LMR_MIN_MOVES
+                    && quiet
+                    && !in_check
+                    && !gives_check
+                {
+                    (lmr_reduction(depth, moves_searched) - pv_node as i32).clamp(0, depth - 2)
+                } else {
+                    0
+                };
+                let mut score = -self.negamax(depth - 1 - reduction, ply + 1, -alpha - 1, -alpha, true);
+                if score > alpha && reduction > 0 {
+                    score = -self.negamax(depth - 1, ply + 1, -alpha - 1, -alpha, true);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(depth - 1, ply + 1, -beta, -alpha, true);
+                }
+                score
+            };
+            self.board.undo_move();
+            if self.stopped {
+                return 0
+            }
+            moves_searched += 1;
+            if quiet {
+                quiets_searched += 1;
+            }
+
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(mv);
+                    self.pv.update(ply, mv);
+                    if alpha >= beta {
+                        break
+                    }
+                }
+            }
+        }
+
+        best = best.clamp(tb_min, tb_max);
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(hash, TtEntry { mv: best_move, score: score_to_tt(best, ply), depth, bound });
+        best
+    }
+
+    /// Whether the side to move has anything besides pawns and its king
+    fn has_non_pawn_material(&self) -> bool {
+        !(self.board.our(Piece::Knight)
+            | self.board.our(Piece::Bishop)
+            | self.board.our(Piece::Rook)
+            | self.board.our(Piece::Queen))
+            .is_empty()
+    }
+
+    /// Only searches captures and promotions (or every evasion when in check), so that the static
+    /// evaluation is only ever taken in quiet positions.
+    fn quiescence(&mut self, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv.clear(ply);
+        self.seldepth = self.seldepth.max(ply);
+        if self.should_stop() {
+            return 0
+        }
+        if self.board.legal_moves().len == 0 {
+            return terminal_score(&self.board, ply)
+        }
+        // check evasions need not be captures, so quiescence can repeat a position too
+        if ply > 0 && self.board.is_repetition() {
+            return 0
+        }
+        if ply >= MAX_PLY {
+            return evaluate(&self.board)
+        }
+        // in Antichess, a side which can capture must, so it cannot stand pat either
+        let forced = self.board.is_check()
+            || (self.board.variant() == Variant::Antichess && self.board.legal_moves().moves[0].is_capture());
+
+        let mut best = -INFINITY;
+        if !forced {
+            best = evaluate(&self.board);
+            if best >= beta {
+                return best
+            }
+            alpha = alpha.max(best);
+        }
+
+        let moves = &mut self.move_lists[ply];
+        moves.clear();
+        moves.extend_from_slice(self.board.legal_moves().as_slice());
+        order_moves(&self.board, moves, None);
+        for i in 0..self.move_lists[ply].len() {
+            let mv = self.move_lists[ply][i];
+            if !forced && !mv.is_capture() && !mv.is_promotion() {
+                continue
+            }
+            self.board.make_move(mv);
+            self.nodes += 1;
+            let score = -self.quiescence(ply + 1, -beta, -alpha);
+            self.board.undo_move();
+            if self.stopped {
+                return 0
+            }
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    self.pv.update(ply, mv);
+                    if alpha >= beta {
+                        break
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::time::{SimulatedClock, TimeLimit};
+
+    fn search(fen: &str, limits: SearchLimits, time: TimeManager) -> SearchResult {
+        let board = Board::new(fen.to_string()).unwrap();
+        Search::new(board, limits, time, Arc::default()).run(|_| {})
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let result = search(
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            SearchLimits { depth: Some(2), ..Default::default() },
+            TimeManager::infinite(),
+        );
+        assert_eq!(result.best_move.unwrap().to_string(), "a1a8");
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    #[test]
+    fn mate_scores_count_moves() {
+        assert_eq!(mate_in(MATE - 1), Some(1));
+        assert_eq!(mate_in(MATE - 5), Some(3));
+        assert_eq!(mate_in(-MATE + 2), Some(-1));
+        assert_eq!(mate_in(-MATE + 4), Some(-2));
+        assert_eq!(mate_in(250), None);
+    }
+
+    #[test]
+    fn prefers_the_shortest_mate() {
+        // Nf6+ gxf6 Bxf7#
+        let result = search(
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+            SearchLimits { depth: Some(5), ..Default::default() },
+            TimeManager::infinite(),
+        );
+        assert_eq!(result.score, MATE - 3);
+    }
+
+    #[test]
+    fn finds_perpetual_check() {
+        // a rook down, White draws by checking from h5 and e8 for ever
+        let result = search(
+            "8/6pk/8/8/8/1r6/q5PP/3Q3K w - - 0 1",
+            SearchLimits { depth: Some(8), ..Default::default() },
+            TimeManager::infinite(),
+        );
+        assert_eq!(result.best_move.unwrap().to_string(), "d1h5");
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn wins_hanging_queen() {
+        let result = search(
+            "4k3/8/8/3q4/8/8/3R4/3K4 w - - 0 1",
+            SearchLimits { depth: Some(2), ..Default::default() },
+            TimeManager::infinite(),
+        );
+        assert_eq!(result.best_move.unwrap().to_string(), "d2d5");
+    }
+
+    #[test]
+    fn pruning_finds_the_same_tactics_with_fewer_nodes() {
+        let fen = "2q1k3/pp3ppp/8/8/4N3/8/PP3PPP/6K1 w - - 0 1";
+        let limits = SearchLimits { depth: Some(4), ..Default::default() };
+        let board = Board::new(fen.to_string()).unwrap();
+        let plain = Search::new(board.clone(), limits, TimeManager::infinite(), Arc::default())
+            .with_options(SearchOptions::none())
+            .run(|_| {});
+        let pruned = Search::new(board, limits, TimeManager::infinite(), Arc::default()).run(|_| {});
+        // knight fork of king and queen
+        assert_eq!(plain.best_move.unwrap().to_string(), "e4d6");
+        assert_eq!(pruned.best_move.unwrap().to_string(), "e4d6");
+        assert!(pruned.nodes < plain.nodes, "{} >= {}", pruned.nodes, plain.nodes);
+    }
+
+    #[test]
+    fn multipv_ranks_distinct_moves() {
+        // queen takes rook wins the most, the other captures less
+        let board = Board::new("3rk3/8/8/1n1q4/8/8/3Q4/4K2R w - - 0 1".to_string()).unwrap();
+        let lines = multipv(&board, 3, SearchLimits { depth: Some(2), ..Default::default() });
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0.to_string(), "d2d5");
+        assert!(lines[0].1 >= lines[1].1 && lines[1].1 >= lines[2].1);
+        assert_ne!(lines[0].0, lines[1].0);
+        assert_ne!(lines[1].0, lines[2].0);
+        assert_ne!(lines[0].0, lines[2].0);
+        for (mv, _, pv) in &lines {
+            assert_eq!(pv[0], *mv);
+        }
+    }
+
+    #[test]
+    fn multipv_is_capped_by_legal_moves() {
+        let board = Board::new("7k/8/8/8/8/8/8/K7 w - - 0 1".to_string()).unwrap();
+        let lines = multipv(&board, 10, SearchLimits { depth: Some(1), ..Default::default() });
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn respects_node_limit() {
+        let result = search(
+            crate::board::START_FEN,
+            SearchLimits { nodes: Some(2_000), ..Default::default() },
+            TimeManager::infinite(),
+        );
+        assert!(result.nodes <= 2_000);
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn hard_limit_stops_search() {
+        // every read of the clock costs 1ms, and the clock is read every 64 nodes
+        let clock = SimulatedClock::with_step(Duration::from_millis(1));
+        let mut time = TimeManager::new(TimeLimit::MoveTime(Duration::from_millis(40)), Duration::ZERO, Box::new(clock.clone()));
+        time.check_interval = 64;
+        let result = search(crate::board::START_FEN, SearchLimits::default(), time);
+        assert!(result.best_move.is_some());
+        assert!(clock.now() <= Duration::from_millis(45));
+        assert!(result.nodes <= 45 * 64);
+    }
+
+    #[test]
+    fn single_thread_is_deterministic() {
+        let board = Board::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3".to_string()).unwrap();
+        let limits = SearchLimits { depth: Some(4), ..Default::default() };
+        let run = || Search::new(board.clone(), limits, TimeManager::infinite(), Arc::default()).run(|_| {});
+        let (first, second) = (run(), run());
+        assert_eq!(first.nodes, second.nodes);
+        assert_eq!(first.pv, second.pv);
+        assert_eq!(first.score, second.score);
+    }
+
+    #[test]
+    fn helper_threads_share_the_work() {
+        let board = Board::new(crate::board::START_FEN.to_string()).unwrap();
+        let limits = SearchLimits { depth: Some(4), ..Default::default() };
+        let mut search = Search::new(board, limits, TimeManager::infinite(), Arc::default()).with_threads(3);
+        let mut reported = 0;
+        let result = search.run(|report| reported = report.nodes);
+        assert_eq!(result.depth, 4);
+        assert!(result.best_move.is_some());
+        let per_thread: Vec<u64> = search.thread_nodes.iter().map(|nodes| nodes.load(Ordering::Relaxed)).collect();
+        assert!(per_thread.iter().all(|&nodes| nodes > 0), "{:?}", per_thread);
+        assert_eq!(result.nodes, per_thread.iter().sum::<u64>());
+        assert!(reported > per_thread[0]);
+    }
+
+    #[test]
+    fn helper_threads_evaluate_with_the_network() {
+        let board = Board::new(crate::board::START_FEN.to_string()).unwrap();
+        let limits = SearchLimits { depth: Some(2), ..Default::default() };
+        let network = Arc::new(Network::material());
+        let search = Search::new(board, limits, TimeManager::infinite(), Arc::default())
+            .with_threads(2)
+            .with_network(network.clone());
+        let helper = search.helper(1, Arc::default());
+        for board in [&search.board, &helper.board] {
+            assert!(Arc::ptr_eq(&board.nnue().unwrap().network, &network));
+        }
+    }
+
+    #[test]
+    fn stop_flag() {
+        let board = Board::new(crate::board::START_FEN.to_string()).unwrap();
+        let stop = Arc::new(AtomicBool::new(true));
+        let result = Search::new(board, SearchLimits::default(), TimeManager::infinite(), stop).run(|_| {});
+        // stopped before finishing an iteration, but we still have a move to play
+        assert!(result.best_move.is_some());
+        assert_eq!(result.depth, 0);
+    }
+}
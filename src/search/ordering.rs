@@ -0,0 +1,34 @@
+use crate::{board::Board, eval::PIECE_VALUES, types::{Move, Piece, MAX_MOVES}};
+
+/// Scores a move for ordering purposes. Captures are ordered by MVV-LVA (most valuable victim,
+/// least valuable attacker), followed by promotions and then quiet moves.
+pub fn score_move(board: &Board, mv: Move) -> i32 {
+    let mut score = 0;
+    if mv.is_capture() {
+        let victim = if mv.is_en_passant() { Piece::Pawn } else { board.piece_on(mv.target()) };
+        let attacker = board.piece_on(mv.start());
+        score += 10_000 + 10 * PIECE_VALUES[victim] - PIECE_VALUES[attacker] / 10;
+    }
+    if mv.is_promotion() {
+        score += 5_000 + PIECE_VALUES[mv.promo_piece()];
+    }
+    score
+}
+
+/// Sorts the moves so that the most promising ones come first. `best` (for instance, the best
+/// move of the previous iteration) is always put at the front.
+pub fn order_moves(board: &Board, moves: &mut [Move], best: Option<Move>) {
+    let mut scores = [0; MAX_MOVES];
+    for (score, mv) in scores.iter_mut().zip(moves.iter()) {
+        *score = if Some(*mv) == best { i32::MAX } else { score_move(board, *mv) };
+    }
+    // insertion sort: the lists are short and often already nearly sorted
+    for i in 1..moves.len() {
+        let mut j = i;
+        while j > 0 && scores[j - 1] < scores[j] {
+            scores.swap(j - 1, j);
+            moves.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Parameters of the selectivity techniques used by the search. Each technique can be switched off
+//! through [`SearchOptions`], which makes it easy to measure what it is worth.
+
+/// Which pruning, reduction and extension techniques the search may use
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchOptions {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility: bool,
+    pub razoring: bool,
+    pub late_move_pruning: bool,
+    pub check_extensions: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility: true,
+            razoring: true,
+            late_move_pruning: true,
+            check_extensions: true,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Plain alpha-beta: every technique switched off
+    pub const fn none() -> Self {
+        Self {
+            null_move: false,
+            late_move_reductions: false,
+            reverse_futility: false,
+            razoring: false,
+            late_move_pruning: false,
+            check_extensions: false,
+        }
+    }
+}
+
+/// Null move pruning needs at least this much depth
+pub const NULL_MOVE_MIN_DEPTH: i32 = 3;
+
+/// How much shallower the search after a null move is
+pub const fn null_move_reduction(depth: i32) -> i32 {
+    3 + depth / 4
+}
+
+/// Reverse futility pruning: prune when the static evaluation beats beta by this much per ply
+pub const REVERSE_FUTILITY_MARGIN: i32 = 80;
+pub const REVERSE_FUTILITY_MAX_DEPTH: i32 = 6;
+
+/// Razoring: drop into quiescence when the static evaluation is this far below alpha per ply
+pub const RAZORING_MARGIN: i32 = 300;
+pub const RAZORING_MAX_DEPTH: i32 = 2;
+
+/// Late move pruning: at low depths, stop searching quiet moves after this many
+pub const LATE_MOVE_PRUNING_MAX_DEPTH: i32 = 3;
+
+pub const fn late_move_pruning_threshold(depth: i32) -> usize {
+    (3 + depth * depth) as usize
+}
+
+/// Late move reductions only start after this many moves have been searched
+pub const LMR_MIN_MOVES: usize = 3;
+pub const LMR_MIN_DEPTH: i32 = 3;
+
+/// How many plies to reduce the `moves_searched`th move by, at the given depth
+pub fn lmr_reduction(depth: i32, moves_searched: usize) -> i32 {
+    (0.75 + (depth as f64).ln() * (moves_searched as f64).ln() / 2.25) as i32
+}
@@ -0,0 +1,428 @@
+//! Deciding how long to think.
+//!
+//! The time manager computes two limits when a search starts:
+//!  - the _soft_ limit is checked between iterations of iterative deepening. It is stretched when
+//!    the best move keeps changing or the score drops, and shrunk when one move dominates.
+//!  - the _hard_ limit is checked inside the search every `check_interval` nodes, and aborts it.
+//!
+//! While pondering (thinking on the opponent's time) neither limit applies. On `ponderhit` the
+//! limits take effect, measured from the start of the ponder search, so time spent pondering on
+//! the right move counts towards this move.
+//!
+//! All time is read through a [`Clock`], so tests can use a [`SimulatedClock`] instead of the wall
+//! clock.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::types::Move;
+
+/// Source of elapsed time since the search started
+pub trait Clock: Send {
+    fn elapsed(&self) -> Duration;
+}
+
+pub struct WallClock(Instant);
+
+impl WallClock {
+    pub fn start() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl Clock for WallClock {
+    fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// A clock which only moves when told to. Every read also advances it by `step`, which lets a
+/// search "spend" time deterministically as it checks the clock. Clones share the same time.
+#[derive(Clone, Default)]
+pub struct SimulatedClock {
+    micros: Arc<AtomicU64>,
+    step: u64,
+}
+
+impl SimulatedClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A clock which advances by `step` every time it is read
+    pub fn with_step(step: Duration) -> Self {
+        Self {
+            micros: Arc::default(),
+            step: step.as_micros() as u64,
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.micros.fetch_add(by.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Current time, without advancing the clock
+    pub fn now(&self) -> Duration {
+        Duration::from_micros(self.micros.load(Ordering::Relaxed))
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn elapsed(&self) -> Duration {
+        Duration::from_micros(self.micros.fetch_add(self.step, Ordering::Relaxed) + self.step)
+    }
+}
+
+/// How much time we may use for the current move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeLimit {
+    /// Search until stopped (or until a depth / node limit is hit)
+    Infinite,
+    /// Search for exactly this long
+    MoveTime(Duration),
+    /// Tournament time control, from the point of view of the side to move
+    Clock {
+        remaining: Duration,
+        increment: Duration,
+        moves_to_go: Option<u32>,
+    },
+}
+
+/// Moves we assume are left in the game when the GUI does not send `movestogo`
+const DEFAULT_MOVES_TO_GO: u32 = 25;
+/// Never plan for more moves than this, even if `movestogo` is larger
+const MAX_MOVES_TO_GO: u32 = 50;
+/// Nodes searched between two hard-limit checks
+pub const DEFAULT_CHECK_INTERVAL: u64 = 1024;
+/// Extra share of the soft limit when the GUI may let us ponder: the opponent's thinking time
+/// will be partly ours
+const PONDER_BONUS: f64 = 0.25;
+
+pub struct TimeManager {
+    clock: Box<dyn Clock>,
+    soft: Option<Duration>,
+    hard: Option<Duration>,
+    /// How many nodes the search visits between two reads of the clock
+    pub check_interval: u64,
+    // iteration history
+    last_best: Option<Move>,
+    last_score: Option<i32>,
+    stable_iterations: u32,
+    /// Multiplier applied to the soft limit, updated after every iteration
+    scale: f64,
+    instability: f64,
+    /// Set while the search is pondering, cleared on `ponderhit`
+    pondering: Option<Arc<AtomicBool>>,
+}
+
+impl TimeManager {
+    pub fn new(limit: TimeLimit, move_overhead: Duration, clock: Box<dyn Clock>) -> Self {
+        let (soft, hard) = match limit {
+            TimeLimit::Infinite => (None, None),
+            TimeLimit::MoveTime(time) => {
+                let time = time.saturating_sub(move_overhead).max(Duration::from_millis(1));
+                (Some(time), Some(time))
+            }
+            TimeLimit::Clock { remaining, increment, moves_to_go } => {
+                let available = remaining.saturating_sub(move_overhead).max(Duration::from_millis(1));
+                let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, MAX_MOVES_TO_GO);
+                // never plan to use more than three quarters of what is left on a single move
+                let hard = if moves_to_go == 1 { available * 9 / 10 } else { available * 3 / 4 };
+                let soft = (available / moves_to_go + increment * 3 / 4).min(hard);
+                (Some(soft), Some((soft * 4).min(hard)))
+            }
+        };
+        Self {
+            clock,
+            soft,
+            hard,
+            check_interval: DEFAULT_CHECK_INTERVAL,
+            last_best: None,
+            last_score: None,
+            stable_iterations: 0,
+            scale: 1.0,
+            instability: 1.0,
+            pondering: None,
+        }
+    }
+
+    /// Ignores the limits for as long as `pondering` is set
+    pub fn with_pondering(mut self, pondering: Arc<AtomicBool>) -> Self {
+        self.pondering = Some(pondering);
+        self
+    }
+
+    /// Plans for a bit more time per move, for games where the engine is allowed to ponder
+    pub fn with_ponder_bonus(mut self) -> Self {
+        self.soft = self.soft.map(|soft| {
+            let soft = soft.mul_f64(1.0 + PONDER_BONUS);
+            self.hard.map_or(soft, |hard| soft.min(hard))
+        });
+        self
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.as_ref().is_some_and(|pondering| pondering.load(Ordering::Relaxed))
+    }
+
+    /// A time manager which never stops the search by itself
+    pub fn infinite() -> Self {
+        Self::new(TimeLimit::Infinite, Duration::ZERO, Box::new(WallClock::start()))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    pub fn soft_limit(&self) -> Option<Duration> {
+        self.soft
+    }
+
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard
+    }
+
+    /// Whether the search is bound by time at all
+    pub fn is_timed(&self) -> bool {
+        self.hard.is_some()
+    }
+
+    /// Checked inside the search. Once this is true, the search must return immediately.
+    pub fn hard_limit_reached(&self) -> bool {
+        if self.is_pondering() {
+            return false
+        }
+        match self.hard {
+            Some(hard) => self.clock.elapsed() >= hard,
+            None => false,
+        }
+    }
+
+    /// Records the outcome of a finished iteration and updates how long we are willing to keep
+    /// searching. `best_move_nodes / total_nodes` is the share of the root's effort spent on the
+    /// best move: when it is close to one, the other moves were refuted quickly and the best move
+    /// dominates.
+    pub fn on_iteration(&mut self, best: Move, score: i32, best_move_nodes: u64, total_nodes: u64) {
+        if self.last_best == Some(best) {
+            self.stable_iterations += 1;
+            self.instability = (self.instability * 0.8).max(1.0);
+        } else {
+            self.stable_iterations = 0;
+            if self.last_best.is_some() {
+                self.instability = (self.instability + 0.5).min(2.0);
+            }
+        }
+
+        // spend more time when the score is dropping, up to twice as long for a pawn or more
+        let score_drop = match self.last_score {
+            Some(last) => (last - score).clamp(0, 100),
+            None => 0,
+        };
+        let falling = if score_drop > 15 { 1.0 + score_drop as f64 / 100.0 } else { 1.0 };
+
+        let share = if total_nodes == 0 { 0.0 } else { best_move_nodes as f64 / total_nodes as f64 };
+        let dominance = if self.stable_iterations >= 3 && share > 0.9 {
+            0.5
+        } else if self.stable_iterations >= 2 && share > 0.75 {
+            0.75
+        } else {
+            1.0
+        };
+
+        self.scale = self.instability * falling * dominance;
+        self.last_best = Some(best);
+        self.last_score = Some(score);
+    }
+
+    /// The soft limit after adjusting it for the iterations seen so far
+    pub fn adjusted_soft_limit(&self) -> Option<Duration> {
+        let soft = self.soft?.mul_f64(self.scale);
+        Some(match self.hard {
+            Some(hard) => soft.min(hard),
+            None => soft,
+        })
+    }
+
+    /// Checked between iterations: whether another iteration should be started.
+    pub fn should_start_iteration(&self) -> bool {
+        if self.is_pondering() {
+            return true
+        }
+        match self.adjusted_soft_limit() {
+            Some(soft) => self.clock.elapsed() < soft,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MoveType, Square};
+
+    const E2E4: Move = Move::new(Square::E2, Square::E4, MoveType::DoublePawnPush);
+    const D2D4: Move = Move::new(Square::D2, Square::D4, MoveType::DoublePawnPush);
+
+    fn clock_limit(remaining_ms: u64, increment_ms: u64, moves_to_go: Option<u32>) -> TimeLimit {
+        TimeLimit::Clock {
+            remaining: Duration::from_millis(remaining_ms),
+            increment: Duration::from_millis(increment_ms),
+            moves_to_go,
+        }
+    }
+
+    /// Plays out one move's worth of iterative deepening on a simulated clock. Iteration `i`
+    /// takes `cost(i)` and reports the given best move and score. Returns the time spent.
+    fn simulate_move<F>(tm: &mut TimeManager, clock: &SimulatedClock, mut iteration: F) -> Duration
+    where
+        F: FnMut(usize) -> (Duration, Move, i32, f64),
+    {
+        let mut depth = 1;
+        while tm.should_start_iteration() && depth < 64 {
+            let (cost, best, score, share) = iteration(depth);
+            clock.advance(cost);
+            if tm.hard_limit_reached() {
+                break
+            }
+            tm.on_iteration(best, score, (share * 1000.0) as u64, 1000);
+            depth += 1;
+        }
+        clock.now()
+    }
+
+    fn manager(limit: TimeLimit, clock: &SimulatedClock) -> TimeManager {
+        TimeManager::new(limit, Duration::from_millis(10), Box::new(clock.clone()))
+    }
+
+    #[test]
+    fn limits_from_clock() {
+        let clock = SimulatedClock::new();
+        let tm = manager(clock_limit(60_000, 1_000, None), &clock);
+        let soft = tm.soft_limit().unwrap();
+        let hard = tm.hard_limit().unwrap();
+        assert!(soft > Duration::from_millis(2_000) && soft < Duration::from_millis(4_000));
+        assert!(hard > soft && hard <= Duration::from_millis(45_000));
+    }
+
+    #[test]
+    fn move_time_and_overhead() {
+        let clock = SimulatedClock::new();
+        let tm = manager(TimeLimit::MoveTime(Duration::from_millis(500)), &clock);
+        assert_eq!(tm.hard_limit(), Some(Duration::from_millis(490)));
+        assert_eq!(tm.soft_limit(), Some(Duration::from_millis(490)));
+    }
+
+    #[test]
+    fn last_move_before_time_control_keeps_a_reserve() {
+        let clock = SimulatedClock::new();
+        let tm = manager(clock_limit(1_000, 0, Some(1)), &clock);
+        assert!(tm.hard_limit().unwrap() < Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn infinite_never_stops() {
+        let clock = SimulatedClock::new();
+        let tm = manager(TimeLimit::Infinite, &clock);
+        clock.advance(Duration::from_secs(3600));
+        assert!(tm.should_start_iteration());
+        assert!(!tm.hard_limit_reached());
+    }
+
+    #[test]
+    fn pondering_ignores_limits_until_ponderhit() {
+        let clock = SimulatedClock::new();
+        let pondering = Arc::new(AtomicBool::new(true));
+        let tm = manager(TimeLimit::MoveTime(Duration::from_millis(500)), &clock).with_pondering(pondering.clone());
+        clock.advance(Duration::from_secs(10));
+        assert!(tm.should_start_iteration());
+        assert!(!tm.hard_limit_reached());
+        // the time spent pondering counts once the opponent plays the expected move
+        pondering.store(false, Ordering::Relaxed);
+        assert!(!tm.should_start_iteration());
+        assert!(tm.hard_limit_reached());
+    }
+
+    #[test]
+    fn ponder_bonus() {
+        let clock = SimulatedClock::new();
+        let limit = clock_limit(60_000, 0, None);
+        let plain = manager(limit, &clock);
+        let bonus = manager(limit, &clock).with_ponder_bonus();
+        assert!(bonus.soft_limit() > plain.soft_limit());
+        assert!(bonus.soft_limit() <= bonus.hard_limit());
+    }
+
+    #[test]
+    fn unstable_best_move_uses_more_time() {
+        let limit = clock_limit(60_000, 0, None);
+        let cost = |_| Duration::from_millis(150);
+
+        let stable_clock = SimulatedClock::new();
+        let mut stable = manager(limit, &stable_clock);
+        let stable_time = simulate_move(&mut stable, &stable_clock, |d| (cost(d), E2E4, 20, 0.5));
+
+        let unstable_clock = SimulatedClock::new();
+        let mut unstable = manager(limit, &unstable_clock);
+        let unstable_time = simulate_move(&mut unstable, &unstable_clock, |d| {
+            (cost(d), if d % 2 == 0 { E2E4 } else { D2D4 }, 20, 0.5)
+        });
+        assert!(unstable_time > stable_time);
+    }
+
+    #[test]
+    fn falling_score_uses_more_time() {
+        let limit = clock_limit(60_000, 0, None);
+        let cost = |_| Duration::from_millis(150);
+
+        let steady_clock = SimulatedClock::new();
+        let mut steady = manager(limit, &steady_clock);
+        let steady_time = simulate_move(&mut steady, &steady_clock, |d| (cost(d), E2E4, 20, 0.5));
+
+        let falling_clock = SimulatedClock::new();
+        let mut falling = manager(limit, &falling_clock);
+        let falling_time = simulate_move(&mut falling, &falling_clock, |d| {
+            (cost(d), E2E4, 20 - 40 * d as i32, 0.5)
+        });
+        assert!(falling_time > steady_time);
+    }
+
+    #[test]
+    fn dominant_move_stops_early() {
+        let limit = clock_limit(60_000, 0, None);
+        let cost = |_| Duration::from_millis(150);
+
+        let normal_clock = SimulatedClock::new();
+        let mut normal = manager(limit, &normal_clock);
+        let normal_time = simulate_move(&mut normal, &normal_clock, |d| (cost(d), E2E4, 20, 0.5));
+
+        let dominant_clock = SimulatedClock::new();
+        let mut dominant = manager(limit, &dominant_clock);
+        let dominant_time = simulate_move(&mut dominant, &dominant_clock, |d| (cost(d), E2E4, 20, 0.97));
+        assert!(dominant_time < normal_time);
+    }
+
+    #[test]
+    fn never_flags_over_a_game() {
+        // 40 moves in 10 seconds + 100ms increment, with wildly varying iteration costs
+        let mut remaining = Duration::from_secs(10);
+        let increment = Duration::from_millis(100);
+        for ply in 0..120u64 {
+            let clock = SimulatedClock::new();
+            let limit = TimeLimit::Clock { remaining, increment, moves_to_go: None };
+            let mut tm = manager(limit, &clock);
+            let used = simulate_move(&mut tm, &clock, |d| {
+                let cost = Duration::from_micros(((ply * 7919 + d as u64 * 104_729) % 5_000) << d.min(8));
+                (cost, if (ply + d as u64).is_multiple_of(3) { E2E4 } else { D2D4 }, 0, 0.4)
+            });
+            // searches that overshoot the hard limit are cut off at it
+            let used = used.min(tm.hard_limit().unwrap());
+            assert!(used < remaining, "flagged on ply {}", ply);
+            remaining = remaining - used + increment;
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Transposition table shared between search threads.
+//!
+//! The table is lock-free: every entry is two `AtomicU64`s, the packed data and the position's key
+//! XORed with that data. Two threads writing the same slot at once can leave a key from one write
+//! next to data from the other, but then the XOR no longer gives back the key being probed, so
+//! the torn entry simply reads as a miss.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::types::Move;
+
+use super::{MATE, MAX_PLY};
+
+/// How a stored score relates to the true score of the position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    /// The score is exact
+    Exact = 1,
+    /// The search failed high: the true score is at least this
+    Lower = 2,
+    /// The search failed low: the true score is at most this
+    Upper = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TtEntry {
+    pub mv: Option<Move>,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+impl TtEntry {
+    /// Packs the entry into 64 bits: move (24), score (16), depth (8) and bound (2)
+    fn pack(&self) -> u64 {
+        let mv = self.mv.map_or(0, |mv| mv.0) as u64;
+        let score = self.score as i16 as u16 as u64;
+        let depth = self.depth.clamp(0, u8::MAX as i32) as u64;
+        mv | score << 24 | depth << 40 | (self.bound as u64) << 48
+    }
+
+    fn unpack(data: u64) -> Option<Self> {
+        let bound = match (data >> 48) & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            // an empty slot
+            _ => return None,
+        };
+        let mv = (data & 0xFF_FFFF) as u32;
+        Some(Self {
+            mv: if mv == 0 { None } else { Some(Move(mv)) },
+            score: (data >> 24) as u16 as i16 as i32,
+            depth: ((data >> 40) & 0xFF) as i32,
+            bound,
+        })
+    }
+}
+
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+/// Size of the table used when none is given
+pub const DEFAULT_HASH_MB: usize = 16;
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        let len = (megabytes.max(1) << 20) / std::mem::size_of::<Slot>();
+        Self {
+            slots: (0..len).map(|_| Slot { key: AtomicU64::new(0), data: AtomicU64::new(0) }).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        // maps the key onto the table without a division
+        let index = ((key as u128 * self.slots.len() as u128) >> 64) as usize;
+        &self.slots[index]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ data != key {
+            return None
+        }
+        TtEntry::unpack(data)
+    }
+
+    /// Stores an entry, unless the slot holds a deeper search of the same position
+    pub fn store(&self, key: u64, entry: TtEntry) {
+        let slot = self.slot(key);
+        let old_data = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ old_data == key {
+            if let Some(old) = TtEntry::unpack(old_data) {
+                if old.depth > entry.depth + 2 && entry.bound != Bound::Exact {
+                    return
+                }
+            }
+        }
+        let data = entry.pack();
+        slot.data.store(data, Ordering::Relaxed);
+        slot.key.store(key ^ data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Mate scores are relative to the root, but a table entry can be reached at any ply, so they are
+/// stored relative to the position itself.
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score <= -MATE + MAX_PLY as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score <= -MATE + MAX_PLY as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MoveType, Square};
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let entry = TtEntry {
+            mv: Some(Move::new(Square::E2, Square::E4, MoveType::DoublePawnPush)),
+            score: -1234,
+            depth: 7,
+            bound: Bound::Lower,
+        };
+        tt.store(0xDEAD_BEEF_1234_5678, entry);
+        assert_eq!(tt.probe(0xDEAD_BEEF_1234_5678), Some(entry));
+        assert_eq!(tt.probe(0x1234_5678_DEAD_BEEF), None);
+        tt.clear();
+        assert_eq!(tt.probe(0xDEAD_BEEF_1234_5678), None);
+    }
+
+    #[test]
+    fn keeps_deeper_entries() {
+        let tt = TranspositionTable::new(1);
+        let deep = TtEntry { mv: None, score: 10, depth: 10, bound: Bound::Upper };
+        let shallow = TtEntry { mv: None, score: 20, depth: 2, bound: Bound::Upper };
+        tt.store(42, deep);
+        tt.store(42, shallow);
+        assert_eq!(tt.probe(42), Some(deep));
+    }
+
+    #[test]
+    fn mate_scores_are_ply_relative() {
+        let score = MATE - 5;
+        assert_eq!(score_from_tt(score_to_tt(score, 3), 3), score);
+        assert_eq!(score_from_tt(score_to_tt(score, 3), 1), MATE - 3);
+    }
+}
@@ -0,0 +1,331 @@
+//! Depth-first proof-number search (df-pn) for proving forced wins.
+//!
+//! Every position gets a _proof number_, the least number of leaves which still have to be shown
+//! to be wins for the attacker, and a _disproof number_, the same for showing that the attacker
+//! cannot win. At the attacker's nodes the proof number is the smallest of the children's and the
+//! disproof number their sum; at the defender's nodes it is the other way around. The search
+//! always expands the most proving node, and only backs up out of a subtree once its numbers
+//! cross the thresholds given by its parent, which keeps memory to the transposition table.
+//!
+//! A win means checkmating the defender. Stalemate, repetition, the fifty-move rule and
+//! insufficient material all count as failures to win, so no evaluation is needed. Repetitions
+//! depend on the path to a position while the table does not, so in rare cases a draw by
+//! repetition can be seen where another path would have avoided it.
+
+use std::{collections::HashMap, fmt};
+
+use crate::board::{Board, GameResult};
+use crate::types::{Color, Move};
+
+/// Proof or disproof number of a solved node
+const INFINITE: u32 = u32::MAX / 2;
+/// Longest main line returned, guarding against cycles through the table
+const MAX_LINE: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    /// The attacker forces checkmate
+    Proven,
+    /// The defender can always avoid being mated
+    Disproven,
+    /// The node budget ran out first
+    Unknown,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Proven => "proven",
+            Outcome::Disproven => "disproven",
+            Outcome::Unknown => "unknown",
+        })
+    }
+}
+
+pub struct SolveResult {
+    pub outcome: Outcome,
+    pub nodes: u64,
+    /// For a proof, a line where the attacker mates while the defender resists; for a disproof,
+    /// the defender's escape. Empty if the outcome is unknown.
+    pub main_line: Vec<Move>,
+}
+
+#[derive(Clone, Copy)]
+struct Numbers {
+    proof: u32,
+    disproof: u32,
+    /// For solved positions, how many plies the winner needs to reach a won leaf against the best
+    /// resistance found. Lets the main line make progress instead of wandering between won
+    /// positions.
+    distance: u32,
+}
+
+impl Numbers {
+    const PROVEN: Self = Self { proof: 0, disproof: INFINITE, distance: 0 };
+    const DISPROVEN: Self = Self { proof: INFINITE, disproof: 0, distance: 0 };
+
+    const fn unsolved(proof: u32, disproof: u32) -> Self {
+        Self { proof, disproof, distance: 0 }
+    }
+
+    fn is_solved(self) -> bool {
+        self.proof == 0 || self.disproof == 0
+    }
+}
+
+pub struct Solver {
+    board: Board,
+    attacker: Color,
+    budget: u64,
+    nodes: u64,
+    table: HashMap<u64, Numbers>,
+}
+
+impl Solver {
+    /// Nodes searched when no budget is given
+    pub const DEFAULT_NODE_BUDGET: u64 = 1_000_000;
+
+    /// Sets up a search for a forced win by `attacker` from `board`, looking at no more than
+    /// `budget` nodes
+    pub fn new(board: Board, attacker: Color, budget: u64) -> Self {
+        Self {
+            board,
+            attacker,
+            budget,
+            nodes: 0,
+            table: HashMap::new(),
+        }
+    }
+
+    pub fn solve(&mut self) -> SolveResult {
+        let root = match self.terminal() {
+            Some(numbers) => numbers,
+            None => self.mid(INFINITE - 1, INFINITE - 1),
+        };
+        let outcome = if root.proof == 0 {
+            Outcome::Proven
+        } else if root.disproof == 0 {
+            Outcome::Disproven
+        } else {
+            Outcome::Unknown
+        };
+        SolveResult {
+            outcome,
+            nodes: self.nodes,
+            main_line: match outcome {
+                Outcome::Unknown => vec![],
+                _ => self.main_line(outcome == Outcome::Proven),
+            },
+        }
+    }
+
+    fn attacker_to_move(&self) -> bool {
+        self.board.side_to_move == self.attacker
+    }
+
+    /// The numbers of a position whose outcome is known without searching it
+    fn terminal(&self) -> Option<Numbers> {
+        // in the variants, a win other than by mate counts as well
+        match self.board.outcome()? {
+            GameResult::Win(color) if color == self.attacker => Some(Numbers::PROVEN),
+            _ => Some(Numbers::DISPROVEN),
+        }
+    }
+
+    /// Numbers of the current position before it is expanded: solved positions are scored as
+    /// such, others by how many moves the side to move has
+    fn initial_numbers(&self) -> Numbers {
+        if let Some(numbers) = self.terminal() {
+            return numbers
+        }
+        if let Some(&numbers) = self.table.get(&self.board.hash()) {
+            return numbers
+        }
+        let moves = self.board.legal_moves().len as u32;
+        if self.attacker_to_move() {
+            Numbers::unsolved(1, moves)
+        } else {
+            Numbers::unsolved(moves, 1)
+        }
+    }
+
+    /// Expands the current position until its proof number reaches `proof_threshold` or its
+    /// disproof number `disproof_threshold`
+    fn mid(&mut self, proof_threshold: u32, disproof_threshold: u32) -> Numbers {
+        let legal = *self.board.legal_moves();
+        let moves = &legal.moves[..legal.len];
+        let mut children: Vec<Numbers> = Vec::with_capacity(moves.len());
+        for &mv in moves {
+            self.board.make_move(mv);
+            self.nodes += 1;
+            let numbers = self.initial_numbers();
+            // a repetition is a draw only through this path, so it is not worth remembering
+            if numbers.is_solved() && !self.board.is_repetition() {
+                self.table.insert(self.board.hash(), numbers);
+            }
+            self.board.undo_move();
+            children.push(numbers);
+        }
+
+        let or_node = self.attacker_to_move();
+        let hash = self.board.hash();
+        loop {
+            let numbers = combine(&children, or_node);
+            if numbers.proof >= proof_threshold || numbers.disproof >= disproof_threshold || self.nodes >= self.budget {
+                self.table.insert(hash, numbers);
+                return numbers
+            }
+
+            // the child with the smallest number that decides this node, and the second smallest
+            let key = |numbers: &Numbers| if or_node { numbers.proof } else { numbers.disproof };
+            let mut best = 0;
+            let mut second = INFINITE;
+            for (i, child) in children.iter().enumerate().skip(1) {
+                if key(child) < key(&children[best]) {
+                    second = key(&children[best]);
+                    best = i;
+                } else {
+                    second = second.min(key(child));
+                }
+            }
+            let child = children[best];
+            let (child_proof, child_disproof) = if or_node {
+                (
+                    proof_threshold.min(second.saturating_add(1)),
+                    (disproof_threshold - numbers.disproof).saturating_add(child.disproof).min(INFINITE - 1),
+                )
+            } else {
+                (
+                    (proof_threshold - numbers.proof).saturating_add(child.proof).min(INFINITE - 1),
+                    disproof_threshold.min(second.saturating_add(1)),
+                )
+            };
+
+            self.board.make_move(moves[best]);
+            children[best] = self.mid(child_proof, child_disproof);
+            self.board.undo_move();
+        }
+    }
+
+    /// Follows solved positions through the table from the root. The winning side plays the move
+    /// which wins fastest, the losing side the one which resists longest.
+    fn main_line(&mut self, proving: bool) -> Vec<Move> {
+        let mut line = vec![];
+        while line.len() < MAX_LINE && self.terminal().is_none() {
+            let winner_to_move = proving == self.attacker_to_move();
+            let legal = *self.board.legal_moves();
+            let mut choice: Option<(Move, u32)> = None;
+            for &mv in &legal.moves[..legal.len] {
+                self.board.make_move(mv);
+                let numbers = self.table.get(&self.board.hash()).copied();
+                self.board.undo_move();
+                let Some(numbers) = numbers else { continue };
+                // the number which is zero when `mv` keeps the outcome
+                let solved = if proving { numbers.proof == 0 } else { numbers.disproof == 0 };
+                if !solved {
+                    continue
+                }
+                let better = match choice {
+                    None => true,
+                    Some((_, distance)) if winner_to_move => numbers.distance < distance,
+                    Some((_, distance)) => numbers.distance > distance,
+                };
+                if better {
+                    choice = Some((mv, numbers.distance));
+                }
+            }
+            let Some((mv, _)) = choice else { break };
+            self.board.make_move(mv);
+            line.push(mv);
+        }
+        for _ in 0..line.len() {
+            self.board.undo_move();
+        }
+        line
+    }
+}
+
+/// The numbers of a node from those of its children
+fn combine(children: &[Numbers], or_node: bool) -> Numbers {
+    let min_proof = children.iter().map(|c| c.proof).min().unwrap_or(INFINITE);
+    let min_disproof = children.iter().map(|c| c.disproof).min().unwrap_or(INFINITE);
+    let sum_proof = children.iter().fold(0u32, |sum, c| sum.saturating_add(c.proof)).min(INFINITE);
+    let sum_disproof = children.iter().fold(0u32, |sum, c| sum.saturating_add(c.disproof)).min(INFINITE);
+    let mut numbers = if or_node {
+        Numbers::unsolved(min_proof, sum_disproof)
+    } else {
+        Numbers::unsolved(sum_proof, min_disproof)
+    };
+    // the attacker wins at its own nodes by picking one won child, and at the defender's nodes
+    // only once every child is won (and the other way around for the defender)
+    let winning = |child: &&Numbers| if numbers.proof == 0 { child.proof == 0 } else { child.disproof == 0 };
+    let winner_to_move = (numbers.proof == 0) == or_node;
+    if numbers.is_solved() {
+        let distances = children.iter().filter(winning).map(|child| child.distance);
+        let distance = if winner_to_move { distances.min() } else { distances.max() };
+        numbers.distance = distance.unwrap_or(0) + 1;
+    }
+    numbers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(fen: &str, budget: u64) -> SolveResult {
+        let board = Board::new(fen.to_string()).unwrap();
+        let attacker = board.side_to_move;
+        let result = Solver::new(board.clone(), attacker, budget).solve();
+        // every line must be legal, and a proof must end in mate
+        let mut board = board;
+        for &mv in &result.main_line {
+            assert!(board.legal_moves().as_slice().contains(&mv));
+            board.make_move(mv);
+        }
+        if result.outcome == Outcome::Proven {
+            assert!(board.is_checkmate());
+        }
+        result
+    }
+
+    #[test]
+    fn proves_mate_in_two() {
+        let result = solve("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1", 100_000);
+        assert_eq!(result.outcome, Outcome::Proven);
+        assert_eq!(result.main_line[0].to_string(), "d5f6");
+    }
+
+    #[test]
+    fn main_line_makes_progress() {
+        // almost every rook move keeps the win, but the line must still end in mate (checked by
+        // `solve`), not wander between won positions
+        let result = solve("7k/8/8/8/5K2/8/8/1R6 w - - 0 1", 100_000);
+        assert_eq!(result.outcome, Outcome::Proven);
+    }
+
+    #[test]
+    fn proves_deeper_mate() {
+        // mate in four, with sacrifices
+        let result = solve("r4r1k/1R1R2p1/7p/8/8/3Q1Ppq/P7/6K1 w - - 0 1", 200_000);
+        assert_eq!(result.outcome, Outcome::Proven);
+        assert_eq!(result.main_line.len() % 2, 1);
+    }
+
+    #[test]
+    fn disproves_drawn_ending() {
+        // a lone knight can never mate
+        let result = solve("8/8/4k3/8/8/3NK3/8/8 w - - 0 1", 10_000);
+        assert_eq!(result.outcome, Outcome::Disproven);
+        // nor can a side which is not to move and has only its king
+        let board = Board::new("7k/8/8/8/8/8/8/KQ6 w - - 0 1".to_string()).unwrap();
+        assert_eq!(Solver::new(board, Color::Black, 10_000).solve().outcome, Outcome::Disproven);
+    }
+
+    #[test]
+    fn budget() {
+        let result = solve("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", 500);
+        assert_eq!(result.outcome, Outcome::Unknown);
+        assert!(result.nodes < 1_000);
+        assert!(result.main_line.is_empty());
+    }
+}
@@ -0,0 +1,414 @@
+//! Distance-to-mate tables we generate ourselves (see [`super::retro`]) for endings of up to four
+//! pieces.
+//!
+//! A table holds one byte for every placement of its pieces with either side to move:
+//!
+//! | byte   | meaning                                                            |
+//! |--------|--------------------------------------------------------------------|
+//! | 0      | draw                                                               |
+//! | 1..255 | `plies + 1`: odd plies the side to move mates, even plies it is mated |
+//! | 255    | not a legal position, or not the canonical form of one             |
+//!
+//! Positions are indexed by the side to move, the white king, the black king and then every other
+//! piece, 64 squares each. The board is mirrored so that the white king stands in the a1-d1-d4
+//! triangle, or only on files a to d when there are pawns. The fifty-move rule is ignored.
+//!
+//! A file is the magic `DTM1`, the length of the ending's name (`KQKR`: the white pieces, then the
+//! black ones), the name, and the table.
+
+use std::{collections::HashMap, fmt, fs, io, path::Path};
+
+use crate::board::{Board, Variant};
+use crate::types::{Color, Piece, Square};
+
+pub const DTM_MAGIC: [u8; 4] = *b"DTM1";
+
+/// The endings the generator builds by default, each after the ones it converts into
+pub const ENDINGS: [&str; 5] = ["KQK", "KRK", "KPK", "KBNK", "KQKR"];
+
+/// Most pieces of an ending we can generate
+pub const MAX_DTM_PIECES: usize = 4;
+
+pub const DRAW: u8 = 0;
+pub const ILLEGAL: u8 = 255;
+
+/// The a1-d1-d4 triangle the white king is mirrored into
+const TRIANGLE: [u8; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+#[derive(Debug)]
+pub enum DtmErr {
+    Io(io::Error),
+    /// Not an ending of two kings and at most two other pieces, like `KBNK`
+    InvalidName(String),
+    /// A table needed to generate another one, which captures or promotes into it
+    MissingTable(String),
+    /// The file is not a table, or its size does not match the ending
+    BadFile,
+}
+
+impl fmt::Display for DtmErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DtmErr::Io(err) => write!(f, "Could not read table: {}", err),
+            DtmErr::InvalidName(name) => write!(f, "{} is not an ending of at most {} pieces", name, MAX_DTM_PIECES),
+            DtmErr::MissingTable(name) => write!(f, "The {} table must be generated first", name),
+            DtmErr::BadFile => write!(f, "Not a distance-to-mate table"),
+        }
+    }
+}
+
+impl From<io::Error> for DtmErr {
+    fn from(err: io::Error) -> Self {
+        DtmErr::Io(err)
+    }
+}
+
+/// Result of a position for the side to move, with the quickest mate for the winner and the
+/// slowest for the loser
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dtm {
+    /// Mates in this many plies
+    Win(u8),
+    /// Is mated in this many plies
+    Loss(u8),
+    Draw,
+}
+
+impl Dtm {
+    /// Reads a stored byte. None for illegal positions.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            DRAW => Some(Dtm::Draw),
+            ILLEGAL => None,
+            byte if byte % 2 == 0 => Some(Dtm::Win(byte - 1)),
+            byte => Some(Dtm::Loss(byte - 1)),
+        }
+    }
+}
+
+/// Piece letters from the strongest, in the order tables list them
+const LETTERS: [(char, Piece); 5] = [('Q', Piece::Queen), ('R', Piece::Rook), ('B', Piece::Bishop), ('N', Piece::Knight), ('P', Piece::Pawn)];
+
+fn letter(piece: Piece) -> char {
+    LETTERS.iter().find(|&&(_, p)| p == piece).map_or('K', |&(c, _)| c)
+}
+
+/// The name of an ending from its pieces, the kings included
+pub fn ending_name(pieces: &[(Color, Piece)]) -> String {
+    let mut name = String::new();
+    for color in [Color::White, Color::Black] {
+        name.push('K');
+        for &(_, piece) in LETTERS.iter() {
+            let count = pieces.iter().filter(|&&(c, p)| c == color && p == piece).count();
+            name.extend(std::iter::repeat_n(letter(piece), count));
+        }
+    }
+    name
+}
+
+/// The pieces of an ending like `KBNK`: the kings first, then the other white and black pieces
+/// from the strongest
+pub fn parse_ending(name: &str) -> Result<Vec<(Color, Piece)>, DtmErr> {
+    let invalid = || DtmErr::InvalidName(name.to_string());
+    let black_king = name.get(1..).and_then(|rest| rest.find('K')).ok_or_else(invalid)? + 1;
+    let (white, black) = name.split_at(black_king);
+    let mut pieces = vec![(Color::White, Piece::King), (Color::Black, Piece::King)];
+    for (color, side) in [(Color::White, white), (Color::Black, black)] {
+        let side = side.strip_prefix('K').ok_or_else(invalid)?;
+        for c in side.chars() {
+            let &(_, piece) = LETTERS.iter().find(|&&(letter, _)| letter == c).ok_or_else(invalid)?;
+            pieces.push((color, piece));
+        }
+    }
+    if pieces.len() > MAX_DTM_PIECES || ending_name(&pieces) != name {
+        return Err(invalid())
+    }
+    Ok(pieces)
+}
+
+/// A position as the tables see it: no castling rights, en passant or move counters
+#[derive(Clone, PartialEq, Debug)]
+pub struct Placement {
+    pub white_to_move: bool,
+    /// Square of each piece, in the order of the table's pieces
+    pub squares: Vec<u8>,
+}
+
+/// One of the eight symmetries of the board: bit 0 mirrors the files, bit 1 the ranks, and bit 2
+/// then swaps them
+fn transform(square: u8, symmetry: usize) -> u8 {
+    let mut square = square;
+    if symmetry & 1 != 0 {
+        square ^= 7;
+    }
+    if symmetry & 2 != 0 {
+        square ^= 56;
+    }
+    if symmetry & 4 != 0 {
+        square = ((square >> 3) | (square << 3)) & 63;
+    }
+    square
+}
+
+pub struct DtmTable {
+    name: String,
+    pieces: Vec<(Color, Piece)>,
+    has_pawns: bool,
+    /// Indices of the white king's squares
+    king_slots: [Option<u8>; 64],
+    values: Vec<u8>,
+}
+
+impl DtmTable {
+    /// A table of the ending with every position illegal
+    pub fn empty(name: &str) -> Result<Self, DtmErr> {
+        let pieces = parse_ending(name)?;
+        let has_pawns = pieces.iter().any(|&(_, piece)| piece == Piece::Pawn);
+        let mut king_slots = [None; 64];
+        if has_pawns {
+            for square in (0..64).filter(|square| square & 7 <= 3) {
+                king_slots[square as usize] = Some((square >> 3) * 4 + (square & 7));
+            }
+        } else {
+            for (slot, &square) in TRIANGLE.iter().enumerate() {
+                king_slots[square as usize] = Some(slot as u8);
+            }
+        }
+        let slots = if has_pawns { 32 } else { TRIANGLE.len() };
+        let size = 2 * slots * 64usize.pow(pieces.len() as u32 - 1);
+        Ok(Self { name: name.to_string(), pieces, has_pawns, king_slots, values: vec![ILLEGAL; size] })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The kings, then the other pieces
+    pub fn pieces(&self) -> &[(Color, Piece)] {
+        &self.pieces
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn set_value(&mut self, index: usize, value: u8) {
+        self.values[index] = value;
+    }
+
+    fn symmetries(&self) -> usize {
+        if self.has_pawns { 2 } else { 8 }
+    }
+
+    /// The index of the position, mirrored into its canonical form. Placements which only differ
+    /// by a symmetry, or by swapping equal pieces, get the same index.
+    pub fn index(&self, placement: &Placement) -> usize {
+        let mut best = usize::MAX;
+        for symmetry in 0..self.symmetries() {
+            let Some(slot) = self.king_slots[transform(placement.squares[0], symmetry) as usize] else { continue };
+            let len = placement.squares.len();
+            let mut squares = [0; MAX_DTM_PIECES];
+            for (square, &from) in squares.iter_mut().zip(&placement.squares) {
+                *square = transform(from, symmetry);
+            }
+            // equal pieces are interchangeable
+            for i in 3..len {
+                if self.pieces[i] == self.pieces[i - 1] && squares[i] < squares[i - 1] {
+                    squares.swap(i, i - 1);
+                }
+            }
+            let mut index = placement.white_to_move as usize;
+            index = index * if self.has_pawns { 32 } else { TRIANGLE.len() } + slot as usize;
+            for &square in &squares[1..len] {
+                index = index * 64 + square as usize;
+            }
+            best = best.min(index);
+        }
+        best
+    }
+
+    /// The position of an index, which may not be legal or canonical
+    pub fn placement(&self, index: usize) -> Placement {
+        let mut index = index;
+        let mut squares = vec![0; self.pieces.len()];
+        for square in squares[1..].iter_mut().rev() {
+            *square = (index % 64) as u8;
+            index /= 64;
+        }
+        let slots = if self.has_pawns { 32 } else { TRIANGLE.len() };
+        let slot = index % slots;
+        squares[0] = if self.has_pawns { (slot / 4 * 8 + slot % 4) as u8 } else { TRIANGLE[slot] };
+        Placement { white_to_move: index / slots == 1, squares }
+    }
+
+    pub fn probe(&self, placement: &Placement) -> Option<Dtm> {
+        Dtm::from_byte(self.values[self.index(placement)])
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = DTM_MAGIC.to_vec();
+        bytes.push(self.name.len() as u8);
+        bytes.extend(self.name.as_bytes());
+        bytes.extend(&self.values);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DtmErr> {
+        if bytes.get(..4) != Some(&DTM_MAGIC) {
+            return Err(DtmErr::BadFile)
+        }
+        let len = *bytes.get(4).ok_or(DtmErr::BadFile)? as usize;
+        let name = bytes.get(5..5 + len).and_then(|name| std::str::from_utf8(name).ok()).ok_or(DtmErr::BadFile)?;
+        let mut table = Self::empty(name)?;
+        let values = &bytes[5 + len..];
+        if values.len() != table.values.len() {
+            return Err(DtmErr::BadFile)
+        }
+        table.values.copy_from_slice(values);
+        Ok(table)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, DtmErr> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Wins and losses for the side to move, draws, and the longest forced mate in plies, counting
+    /// each canonical position once
+    pub fn stats(&self) -> (usize, usize, usize, u8) {
+        let (mut wins, mut losses, mut draws, mut longest) = (0, 0, 0, 0);
+        for &value in &self.values {
+            match Dtm::from_byte(value) {
+                Some(Dtm::Win(plies)) => {
+                    wins += 1;
+                    longest = longest.max(plies);
+                }
+                Some(Dtm::Loss(_)) => losses += 1,
+                Some(Dtm::Draw) => draws += 1,
+                None => {}
+            }
+        }
+        (wins, losses, draws, longest)
+    }
+}
+
+/// The generated tables, by name
+#[derive(Default)]
+pub struct DtmTables {
+    tables: HashMap<String, DtmTable>,
+    max_pieces: usize,
+}
+
+impl DtmTables {
+    /// Loads every `.dtm` file of a directory. Files which are not tables are skipped.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let mut tables = Self::default();
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "dtm") {
+                if let Ok(table) = DtmTable::load(&path) {
+                    tables.insert(table);
+                }
+            }
+        }
+        Ok(tables)
+    }
+
+    pub fn insert(&mut self, table: DtmTable) {
+        self.max_pieces = self.max_pieces.max(table.pieces.len());
+        self.tables.insert(table.name.clone(), table);
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, name: &str) -> Option<&DtmTable> {
+        self.tables.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Looks up pieces on squares. Positions which cannot be won by either side are draws without
+    /// a table. None if the ending has no table.
+    pub fn probe_pieces(&self, pieces: &[(Color, Piece, u8)], white_to_move: bool) -> Option<Dtm> {
+        let kinds: Vec<(Color, Piece)> = pieces.iter().map(|&(color, piece, _)| (color, piece)).collect();
+        if insufficient_material(&kinds) {
+            return Some(Dtm::Draw)
+        }
+        // a table of the same ending with the colours swapped will do, with the board flipped
+        for swapped in [false, true] {
+            let flip = |color: Color| if swapped { !color } else { color };
+            let name = ending_name(&kinds.iter().map(|&(color, piece)| (flip(color), piece)).collect::<Vec<_>>());
+            let Some(table) = self.tables.get(&name) else { continue };
+            let mut squares = vec![0; pieces.len()];
+            let mut used = vec![false; pieces.len()];
+            for (i, &(color, piece)) in table.pieces.iter().enumerate() {
+                let j = (0..pieces.len()).find(|&j| !used[j] && flip(pieces[j].0) == color && pieces[j].1 == piece)?;
+                used[j] = true;
+                squares[i] = if swapped { pieces[j].2 ^ 56 } else { pieces[j].2 };
+            }
+            return table.probe(&Placement { white_to_move: white_to_move != swapped, squares })
+        }
+        None
+    }
+
+    /// Looks up a position. None if its ending has no table, or it has castling rights.
+    pub fn probe(&self, board: &Board) -> Option<Dtm> {
+        if !board.castling().is_empty() || board.occupied().count() > self.max_pieces.max(3) || board.variant() != Variant::Standard {
+            return None
+        }
+        let mut pieces = vec![];
+        for color in [Color::White, Color::Black] {
+            for p in 0..Piece::NUM {
+                let piece = Piece::new(p);
+                pieces.extend(board.pieces_of(piece, color).map(|square: Square| (color, piece, square as u8)));
+            }
+        }
+        self.probe_pieces(&pieces, board.side_to_move == Color::White)
+    }
+}
+
+/// Bare kings, or a single minor piece
+fn insufficient_material(pieces: &[(Color, Piece)]) -> bool {
+    pieces.len() == 2 || (pieces.len() == 3 && pieces.iter().any(|&(_, piece)| matches!(piece, Piece::Bishop | Piece::Knight)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(parse_ending("KQKR").unwrap().len(), 4);
+        assert!(parse_ending("KRKQ").unwrap()[3] == (Color::Black, Piece::Queen));
+        assert_eq!(ending_name(&parse_ending("KBNK").unwrap()), "KBNK");
+        // pieces must come strongest first, and there must be two kings
+        assert!(parse_ending("KNBK").is_err());
+        assert!(parse_ending("KQ").is_err());
+        assert!(parse_ending("KQRPK").is_err());
+    }
+
+    #[test]
+    fn symmetric_placements_share_an_index() {
+        let table = DtmTable::empty("KRK").unwrap();
+        let placement = Placement { white_to_move: true, squares: vec![62, 20, 33] };
+        let index = table.index(&placement);
+        assert_eq!(table.placement(index).squares[0], 1);
+        for symmetry in 0..8 {
+            let squares = placement.squares.iter().map(|&square| transform(square, symmetry)).collect();
+            assert_eq!(table.index(&Placement { white_to_move: true, squares }), index);
+        }
+        // with pawns only the files can be mirrored
+        let table = DtmTable::empty("KPK").unwrap();
+        let index = table.index(&Placement { white_to_move: false, squares: vec![6, 60, 14] });
+        assert_eq!(table.placement(index), Placement { white_to_move: false, squares: vec![1, 59, 9] });
+        assert_ne!(table.index(&Placement { white_to_move: false, squares: vec![62, 4, 54] }), index);
+    }
+}
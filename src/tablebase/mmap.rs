@@ -0,0 +1,84 @@
+//! Read-only memory maps of tablebase files. Only the parts of a table that are probed are ever
+//! read from disk, which matters for the larger tables.
+
+use std::{fs::File, io, ops::Deref, path::Path};
+
+pub struct Mmap {
+    inner: Inner,
+}
+
+enum Inner {
+    #[cfg(unix)]
+    Mapped { ptr: *const u8, len: usize },
+    /// Empty files cannot be mapped, and other platforms read the whole file
+    Owned(Vec<u8>),
+}
+
+// the mapping is never written to, so it can be shared between search threads
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_SHARED: c_int = 1;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    extern "C" {
+        pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+impl Mmap {
+    #[cfg(unix)]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(Self { inner: Inner::Owned(vec![]) })
+        }
+        let ptr = unsafe { sys::mmap(std::ptr::null_mut(), len, sys::PROT_READ, sys::MAP_SHARED, file.as_raw_fd(), 0) };
+        if ptr == sys::MAP_FAILED {
+            return Err(io::Error::last_os_error())
+        }
+        // the mapping stays valid after the file is closed
+        Ok(Self { inner: Inner::Mapped { ptr: ptr as *const u8, len } })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        use std::io::Read;
+
+        let mut bytes = vec![];
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Ok(Self { inner: Inner::Owned(bytes) })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.inner {
+            #[cfg(unix)]
+            Inner::Mapped { ptr, len } => unsafe { std::slice::from_raw_parts(*ptr, *len) },
+            Inner::Owned(bytes) => bytes,
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Inner::Mapped { ptr, len } = self.inner {
+            unsafe {
+                sys::munmap(ptr as *mut _, len);
+            }
+        }
+    }
+}
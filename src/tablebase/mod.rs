@@ -0,0 +1,632 @@
+//! Probing Syzygy endgame tablebases from local files.
+//!
+//! Two kinds of tables are read: WDL tables (`.rtbw`) give the result of a position with best
+//! play, taking the fifty-move rule into account, and DTZ tables (`.rtbz`) give the distance to
+//! the next capture or pawn move (zeroing the fifty-move counter) on the way to that result. Both
+//! only store positions without castling rights, and leave out positions where a capture is best,
+//! so probing searches the captures first.
+//!
+//! The search uses WDL results to cut off subtrees, and the root uses DTZ to keep only the moves
+//! which make progress towards the best result.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    ops::Neg,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::board::{Board, Variant};
+use crate::types::{Color, Move, Piece};
+
+use self::mmap::Mmap;
+use self::table::{Material, Position, Probe, Table, TableKind, BLACK, KING};
+
+mod dtm;
+mod mmap;
+mod retro;
+mod table;
+#[cfg(test)]
+mod writer;
+
+pub use self::dtm::{Dtm, DtmTables, ENDINGS};
+pub use self::retro::generate;
+pub use self::table::MAX_PIECES;
+
+/// Result of a position for the side to move, with best play by both sides
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    /// A loss which the fifty-move rule turns into a draw
+    BlessedLoss = -1,
+    Draw = 0,
+    /// A win which the fifty-move rule turns into a draw
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Option<Self> {
+        Some(match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            2 => Wdl::Win,
+            _ => return None,
+        })
+    }
+
+    /// The DTZ of a position where the best move zeroes the fifty-move counter
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Wdl::Loss => -1,
+            Wdl::BlessedLoss => -101,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 101,
+            Wdl::Win => 1,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Wdl::from_value(-(self as i32)).unwrap()
+    }
+}
+
+/// Root moves which reach a win within this many plies rank the same
+const MAX_DTZ: i32 = 1 << 18;
+
+#[derive(Default)]
+pub struct Tablebases {
+    /// Tables by the material they are named after, white first
+    wdl: HashMap<Material, Arc<Table>>,
+    dtz: HashMap<Material, Arc<Table>>,
+    /// Most pieces of any table found
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    /// Opens the tables in the given directories, separated like the `PATH` variable. Files
+    /// which are not valid tables are skipped.
+    pub fn open(paths: &str) -> Self {
+        let mut tablebases = Self::default();
+        for dir in env::split_paths(paths) {
+            let Ok(entries) = fs::read_dir(&dir) else { continue };
+            for entry in entries.flatten() {
+                tablebases.add(&entry.path());
+            }
+        }
+        tablebases
+    }
+
+    /// Adds a single table file, named after its material like `KRvK.rtbw`. Returns false if it
+    /// is not a table.
+    pub fn add(&mut self, path: &Path) -> bool {
+        let kind = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("rtbw") => TableKind::Wdl,
+            Some("rtbz") => TableKind::Dtz,
+            _ => return false,
+        };
+        let Some(material) = path.file_stem().and_then(|stem| stem.to_str()).and_then(parse_material) else {
+            return false
+        };
+        let Ok(bytes) = Mmap::open(path) else { return false };
+        let Some(table) = Table::new(bytes, kind, material) else { return false };
+        self.max_pieces = self.max_pieces.max(table.piece_count);
+        let tables = match kind {
+            TableKind::Wdl => &mut self.wdl,
+            TableKind::Dtz => &mut self.dtz,
+        };
+        tables.insert(material, Arc::new(table));
+        true
+    }
+
+    /// Number of tables found, of both kinds
+    pub fn len(&self) -> usize {
+        self.wdl.len() + self.dtz.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Whether the position can be looked up: few enough pieces and no castling rights
+    pub fn can_probe(&self, board: &Board) -> bool {
+        board.occupied().count() <= self.max_pieces && board.castling().is_empty() && board.variant() == Variant::Standard
+    }
+
+    /// The result of the position for the side to move. None if it is not in the tables.
+    pub fn probe_wdl(&self, board: &mut Board) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None
+        }
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Plies to the next capture or pawn move with best play, positive if the side to move wins
+    /// and negative if it loses, or 0 for a draw. Wins and losses the fifty-move rule turns into
+    /// draws are off by 100. It may be one ply too many when the result is a win or a loss within
+    /// the fifty-move rule, as the tables round to whole moves.
+    pub fn probe_dtz(&self, board: &mut Board) -> Option<i32> {
+        if !self.can_probe(board) {
+            return None
+        }
+        self.dtz(board)
+    }
+
+    /// The root moves which keep the best result and make the most progress towards it, taking
+    /// the fifty-move rule into account. None if the position is not in the tables.
+    pub fn root_moves(&self, board: &mut Board) -> Option<Vec<Move>> {
+        if !self.can_probe(board) || board.legal_moves().len == 0 {
+            return None
+        }
+        let halfmove_clock = board.halfmove_clock() as i32;
+        let repeated = board.is_repetition();
+        let moves = *board.legal_moves();
+        let mut ranked = Vec::with_capacity(moves.len);
+        for &mv in &moves.moves[..moves.len] {
+            board.make_move(mv);
+            let dtz = if board.halfmove_clock() == 0 {
+                self.search(board, false).map(|(wdl, _)| (-wdl).dtz_before_zeroing())
+            } else if board.is_repetition() || board.is_fifty_move_draw() {
+                Some(0)
+            } else {
+                // one more ply from the root
+                self.probe_dtz(board).map(|dtz| -dtz + dtz.signum())
+            };
+            let mates = board.is_checkmate();
+            board.undo_move();
+            let mut dtz = dtz?;
+            if mates && dtz == 2 {
+                dtz = 1;
+            }
+            // Faster wins and slower losses rank higher, unless the fifty-move rule gets in the
+            // way: then only how far the draw claim is matters.
+            let rank = if dtz > 0 {
+                if dtz + halfmove_clock <= 99 && !repeated { MAX_DTZ - dtz } else { MAX_DTZ / 2 - (dtz + halfmove_clock) }
+            } else if dtz < 0 {
+                if -dtz * 2 + halfmove_clock < 100 { -MAX_DTZ - dtz } else { -MAX_DTZ / 2 + (-dtz + halfmove_clock) }
+            } else {
+                0
+            };
+            ranked.push((mv, rank));
+        }
+        let best = ranked.iter().map(|&(_, rank)| rank).max()?;
+        Some(ranked.into_iter().filter(|&(_, rank)| rank == best).map(|(mv, _)| mv).collect())
+    }
+
+    fn table(&self, kind: TableKind, position: &Position) -> Option<&Table> {
+        let tables = match kind {
+            TableKind::Wdl => &self.wdl,
+            TableKind::Dtz => &self.dtz,
+        };
+        let [white, black] = position.material;
+        tables.get(&position.material).or_else(|| tables.get(&[black, white])).map(|table| &**table)
+    }
+
+    /// Looks the position up without searching captures first
+    fn probe_table(&self, board: &Board, kind: TableKind, wdl: Wdl) -> Option<Probe> {
+        let position = position(board);
+        if position.pieces.len() == 2 {
+            // bare kings
+            return Some(Probe::Value(Wdl::Draw as i32 + 2))
+        }
+        self.table(kind, &position)?.probe(&position, wdl as i32)
+    }
+
+    /// The WDL result, and whether the best move zeroes the fifty-move counter. The tables do not
+    /// store positions where a capture is best, so captures (and with `zeroing`, pawn moves) are
+    /// searched first.
+    fn search(&self, board: &mut Board, zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves = *board.legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for &mv in &moves.moves[..moves.len] {
+            if !(mv.is_capture() || zeroing && board.piece_on(mv.start()) == Piece::Pawn) {
+                continue
+            }
+            searched += 1;
+            board.make_move(mv);
+            let value = self.search(board, false).map(|(wdl, _)| -wdl);
+            board.undo_move();
+            let value = value?;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true))
+                }
+            }
+        }
+        // when every move was searched, the table is not needed
+        let no_more_moves = searched > 0 && searched == moves.len;
+        let value = if no_more_moves {
+            best
+        } else {
+            match self.probe_table(board, TableKind::Wdl, Wdl::Draw)? {
+                Probe::Value(value) => Wdl::from_value(value - 2)?,
+                Probe::ChangeStm => return None,
+            }
+        };
+        if best >= value {
+            Some((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn dtz(&self, board: &mut Board) -> Option<i32> {
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0)
+        }
+        if zeroing {
+            return Some(wdl.dtz_before_zeroing())
+        }
+        let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+        let sign = (wdl as i32).signum();
+        match self.probe_table(board, TableKind::Dtz, wdl)? {
+            Probe::Value(dtz) => Some((dtz + 100 * cursed as i32) * sign),
+            Probe::ChangeStm => {
+                // The table only stores the other side to move: look one ply ahead. The best move
+                // cannot be a zeroing one, or the search above would have found it.
+                let moves = *board.legal_moves();
+                let mut min_dtz = i32::MAX;
+                for &mv in &moves.moves[..moves.len] {
+                    let zeroing = mv.is_capture() || board.piece_on(mv.start()) == Piece::Pawn;
+                    board.make_move(mv);
+                    let dtz = if zeroing {
+                        self.search(board, false).map(|(wdl, _)| -wdl.dtz_before_zeroing())
+                    } else {
+                        self.dtz(board).map(|dtz| -dtz)
+                    };
+                    let mates = board.is_checkmate();
+                    board.undo_move();
+                    let mut dtz = dtz?;
+                    if dtz == 1 && mates {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min_dtz && dtz.signum() == sign {
+                        min_dtz = dtz;
+                    }
+                }
+                Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+            }
+        }
+    }
+}
+
+/// Reads material like `KRvKP`: the white pieces, then the black ones
+fn parse_material(name: &str) -> Option<Material> {
+    let (white, black) = name.split_once('v')?;
+    let mut material = [[0; 6]; 2];
+    for (side, pieces) in [white, black].into_iter().enumerate() {
+        for c in pieces.chars() {
+            let piece = "PNBRQK".find(c)?;
+            material[side][piece] += 1;
+        }
+        if material[side][KING as usize - 1] != 1 {
+            return None
+        }
+    }
+    Some(material)
+}
+
+/// The pieces of the board as the tables see them
+fn position(board: &Board) -> Position {
+    let mut pieces = Vec::with_capacity(MAX_PIECES);
+    let mut material = [[0; 6]; 2];
+    for (side, color) in [Color::White, Color::Black].into_iter().enumerate() {
+        for (p, count) in material[side].iter_mut().enumerate() {
+            for square in board.pieces_of(Piece::new(p), color) {
+                pieces.push((square as u8, p as u8 + 1 + if side == 1 { BLACK } else { 0 }));
+                *count += 1;
+            }
+        }
+    }
+    Position { pieces, black_to_move: board.side_to_move == Color::Black, material }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MAX_PLY;
+    use crate::types::Square;
+
+    use self::writer::TableWriter;
+
+    /// A directory for the tables of one test
+    fn table_dir(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("tablebase-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn adjacent(a: u8, b: u8) -> bool {
+        (a >> 3).abs_diff(b >> 3) <= 1 && (a & 7).abs_diff(b & 7) <= 1
+    }
+
+    /// Squares a rook on `rook` attacks when only `blocker` is in the way
+    fn rook_attacks(rook: u8, blocker: u8, square: u8) -> bool {
+        if square == rook || ((square >> 3) != (rook >> 3) && (square & 7) != (rook & 7)) {
+            return false
+        }
+        let (low, high) = (rook.min(square), rook.max(square));
+        let step = if (square >> 3) == (rook >> 3) { 1 } else { 8 };
+        !(low + step..high).step_by(step as usize).any(|between| between == blocker)
+    }
+
+    /// The true result of KRvK with the stronger side as white: white to move always wins, black
+    /// to move draws by taking an unprotected rook or by being stalemated.
+    fn krvk_wdl(position: &Position) -> Wdl {
+        if !position.black_to_move {
+            return Wdl::Win
+        }
+        let find = |piece| position.pieces.iter().find(|&&(_, p)| p == piece).unwrap().0;
+        let (king, rook, their_king) = (find(KING), find(4), find(KING | BLACK));
+        if adjacent(their_king, rook) && !adjacent(king, rook) {
+            return Wdl::Draw
+        }
+        let attacked = |square: u8| adjacent(king, square) || rook_attacks(rook, king, square);
+        let can_move = (0..64u8).any(|square| square != their_king && adjacent(their_king, square) && !attacked(square));
+        if !can_move && !attacked(their_king) { Wdl::Draw } else { Wdl::Loss }
+    }
+
+    /// Writes KRvK tables: WDL, and a made up DTZ of the distance between the kings
+    fn krvk_tables(name: &str, dtz_map: Option<[Vec<u8>; 4]>) -> Tablebases {
+        let dir = table_dir(name);
+        let material = parse_material("KRvK").unwrap();
+        TableWriter::new(TableKind::Wdl, material)
+            .write(&dir.join("KRvK.rtbw"), |position| Some((krvk_wdl(position) as i32 + 2) as u16));
+        let mut writer = TableWriter::new(TableKind::Dtz, material);
+        if let Some(map) = dtz_map {
+            writer = writer.with_map(map);
+        }
+        writer.write(&dir.join("KRvK.rtbz"), |position| {
+            if position.black_to_move {
+                return None
+            }
+            let find = |piece| position.pieces.iter().find(|&&(_, p)| p == piece).unwrap().0;
+            let (king, their_king) = (find(KING), find(KING | BLACK));
+            Some((king >> 3).abs_diff(their_king >> 3).max((king & 7).abs_diff(their_king & 7)) as u16)
+        });
+        let tablebases = Tablebases::open(dir.to_str().unwrap());
+        assert_eq!(tablebases.len(), 2);
+        assert_eq!(tablebases.max_pieces(), 3);
+        tablebases
+    }
+
+    /// The result from the board's own move generation, for positions with a single rook
+    fn board_wdl(board: &Board) -> Wdl {
+        let rook_side = if board.pieces_of(Piece::Rook, Color::White).is_empty() { Color::Black } else { Color::White };
+        let legal = board.legal_moves().as_slice();
+        if board.side_to_move == rook_side {
+            Wdl::Win
+        } else if board.is_stalemate() || legal.iter().any(|mv| mv.is_capture()) {
+            Wdl::Draw
+        } else {
+            Wdl::Loss
+        }
+    }
+
+    /// Calls `f` with positions of the kings and a rook spread over the board, with either side
+    /// to move
+    fn for_krvk_positions<F: FnMut(&mut Board)>(rook_side: Color, mut f: F) {
+        // setting up boards from FEN is slow, so the pieces of one board are moved around
+        let mut board = Board::new("k7/8/8/8/8/8/8/K6R w - - 0 1".to_string()).unwrap();
+        let mut squares = [Square::A1, Square::A8, Square::H1];
+        let (strong, weak) = (rook_side, !rook_side);
+        board.remove_piece(squares[0], Color::White, Piece::King);
+        board.remove_piece(squares[1], Color::Black, Piece::King);
+        board.remove_piece(squares[2], Color::White, Piece::Rook);
+        for king in (0..64u8).step_by(5) {
+            for their_king in (0..64u8).step_by(3) {
+                for rook in (0..64u8).step_by(7) {
+                    if adjacent(king, their_king) || rook == king || rook == their_king {
+                        continue
+                    }
+                    squares = [Square::new(king), Square::new(their_king), Square::new(rook)];
+                    board.add_piece(squares[0], strong, Piece::King);
+                    board.add_piece(squares[1], weak, Piece::King);
+                    board.add_piece(squares[2], strong, Piece::Rook);
+                    for side in [Color::White, Color::Black] {
+                        // the side not to move cannot be in check
+                        board.side_to_move = !side;
+                        board.analyze_board();
+                        if board.is_check() {
+                            continue
+                        }
+                        board.side_to_move = side;
+                        board.analyze_board();
+                        f(&mut board);
+                    }
+                    board.remove_piece(squares[0], strong, Piece::King);
+                    board.remove_piece(squares[1], weak, Piece::King);
+                    board.remove_piece(squares[2], strong, Piece::Rook);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wdl_matches_the_rules() {
+        let tablebases = krvk_tables("wdl", None);
+        let mut probed = 0;
+        for rook_side in [Color::White, Color::Black] {
+            for_krvk_positions(rook_side, |board| {
+                let expected = board_wdl(board);
+                assert_eq!(tablebases.probe_wdl(board), Some(expected));
+                probed += 1;
+            });
+        }
+        assert!(probed > 1000);
+
+        // other material, and castling rights, are not in the tables
+        let mut board = Board::new("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1".to_string()).unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut board), None);
+        let mut board = Board::new("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1".to_string()).unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut board), None);
+        let mut board = Board::new("4k3/8/8/8/8/8/8/4K3 w - - 0 1".to_string()).unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut board), Some(Wdl::Draw));
+    }
+
+    #[test]
+    fn dtz_steps_towards_zeroing() {
+        // a mapped table, which stores 10 more than the distance for wins
+        let map = [(10..18).collect(), vec![], vec![], vec![]];
+        let tablebases = krvk_tables("dtz", Some(map));
+        let distance = |a: Square, b: Square| crate::types::distance(&a, &b) as i32;
+        let king = |board: &Board, color| board.pieces_of(Piece::King, color).into_iter().next().unwrap();
+        for rook_side in [Color::White, Color::Black] {
+            for_krvk_positions(rook_side, |board| {
+                let dtz = tablebases.probe_dtz(board).unwrap();
+                let expected = if board.side_to_move == rook_side {
+                    2 * (distance(king(board, Color::White), king(board, Color::Black)) + 10) + 1
+                } else if board_wdl(board) == Wdl::Draw {
+                    0
+                } else {
+                    // the stronger side's DTZ after the move which keeps the kings furthest apart
+                    let moves = *board.legal_moves();
+                    let furthest = moves.moves[..moves.len]
+                        .iter()
+                        .map(|mv| distance(mv.target(), king(board, rook_side)))
+                        .max();
+                    furthest.map_or(-1, |d| -(2 * (d + 10) + 2))
+                };
+                assert_eq!(dtz, expected);
+            });
+        }
+    }
+
+    #[test]
+    fn root_keeps_the_rook() {
+        let tablebases = krvk_tables("root", None);
+        // Rb2 and the king can hang the rook to the king on c3
+        for halfmove_clock in [0, 98] {
+            let fen = format!("8/8/8/8/8/2k5/1R6/6K1 w - - {} 60", halfmove_clock);
+            let mut board = Board::new(fen).unwrap();
+            let moves = tablebases.root_moves(&mut board).unwrap();
+            assert!(!moves.is_empty());
+            for mv in &moves {
+                board.make_move(*mv);
+                assert_eq!(tablebases.probe_wdl(&mut board), Some(Wdl::Loss));
+                board.undo_move();
+            }
+            // every move left heads for the same, smallest, DTZ
+            let dtz: Vec<i32> = moves
+                .iter()
+                .map(|&mv| {
+                    board.make_move(mv);
+                    let dtz = tablebases.probe_dtz(&mut board).unwrap();
+                    board.undo_move();
+                    dtz
+                })
+                .collect();
+            assert!(dtz.windows(2).all(|pair| pair[0] == pair[1]));
+            let legal = *board.legal_moves();
+            for &mv in &legal.moves[..legal.len] {
+                board.make_move(mv);
+                let child = tablebases.probe_dtz(&mut board).unwrap();
+                board.undo_move();
+                if child < 0 {
+                    assert!(child <= dtz[0]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn search_keeps_the_win() {
+        use crate::search::{time::TimeManager, Search, SearchLimits, TB_WIN};
+
+        let tablebases = Arc::new(krvk_tables("search", None));
+        let search = |fen: &str| {
+            let board = Board::new(fen.to_string()).unwrap();
+            let limits = SearchLimits { depth: Some(3), ..Default::default() };
+            Search::new(board, limits, TimeManager::infinite(), Arc::default())
+                .with_tablebases(tablebases.clone(), MAX_PIECES)
+                .run(|_| {})
+        };
+        // the root moves are narrowed down to those which keep the rook
+        let fen = "8/8/8/8/8/2k5/1R6/6K1 w - - 0 1";
+        let best_move = search(fen).best_move.unwrap();
+        let mut board = Board::new(fen.to_string()).unwrap();
+        assert!(tablebases.root_moves(&mut board).unwrap().contains(&best_move));
+        // taking the knight reaches a won position in the tables
+        let result = search("8/8/8/3k4/8/8/1n6/1R4K1 w - - 0 1");
+        assert_eq!(result.best_move.unwrap().to_string(), "b1b2");
+        assert!(result.score >= TB_WIN - MAX_PLY as i32);
+    }
+
+    #[test]
+    fn symmetric_positions_share_an_index() {
+        let dir = table_dir("index");
+        let material = parse_material("KRvK").unwrap();
+        let path = dir.join("KRvK.rtbw");
+        TableWriter::new(TableKind::Wdl, material).write(&path, |_| Some(2));
+        let table = Table::new(Mmap::open(&path).unwrap(), TableKind::Wdl, material).unwrap();
+        let data = table.pairs(0, 0);
+        let size = data.size();
+        assert_eq!(size, 31332);
+        let index = |squares: [u8; 3]| {
+            let mut squares = squares;
+            let mut pieces = [KING, 4, KING | BLACK];
+            table.index(data, &mut squares, &mut pieces, 0).unwrap()
+        };
+        let transforms: [fn(u8) -> u8; 4] = [|s| s ^ 7, |s| s ^ 56, |s| s ^ 63, |s| ((s >> 3) | (s << 3)) & 63];
+        for king in 0..64 {
+            for rook in (0..64).filter(|&rook| rook != king) {
+                for their_king in (0..64).filter(|&square| square != king && square != rook && !adjacent(square, king)) {
+                    let idx = index([king, rook, their_king]);
+                    assert!(idx < size);
+                    for transform in transforms {
+                        assert_eq!(index([transform(king), transform(rook), transform(their_king)]), idx);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tables from the published Syzygy set, which the writer cannot stand in for: they have
+    /// pawns and Huffman-coded pairs. They are read from `tests/syzygy`, or from the directories
+    /// in `SYZYGY_PATH`, and are not in the repository, so the test only runs when asked for.
+    #[test]
+    #[ignore = "needs the published KQvK, KRvK, KPvK and KBNvK tables, see tests/syzygy/README.md"]
+    fn real_tables() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+        let paths = env::var("SYZYGY_PATH").unwrap_or_else(|_| fixtures.to_string());
+        let tablebases = Tablebases::open(&paths);
+        assert!(!tablebases.is_empty(), "no tables in {}", paths);
+        let cases = [
+            // Qa8 mates
+            ("7k/8/6K1/8/8/8/8/Q7 w - - 0 1", Wdl::Win, Some(1)),
+            ("8/8/8/8/8/2k5/1R6/6K1 w - - 0 1", Wdl::Win, None),
+            // Kxb2
+            ("8/8/8/8/8/2k5/1R6/6K1 b - - 0 1", Wdl::Draw, Some(0)),
+            // a rook's pawn, with the defending king in the corner
+            ("7k/8/8/8/8/8/7P/7K w - - 0 1", Wdl::Draw, Some(0)),
+            ("8/8/8/4k3/8/8/8/KBN5 w - - 0 1", Wdl::Win, None),
+            ("8/8/8/4k3/8/8/8/KBN5 b - - 0 1", Wdl::Loss, None),
+        ];
+        for (fen, wdl, dtz) in cases {
+            let mut board = Board::new(fen.to_string()).unwrap();
+            assert_eq!(tablebases.probe_wdl(&mut board), Some(wdl), "{}", fen);
+            let probed = tablebases.probe_dtz(&mut board).unwrap();
+            assert_eq!(probed.signum(), (wdl as i32).signum(), "{}", fen);
+            if let Some(dtz) = dtz {
+                assert_eq!(probed, dtz, "{}", fen);
+            }
+        }
+    }
+}
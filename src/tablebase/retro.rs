@@ -0,0 +1,431 @@
+//! Builds the distance-to-mate tables of [`super::dtm`] by retrograde analysis.
+//!
+//! Every legal placement is first set up on a [`Board`], whose legal moves give the number of
+//! positions it can move to. Captures and promotions leave the ending, so their results come from
+//! the tables generated before. Then, one ply at a time, the positions decided at that distance
+//! are un-moved: a position with a move into a loss is won one ply later, and a position whose
+//! last move turns out to lose is lost. Whatever is left at the end is a draw.
+//!
+//! The un-move generator is written independently of the board's move generation, so generating a
+//! table also checks the two against each other on sparse boards.
+
+use crate::board::Board;
+use crate::types::{Color, Piece, Square};
+
+use super::dtm::{ending_name, Dtm, DtmErr, DtmTable, DtmTables, Placement, DRAW, ILLEGAL};
+
+/// Values of positions during generation, besides their distance to mate
+const UNKNOWN: u8 = 254;
+const DRAWN: u8 = 253;
+/// The longest mate a table can store
+const MAX_PLIES: u8 = 252;
+
+const KING_STEPS: [(i8, i8); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+const KNIGHT_STEPS: [(i8, i8); 8] = [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+/// The square a number of files and ranks away, if it is on the board
+fn step(square: u8, (files, ranks): (i8, i8)) -> Option<u8> {
+    let file = (square & 7) as i8 + files;
+    let rank = (square >> 3) as i8 + ranks;
+    ((0..8).contains(&file) && (0..8).contains(&rank)).then_some((rank * 8 + file) as u8)
+}
+
+/// The directions a piece slides in. A queen goes the way a king steps.
+fn directions(piece: Piece) -> &'static [(i8, i8)] {
+    match piece {
+        Piece::Queen => &KING_STEPS,
+        Piece::Rook => &ROOK_DIRECTIONS,
+        Piece::Bishop => &BISHOP_DIRECTIONS,
+        _ => &[],
+    }
+}
+
+fn attacks(piece: Piece, color: Color, from: u8, target: u8, occupied: u64) -> bool {
+    match piece {
+        Piece::Pawn => {
+            let forward = if color == Color::White { 1 } else { -1 };
+            [(-1, forward), (1, forward)].iter().any(|&offset| step(from, offset) == Some(target))
+        }
+        Piece::Knight => KNIGHT_STEPS.iter().any(|&offset| step(from, offset) == Some(target)),
+        Piece::King => KING_STEPS.iter().any(|&offset| step(from, offset) == Some(target)),
+        _ => directions(piece).iter().any(|&direction| {
+            let mut square = from;
+            while let Some(next) = step(square, direction) {
+                if next == target {
+                    return true
+                }
+                if occupied >> next & 1 != 0 {
+                    break
+                }
+                square = next;
+            }
+            false
+        }),
+    }
+}
+
+fn occupancy(squares: &[u8]) -> u64 {
+    squares.iter().fold(0, |occupied, &square| occupied | 1 << square)
+}
+
+/// Whether the pieces can stand on these squares: one piece per square, no pawns on the first or
+/// last rank, and the side not to move is not in check
+pub fn is_legal(pieces: &[(Color, Piece)], placement: &Placement) -> bool {
+    let squares = &placement.squares;
+    let occupied = occupancy(squares);
+    if occupied.count_ones() as usize != squares.len() {
+        return false
+    }
+    let pawn_on_back_rank = pieces.iter().zip(squares).any(|(&(_, piece), &square)| piece == Piece::Pawn && !(8..56).contains(&square));
+    if pawn_on_back_rank || attacks(Piece::King, Color::White, squares[0], squares[1], occupied) {
+        return false
+    }
+    let mover = if placement.white_to_move { Color::White } else { Color::Black };
+    let their_king = squares[if mover == Color::White { 1 } else { 0 }];
+    !pieces.iter().zip(squares).any(|(&(color, piece), &from)| color == mover && attacks(piece, color, from, their_king, occupied))
+}
+
+/// The positions which reach this one by a move of the side not to move that neither captures
+/// nor promotes. Some of them may not be legal.
+pub fn unmoves(pieces: &[(Color, Piece)], placement: &Placement) -> Vec<Placement> {
+    let mover = if placement.white_to_move { Color::Black } else { Color::White };
+    let occupied = occupancy(&placement.squares);
+    let empty = |square: u8| occupied >> square & 1 == 0;
+    let mut predecessors = vec![];
+    for (i, &(color, piece)) in pieces.iter().enumerate() {
+        if color != mover {
+            continue
+        }
+        let to = placement.squares[i];
+        let mut origins = vec![];
+        match piece {
+            Piece::Pawn => {
+                let (back, double_push_rank) = if color == Color::White { (-1, 3) } else { (1, 4) };
+                if let Some(from) = step(to, (0, back)).filter(|&from| empty(from) && (8..56).contains(&from)) {
+                    origins.push(from);
+                    if to >> 3 == double_push_rank {
+                        origins.extend(step(from, (0, back)).filter(|&from| empty(from)));
+                    }
+                }
+            }
+            Piece::Knight => origins.extend(KNIGHT_STEPS.iter().filter_map(|&offset| step(to, offset)).filter(|&from| empty(from))),
+            Piece::King => origins.extend(KING_STEPS.iter().filter_map(|&offset| step(to, offset)).filter(|&from| empty(from))),
+            _ => {
+                for &direction in directions(piece) {
+                    let mut square = to;
+                    while let Some(from) = step(square, direction).filter(|&from| empty(from)) {
+                        origins.push(from);
+                        square = from;
+                    }
+                }
+            }
+        }
+        for from in origins {
+            let mut squares = placement.squares.clone();
+            squares[i] = from;
+            predecessors.push(Placement { white_to_move: !placement.white_to_move, squares });
+        }
+    }
+    predecessors
+}
+
+/// A board which placements are set up on. Setting up boards from FEN is slow, so the pieces of
+/// the last placement are taken off instead.
+struct Setter {
+    board: Board,
+    placed: Vec<(Square, Color, Piece)>,
+}
+
+impl Setter {
+    fn new() -> Self {
+        let mut board = Board::new("4k3/8/8/8/8/8/8/4K3 w - - 0 1".to_string()).unwrap();
+        board.remove_piece(Square::E1, Color::White, Piece::King);
+        board.remove_piece(Square::E8, Color::Black, Piece::King);
+        Self { board, placed: vec![] }
+    }
+
+    fn set(&mut self, pieces: &[(Color, Piece)], placement: &Placement) -> &Board {
+        for (square, color, piece) in self.placed.drain(..) {
+            self.board.remove_piece(square, color, piece);
+        }
+        for (&(color, piece), &square) in pieces.iter().zip(&placement.squares) {
+            self.board.add_piece(Square::new(square), color, piece);
+            self.placed.push((Square::new(square), color, piece));
+        }
+        self.board.side_to_move = if placement.white_to_move { Color::White } else { Color::Black };
+        self.board.analyze_board();
+        &self.board
+    }
+}
+
+/// The position after a move which captures or promotes, as pieces on squares
+fn converted(pieces: &[(Color, Piece)], placement: &Placement, moving: usize, to: u8, promotion: Option<Piece>) -> Vec<(Color, Piece, u8)> {
+    pieces
+        .iter()
+        .zip(&placement.squares)
+        .enumerate()
+        .filter(|&(_, (_, &square))| square != to)
+        .map(|(i, (&(color, piece), &square))| match i == moving {
+            true => (color, promotion.unwrap_or(piece), to),
+            false => (color, piece, square),
+        })
+        .collect()
+}
+
+/// Generates the table of an ending. The endings it captures or promotes into must be in
+/// `tables`, unless neither side can win them.
+pub fn generate(name: &str, tables: &DtmTables) -> Result<DtmTable, DtmErr> {
+    let mut table = DtmTable::empty(name)?;
+    let pieces = table.pieces().to_vec();
+    let len = table.len();
+    let mut values = vec![UNKNOWN; len];
+    // moves which have not been shown to lose, including captures and promotions which don't
+    let mut counters = vec![0u8; len];
+    // the quickest mate by capturing or promoting, and the slowest way to be mated that way
+    let mut conversion_wins = vec![UNKNOWN; len];
+    let mut conversion_losses = vec![0u8; len];
+    // the longest mate found so far, which the plies must reach
+    let mut longest = 0;
+
+    let mut setter = Setter::new();
+    let mut children = vec![];
+    for index in 0..len {
+        let placement = table.placement(index);
+        if table.index(&placement) != index || !is_legal(&pieces, &placement) {
+            values[index] = ILLEGAL;
+            continue
+        }
+        let board = setter.set(&pieces, &placement);
+        let moves = *board.legal_moves();
+        if moves.len == 0 {
+            values[index] = if board.is_check() { 0 } else { DRAWN };
+            continue
+        }
+        children.clear();
+        let mut unresolved = 0;
+        for mv in &moves.moves[..moves.len] {
+            let (from, to) = (mv.start() as u8, mv.target() as u8);
+            let moving = placement.squares.iter().position(|&square| square == from).unwrap();
+            if !placement.squares.contains(&to) && !mv.is_promotion() {
+                let mut child = placement.clone();
+                child.squares[moving] = to;
+                child.white_to_move = !child.white_to_move;
+                children.push(table.index(&child));
+                continue
+            }
+            let promotion = mv.is_promotion().then(|| mv.promo_piece());
+            let child = converted(&pieces, &placement, moving, to, promotion);
+            let result = tables.probe_pieces(&child, !placement.white_to_move).ok_or_else(|| {
+                DtmErr::MissingTable(ending_name(&child.iter().map(|&(color, piece, _)| (color, piece)).collect::<Vec<_>>()))
+            })?;
+            match result {
+                Dtm::Loss(plies) => {
+                    conversion_wins[index] = conversion_wins[index].min(plies + 1);
+                    longest = longest.max(plies + 1);
+                    unresolved += 1;
+                }
+                Dtm::Win(plies) => conversion_losses[index] = conversion_losses[index].max(plies + 1),
+                Dtm::Draw => unresolved += 1,
+            }
+        }
+        children.sort_unstable();
+        children.dedup();
+        counters[index] = (children.len() + unresolved) as u8;
+        if counters[index] == 0 {
+            // every move converts into a loss
+            values[index] = conversion_losses[index];
+            longest = longest.max(values[index]);
+        }
+    }
+
+    let mut predecessors = vec![];
+    let mut plies = 0;
+    loop {
+        if plies % 2 == 1 {
+            for (value, &win) in values.iter_mut().zip(&conversion_wins) {
+                if *value == UNKNOWN && win == plies {
+                    *value = plies;
+                }
+            }
+        }
+        let mut decided = false;
+        for index in 0..len {
+            if values[index] != plies {
+                continue
+            }
+            decided = true;
+            predecessors.clear();
+            predecessors.extend(unmoves(&pieces, &table.placement(index)).iter().map(|placement| table.index(placement)));
+            predecessors.sort_unstable();
+            predecessors.dedup();
+            for &predecessor in &predecessors {
+                if values[predecessor] != UNKNOWN {
+                    continue
+                }
+                if plies % 2 == 0 {
+                    values[predecessor] = plies + 1;
+                } else {
+                    counters[predecessor] -= 1;
+                    if counters[predecessor] > 0 {
+                        continue
+                    }
+                    values[predecessor] = (plies + 1).max(conversion_losses[predecessor]);
+                }
+                longest = longest.max(values[predecessor]);
+            }
+        }
+        if !decided && plies >= longest {
+            break
+        }
+        assert!(plies < MAX_PLIES, "mates in {} are too long to store", name);
+        plies += 1;
+    }
+
+    for (index, &value) in values.iter().enumerate() {
+        table.set_value(index, match value {
+            UNKNOWN | DRAWN => DRAW,
+            ILLEGAL => ILLEGAL,
+            plies => plies + 1,
+        });
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tablebase::dtm::parse_ending;
+
+    fn tables(names: &[&str]) -> DtmTables {
+        let mut tables = DtmTables::default();
+        for name in names {
+            let table = generate(name, &tables).unwrap();
+            tables.insert(table);
+        }
+        tables
+    }
+
+    fn probe(tables: &DtmTables, fen: &str) -> Option<Dtm> {
+        tables.probe(&Board::new(fen.to_string()).unwrap())
+    }
+
+    #[test]
+    fn longest_mates() {
+        let tables = tables(&["KQK", "KRK"]);
+        // mate in 10 and 16 moves
+        assert_eq!(tables.get("KQK").unwrap().stats().3, 19);
+        assert_eq!(tables.get("KRK").unwrap().stats().3, 31);
+        assert_eq!(probe(&tables, "7k/8/6K1/8/8/8/8/1Q6 w - - 0 1"), Some(Dtm::Win(1)));
+        assert_eq!(probe(&tables, "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), Some(Dtm::Draw));
+        assert_eq!(probe(&tables, "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1"), Some(Dtm::Loss(0)));
+        // the same positions with the colours swapped
+        assert_eq!(probe(&tables, "1q6/8/8/8/8/6k1/8/7K b - - 0 1"), Some(Dtm::Win(1)));
+        assert_eq!(probe(&tables, "8/8/8/8/8/6k1/6q1/7K w - - 0 1"), Some(Dtm::Loss(0)));
+        // the side to move can take the queen
+        assert_eq!(probe(&tables, "8/8/8/8/8/8/6Qk/K7 b - - 0 1"), Some(Dtm::Draw));
+        // castling rights are not in the tables
+        assert_eq!(probe(&tables, "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"), None);
+        assert_eq!(probe(&tables, "4k3/8/8/8/8/8/8/4K2B w - - 0 1"), Some(Dtm::Draw));
+    }
+
+    #[test]
+    fn pawn_endings() {
+        let tables = tables(&["KQK", "KRK", "KPK"]);
+        assert!(matches!(probe(&tables, "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(Dtm::Win(_))));
+        assert_eq!(probe(&tables, "8/8/8/8/8/4k3/4P3/4K3 w - - 0 1"), Some(Dtm::Draw));
+        // a rook's pawn only draws against a king in front of it
+        assert_eq!(probe(&tables, "k7/8/8/8/8/8/P7/K7 w - - 0 1"), Some(Dtm::Draw));
+        // the pawn promotes, and the queen mates
+        assert!(matches!(probe(&tables, "8/4P3/8/8/8/k7/8/4K3 b - - 0 1"), Some(Dtm::Loss(_))));
+    }
+
+    #[test]
+    fn search_finds_the_mate() {
+        use std::sync::Arc;
+        use crate::search::{time::TimeManager, Search, SearchLimits, MATE};
+
+        let tables = Arc::new(tables(&["KQK", "KRK"]));
+        let fen = "8/8/8/3k4/8/8/8/R3K3 w - - 0 1";
+        let Some(Dtm::Win(plies)) = probe(&tables, fen) else { panic!("KRK is won") };
+        let limits = SearchLimits { depth: Some(2), ..Default::default() };
+        let result = Search::new(Board::new(fen.to_string()).unwrap(), limits, TimeManager::infinite(), Arc::default())
+            .with_dtm_tables(tables)
+            .run(|_| {});
+        assert_eq!(result.score, MATE - plies as i32);
+    }
+
+    /// Every move the board generates must be un-moved back, and every un-move must be a move.
+    /// Only every `stride`-th position is checked, to keep the test quick.
+    fn check_unmoves(name: &str, stride: usize) {
+        let table = DtmTable::empty(name).unwrap();
+        let pieces = parse_ending(name).unwrap();
+        let mut setter = Setter::new();
+        let mut checked = 0;
+        for index in (0..table.len()).step_by(stride) {
+            let placement = table.placement(index);
+            if table.index(&placement) != index || !is_legal(&pieces, &placement) {
+                continue
+            }
+            let board = setter.set(&pieces, &placement);
+            let moves = *board.legal_moves();
+            let mut children = vec![];
+            for mv in &moves.moves[..moves.len] {
+                let (from, to) = (mv.start() as u8, mv.target() as u8);
+                if placement.squares.contains(&to) || mv.is_promotion() {
+                    continue
+                }
+                let mut child = placement.clone();
+                let moving = child.squares.iter().position(|&square| square == from).unwrap();
+                child.squares[moving] = to;
+                child.white_to_move = !child.white_to_move;
+                assert!(unmoves(&pieces, &child).contains(&placement), "{} is not un-moved", mv);
+                children.push(child);
+            }
+            for predecessor in unmoves(&pieces, &placement) {
+                if !is_legal(&pieces, &predecessor) {
+                    continue
+                }
+                let board = setter.set(&pieces, &predecessor);
+                let moves = *board.legal_moves();
+                let reached = moves.moves[..moves.len].iter().any(|mv| {
+                    let moving = predecessor.squares.iter().position(|&square| square == mv.start() as u8).unwrap();
+                    let mut child = predecessor.clone();
+                    child.squares[moving] = mv.target() as u8;
+                    child.white_to_move = !child.white_to_move;
+                    !mv.is_promotion() && child == placement
+                });
+                assert!(reached, "{:?} does not move to {:?}", predecessor, placement);
+            }
+            checked += children.len();
+        }
+        assert!(checked > 10000);
+    }
+
+    #[test]
+    fn unmoves_match_moves() {
+        check_unmoves("KRK", 2);
+        check_unmoves("KPK", 4);
+        check_unmoves("KQKR", 401);
+    }
+
+    #[test]
+    fn missing_tables() {
+        assert!(matches!(generate("KQKR", &DtmTables::default()), Err(DtmErr::MissingTable(_))));
+        assert!(matches!(generate("KQKQK", &DtmTables::default()), Err(DtmErr::InvalidName(_))));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let tables = tables(&["KQK"]);
+        let table = tables.get("KQK").unwrap();
+        let path = std::env::temp_dir().join(format!("dtm-test-{}.dtm", std::process::id()));
+        table.save(&path).unwrap();
+        let loaded = DtmTable::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.name(), "KQK");
+        assert_eq!(loaded.stats(), table.stats());
+        assert!(matches!(DtmTable::from_bytes(b"DTM1\x03KQK"), Err(DtmErr::BadFile)));
+    }
+}
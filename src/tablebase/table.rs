@@ -0,0 +1,785 @@
+//! The Syzygy file format: how positions are mapped to indices, and how the values at those
+//! indices are decompressed.
+//!
+//! A table file starts with a magic number and a flags byte, followed by one sub-table for each
+//! side to move (WDL tables of unsymmetric material have two, DTZ tables always one) and, for
+//! tables with pawns, each file a to d of the leading pawn. Every sub-table lists its pieces in
+//! the order they are indexed in, and holds its values compressed with recursive pairing and a
+//! canonical Huffman code, in blocks which can be decompressed on their own.
+
+use std::sync::OnceLock;
+
+use super::mmap::Mmap;
+
+pub const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+pub const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+/// Most pieces the format can index
+pub const MAX_PIECES: usize = 7;
+
+// flags of the whole file
+const HAS_PAWNS: u8 = 2;
+
+// flags of a sub-table
+pub const STM: u8 = 1;
+pub const MAPPED: u8 = 2;
+pub const WIN_PLIES: u8 = 4;
+pub const LOSS_PLIES: u8 = 8;
+pub const WIDE: u8 = 16;
+pub const SINGLE_VALUE: u8 = 128;
+
+/// Pieces as the files encode them: 1 (pawn) to 6 (king), plus 8 for black
+pub type PieceCode = u8;
+pub const KING: PieceCode = 6;
+pub const BLACK: PieceCode = 8;
+
+/// How many of each piece (pawn to king) each side has
+pub type Material = [[u8; 6]; 2];
+
+/// The same material with the colours swapped
+pub fn mirror(material: &Material) -> Material {
+    [material[1], material[0]]
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+/// A position as the tables see it
+pub struct Position {
+    /// Square (0 for a1 to 63 for h8) and piece
+    pub pieces: Vec<(u8, PieceCode)>,
+    pub black_to_move: bool,
+    pub material: Material,
+}
+
+/// What probing a table gives
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Probe {
+    /// The stored value: the WDL score plus 2, or the DTZ after mapping
+    Value(i32),
+    /// A DTZ table which only stores the other side to move
+    ChangeStm,
+}
+
+/// Tables computed once, which map placements of pieces to indices
+struct Indices {
+    /// Squares below the a1-h8 diagonal to 0..28
+    map_b1h1h7: [u64; 64],
+    /// The a1-d1-d4 triangle to 0..10, the diagonal last
+    map_a1d1d4: [u64; 64],
+    /// The 462 placements of two kings, the first in the a1-d1-d4 triangle
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; MAX_PIECES],
+    /// Pawn squares a2 to h7 to 0..48. The leading pawn is the one with the highest value: nearest
+    /// the edge, then lowest.
+    map_pawns: [u64; 64],
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+}
+
+/// Rank minus file: zero on the a1-h8 diagonal, negative below it
+fn off_a1h8(square: u8) -> i32 {
+    (square >> 3) as i32 - (square & 7) as i32
+}
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(|| {
+        let mut indices = Indices {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; MAX_PIECES],
+            lead_pawns_size: [[0; 4]; MAX_PIECES],
+        };
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_a1h8(square) < 0 {
+                indices.map_b1h1h7[square as usize] = code;
+                code += 1;
+            }
+        }
+
+        let mut code = 0;
+        let mut diagonal = vec![];
+        for square in 0..=27u8 {
+            if off_a1h8(square) < 0 && square & 7 <= 3 {
+                indices.map_a1d1d4[square as usize] = code;
+                code += 1;
+            } else if off_a1h8(square) == 0 && square & 7 <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            indices.map_a1d1d4[square as usize] = code;
+            code += 1;
+        }
+
+        let adjacent = |a: u8, b: u8| (a >> 3).abs_diff(b >> 3) <= 1 && (a & 7).abs_diff(b & 7) <= 1;
+        let mut both_on_diagonal = vec![];
+        let mut code = 0;
+        for idx in 0..10 {
+            // b1 is the only square mapped to 0, the others just default to it
+            for s1 in (0..=27u8).filter(|&s1| indices.map_a1d1d4[s1 as usize] == idx && (idx != 0 || s1 == 1)) {
+                for s2 in 0..64u8 {
+                    if adjacent(s1, s2) || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {
+                        // touching kings, or both on the diagonal's upper side
+                        continue
+                    }
+                    if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        indices.map_kk[idx as usize][s2 as usize] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            indices.map_kk[idx as usize][s2 as usize] = code;
+            code += 1;
+        }
+
+        indices.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                indices.binomial[k][n] = if k > 0 { indices.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { indices.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut available = 47;
+        for lead_pawns in 1..MAX_PIECES {
+            for file in 0..4u8 {
+                let mut idx = 0;
+                for rank in 1..7u8 {
+                    let square = (rank << 3 | file) as usize;
+                    if lead_pawns == 1 {
+                        indices.map_pawns[square] = available;
+                        indices.map_pawns[square ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+                    indices.lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += indices.binomial[lead_pawns - 1][indices.map_pawns[square] as usize];
+                }
+                indices.lead_pawns_size[lead_pawns][file as usize] = idx;
+            }
+        }
+        indices
+    })
+}
+
+/// Little-endian reads at an offset into the file, checked against its end
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let value = read_u16(self.bytes, self.pos)?;
+        self.pos += 2;
+        Some(value)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+        Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn align(&mut self, to: usize) {
+        self.pos = self.pos.next_multiple_of(to);
+    }
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(pos..pos + 2)?.try_into().unwrap()))
+}
+
+/// Big-endian, reading zeroes past the end of the file
+fn read_u32_be(bytes: &[u8], pos: usize) -> u32 {
+    let mut word = [0; 4];
+    for (i, byte) in word.iter_mut().enumerate() {
+        *byte = bytes.get(pos + i).copied().unwrap_or(0);
+    }
+    u32::from_be_bytes(word)
+}
+
+/// One sub-table: the order its pieces are indexed in and how its values are compressed. Offsets
+/// are from the start of the file.
+#[derive(Clone, Default)]
+pub struct PairsData {
+    pub flags: u8,
+    pub pieces: [PieceCode; MAX_PIECES],
+    /// Sizes of the groups of pieces indexed together, ending with a zero
+    pub group_len: [usize; MAX_PIECES + 1],
+    /// What the index of each group is multiplied by. The entry after the last group is the size
+    /// of the table.
+    pub group_idx: [u64; MAX_PIECES + 1],
+    block_size: u64,
+    span: u64,
+    num_blocks: u64,
+    block_length_size: u64,
+    min_sym_len: u8,
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    /// How many values each symbol stands for, minus one
+    symlen: Vec<u8>,
+    btree: usize,
+    sparse_index: usize,
+    sparse_index_size: u64,
+    block_lengths: usize,
+    data: usize,
+    /// Where the value maps of a DTZ table start, for each kind of result
+    map_idx: [u16; 4],
+}
+
+impl PairsData {
+    pub fn size(&self) -> u64 {
+        let groups = self.group_len.iter().position(|&len| len == 0).unwrap_or(MAX_PIECES);
+        self.group_idx[groups]
+    }
+
+    /// Left child of a symbol, or its value if it is a leaf
+    fn left(&self, bytes: &[u8], sym: usize) -> usize {
+        let lr = &bytes[self.btree + 3 * sym..];
+        ((lr[1] as usize & 0xF) << 8) | lr[0] as usize
+    }
+
+    fn right(&self, bytes: &[u8], sym: usize) -> usize {
+        let lr = &bytes[self.btree + 3 * sym..];
+        (lr[2] as usize) << 4 | (lr[1] as usize) >> 4
+    }
+
+    fn lowest_sym(&self, bytes: &[u8], len: usize) -> u64 {
+        read_u16(bytes, self.lowest_sym + 2 * len).unwrap_or(0) as u64
+    }
+
+    /// Counts the values every symbol stands for. Each pair symbol stands for its two halves.
+    fn set_symlen(&mut self, bytes: &[u8], sym: usize, visited: &mut [bool]) -> u8 {
+        visited[sym] = true;
+        let right = self.right(bytes, sym);
+        if right == 0xFFF {
+            return 0
+        }
+        let left = self.left(bytes, sym);
+        for child in [left, right] {
+            if child < visited.len() && !visited[child] {
+                self.symlen[child] = self.set_symlen(bytes, child, visited);
+            }
+        }
+        let len = |child: usize| self.symlen.get(child).copied().unwrap_or(0);
+        len(left).wrapping_add(len(right)).wrapping_add(1)
+    }
+
+    /// Reads how the sub-table is compressed
+    fn set_sizes(&mut self, cursor: &mut Cursor) -> Option<()> {
+        self.flags = cursor.u8()?;
+        if self.flags & SINGLE_VALUE != 0 {
+            // the one value every position has
+            self.min_sym_len = cursor.u8()?;
+            return Some(())
+        }
+        let size = self.size();
+        self.block_size = 1u64.checked_shl(cursor.u8()? as u32)?;
+        self.span = 1u64.checked_shl(cursor.u8()? as u32)?;
+        self.sparse_index_size = size.div_ceil(self.span);
+        let padding = cursor.u8()?;
+        self.num_blocks = cursor.u32()? as u64;
+        // padded so that the sparse index never points past the end
+        self.block_length_size = self.num_blocks + padding as u64;
+        let max_sym_len = cursor.u8()?;
+        self.min_sym_len = cursor.u8()?;
+        if max_sym_len < self.min_sym_len || self.min_sym_len == 0 {
+            return None
+        }
+        self.lowest_sym = cursor.pos;
+        let lengths = (max_sym_len - self.min_sym_len) as usize + 1;
+        let bytes = cursor.bytes;
+        // The canonical code puts longer codes at lower values. base64[i] is the lowest code of
+        // length min_sym_len + i, left-aligned in 64 bits, so codes of that length lie between
+        // base64[i] and base64[i - 1].
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            self.base64[i] = self.base64[i + 1]
+                .wrapping_add(self.lowest_sym(bytes, i))
+                .wrapping_sub(self.lowest_sym(bytes, i + 1))
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base.checked_shl(64 - i as u32 - self.min_sym_len as u32).unwrap_or(0);
+        }
+        cursor.pos += 2 * lengths;
+        let symbols = cursor.u16()? as usize;
+        self.btree = cursor.pos;
+        cursor.pos += 3 * symbols + (symbols & 1);
+        if cursor.pos > bytes.len() {
+            return None
+        }
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(bytes, sym, &mut visited);
+            }
+        }
+        Some(())
+    }
+
+    /// The value at `idx`
+    fn decompress(&self, bytes: &[u8], idx: u64) -> Option<i32> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as i32)
+        }
+        // The sparse index gives, for every `span` values, the block and offset of the value in
+        // the middle. From there we walk to the block holding `idx`.
+        let k = idx / self.span;
+        let entry = self.sparse_index + 6 * k as usize;
+        let mut block = u32::from_le_bytes(bytes.get(entry..entry + 4)?.try_into().unwrap()) as u64;
+        let mut offset = read_u16(bytes, entry + 4)? as i64;
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+        let block_length = |block: u64| -> Option<i64> {
+            if block >= self.block_length_size {
+                return None
+            }
+            Some(read_u16(bytes, self.block_lengths + 2 * block as usize)? as i64)
+        };
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        // walk the Huffman codes of the block until the symbol which holds our value
+        let mut pos = self.data + (block * self.block_size) as usize;
+        let mut buf = (read_u32_be(bytes, pos) as u64) << 32 | read_u32_be(bytes, pos + 4) as u64;
+        pos += 8;
+        let mut buf_size = 64;
+        let min_len = self.min_sym_len as u32;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while len + 1 < self.base64.len() && buf < self.base64[len] {
+                len += 1;
+            }
+            sym = ((buf - self.base64[len]) >> (64 - len as u32 - min_len)) as usize;
+            sym += self.lowest_sym(bytes, len) as usize;
+            let values = *self.symlen.get(sym)? as i64 + 1;
+            if offset < values {
+                break
+            }
+            offset -= values;
+            let len = len as u32 + min_len;
+            buf <<= len;
+            buf_size -= len as i32;
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= (read_u32_be(bytes, pos) as u64) << (64 - buf_size);
+                pos += 4;
+            }
+        }
+
+        // the symbol stands for a run of values: go down the pairs to the one we want
+        while self.symlen[sym] != 0 {
+            let left = self.left(bytes, sym);
+            let left_values = *self.symlen.get(left)? as i64 + 1;
+            if offset < left_values {
+                sym = left;
+            } else {
+                offset -= left_values;
+                sym = self.right(bytes, sym);
+            }
+            if sym >= self.symlen.len() {
+                return None
+            }
+        }
+        Some(self.left(bytes, sym) as i32)
+    }
+}
+
+pub struct Table {
+    bytes: Mmap,
+    kind: TableKind,
+    /// The material as the file is named, and with the colours swapped
+    pub key: Material,
+    pub key2: Material,
+    pub piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    /// Pawns of the leading side (the one with fewer pawns, if both have some) and of the other
+    pawn_count: [usize; 2],
+    /// By side to move, then by file of the leading pawn
+    pairs: Vec<Vec<PairsData>>,
+    /// Start of the value maps of a DTZ table
+    map: usize,
+}
+
+impl Table {
+    /// Reads the header of a table file for `material`. None if it is not a valid table.
+    pub fn new(bytes: Mmap, kind: TableKind, material: Material) -> Option<Self> {
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if bytes.get(..4)? != magic {
+            return None
+        }
+        let [white, black] = material;
+        let piece_count = material.iter().flatten().map(|&n| n as usize).sum();
+        let white_leads = black[0] == 0 || (white[0] > 0 && black[0] >= white[0]);
+        let (lead, other) = if white_leads { (white[0], black[0]) } else { (black[0], white[0]) };
+        let mut table = Self {
+            bytes,
+            kind,
+            key: material,
+            key2: mirror(&material),
+            piece_count,
+            has_pawns: white[0] + black[0] > 0,
+            has_unique_pieces: material.iter().any(|side| side[..5].contains(&1)),
+            pawn_count: [lead as usize, other as usize],
+            pairs: vec![],
+            map: 0,
+        };
+        if piece_count > MAX_PIECES {
+            return None
+        }
+        table.read_header()?;
+        Some(table)
+    }
+
+    fn sides(&self) -> usize {
+        if self.kind == TableKind::Wdl && self.key != self.key2 { 2 } else { 1 }
+    }
+
+    fn files(&self) -> usize {
+        if self.has_pawns { 4 } else { 1 }
+    }
+
+    fn read_header(&mut self) -> Option<()> {
+        let bytes: &[u8] = &self.bytes;
+        let mut cursor = Cursor { bytes, pos: 4 };
+        let flags = cursor.u8()?;
+        if (flags & HAS_PAWNS != 0) != self.has_pawns {
+            return None
+        }
+        let (sides, files) = (self.sides(), self.files());
+        // both sides have pawns
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+        for file in 0..files {
+            let first = cursor.u8()?;
+            let second = if pp { cursor.u8()? } else { 0xFF };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            for k in 0..self.piece_count {
+                let byte = cursor.u8()?;
+                for (side, pairs) in pairs.iter_mut().enumerate() {
+                    pairs[file].pieces[k] = if side == 0 { byte & 0xF } else { byte >> 4 };
+                }
+            }
+            for (side, pairs) in pairs.iter_mut().enumerate() {
+                self.set_groups(&mut pairs[file], order[side], file)?;
+            }
+        }
+        cursor.align(2);
+        for file in 0..files {
+            for pairs in pairs.iter_mut() {
+                pairs[file].set_sizes(&mut cursor)?;
+            }
+        }
+        if self.kind == TableKind::Dtz {
+            self.map = cursor.pos;
+            for data in pairs[0].iter_mut() {
+                if data.flags & MAPPED == 0 {
+                    continue
+                }
+                if data.flags & WIDE != 0 {
+                    cursor.align(2);
+                    for i in 0..4 {
+                        data.map_idx[i] = ((cursor.pos - self.map) / 2 + 1) as u16;
+                        cursor.pos += 2 * cursor.u16()? as usize;
+                    }
+                } else {
+                    for i in 0..4 {
+                        data.map_idx[i] = (cursor.pos - self.map + 1) as u16;
+                        cursor.pos += cursor.u8()? as usize;
+                    }
+                }
+            }
+            cursor.align(2);
+        }
+        for file in 0..files {
+            for pairs in pairs.iter_mut() {
+                pairs[file].sparse_index = cursor.pos;
+                cursor.pos += 6 * pairs[file].sparse_index_size as usize;
+            }
+        }
+        for file in 0..files {
+            for pairs in pairs.iter_mut() {
+                pairs[file].block_lengths = cursor.pos;
+                cursor.pos += 2 * pairs[file].block_length_size as usize;
+            }
+        }
+        for file in 0..files {
+            for pairs in pairs.iter_mut() {
+                // single values have no data, and files may end before the alignment
+                if pairs[file].num_blocks > 0 {
+                    cursor.align(64);
+                }
+                pairs[file].data = cursor.pos;
+                cursor.pos += (pairs[file].num_blocks * pairs[file].block_size) as usize;
+            }
+        }
+        if cursor.pos > bytes.len() {
+            return None
+        }
+        self.pairs = pairs;
+        Some(())
+    }
+
+    /// Splits the pieces into the groups which are indexed together, and works out the factor of
+    /// each group. `order` gives the position of the leading group and of the other side's pawns
+    /// in the index.
+    fn set_groups(&self, data: &mut PairsData, order: [u8; 2], file: usize) -> Option<()> {
+        let indices = indices();
+        let mut n = 0;
+        // the leading group: the two kings, three unique pieces, or the leading pawns
+        let mut first_len: i32 = if self.has_pawns { 0 } else if self.has_unique_pieces { 3 } else { 2 };
+        data.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || data.pieces[i] == data.pieces[i - 1] {
+                data.group_len[n] += 1;
+            } else {
+                n += 1;
+                data.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        data.group_len[n] = 0;
+
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if pp { 2 } else { 1 };
+        let mut free_squares = 64 - data.group_len[0] - if pp { data.group_len[1] } else { 0 };
+        let mut idx = 1u64;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                data.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    indices.lead_pawns_size[data.group_len[0]][file]
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                data.group_idx[1] = idx;
+                idx *= indices.binomial[data.group_len[1]][48 - data.group_len[0]];
+            } else {
+                if next >= MAX_PIECES {
+                    return None
+                }
+                data.group_idx[next] = idx;
+                idx *= indices.binomial[*data.group_len.get(next)?][free_squares];
+                free_squares = free_squares.checked_sub(data.group_len[next])?;
+                next += 1;
+            }
+            k += 1;
+        }
+        data.group_idx[n] = idx;
+        Some(())
+    }
+
+    /// Looks up a position with this table's material (either way round). `wdl` is the result of
+    /// the position, which DTZ tables need to map their values.
+    pub fn probe(&self, position: &Position, wdl: i32) -> Option<Probe> {
+        // Tables are stored with the named side as white. Positions where black has that material,
+        // and positions of symmetric material with black to move, are looked up with the colours
+        // swapped and the board flipped.
+        let symmetric_btm = self.key == self.key2 && position.black_to_move;
+        let flip = symmetric_btm || position.material != self.key;
+        let flip_color = if flip { BLACK } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip ^ position.black_to_move) as usize;
+
+        let mut squares = [0u8; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0;
+        let mut file = 0;
+        let lead_pawn = if self.has_pawns { Some(self.pairs[0][0].pieces[0] ^ flip_color) } else { None };
+        if let Some(lead_pawn) = lead_pawn {
+            for &(square, piece) in position.pieces.iter().filter(|&&(_, piece)| piece == lead_pawn) {
+                squares[size] = square ^ flip_squares;
+                pieces[size] = piece ^ flip_color;
+                size += 1;
+            }
+            lead_pawns = size;
+            let map_pawns = &indices().map_pawns;
+            let lead = (0..lead_pawns).max_by_key(|&i| map_pawns[squares[i] as usize])?;
+            squares.swap(0, lead);
+            file = (squares[0] & 7).min(7 - (squares[0] & 7)) as usize;
+        }
+
+        if self.kind == TableKind::Dtz {
+            let flags = self.pairs[0][file].flags;
+            // without pawns, symmetric material with the other side to move is the same position with
+            // the colours swapped
+            if (self.has_pawns || self.key != self.key2) && (flags & STM) as usize != stm {
+                return Some(Probe::ChangeStm)
+            }
+        }
+
+        for &(square, piece) in position.pieces.iter().filter(|&&(_, piece)| Some(piece) != lead_pawn) {
+            if size == MAX_PIECES {
+                return None
+            }
+            squares[size] = square ^ flip_squares;
+            pieces[size] = piece ^ flip_color;
+            size += 1;
+        }
+        if size != self.piece_count {
+            return None
+        }
+
+        let data = &self.pairs[stm % self.sides()][file];
+        let idx = self.index(data, &mut squares[..size], &mut pieces[..size], lead_pawns);
+        let value = data.decompress(&self.bytes, idx?)?;
+        Some(Probe::Value(match self.kind {
+            TableKind::Wdl => value,
+            TableKind::Dtz => self.map_score(file, value, wdl)?,
+        }))
+    }
+
+    /// The index of a position, given with the table's colours. The leading pawns come first.
+    pub fn index(&self, data: &PairsData, squares: &mut [u8], pieces: &mut [PieceCode], lead_pawns: usize) -> Option<u64> {
+        let indices = indices();
+        let size = squares.len();
+        // put the pieces in the order of the table
+        for i in lead_pawns..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| data.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // mirror so that the leading piece is on files a to d
+        if squares[0] & 7 > 3 {
+            squares.iter_mut().for_each(|square| *square ^= 7);
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = indices.lead_pawn_idx[lead_pawns][squares[0] as usize];
+            squares[1..lead_pawns].sort_by_key(|&square| indices.map_pawns[square as usize]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                idx += indices.binomial[i][indices.map_pawns[square as usize] as usize];
+            }
+        } else {
+            // without pawns, also mirror to ranks 1 to 4, then below the a1-h8 diagonal
+            if squares[0] >> 3 > 3 {
+                squares.iter_mut().for_each(|square| *square ^= 56);
+            }
+            for i in 0..data.group_len[0] {
+                match off_a1h8(squares[i]) {
+                    0 => continue,
+                    off if off > 0 => {
+                        for square in &mut squares[i..] {
+                            *square = ((*square >> 3) | (*square << 3)) & 63;
+                        }
+                    }
+                    _ => {}
+                }
+                break
+            }
+
+            let rank = |square: u8| (square >> 3) as u64;
+            let map = |square: u8| square as u64;
+            if self.has_unique_pieces {
+                let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+                let adjust1 = (s1 > s0) as u64;
+                let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+                idx = if off_a1h8(s0) != 0 {
+                    (indices.map_a1d1d4[s0 as usize] * 63 + (map(s1) - adjust1)) * 62 + map(s2) - adjust2
+                } else if off_a1h8(s1) != 0 {
+                    (6 * 63 + rank(s0) * 28 + indices.map_b1h1h7[s1 as usize]) * 62 + map(s2) - adjust2
+                } else if off_a1h8(s2) != 0 {
+                    6 * 63 * 62 + 4 * 28 * 62 + rank(s0) * 7 * 28 + (rank(s1) - adjust1) * 28
+                        + indices.map_b1h1h7[s2 as usize]
+                } else {
+                    6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank(s0) * 7 * 6 + (rank(s1) - adjust1) * 6
+                        + (rank(s2) - adjust2)
+                };
+            } else {
+                idx = indices.map_kk[indices.map_a1d1d4[squares[0] as usize] as usize][squares[1] as usize];
+            }
+        }
+        idx *= data.group_idx[0];
+
+        // the other groups, each an ascending combination of the squares the earlier groups left
+        let mut start = data.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while data.group_len[next] != 0 {
+            let len = data.group_len[next];
+            squares[start..start + len].sort();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&earlier| square > earlier).count();
+                let free = (square as usize).checked_sub(adjust + 8 * remaining_pawns as usize)?;
+                n += indices.binomial[i + 1][free];
+            }
+            remaining_pawns = false;
+            idx += n * data.group_idx[next];
+            start += len;
+            next += 1;
+        }
+        Some(idx)
+    }
+
+    /// Turns a stored DTZ value into plies to a zeroing move
+    fn map_score(&self, file: usize, value: i32, wdl: i32) -> Option<i32> {
+        // the maps are stored for a win, a loss, a cursed win and a blessed loss
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        let data = &self.pairs[0][file];
+        let mut value = value;
+        if data.flags & MAPPED != 0 {
+            let i = data.map_idx[WDL_MAP[(wdl + 2) as usize]] as usize + value as usize;
+            value = if data.flags & WIDE != 0 {
+                read_u16(&self.bytes, self.map + 2 * i)? as i32
+            } else {
+                *self.bytes.get(self.map + i)? as i32
+            };
+        }
+        // values are in moves unless flagged as plies, and always in moves for the results
+        // the fifty-move rule turns into draws
+        if (wdl == 2 && data.flags & WIN_PLIES == 0) || (wdl == -2 && data.flags & LOSS_PLIES == 0) || wdl.abs() == 1 {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+
+    /// The sub-table positions of the given side and leading pawn file are looked up in, for
+    /// writing tables
+    #[cfg(test)]
+    pub fn pairs(&self, side: usize, file: usize) -> &PairsData {
+        &self.pairs[side][file]
+    }
+}
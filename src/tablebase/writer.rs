@@ -0,0 +1,221 @@
+//! Writes small pawnless tables in the Syzygy format, so that probing can be tested without the
+//! real files. Values are stored with a fixed-length code and no pairs, which the format allows:
+//! every symbol is a leaf standing for a single value.
+
+use std::{fs, path::Path};
+
+use super::mmap::Mmap;
+use super::table::{mirror, Material, PairsData, PieceCode, Position, Table, TableKind, BLACK, DTZ_MAGIC, KING, MAPPED, SINGLE_VALUE, WDL_MAGIC};
+
+/// Bytes per block of compressed values, as a power of two
+const BLOCK_SIZE_LOG: u8 = 6;
+/// Values per sparse index entry, as a power of two
+const SPAN_LOG: u8 = 7;
+
+pub struct TableWriter {
+    kind: TableKind,
+    material: Material,
+    /// For DTZ tables: the values stored for wins, losses, cursed wins and blessed losses
+    map: Option<[Vec<u8>; 4]>,
+}
+
+impl TableWriter {
+    pub fn new(kind: TableKind, material: Material) -> Self {
+        assert!(material[0][0] + material[1][0] == 0, "only pawnless tables can be written");
+        Self { kind, material, map: None }
+    }
+
+    /// Stores values through a map, as DTZ tables with large values do
+    pub fn with_map(mut self, map: [Vec<u8>; 4]) -> Self {
+        self.map = Some(map);
+        self
+    }
+
+    fn sides(&self) -> usize {
+        if self.kind == TableKind::Wdl && self.material != mirror(&self.material) { 2 } else { 1 }
+    }
+
+    /// The pieces in the order they are indexed: the leading group first, then equal pieces
+    /// together
+    fn pieces(&self) -> Vec<PieceCode> {
+        let mut pieces = vec![];
+        for (side, counts) in self.material.iter().enumerate() {
+            for (p, &count) in counts.iter().enumerate() {
+                let code = p as u8 + 1 + if side == 1 { BLACK } else { 0 };
+                pieces.extend(std::iter::repeat_n(code, count as usize));
+            }
+        }
+        // kings first, then a piece there is only one of, if any
+        pieces.sort_by_key(|&code| (code & 7 != KING, code));
+        let unique = (2..pieces.len()).find(|&i| pieces.iter().filter(|&&code| code == pieces[i]).count() == 1);
+        if let Some(i) = unique {
+            let piece = pieces.remove(i);
+            pieces.insert(2, piece);
+        }
+        pieces
+    }
+
+    /// The magic, flags and pieces, which the tables read before the sizes
+    fn header(&self) -> Vec<u8> {
+        let mut bytes = match self.kind {
+            TableKind::Wdl => WDL_MAGIC.to_vec(),
+            TableKind::Dtz => DTZ_MAGIC.to_vec(),
+        };
+        // no pawns, and the leading group is indexed first for both sides
+        bytes.extend([0, 0]);
+        bytes.extend(self.pieces().iter().map(|&code| code | code << 4));
+        if bytes.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    /// Writes the table, with the value `value` gives for each position stored. Positions it
+    /// gives no value for may get any.
+    pub fn write<F: Fn(&Position) -> Option<u16>>(&self, path: &Path, value: F) {
+        // A table where every position has the same value gives the index of each position
+        let mut bytes = self.header();
+        for _ in 0..self.sides() {
+            bytes.extend([SINGLE_VALUE, 0]);
+        }
+        fs::write(path, &bytes).unwrap();
+        let table = Table::new(Mmap::open(path).unwrap(), self.kind, self.material).unwrap();
+
+        let pieces = self.pieces();
+        let mut sub_tables = vec![];
+        for side in 0..self.sides() {
+            let data = table.pairs(side, 0);
+            let mut values = vec![None; data.size() as usize];
+            let mut squares = vec![];
+            self.place(&table, data, &pieces, &mut squares, side == 1, &value, &mut values);
+            sub_tables.push(values.into_iter().map(|value| value.unwrap_or(0)).collect::<Vec<u16>>());
+        }
+
+        let mut bytes = self.header();
+        let flags = if self.map.is_some() { MAPPED } else { 0 };
+        let mut blocks = vec![];
+        for values in &sub_tables {
+            if values.iter().all(|&value| value == values[0]) {
+                bytes.extend([flags | SINGLE_VALUE, values[0] as u8]);
+                blocks.push((vec![], vec![], vec![]));
+                continue
+            }
+            blocks.push(compress(values));
+            let bits = bits(values);
+            let symbols = values.iter().max().unwrap() + 1;
+            bytes.extend([flags, BLOCK_SIZE_LOG, SPAN_LOG, 0]);
+            bytes.extend((blocks.last().unwrap().1.len() as u32).to_le_bytes());
+            // the shortest and longest codes, and the lowest symbol of that one length
+            bytes.extend([bits, bits, 0, 0]);
+            bytes.extend(symbols.to_le_bytes());
+            for symbol in 0..symbols {
+                // a leaf: the left child is the value, and there is no right child
+                bytes.extend([symbol as u8, (symbol >> 8) as u8 | 0xF0, 0xFF]);
+            }
+            if symbols % 2 == 1 {
+                bytes.push(0);
+            }
+        }
+        if let Some(map) = &self.map {
+            for values in map {
+                bytes.push(values.len() as u8);
+                bytes.extend(values);
+            }
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+        }
+        for (sparse, _, _) in &blocks {
+            bytes.extend(sparse);
+        }
+        for (_, lengths, _) in &blocks {
+            for length in lengths {
+                bytes.extend(length.to_le_bytes());
+            }
+        }
+        for (_, _, data) in &blocks {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            bytes.extend(data);
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    /// Puts the remaining pieces on every free square, and records the value of each complete
+    /// placement
+    #[allow(clippy::too_many_arguments)]
+    fn place<F: Fn(&Position) -> Option<u16>>(
+        &self,
+        table: &Table,
+        data: &PairsData,
+        pieces: &[PieceCode],
+        squares: &mut Vec<u8>,
+        black_to_move: bool,
+        value: &F,
+        values: &mut [Option<u16>],
+    ) {
+        if squares.len() == pieces.len() {
+            let position = Position {
+                pieces: squares.iter().copied().zip(pieces.iter().copied()).collect(),
+                black_to_move,
+                material: self.material,
+            };
+            let Some(value) = value(&position) else { return };
+            let idx = table.index(data, &mut squares.clone(), &mut pieces.to_vec(), 0).unwrap() as usize;
+            match values[idx] {
+                Some(stored) => assert_eq!(stored, value, "positions with the same index differ"),
+                None => values[idx] = Some(value),
+            }
+            return
+        }
+        for square in 0..64 {
+            if squares.contains(&square) {
+                continue
+            }
+            // touching kings would share indices with legal positions
+            if squares.len() == 1 && (square >> 3).abs_diff(squares[0] >> 3) <= 1 && (square & 7).abs_diff(squares[0] & 7) <= 1 {
+                continue
+            }
+            squares.push(square);
+            self.place(table, data, pieces, squares, black_to_move, value, values);
+            squares.pop();
+        }
+    }
+}
+
+/// Bits needed for the largest value
+fn bits(values: &[u16]) -> u8 {
+    (16 - values.iter().max().unwrap().leading_zeros()).max(1) as u8
+}
+
+/// Splits the values into blocks of fixed-length codes. Returns the sparse index, the length of
+/// each block (less one) and the blocks themselves.
+fn compress(values: &[u16]) -> (Vec<u8>, Vec<u16>, Vec<u8>) {
+    let bits = bits(values) as usize;
+    let block_size = 1 << BLOCK_SIZE_LOG;
+    let per_block = block_size * 8 / bits;
+    let blocks = values.len().div_ceil(per_block);
+    let lengths = (0..blocks).map(|block| ((values.len() - block * per_block).min(per_block) - 1) as u16).collect();
+
+    let mut data = vec![0u8; blocks * block_size];
+    for (i, &value) in values.iter().enumerate() {
+        let start = (i / per_block) * block_size * 8 + (i % per_block) * bits;
+        for bit in 0..bits {
+            if value >> (bits - 1 - bit) & 1 != 0 {
+                let pos = start + bit;
+                data[pos / 8] |= 0x80 >> (pos % 8);
+            }
+        }
+    }
+
+    // every entry points at the value in the middle of its span, which for the last one may be
+    // past the end
+    let span = 1 << SPAN_LOG;
+    let mut sparse = vec![];
+    for k in 0..values.len().div_ceil(span) {
+        let middle = k * span + span / 2;
+        let block = (middle / per_block).min(blocks - 1);
+        sparse.extend((block as u32).to_le_bytes());
+        sparse.extend(((middle - block * per_block) as u16).to_le_bytes());
+    }
+    (sparse, lengths, data)
+}
@@ -0,0 +1,75 @@
+use std::{fmt, sync::Arc, time::{Duration, Instant}};
+
+use crate::{
+    board::Board,
+    eval::nnue::Network,
+    search::{pruning::SearchOptions, time::TimeManager, Search, SearchLimits},
+};
+
+/// Depth used by `bench` when none is given
+pub const DEFAULT_BENCH_DEPTH: usize = 6;
+
+/// A fixed set of positions, so that node counts can be compared between versions. Any change to
+/// the search which is not meant to change its behaviour must leave the total node count alone.
+pub const BENCH_POSITIONS: [&str; 12] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "2r3k1/pp3ppp/4p3/3pP3/3P4/P4N2/1P3PPP/2R3K1 w - - 0 25",
+    "r2q1rk1/ppp2ppp/2np1n2/2b1p1B1/2B1P1b1/2NP1N2/PPP2PPP/R2Q1RK1 w - - 2 8",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+];
+
+#[derive(Default, Debug)]
+pub struct BenchResult {
+    pub nodes: u64,
+    pub time: Duration,
+    /// Nodes searched in each of the [`BENCH_POSITIONS`]
+    pub position_nodes: Vec<u64>,
+}
+
+impl BenchResult {
+    pub fn nps(&self) -> u64 {
+        self.nodes * 1_000_000 / (self.time.as_micros().max(1) as u64)
+    }
+}
+
+/// The node count of each position, then the totals
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (nodes, fen)) in self.position_nodes.iter().zip(BENCH_POSITIONS).enumerate() {
+            writeln!(f, "Position {:>2}/{}: {:>10} nodes  {}", i + 1, BENCH_POSITIONS.len(), nodes, fen)?;
+        }
+        writeln!(f, "===========================")?;
+        writeln!(f, "Total time (ms) : {}", self.time.as_millis())?;
+        writeln!(f, "Nodes searched  : {}", self.nodes)?;
+        write!(f, "Nodes/second    : {}", self.nps())
+    }
+}
+
+/// Searches every bench position to `depth`, counting the nodes of each one. Without a network,
+/// the search counts material.
+pub fn bench(depth: usize, options: SearchOptions, network: Option<Arc<Network>>) -> BenchResult {
+    let mut result = BenchResult::default();
+    for fen in BENCH_POSITIONS {
+        let board = Board::new(fen.to_string()).unwrap();
+        let limits = SearchLimits { depth: Some(depth), ..Default::default() };
+        let start = Instant::now();
+        let mut search = Search::new(board, limits, TimeManager::infinite(), Arc::default()).with_options(options);
+        if let Some(network) = &network {
+            search = search.with_network(network.clone());
+        }
+        let searched = search.run(|_| {});
+        let time = start.elapsed();
+        result.position_nodes.push(searched.nodes);
+        result.nodes += searched.nodes;
+        result.time += time;
+    }
+    result
+}
@@ -1,2 +1,8 @@
-pub mod perft;
+pub(crate) mod bench;
+pub(crate) mod openings;
+pub(crate) mod perft;
+pub(crate) mod tune;
+pub use bench::*;
+pub use openings::*;
 pub use perft::*;
+pub use tune::{fit_k, mean_error, parse_labeled_position, Dataset, DatasetErr, EvalParams, Tuner, DEFAULT_EPOCHS, DEFAULT_LEARNING_RATE};
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use crate::{
+    board::{chess960_fen, dfrc_fen, Board, START_FEN},
+    search::{time::TimeManager, tt::TranspositionTable, Search, SearchLimits},
+};
+
+/// Plies of random moves played by default from the start position
+pub const DEFAULT_OPENING_PLIES: usize = 8;
+/// Depth of the search which checks that a position is not already decided
+pub const DEFAULT_OPENING_DEPTH: usize = 4;
+/// Positions whose score is further than this from equality (in centipawns) are thrown away
+pub const DEFAULT_MAX_SCORE: i32 = 150;
+/// How many random games are tried for each position before giving up
+const MAX_ATTEMPTS: usize = 1000;
+
+/// The positions games start from, before the random moves
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StartPositions {
+    Standard,
+    /// One of the 960 Fischer Random positions, the same for both sides
+    Chess960,
+    /// Double Fischer Random: White and Black each get their own Chess960 back rank
+    Dfrc,
+}
+
+impl TryFrom<&str> for StartPositions {
+    type Error = ();
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "standard" => Ok(Self::Standard),
+            "chess960" | "frc" => Ok(Self::Chess960),
+            "dfrc" => Ok(Self::Dfrc),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A position for the opening suite, as an EPD record
+pub struct Opening {
+    pub epd: String,
+    /// Where the random moves started from
+    pub start: String,
+    /// The score of the quick search, from the side to move's point of view
+    pub score: i32,
+}
+
+impl Opening {
+    /// The EPD record with `id` naming the start position and `ce` holding the score
+    pub fn to_epd(&self) -> String {
+        format!("{} id \"{}\"; ce {};", self.epd, self.start, self.score)
+    }
+}
+
+/// Makes varied positions to start games from ("book exits"), by playing random legal moves from
+/// a start position and throwing away the positions which a quick search finds already decided.
+pub struct OpeningGenerator {
+    start: StartPositions,
+    plies: usize,
+    depth: usize,
+    max_score: i32,
+    random: u64,
+    tt: Arc<TranspositionTable>,
+}
+
+impl OpeningGenerator {
+    pub fn new(start: StartPositions, seed: u64) -> Self {
+        Self {
+            start,
+            plies: DEFAULT_OPENING_PLIES,
+            depth: DEFAULT_OPENING_DEPTH,
+            max_score: DEFAULT_MAX_SCORE,
+            // xorshift never leaves zero
+            random: seed.max(1),
+            tt: Arc::new(TranspositionTable::new(1)),
+        }
+    }
+
+    /// Plays `plies` random moves from the start position (none gives the start positions)
+    pub fn with_plies(mut self, plies: usize) -> Self {
+        self.plies = plies;
+        self
+    }
+
+    /// Checks the positions with a search of this depth (0 for no check at all)
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_max_score(mut self, max_score: i32) -> Self {
+        self.max_score = max_score;
+        self
+    }
+
+    fn next_random(&mut self) -> u64 {
+        // xorshift, as for the Zobrist keys
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random
+    }
+
+    /// A random start position and its name, e.g. `chess960 518` or `dfrc 12/345`
+    fn start_position(&mut self) -> (String, String) {
+        match self.start {
+            StartPositions::Standard => (START_FEN.to_string(), "standard".to_string()),
+            StartPositions::Chess960 => {
+                let index = (self.next_random() % 960) as usize;
+                (chess960_fen(index), format!("chess960 {}", index))
+            }
+            StartPositions::Dfrc => {
+                let white = (self.next_random() % 960) as usize;
+                let black = (self.next_random() % 960) as usize;
+                (dfrc_fen(white, black), format!("dfrc {}/{}", white, black))
+            }
+        }
+    }
+
+    /// The next position, or `None` if no random game in many got to one which is still open
+    pub fn next_opening(&mut self) -> Option<Opening> {
+        let (fen, start) = self.start_position();
+        let mut board = Board::new(fen).unwrap();
+        let root_moves = board.move_stack().len();
+        for _ in 0..MAX_ATTEMPTS {
+            while board.move_stack().len() > root_moves {
+                board.undo_move();
+            }
+            if let Some(score) = self.play_out(&mut board) {
+                return Some(Opening { epd: board.epd(), start, score })
+            }
+        }
+        None
+    }
+
+    /// Plays the random moves, and scores the position they lead to unless the game is over or
+    /// the search finds one side already well ahead
+    fn play_out(&mut self, board: &mut Board) -> Option<i32> {
+        for _ in 0..self.plies {
+            if board.legal_moves().len == 0 {
+                return None
+            }
+            let index = (self.next_random() % board.legal_moves().len as u64) as usize;
+            board.make_move(board.legal_moves().moves[index]);
+        }
+        if board.legal_moves().len == 0 || board.is_draw() {
+            return None
+        }
+        if self.depth == 0 {
+            return Some(0)
+        }
+        let limits = SearchLimits { depth: Some(self.depth), ..Default::default() };
+        self.tt.clear();
+        let result = Search::new(board.clone(), limits, TimeManager::infinite(), Arc::default())
+            .with_tt(self.tt.clone())
+            .run(|_| {});
+        (result.score.abs() <= self.max_score).then_some(result.score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_positions() {
+        let mut generator = OpeningGenerator::new(StartPositions::Dfrc, 7).with_plies(0).with_depth(0);
+        let opening = generator.next_opening().unwrap();
+        let (white, black) = opening.start.strip_prefix("dfrc ").unwrap().split_once('/').unwrap();
+        let fen = dfrc_fen(white.parse().unwrap(), black.parse().unwrap());
+        assert!(fen.starts_with(&opening.epd[..opening.epd.find(' ').unwrap()]));
+
+        let opening = OpeningGenerator::new(StartPositions::Standard, 1).with_plies(0).with_depth(0).next_opening().unwrap();
+        assert_eq!(opening.to_epd(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id \"standard\"; ce 0;");
+    }
+
+    #[test]
+    fn book_exits() {
+        let mut generator = OpeningGenerator::new(StartPositions::Chess960, 12345).with_plies(6).with_depth(2).with_max_score(100);
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            let opening = generator.next_opening().unwrap();
+            assert!(opening.score.abs() <= 100);
+            // the record reads back as a position six plies in, which is not over yet
+            let board = Board::new(opening.epd.clone()).unwrap();
+            assert!(board.legal_moves().len > 0);
+            assert!(board.side_to_move == crate::types::Color::White);
+            seen.push(opening.epd);
+        }
+        seen.dedup();
+        assert_eq!(seen.len(), 3);
+    }
+}
@@ -1,16 +1,18 @@
 use std::{fmt::Display, ops::{Add, AddAssign}};
 
-use crate::board::Board;
+use crate::{board::Board, types::Move};
 
+/// What a perft counted: the positions at the last ply, and how many of them were reached by
+/// each kind of move or are in check or checkmate
 #[derive(Default, Debug, PartialEq, Eq)]
 pub struct PerftResult {
-    nodes: u64,
-    captures: u64,
-    en_passants: u64,
-    castles: u64,
-    promotions: u64,
-    checks: u64,
-    checkmates: u64,
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passants: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub checkmates: u64,
 }
 
 impl Add for PerftResult {
@@ -46,6 +48,15 @@ impl Display for PerftResult {
     }
 }
 
+/// Counts the positions `depth` plies from the board's position
+///
+/// ```
+/// use mascagni::{tools::perft, Board, START_FEN};
+///
+/// let mut board = Board::new(START_FEN.to_string()).unwrap();
+/// let result = perft(&mut board, 3);
+/// assert_eq!((result.nodes, result.captures, result.checks), (8902, 34, 12));
+/// ```
 pub fn perft(board: &mut Board, depth: usize) -> PerftResult {
     let mut nodes = PerftResult{
         nodes: 0,
@@ -57,7 +68,7 @@ pub fn perft(board: &mut Board, depth: usize) -> PerftResult {
         checkmates: 0,
     };
     if depth == 0 {
-        let len = board.move_stack.len();
+        let len = board.move_stack().len();
         if len == 0 {
             return PerftResult {
                 nodes: 1,
@@ -69,7 +80,7 @@ pub fn perft(board: &mut Board, depth: usize) -> PerftResult {
                 checkmates: 0,
             }
         } else {
-            let mv = board.move_stack[len - 1].inner_move;
+            let mv = board.move_stack()[len - 1].inner_move;
             return PerftResult {
                 nodes: 1,
                 captures: mv.is_capture() as u64,
@@ -81,20 +92,35 @@ pub fn perft(board: &mut Board, depth: usize) -> PerftResult {
             }
         }
     }
-    for i in 0..board.legal_moves.len {
-        let mv = board.legal_moves.moves[i];
+    for i in 0..board.legal_moves().len {
+        let mv = board.legal_moves().moves[i];
         board.make_move(mv);
         nodes += perft(board, depth - 1);
         // the move has been made
         board.undo_move();
         // the move has been unmade
     }
-    return nodes
+    nodes
+}
+
+/// The perft of each legal move, to find which one a move generator gets wrong
+pub fn divide(board: &mut Board, depth: usize) -> Vec<(Move, u64)> {
+    let moves = *board.legal_moves();
+    moves.moves[..moves.len]
+        .iter()
+        .map(|&mv| {
+            board.make_move(mv);
+            let nodes = perft(board, depth.saturating_sub(1)).nodes;
+            board.undo_move();
+            (mv, nodes)
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::board::{Variant, START_FEN};
 
     #[test]
     fn one() {
@@ -144,6 +170,118 @@ mod tests {
         });
     }
 
+    #[test]
+    fn en_passant_discovered_check() {
+        // both pawns leave the fifth rank, which would expose the king to the rook
+        let mut board = Board::new("8/8/8/KPp4r/8/8/8/7k w - c6 0 1".to_string()).unwrap();
+        assert!(!board.legal_moves().as_slice().iter().any(|m| m.is_en_passant()));
+        let mut board_3 = Board::new("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1".to_string()).unwrap();
+        assert_eq!(perft(&mut board_3, 3), PerftResult {
+            nodes: 2812,
+            captures: 209,
+            en_passants: 2,
+            castles: 0,
+            promotions: 0,
+            checks: 267,
+            checkmates: 0,
+        });
+        assert_eq!(perft(&mut board, 1).nodes, 4);
+    }
+
+    #[test]
+    fn en_passant_out_of_check() {
+        // the pawn on d4 gives check and can be captured en passant
+        let board = Board::new("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1".to_string()).unwrap();
+        assert!(board.legal_moves().as_slice().iter().any(|m| m.to_string() == "e4d3"));
+    }
+
+    #[test]
+    fn chess960() {
+        // from the published Chess960 perft results, to depth 3
+        let suite: [(&str, [u64; 3]); 6] = [
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", [21, 528, 12189]),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", [21, 807, 18002]),
+            ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", [20, 479, 10471]),
+            ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", [22, 593, 13440]),
+            ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", [28, 1120, 31058]),
+            ("qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9", [29, 899, 26578]),
+        ];
+        for (fen, counts) in suite {
+            let mut board = Board::new(fen.to_string()).unwrap();
+            for (depth, &count) in counts.iter().enumerate() {
+                assert_eq!(perft(&mut board, depth + 1).nodes, count, "{} at depth {}", fen, depth + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn divide_adds_up() {
+        let mut board = Board::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".to_string()).unwrap();
+        let moves = divide(&mut board, 2);
+        assert_eq!(moves.len(), 48);
+        assert_eq!(moves.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+        let castle = moves.iter().find(|(mv, _)| mv.to_string() == "e1g1").unwrap();
+        assert_eq!(castle.1, 43);
+    }
+
+    /// Checks the node counts of a variant position from depth 1 up
+    fn assert_variant_counts(fen: &str, variant: Variant, counts: &[u64]) {
+        let mut board = Board::new_variant(fen.to_string(), variant).unwrap();
+        for (depth, &count) in counts.iter().enumerate() {
+            assert_eq!(perft(&mut board, depth + 1).nodes, count, "{} {} at depth {}", variant, fen, depth + 1);
+        }
+    }
+
+    #[test]
+    fn racing_kings() {
+        // published results
+        assert_variant_counts("8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1", Variant::RacingKings, &[21, 421, 11264]);
+        assert_variant_counts("4brn1/2K2k2/8/8/8/8/8/8 w - - 0 1", Variant::RacingKings, &[6, 33, 178, 3151]);
+    }
+
+    #[test]
+    fn three_check() {
+        // Kiwipete with one check left for each side, from published results
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1 +2+2";
+        assert_variant_counts(fen, Variant::ThreeCheck, &[48, 2039, 97848]);
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        assert_variant_counts(START_FEN, Variant::KingOfTheHill, &[20, 400, 8902]);
+        // Kd4 wins on the spot, leaving three replies to each of the seven other king moves
+        assert_variant_counts("8/8/8/8/8/2K5/8/7k w - - 0 1", Variant::KingOfTheHill, &[8, 21]);
+    }
+
+    #[test]
+    fn antichess() {
+        // published results: the start position (without castling rights), and a pawn each, where
+        // after one capture the side left without pieces has won
+        assert_variant_counts(Variant::Antichess.start_fen(), Variant::Antichess, &[20, 400, 8067]);
+        assert_variant_counts("8/1p6/8/8/8/8/P7/8 w - - 0 1", Variant::Antichess, &[2, 4, 4, 3, 1, 0]);
+    }
+
+    #[test]
+    fn crazyhouse() {
+        // every piece in both pockets: pawns cannot go on the back ranks
+        assert_variant_counts("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", Variant::Crazyhouse, &[301, 75353]);
+    }
+
+    #[test]
+    fn atomic() {
+        // published results, with castling next to the enemy king, through squares which only the
+        // enemy king touches
+        let suite: [(&str, &[u64]); 4] = [
+            ("rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1", &[40, 1238]),
+            ("8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1", &[18, 180, 4364]),
+            ("r3k1rR/5K2/8/8/8/8/8/8 b kq - 0 1", &[25, 282, 6753]),
+            ("Rr2k1rR/3K4/3p4/8/8/8/7P/8 w kq - 0 1", &[21, 465, 10631]),
+        ];
+        for (fen, counts) in suite {
+            assert_variant_counts(fen, Variant::Atomic, counts);
+        }
+    }
+
     // SHOULDN"T TAKE THIS LONG
 
     // #[test]
@@ -177,3 +315,5 @@ mod tests {
     //     });
     // }
 }
+
+
@@ -0,0 +1,487 @@
+//! Texel tuning: fitting the evaluation to the results of the games positions were taken from.
+//!
+//! The evaluation tuned is linear: a piece-square table for each piece type, which also holds its
+//! material value, and a tempo bonus for the side to move. A position's score is then the sum of
+//! the parameters of its features, and the expected result of the game is
+//! `1 / (1 + 10^(-K * score / 400))`. The tuner first fits `K` to the starting parameters, then
+//! minimizes the mean squared difference between expected and actual results with Adam.
+//!
+//! This is not the evaluation the engine plays with: the tuner fits its own tables from material
+//! values, and does not adjust the weights of the embedded network. The tuned tables are saved as
+//! a network ([`Network::piece_square`]) which replaces the embedded one when loaded through the
+//! `EvalFile` option, or by `eval --net`.
+//!
+//! Each position is replaced by the end of the principal variation of a quiescence search, so
+//! that the evaluation is fitted on quiet positions, as the search uses it.
+
+use std::{fmt, fs, io, path::Path, thread};
+
+use crate::board::Board;
+use crate::eval::{material, nnue::Network, PIECE_VALUES};
+use crate::types::{Color, Move, Piece, Square};
+
+/// A parameter for each piece type on each square, then the tempo bonus
+pub const PARAMS: usize = Piece::NUM * Square::NUM + 1;
+const TEMPO: usize = PARAMS - 1;
+/// Epochs of Adam when no number is given
+pub const DEFAULT_EPOCHS: usize = 1000;
+/// Step size of Adam when none is given, in centipawns
+pub const DEFAULT_LEARNING_RATE: f64 = 1.0;
+/// Deepest the quiescence search goes looking for a quiet position
+const MAX_QUIESCENCE_PLY: usize = 32;
+
+#[derive(Debug)]
+pub enum DatasetErr {
+    Io(io::Error),
+    /// A line, by number from 1, which is not a position and a result
+    InvalidLine(usize, String),
+}
+
+impl fmt::Display for DatasetErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetErr::Io(err) => write!(f, "Could not read positions: {}", err),
+            DatasetErr::InvalidLine(number, line) => write!(f, "Line {} is not a position and a result: {}", number, line),
+        }
+    }
+}
+
+impl From<io::Error> for DatasetErr {
+    fn from(err: io::Error) -> Self {
+        DatasetErr::Io(err)
+    }
+}
+
+/// Reads a position and the result of its game from a line, which is either a FEN or EPD record
+/// followed by the result (`1-0`, `1/2-1/2`, `0-1`, or `1.0`, `0.5`, `0.0`, maybe in brackets or
+/// quotes), or an EPD record with the result in a `c9` operation. The result is White's score.
+pub fn parse_labeled_position(line: &str) -> Option<(Board, f64)> {
+    let (position, result) = match line.find(" c9 ") {
+        Some(index) => (&line[..index], line[index + 4..].split(';').next()?),
+        None => line.trim_end().rsplit_once(char::is_whitespace)?,
+    };
+    let result = match result.trim().trim_matches(|c| matches!(c, '"' | '[' | ']' | ';' | '(' | ')')) {
+        "1-0" | "1.0" | "1" => 1.0,
+        "1/2-1/2" | "0.5" | "1/2" => 0.5,
+        "0-1" | "0.0" | "0" => 0.0,
+        _ => return None,
+    };
+    // the four fields of the position, and the clocks of a FEN
+    let fields: Vec<&str> = position
+        .trim_end()
+        .trim_end_matches([';', '|', ','])
+        .split_whitespace()
+        .enumerate()
+        .take_while(|&(i, field)| i < 4 || (i < 6 && field.parse::<u16>().is_ok()))
+        .map(|(_, field)| field)
+        .collect();
+    let board = Board::new(fields.join(" ")).ok()?;
+    Some((board, result))
+}
+
+/// The moves of the principal variation of a quiescence search with the material evaluation,
+/// which leads to a position without winning captures
+fn quiescence(board: &mut Board, ply: usize, mut alpha: i32, beta: i32, pv: &mut Vec<Move>) -> i32 {
+    pv.clear();
+    let stand_pat = material(board);
+    if stand_pat >= beta || ply >= MAX_QUIESCENCE_PLY {
+        return stand_pat
+    }
+    alpha = alpha.max(stand_pat);
+    let mut line = vec![];
+    let moves = *board.legal_moves();
+    for &mv in &moves.moves[..moves.len] {
+        if !mv.is_capture() && !mv.is_promotion() {
+            continue
+        }
+        board.make_move(mv);
+        let score = -quiescence(board, ply + 1, -beta, -alpha, &mut line);
+        board.undo_move();
+        if score > alpha {
+            alpha = score;
+            pv.clear();
+            pv.push(mv);
+            pv.append(&mut line);
+            if alpha >= beta {
+                break
+            }
+        }
+    }
+    alpha
+}
+
+/// A position reduced to the features of the linear evaluation
+struct Entry {
+    /// Parameter indices with their coefficients in White's score: +1 for a White piece, -1 for a
+    /// Black one, and the sum when pieces of both sides use the same parameter
+    features: Vec<(u16, i8)>,
+    /// White's result
+    result: f64,
+}
+
+impl Entry {
+    /// The quiet position at the end of the quiescence search, unless it is in check or the game
+    /// is over, where the evaluation does not apply
+    fn new(board: &Board, result: f64) -> Option<Self> {
+        let mut board = board.clone();
+        let mut pv = vec![];
+        quiescence(&mut board, 0, -i32::MAX, i32::MAX, &mut pv);
+        for mv in pv {
+            board.make_move(mv);
+        }
+        if board.is_check() || board.legal_moves().len == 0 {
+            return None
+        }
+        let mut coefficients = [0i8; PARAMS];
+        for p in 0..Piece::NUM {
+            let piece = Piece::new(p);
+            for square in board.pieces_of(piece, Color::White) {
+                coefficients[p * Square::NUM + square as usize] += 1;
+            }
+            for square in board.pieces_of(piece, Color::Black) {
+                coefficients[p * Square::NUM + (square as usize ^ 56)] -= 1;
+            }
+        }
+        coefficients[TEMPO] = if board.side_to_move == Color::White { 1 } else { -1 };
+        let features = (0..PARAMS).filter(|&i| coefficients[i] != 0).map(|i| (i as u16, coefficients[i])).collect();
+        Some(Self { features, result })
+    }
+}
+
+/// Labeled positions to tune on
+#[derive(Default)]
+pub struct Dataset {
+    entries: Vec<Entry>,
+    /// Positions left out for being in check or over after the quiescence search
+    skipped: usize,
+}
+
+impl Dataset {
+    /// Reads a file of labeled positions, one per line (see [`parse_labeled_position`])
+    pub fn load<P: AsRef<Path>>(path: P, threads: usize) -> Result<Self, DatasetErr> {
+        Self::parse(&fs::read_to_string(path)?, threads)
+    }
+
+    /// Reads labeled positions, one per line, skipping blank lines and lines starting with `#`.
+    /// The quiescence searches are shared out among `threads` threads.
+    pub fn parse(text: &str, threads: usize) -> Result<Self, DatasetErr> {
+        let lines: Vec<(usize, &str)> = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .collect();
+        let chunk = lines.len().div_ceil(threads.max(1)).max(1);
+        let parts: Vec<Result<Self, DatasetErr>> = thread::scope(|scope| {
+            let handles: Vec<_> = lines
+                .chunks(chunk)
+                .map(|lines| {
+                    scope.spawn(move || {
+                        let mut part = Self::default();
+                        for &(number, line) in lines {
+                            let Some((board, result)) = parse_labeled_position(line) else {
+                                return Err(DatasetErr::InvalidLine(number + 1, line.to_string()))
+                            };
+                            match Entry::new(&board, result) {
+                                Some(entry) => part.entries.push(entry),
+                                None => part.skipped += 1,
+                            }
+                        }
+                        Ok(part)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        let mut dataset = Self::default();
+        for part in parts {
+            let mut part = part?;
+            dataset.entries.append(&mut part.entries);
+            dataset.skipped += part.skipped;
+        }
+        Ok(dataset)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+/// The parameters of the linear evaluation, in centipawns
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    values: Vec<f64>,
+}
+
+impl Default for EvalParams {
+    /// The material values of the pieces on every square, and no tempo bonus
+    fn default() -> Self {
+        let mut values = vec![0.0; PARAMS];
+        for p in 0..Piece::NUM {
+            values[p * Square::NUM..(p + 1) * Square::NUM].fill(PIECE_VALUES[p] as f64);
+        }
+        Self { values }
+    }
+}
+
+impl EvalParams {
+    /// The value of a White piece on `square`, and of a Black one on the mirrored square
+    pub fn piece_square(&self, piece: Piece, square: Square) -> f64 {
+        self.values[piece as usize * Square::NUM + square as usize]
+    }
+
+    pub fn tempo(&self) -> f64 {
+        self.values[TEMPO]
+    }
+
+    /// White's score of a position
+    fn evaluate(&self, entry: &Entry) -> f64 {
+        entry.features.iter().map(|&(index, coefficient)| self.values[index as usize] * coefficient as f64).sum()
+    }
+
+    /// The parameters rounded to whole centipawns, as a network the engine can load
+    pub fn to_network(&self) -> Network {
+        let mut tables = [[0; Square::NUM]; Piece::NUM];
+        for (p, table) in tables.iter_mut().enumerate() {
+            for (square, value) in table.iter_mut().enumerate() {
+                *value = self.values[p * Square::NUM + square].round() as i32;
+            }
+        }
+        Network::piece_square(&tables, self.values[TEMPO].round() as i32)
+    }
+}
+
+/// The expected result of a position scored `score` for White
+pub fn expected_result(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+/// Maps `f` over the entries, a slice of them on each of `threads` threads, and adds up the results
+fn par_sum<T: Send>(dataset: &Dataset, threads: usize, zero: impl Fn() -> T + Sync, f: impl Fn(&mut T, &Entry) + Sync, add: impl Fn(&mut T, T)) -> T {
+    let chunk = dataset.entries.len().div_ceil(threads.max(1)).max(1);
+    let parts: Vec<T> = thread::scope(|scope| {
+        let handles: Vec<_> = dataset
+            .entries
+            .chunks(chunk)
+            .map(|entries| {
+                let (zero, f) = (&zero, &f);
+                scope.spawn(move || {
+                    let mut sum = zero();
+                    for entry in entries {
+                        f(&mut sum, entry);
+                    }
+                    sum
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    let mut total = zero();
+    for part in parts {
+        add(&mut total, part);
+    }
+    total
+}
+
+/// Mean squared difference between the expected and actual results
+pub fn mean_error(dataset: &Dataset, params: &EvalParams, k: f64, threads: usize) -> f64 {
+    if dataset.is_empty() {
+        return 0.0
+    }
+    let sum = par_sum(
+        dataset,
+        threads,
+        || 0.0,
+        |sum, entry| *sum += (entry.result - expected_result(params.evaluate(entry), k)).powi(2),
+        |total, part| *total += part,
+    );
+    sum / dataset.len() as f64
+}
+
+/// The scaling constant `K` which makes `params` fit the results best, to three decimals
+pub fn fit_k(dataset: &Dataset, params: &EvalParams, threads: usize) -> f64 {
+    let (mut best, mut step) = (1.0, 1.0);
+    let mut best_error = mean_error(dataset, params, best, threads);
+    for _ in 0..4 {
+        // the error is convex enough in K for a walk of shrinking steps each way
+        for direction in [-1.0, 1.0] {
+            loop {
+                let k = best + direction * step;
+                if k <= 0.0 {
+                    break
+                }
+                let error = mean_error(dataset, params, k, threads);
+                if error >= best_error {
+                    break
+                }
+                (best, best_error) = (k, error);
+            }
+        }
+        step /= 10.0;
+    }
+    best
+}
+
+/// Minimizes the error of the parameters by gradient descent with Adam, using every position for
+/// each step
+pub struct Tuner {
+    k: f64,
+    learning_rate: f64,
+    epochs: usize,
+    threads: usize,
+}
+
+impl Tuner {
+    const BETA1: f64 = 0.9;
+    const BETA2: f64 = 0.999;
+    const EPSILON: f64 = 1e-8;
+
+    pub fn new(k: f64, threads: usize) -> Self {
+        Self { k, learning_rate: DEFAULT_LEARNING_RATE, epochs: DEFAULT_EPOCHS, threads }
+    }
+
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    pub fn with_epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// The gradient of the mean error
+    fn gradient(&self, dataset: &Dataset, params: &EvalParams) -> Vec<f64> {
+        let k = self.k;
+        let mut gradient = par_sum(
+            dataset,
+            self.threads,
+            || vec![0.0; PARAMS],
+            |gradient, entry| {
+                let expected = expected_result(params.evaluate(entry), k);
+                // d/dscore of (result - expected)^2
+                let slope = 2.0 * (expected - entry.result) * expected * (1.0 - expected) * k * 10f64.ln() / 400.0;
+                for &(index, coefficient) in &entry.features {
+                    gradient[index as usize] += slope * coefficient as f64;
+                }
+            },
+            |total, part| total.iter_mut().zip(part).for_each(|(total, part)| *total += part),
+        );
+        let count = dataset.len().max(1) as f64;
+        gradient.iter_mut().for_each(|value| *value /= count);
+        gradient
+    }
+
+    /// Tunes the parameters, calling `on_epoch` with the epoch number from 1 and the parameters
+    /// after it
+    pub fn tune(&self, dataset: &Dataset, mut params: EvalParams, mut on_epoch: impl FnMut(usize, &EvalParams)) -> EvalParams {
+        let mut momentum = vec![0.0; PARAMS];
+        let mut velocity = vec![0.0; PARAMS];
+        for epoch in 1..=self.epochs {
+            let gradient = self.gradient(dataset, &params);
+            // corrections for the moments starting at zero
+            let correction1 = 1.0 - Self::BETA1.powi(epoch as i32);
+            let correction2 = 1.0 - Self::BETA2.powi(epoch as i32);
+            for i in 0..PARAMS {
+                momentum[i] = Self::BETA1 * momentum[i] + (1.0 - Self::BETA1) * gradient[i];
+                velocity[i] = Self::BETA2 * velocity[i] + (1.0 - Self::BETA2) * gradient[i] * gradient[i];
+                let step = momentum[i] / correction1 / ((velocity[i] / correction2).sqrt() + Self::EPSILON);
+                params.values[i] -= self.learning_rate * step;
+            }
+            on_epoch(epoch, &params);
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn labeled_positions() {
+        let parse = |line: &str| parse_labeled_position(line).map(|(board, result)| (board.fen(), result));
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        assert_eq!(parse(&format!("{} [1.0]", fen)), Some((fen.to_string(), 1.0)));
+        assert_eq!(parse(&format!("{} 0-1", fen)), Some((fen.to_string(), 0.0)));
+        assert_eq!(parse(&format!("{}; 1/2-1/2", fen)), Some((fen.to_string(), 0.5)));
+        let epd = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - c9 \"1/2-1/2\"; id \"x\";";
+        assert_eq!(parse(epd), Some((fen.replace(" 0 2", " 0 1"), 0.5)));
+        assert_eq!(parse(&format!("{} win", fen)), None);
+        assert_eq!(parse("8/8/8/8/8/8/8/8 x - - 1-0"), None);
+    }
+
+    #[test]
+    fn quiet_positions() {
+        // White's pawn takes on d5 and Black's knight takes back: the position after is tuned on
+        let text = "4k3/8/1n6/3r4/4P3/8/8/4K3 w - - 0 1 [0.5]\n\
+                    # in check, and not quiet either\n\
+                    4k3/8/8/8/8/5p2/4q3/4K3 w - - 0 1 [0.0]\n\
+                    4k3/8/8/8/8/8/8/3QK3 b - - 0 1 [1.0]\n";
+        let dataset = Dataset::parse(text, 2).unwrap();
+        assert_eq!((dataset.len(), dataset.skipped()), (2, 1));
+        let params = EvalParams::default();
+        // a knight left for Black; then a queen up for White
+        assert_eq!(params.evaluate(&dataset.entries[0]), -320.0);
+        assert_eq!(params.evaluate(&dataset.entries[1]), 900.0);
+        assert!(matches!(Dataset::parse("\n\nnot a position\n", 1), Err(DatasetErr::InvalidLine(3, _))));
+    }
+
+    #[test]
+    fn tunes_towards_the_results() {
+        // a pawn on the sixth rank wins, one on the second draws
+        let mut text = String::new();
+        for file in 0..8u8 {
+            let sixth = Square::new(40 + file);
+            let second = Square::new(8 + file);
+            let king = if file < 4 { "7k" } else { "k7" };
+            text += &format!("{}/8/{}/8/8/8/8/K7 w - - 0 1 1-0\n", king, rank_with_pawn(sixth));
+            text += &format!("{}/8/8/8/8/8/{}/K7 b - - 0 1 1/2-1/2\n", king, rank_with_pawn(second));
+        }
+        let dataset = Dataset::parse(&text, 3).unwrap();
+        assert_eq!(dataset.len(), 16);
+        let start = EvalParams::default();
+        let k = fit_k(&dataset, &start, 2);
+        assert!(k > 0.0);
+        let mut errors = vec![];
+        let tuned = Tuner::new(k, 2).with_epochs(200).with_learning_rate(2.0).tune(&dataset, start.clone(), |_, params| {
+            errors.push(mean_error(&dataset, params, k, 2));
+        });
+        assert!(errors.last().unwrap() < &(mean_error(&dataset, &start, k, 1) / 2.0));
+        assert!(tuned.piece_square(Piece::Pawn, Square::E6) > tuned.piece_square(Piece::Pawn, Square::E2) + 100.0);
+
+        // the engine scores positions with the saved tables as the tuner does, once it loads them
+        // as it loads an `EvalFile`
+        let path = std::env::temp_dir().join(format!("mascagni-tuned-{}.nnue", std::process::id()));
+        tuned.to_network().save(&path).unwrap();
+        let network = Network::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut board = Board::new("7k/8/4P3/8/8/8/8/K7 b - - 0 1".to_string()).unwrap();
+        board.set_network(Arc::new(network));
+        // Black to move, so the negated score for White
+        let expected = -tuned.evaluate(&Entry::new(&board, 0.5).unwrap());
+        assert!((board.nnue_evaluate().unwrap() as f64 - expected).abs() <= 2.0);
+    }
+
+    /// A rank of a FEN with a White pawn on the file of `square`
+    fn rank_with_pawn(square: Square) -> String {
+        let file = square as u8 % 8;
+        let mut rank = String::new();
+        if file > 0 {
+            rank += &file.to_string();
+        }
+        rank.push('P');
+        if file < 7 {
+            rank += &(7 - file).to_string();
+        }
+        rank
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::search::MATE;
+
+use super::random_mover::RandomMover;
+
+/// How long an engine may take to answer `uci` and `isready`
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long an engine which ran out of time gets to answer `stop`, and one told to `quit` gets to
+/// exit before it is killed
+const GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum EngineErr {
+    /// The engine could not be started, or written to
+    Io(io::Error),
+    /// The engine closed its output, usually because it exited
+    Disconnected,
+    /// The engine did not answer in time
+    Timeout,
+    /// The engine answered with something we cannot make sense of
+    Protocol(String),
+}
+
+impl fmt::Display for EngineErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineErr::Io(err) => write!(f, "{}", err),
+            EngineErr::Disconnected => write!(f, "the engine disconnected"),
+            EngineErr::Timeout => write!(f, "the engine did not answer in time"),
+            EngineErr::Protocol(line) => write!(f, "unexpected answer: {}", line),
+        }
+    }
+}
+
+impl From<io::Error> for EngineErr {
+    fn from(err: io::Error) -> Self {
+        EngineErr::Io(err)
+    }
+}
+
+/// How to start an engine: a program with its arguments, and the UCI options to set
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineConfig {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Overrides the name the engine gives in `id name`
+    pub name: Option<String>,
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineConfig {
+    /// An engine started by a command line, split at whitespace (`./mascagni uci`)
+    pub fn new(command_line: &str) -> Self {
+        let mut words = command_line.split_whitespace().map(String::from);
+        Self {
+            command: words.next().unwrap_or_default(),
+            args: words.collect(),
+            name: None,
+            options: vec![],
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Sets a UCI option once the engine has started
+    pub fn with_option(mut self, name: &str, value: &str) -> Self {
+        self.options.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// What an engine answered to `go`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BestMove {
+    /// The move in UCI notation, as the engine sent it
+    pub mv: String,
+    /// The last score the engine reported, from its own point of view, with mates as
+    /// [`MATE`] less the number of moves
+    pub score: Option<i32>,
+    /// From sending `go` to reading `bestmove`
+    pub elapsed: Duration,
+}
+
+/// Sends what is written to it over a channel, a line at a time. It stands in for the pipes of a
+/// child process when the engine runs on a thread.
+struct LineWriter {
+    sender: Sender<String>,
+    buffer: Vec<u8>,
+}
+
+impl Write for LineWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]).trim_end_matches('\r').to_string();
+            self.sender.send(line).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A running UCI engine, spoken to a command at a time. The engine's output is read on a thread
+/// of its own, so that we can stop waiting for an answer which does not come.
+pub struct Engine {
+    name: String,
+    input: Box<dyn Write + Send>,
+    lines: Receiver<String>,
+    child: Option<Child>,
+}
+
+impl Engine {
+    /// Starts an engine as a child process and goes through the UCI handshake
+    pub fn start(config: &EngineConfig) -> Result<Self, EngineErr> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let input = child.stdin.take().unwrap();
+        let output = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break
+                }
+            }
+        });
+        let name = config.name.clone().unwrap_or_else(|| config.command.clone());
+        let mut engine = Self { name, input: Box::new(input), lines, child: Some(child) };
+        engine.handshake(config)?;
+        Ok(engine)
+    }
+
+    /// Runs a [`RandomMover`] on a thread, as an opponent which needs no other program
+    pub fn random_mover(seed: u64) -> Result<Self, EngineErr> {
+        let (input, commands) = mpsc::channel();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            let output = LineWriter { sender, buffer: vec![] };
+            let _ = RandomMover::new(seed).run(commands, output);
+        });
+        let mut engine = Self {
+            name: "Random Mover".to_string(),
+            input: Box::new(LineWriter { sender: input, buffer: vec![] }),
+            lines,
+            child: None,
+        };
+        engine.handshake(&EngineConfig::new("random"))?;
+        Ok(engine)
+    }
+
+    /// `uci`, taking the engine's name from `id name` unless the config gives one, then the
+    /// options
+    fn handshake(&mut self, config: &EngineConfig) -> Result<(), EngineErr> {
+        self.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = self.read_line(deadline)?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                if config.name.is_none() {
+                    self.name = name.trim().to_string();
+                }
+            } else if line.trim() == "uciok" {
+                break
+            }
+        }
+        for (name, value) in &config.options {
+            self.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        self.is_ready()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), EngineErr> {
+        writeln!(self.input, "{}", command)?;
+        self.input.flush()?;
+        Ok(())
+    }
+
+    fn read_line(&mut self, deadline: Instant) -> Result<String, EngineErr> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.lines.recv_timeout(timeout).map_err(|err| match err {
+            RecvTimeoutError::Timeout => EngineErr::Timeout,
+            RecvTimeoutError::Disconnected => EngineErr::Disconnected,
+        })
+    }
+
+    /// `isready`, skipping whatever the engine still had to say until `readyok`
+    fn is_ready(&mut self) -> Result<(), EngineErr> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        while self.read_line(deadline)?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    /// Tells the engine a new game starts
+    pub fn new_game(&mut self) -> Result<(), EngineErr> {
+        self.send("ucinewgame")?;
+        self.is_ready()
+    }
+
+    /// Sends a `position` and a `go` command and waits at most `timeout` for the best move. An
+    /// engine which takes longer is stopped, and `Timeout` returned.
+    pub fn go(&mut self, position: &str, go: &str, timeout: Duration) -> Result<BestMove, EngineErr> {
+        self.send(position)?;
+        self.send(go)?;
+        let start = Instant::now();
+        let mut score = None;
+        loop {
+            let line = match self.read_line(start + timeout) {
+                Ok(line) => line,
+                Err(EngineErr::Timeout) => {
+                    // collect the late move, so that it is not taken for the answer to the next
+                    // command
+                    self.send("stop")?;
+                    let deadline = Instant::now() + GRACE_PERIOD;
+                    while let Ok(line) = self.read_line(deadline) {
+                        if line.starts_with("bestmove") {
+                            break
+                        }
+                    }
+                    return Err(EngineErr::Timeout)
+                }
+                Err(err) => return Err(err),
+            };
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.first().copied() {
+                Some("info") => score = parse_score(&tokens).or(score),
+                Some("bestmove") => {
+                    let Some(&mv) = tokens.get(1) else { return Err(EngineErr::Protocol(line)) };
+                    return Ok(BestMove { mv: mv.to_string(), score, elapsed: start.elapsed() })
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for Engine {
+    /// Asks the engine to quit, and kills it if it does not
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let Some(child) = &mut self.child else { return };
+        let deadline = Instant::now() + GRACE_PERIOD;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = child.try_wait() {
+                return
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// The score of an `info` line: `score cp X`, or `score mate N` as [`MATE`] less the number of
+/// moves
+fn parse_score(tokens: &[&str]) -> Option<i32> {
+    let index = tokens.iter().position(|&token| token == "score")?;
+    let value: i32 = tokens.get(index + 2)?.parse().ok()?;
+    match tokens.get(index + 1).copied()? {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE - value),
+        "mate" => Some(-MATE - value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_mover_engine() {
+        let mut engine = Engine::random_mover(3).unwrap();
+        assert_eq!(engine.name(), "Random Mover");
+        engine.new_game().unwrap();
+        let best = engine.go("position startpos moves e2e4", "go wtime 1000 btime 1000", Duration::from_secs(5)).unwrap();
+        assert_eq!(best.score, Some(0));
+        assert_eq!(best.mv.len(), 4);
+        // a program which is not there
+        assert!(matches!(Engine::start(&EngineConfig::new("./no-such-engine")), Err(EngineErr::Io(_))));
+    }
+
+    #[test]
+    fn scores() {
+        let score = |line: &str| parse_score(&line.split_whitespace().collect::<Vec<_>>());
+        assert_eq!(score("info depth 9 score cp -35 nodes 1000 pv e2e4"), Some(-35));
+        assert_eq!(score("info depth 9 score mate 3 pv a1a8"), Some(MATE - 3));
+        assert_eq!(score("info depth 9 score mate -2"), Some(-MATE + 2));
+        assert_eq!(score("info string hello"), None);
+        let config = EngineConfig::new("./engine --uci  -v").with_name("Test").with_option("Hash", "16");
+        assert_eq!(config.command, "./engine");
+        assert_eq!(config.args, ["--uci", "-v"]);
+        assert_eq!(config.options, [("Hash".to_string(), "16".to_string())]);
+    }
+}
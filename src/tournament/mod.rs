@@ -0,0 +1,476 @@
+//! Matches between UCI engines, for telling whether a change makes the engine stronger.
+//!
+//! The engines run as child processes. Each opening is played twice, with the colours swapped, so
+//! that an unbalanced opening favours neither engine. Games are played on clocks and end by the
+//! rules of the [`Board`], by a forfeit (a move out of time or an illegal one), or by adjudication
+//! once both engines agree the game is decided or dead drawn. The results give the Elo difference
+//! of the engines, and a sequential probability ratio test can stop the match as soon as it is
+//! clear whether a change gains Elo.
+
+use std::{fmt, fs, io, path::Path, str::FromStr, time::Duration};
+
+use crate::board::{Board, FenParseErr, GameResult, START_FEN};
+use crate::game::{outcome_reason, today};
+use crate::pgn::{parse_pgn, PgnGame};
+use crate::types::{Color, Move};
+use crate::uci::{move_to_san, parse_uci_move};
+
+mod engine;
+mod random_mover;
+mod stats;
+
+pub use engine::{BestMove, Engine, EngineConfig, EngineErr};
+pub use random_mover::RandomMover;
+pub use stats::{elo_difference, expected_score, Sprt, SprtDecision, Tally};
+
+/// Time a move may overrun the clock by before it counts as a loss on time, for the time it takes
+/// to pass the move on
+const TIME_MARGIN: Duration = Duration::from_millis(100);
+/// Moves each engine must agree on the score for before a game is adjudicated, by default
+pub const DEFAULT_RESIGN_MOVES: usize = 3;
+pub const DEFAULT_DRAW_MOVES: usize = 8;
+/// No game is adjudicated a draw before this move
+pub const DEFAULT_DRAW_AFTER: u16 = 40;
+
+#[derive(Debug)]
+pub enum OpeningsErr {
+    Io(io::Error),
+    /// An EPD record, by line number from 1, does not hold a valid position
+    InvalidPosition(usize, FenParseErr),
+    /// A PGN game, by number from 1, has an invalid `FEN` tag
+    InvalidGame(usize),
+    Empty,
+}
+
+impl fmt::Display for OpeningsErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpeningsErr::Io(err) => write!(f, "Could not read openings: {}", err),
+            OpeningsErr::InvalidPosition(line, err) => write!(f, "Invalid position on line {}: {}", line, err),
+            OpeningsErr::InvalidGame(game) => write!(f, "Game {} has an invalid FEN tag", game),
+            OpeningsErr::Empty => write!(f, "There are no openings in the file"),
+        }
+    }
+}
+
+impl From<io::Error> for OpeningsErr {
+    fn from(err: io::Error) -> Self {
+        OpeningsErr::Io(err)
+    }
+}
+
+/// Where the games of a pair start: a position and the moves played from it, which the engines
+/// are given as the beginning of the game
+#[derive(Clone)]
+pub struct OpeningLine {
+    pub start: Board,
+    pub moves: Vec<Move>,
+}
+
+impl Default for OpeningLine {
+    fn default() -> Self {
+        Self { start: Board::new(START_FEN.to_string()).unwrap(), moves: vec![] }
+    }
+}
+
+/// Reads openings from an EPD file or, if its name ends in `.pgn`, a PGN file
+pub fn load_openings<P: AsRef<Path>>(path: P) -> Result<Vec<OpeningLine>, OpeningsErr> {
+    let text = fs::read_to_string(&path)?;
+    let pgn = path.as_ref().extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pgn"));
+    let openings = if pgn { pgn_openings(&text)? } else { epd_openings(&text)? };
+    if openings.is_empty() {
+        return Err(OpeningsErr::Empty)
+    }
+    Ok(openings)
+}
+
+/// One position per line, as an EPD record (the operations after the position are ignored) or a
+/// FEN. Blank lines and lines starting with `#` are skipped.
+pub fn epd_openings(text: &str) -> Result<Vec<OpeningLine>, OpeningsErr> {
+    let mut openings = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        // the four fields of the position, and the clocks of a FEN
+        let fields: Vec<&str> = line
+            .split_whitespace()
+            .enumerate()
+            .take_while(|&(i, field)| i < 4 || (i < 6 && field.parse::<u16>().is_ok()))
+            .map(|(_, field)| field)
+            .collect();
+        let start = Board::new(fields.join(" ")).map_err(|err| OpeningsErr::InvalidPosition(number + 1, err))?;
+        openings.push(OpeningLine { start, moves: vec![] });
+    }
+    Ok(openings)
+}
+
+/// The moves of each game, up to the first one which is not legal
+pub fn pgn_openings(text: &str) -> Result<Vec<OpeningLine>, OpeningsErr> {
+    parse_pgn(text)
+        .iter()
+        .enumerate()
+        .map(|(number, game)| {
+            let (start, moves) = game.replay().ok_or(OpeningsErr::InvalidGame(number + 1))?;
+            Ok(OpeningLine { start, moves })
+        })
+        .collect()
+}
+
+/// A clock which starts at `base` and gains `increment` after every move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl FromStr for TimeControl {
+    type Err = ();
+
+    /// `BASE+INCREMENT` or `BASE`, in seconds: `10+0.1`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let seconds = |text: &str| text.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()).ok_or(());
+        Ok(Self { base: seconds(base)?, increment: seconds(increment)? })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    /// As PGN writes it: `10+0.1`, or `60` without an increment
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.base.as_secs_f64())?;
+        if !self.increment.is_zero() {
+            write!(f, "+{}", self.increment.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+/// When a game is called before it is over. An engine resigns when, for `resign_moves` moves in a
+/// row, its scores are `resign_score` centipawns or more behind and its opponent's as far ahead.
+/// A game is drawn from move `draw_after` on when, for `draw_moves` moves in a row, both engines
+/// score it within `draw_score` of equality. `None` turns either off.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adjudication {
+    pub resign_score: Option<i32>,
+    pub resign_moves: usize,
+    pub draw_score: Option<i32>,
+    pub draw_moves: usize,
+    pub draw_after: u16,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self {
+            resign_score: None,
+            resign_moves: DEFAULT_RESIGN_MOVES,
+            draw_score: None,
+            draw_moves: DEFAULT_DRAW_MOVES,
+            draw_after: DEFAULT_DRAW_AFTER,
+        }
+    }
+}
+
+impl Adjudication {
+    /// The result of a game whose moves were scored `scores`, each from the point of view of the
+    /// engine which played it, with the last move made by `last_mover` and the game at move
+    /// `fullmove`
+    fn adjudicate(&self, scores: &[Option<i32>], last_mover: Color, fullmove: u16) -> Option<(GameResult, String)> {
+        // the scores of the last `moves` moves of each side, the last mover's first
+        let recent = |moves: usize| -> Option<Vec<i32>> {
+            if moves == 0 || scores.len() < 2 * moves {
+                return None
+            }
+            scores[scores.len() - 2 * moves..].iter().rev().copied().collect()
+        };
+        if let (Some(threshold), Some(recent)) = (self.resign_score, recent(self.resign_moves)) {
+            let mover: Vec<i32> = recent.iter().step_by(2).copied().collect();
+            let opponent: Vec<i32> = recent.iter().skip(1).step_by(2).copied().collect();
+            for (loser, loser_scores, winner_scores) in [(last_mover, &mover, &opponent), (!last_mover, &opponent, &mover)] {
+                if loser_scores.iter().all(|&score| score <= -threshold) && winner_scores.iter().all(|&score| score >= threshold) {
+                    return Some((GameResult::Win(!loser), format!("{} resigns", loser)))
+                }
+            }
+        }
+        if let (Some(threshold), Some(recent)) = (self.draw_score, recent(self.draw_moves)) {
+            if fullmove >= self.draw_after && recent.iter().all(|score| score.abs() <= threshold) {
+                return Some((GameResult::Draw, "Draw by adjudication".to_string()))
+            }
+        }
+        None
+    }
+}
+
+/// A finished game of a match
+pub struct GameRecord {
+    /// From 1
+    pub round: usize,
+    /// The engine which played White, 0 for the first
+    pub white: usize,
+    pub result: GameResult,
+    pub reason: String,
+    pub pgn: PgnGame,
+}
+
+/// A match of `games` games between two engines. The first engine has White in the first game of
+/// each pair. Without openings, every game starts from the standard position.
+pub struct Match {
+    engines: [Engine; 2],
+    games: usize,
+    openings: Vec<OpeningLine>,
+    time_control: TimeControl,
+    adjudication: Adjudication,
+    sprt: Option<Sprt>,
+    event: String,
+}
+
+impl Match {
+    pub fn new(engines: [Engine; 2], games: usize) -> Self {
+        Self {
+            engines,
+            games,
+            openings: vec![],
+            time_control: TimeControl { base: Duration::from_secs(10), increment: Duration::from_millis(100) },
+            adjudication: Adjudication::default(),
+            sprt: None,
+            event: "Engine match".to_string(),
+        }
+    }
+
+    /// Plays the openings in order, starting over when there are more pairs of games than openings
+    pub fn with_openings(mut self, openings: Vec<OpeningLine>) -> Self {
+        self.openings = openings;
+        self
+    }
+
+    pub fn with_time_control(mut self, time_control: TimeControl) -> Self {
+        self.time_control = time_control;
+        self
+    }
+
+    pub fn with_adjudication(mut self, adjudication: Adjudication) -> Self {
+        self.adjudication = adjudication;
+        self
+    }
+
+    /// Stops the match as soon as the test comes to a decision
+    pub fn with_sprt(mut self, sprt: Sprt) -> Self {
+        self.sprt = Some(sprt);
+        self
+    }
+
+    /// The `Event` tag of the games
+    pub fn with_event(mut self, event: &str) -> Self {
+        self.event = event.to_string();
+        self
+    }
+
+    pub fn engine_names(&self) -> [&str; 2] {
+        [self.engines[0].name(), self.engines[1].name()]
+    }
+
+    /// Plays the games, calling `on_game` with each one and the results so far, and returns the
+    /// results of the first engine. Fails when an engine cannot go on, after the game it lost by
+    /// disconnecting has been passed to `on_game`.
+    pub fn run(&mut self, mut on_game: impl FnMut(&GameRecord, &Tally)) -> Result<Tally, EngineErr> {
+        let mut tally = Tally::default();
+        for game in 0..self.games {
+            let opening = match self.openings.is_empty() {
+                true => OpeningLine::default(),
+                false => self.openings[game / 2 % self.openings.len()].clone(),
+            };
+            let white = game % 2;
+            let (record, error) = self.play_game(game + 1, &opening, white)?;
+            let first_color = if white == 0 { Color::White } else { Color::Black };
+            tally.add(record.result, first_color);
+            on_game(&record, &tally);
+            if let Some(error) = error {
+                return Err(error)
+            }
+            if self.sprt.is_some_and(|sprt| sprt.decision(&tally) != SprtDecision::Continue) {
+                break
+            }
+        }
+        Ok(tally)
+    }
+
+    /// Plays one game, with the first engine as White if `white` is 0. An engine which can no
+    /// longer be spoken to loses the game, and its error is returned with it.
+    fn play_game(&mut self, round: usize, opening: &OpeningLine, white: usize) -> Result<(GameRecord, Option<EngineErr>), EngineErr> {
+        for engine in &mut self.engines {
+            engine.new_game()?;
+        }
+        let start_fen = opening.start.fen();
+        let mut board = opening.start.clone();
+        let mut moves = vec![];
+        for &mv in &opening.moves {
+            board.make_move(mv);
+            moves.push(mv);
+        }
+        let mut clocks = [self.time_control.base; Color::NUM];
+        let mut scores = vec![];
+        let mut error = None;
+        let (result, reason) = loop {
+            if let Some(result) = board.outcome() {
+                break (result, outcome_reason(&board, result))
+            }
+            let side = board.side_to_move;
+            if let Some(adjudicated) = self.adjudication.adjudicate(&scores, !side, board.fullmove_number()) {
+                break adjudicated
+            }
+            let engine = if side == Color::White { white } else { 1 - white };
+            let position = match start_fen == START_FEN {
+                true => "position startpos".to_string(),
+                false => format!("position fen {}", start_fen),
+            };
+            let moves_text: Vec<String> = moves.iter().map(|mv: &Move| mv.to_string()).collect();
+            let position = match moves.is_empty() {
+                true => position,
+                false => format!("{} moves {}", position, moves_text.join(" ")),
+            };
+            let increment = self.time_control.increment.as_millis();
+            let go = format!(
+                "go wtime {} btime {} winc {} binc {}",
+                clocks[Color::White].as_millis(),
+                clocks[Color::Black].as_millis(),
+                increment,
+                increment
+            );
+            let reply = match self.engines[engine].go(&position, &go, clocks[side] + TIME_MARGIN) {
+                Ok(reply) => reply,
+                Err(EngineErr::Timeout) => break (GameResult::Win(!side), format!("{} loses on time", side)),
+                Err(EngineErr::Protocol(line)) => break (GameResult::Win(!side), format!("{} sends {}", side, line)),
+                Err(err) => {
+                    error = Some(err);
+                    break (GameResult::Win(!side), format!("{} disconnects", side))
+                }
+            };
+            let Some(remaining) = (clocks[side] + TIME_MARGIN).checked_sub(reply.elapsed) else {
+                break (GameResult::Win(!side), format!("{} loses on time", side))
+            };
+            clocks[side] = remaining.saturating_sub(TIME_MARGIN) + self.time_control.increment;
+            let Some(mv) = parse_uci_move(&board, &reply.mv) else {
+                break (GameResult::Win(!side), format!("{} makes an illegal move: {}", side, reply.mv))
+            };
+            board.make_move(mv);
+            moves.push(mv);
+            scores.push(reply.score);
+        };
+        let names = [self.engines[white].name(), self.engines[1 - white].name()];
+        let mut tags = vec![
+            ("Event".to_string(), self.event.clone()),
+            ("Site".to_string(), "?".to_string()),
+            ("Date".to_string(), today()),
+            ("Round".to_string(), round.to_string()),
+            ("White".to_string(), names[0].to_string()),
+            ("Black".to_string(), names[1].to_string()),
+            ("Result".to_string(), result.to_string()),
+            ("Termination".to_string(), reason.clone()),
+            ("TimeControl".to_string(), self.time_control.to_string()),
+        ];
+        if start_fen != START_FEN {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), start_fen));
+        }
+        let mut board = opening.start.clone();
+        let moves = moves
+            .iter()
+            .map(|&mv| {
+                let san = move_to_san(&board, mv).unwrap_or_else(|| mv.to_string());
+                board.make_move(mv);
+                san
+            })
+            .collect();
+        let record = GameRecord { round, white, result, reason, pgn: PgnGame { tags, moves } };
+        Ok((record, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openings() {
+        let epd = "# two book exits\n\
+                   rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - id \"1. e4 e5\"; ce 20;\n\
+                   \n\
+                   rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1\n";
+        let openings = epd_openings(epd).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[0].start.fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1");
+        assert!(openings[1].moves.is_empty());
+        assert!(matches!(epd_openings("4k3/8/8/8/8/8/8/4K3 x - -"), Err(OpeningsErr::InvalidPosition(1, _))));
+
+        let pgn = "[Event \"?\"]\n\n1. d4 Nf6 2. c4 e6 *\n\n[FEN \"4k3/8/8/8/8/8/8/4K3 x - - 0 1\"]\n\n1. e4 *\n";
+        assert!(matches!(pgn_openings(pgn), Err(OpeningsErr::InvalidGame(2))));
+        let openings = pgn_openings("1. d4 Nf6 2. c4 e6 3. Nzz *\n").unwrap();
+        assert_eq!(openings[0].moves.len(), 4);
+    }
+
+    #[test]
+    fn time_controls() {
+        let tc: TimeControl = "10+0.1".parse().unwrap();
+        assert_eq!(tc, TimeControl { base: Duration::from_secs(10), increment: Duration::from_millis(100) });
+        assert_eq!(tc.to_string(), "10+0.1");
+        assert_eq!("40".parse::<TimeControl>().unwrap().to_string(), "40");
+        assert!("10+x".parse::<TimeControl>().is_err());
+    }
+
+    #[test]
+    fn adjudication() {
+        let rules = Adjudication { resign_score: Some(500), resign_moves: 2, draw_score: Some(10), draw_moves: 2, draw_after: 30 };
+        let adjudicate = |rules: &Adjudication, scores: &[Option<i32>], last_mover: Color, fullmove: u16| {
+            rules.adjudicate(scores, last_mover, fullmove).map(|(result, reason)| format!("{} {{{}}}", result, reason))
+        };
+        // White's moves are the even ones; Black is losing and knows it
+        let scores = [Some(30), Some(-20), Some(600), Some(-550), Some(700), Some(-900)];
+        assert_eq!(adjudicate(&rules, &scores, Color::Black, 20).as_deref(), Some("1-0 {Black resigns}"));
+        assert_eq!(adjudicate(&rules, &scores[..5], Color::White, 20), None);
+        // one engine disagrees
+        let scores = [Some(600), Some(-550), Some(700), Some(100)];
+        assert_eq!(adjudicate(&rules, &scores, Color::Black, 20), None);
+        let scores = [Some(50), Some(5), Some(-3), Some(0), Some(8), None];
+        assert_eq!(adjudicate(&rules, &scores[..4], Color::Black, 30), None);
+        assert_eq!(adjudicate(&rules, &scores[..5], Color::White, 30).as_deref(), Some("1/2-1/2 {Draw by adjudication}"));
+        assert_eq!(adjudicate(&rules, &scores[..5], Color::White, 29), None);
+        // a move without a score breaks the run
+        assert_eq!(adjudicate(&rules, &scores, Color::Black, 30), None);
+        assert_eq!(adjudicate(&Adjudication::default(), &[Some(0); 20], Color::White, 50), None);
+    }
+
+    #[test]
+    fn random_movers() {
+        let engines = [Engine::random_mover(1).unwrap(), Engine::random_mover(2).unwrap()];
+        let openings = epd_openings("4k3/8/8/8/8/8/8/3QK3 w - -\n8/5k2/8/8/8/8/2K5/2R5 b - -").unwrap();
+        let mut played = vec![];
+        let tally = Match::new(engines, 4)
+            .with_openings(openings)
+            .with_event("Test")
+            .run(|record, tally| played.push((record.round, record.white, record.result.to_string(), record.pgn.clone(), *tally)))
+            .unwrap();
+        assert_eq!(tally.games(), 4);
+        assert_eq!(played.iter().map(|game| (game.0, game.1)).collect::<Vec<_>>(), [(1, 0), (2, 1), (3, 0), (4, 1)]);
+        assert_eq!(played[3].4, tally);
+        let pgn = &played[2].3;
+        assert_eq!(pgn.tag("Event"), Some("Test"));
+        assert_eq!(pgn.tag("White"), Some("Random Mover"));
+        assert_eq!(pgn.tag("FEN"), Some("8/5k2/8/8/8/8/2K5/2R5 b - - 0 1"));
+        assert_eq!(pgn.tag("TimeControl"), Some("10+0.1"));
+        assert_eq!(pgn.tag("Result"), Some(played[2].2.as_str()));
+        // the game replays to its end
+        let (_, moves) = pgn.replay().unwrap();
+        assert_eq!(moves.len(), pgn.moves.len());
+    }
+
+    #[test]
+    fn single_repetition_plays_on() {
+        // the opening repeats the start position once, which does not end the game
+        let engines = [Engine::random_mover(3).unwrap(), Engine::random_mover(4).unwrap()];
+        let openings = pgn_openings("1. Nf3 Nf6 2. Ng1 Ng8 *\n").unwrap();
+        let mut played = vec![];
+        Match::new(engines, 1).with_openings(openings).run(|record, _| played.push(record.pgn.clone())).unwrap();
+        assert!(played[0].moves.len() > 4);
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Write};
+
+use crate::board::{Board, START_FEN};
+use crate::uci::parse_uci_move;
+
+/// A UCI engine which plays a random legal move at once. It is the weakest possible opponent,
+/// which makes it useful for testing the match runner and anything else which talks to engines.
+pub struct RandomMover {
+    board: Board,
+    random: u64,
+}
+
+impl RandomMover {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            board: Board::new(START_FEN.to_string()).unwrap(),
+            // xorshift never leaves zero
+            random: seed.max(1),
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random
+    }
+
+    /// Answers the commands until `quit` or the end of the input
+    pub fn run<I: IntoIterator<Item = String>, W: Write>(&mut self, input: I, mut out: W) -> io::Result<()> {
+        for line in input {
+            if !self.handle(&line, &mut out)? {
+                break
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers one command. Returns false on `quit`.
+    pub fn handle<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first().copied() {
+            Some("uci") => {
+                writeln!(out, "id name Random Mover")?;
+                writeln!(out, "id author the Mascagni developers")?;
+                writeln!(out, "option name Seed type spin default 1 min 1 max 2147483647")?;
+                writeln!(out, "uciok")?;
+            }
+            Some("isready") => writeln!(out, "readyok")?,
+            Some("setoption") => {
+                // setoption name Seed value N
+                if let ["name", name, "value", value] = tokens[1..] {
+                    if name.eq_ignore_ascii_case("seed") {
+                        if let Ok(seed) = value.parse::<u64>() {
+                            self.random = seed.max(1);
+                        }
+                    }
+                }
+            }
+            Some("position") => {
+                let moves_index = tokens.iter().position(|t| *t == "moves").unwrap_or(tokens.len());
+                let fen = match tokens.get(1) {
+                    Some(&"startpos") => START_FEN.to_string(),
+                    Some(&"fen") => tokens[2..moves_index].join(" "),
+                    _ => return Ok(true),
+                };
+                let Ok(mut board) = Board::new(fen) else { return Ok(true) };
+                for mv in tokens.iter().skip(moves_index + 1) {
+                    match parse_uci_move(&board, mv) {
+                        Some(mv) => board.make_move(mv),
+                        None => break,
+                    }
+                }
+                self.board = board;
+            }
+            Some("go") => {
+                let count = self.board.legal_moves().len;
+                if count == 0 {
+                    writeln!(out, "bestmove 0000")?;
+                } else {
+                    let index = (self.next_random() % count as u64) as usize;
+                    let mv = self.board.legal_moves().moves[index];
+                    writeln!(out, "info depth 1 score cp 0 nodes 1 pv {}", mv)?;
+                    writeln!(out, "bestmove {}", mv)?;
+                }
+            }
+            Some("quit") => return Ok(false),
+            // `ucinewgame`, `stop` and anything else need no answer
+            _ => {}
+        }
+        out.flush()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_legal_moves() {
+        let mut engine = RandomMover::new(7);
+        let mut out = vec![];
+        let commands = ["uci", "isready", "position startpos moves e2e4 e7e5", "go wtime 1000 btime 1000", "quit", "isready"];
+        engine.run(commands.map(String::from), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("uciok\nreadyok\n"));
+        // the commands after `quit` are not read
+        assert_eq!(out.matches("readyok").count(), 1);
+        let bestmove = out.lines().last().unwrap().strip_prefix("bestmove ").unwrap();
+        let mut board = Board::new(START_FEN.to_string()).unwrap();
+        for mv in ["e2e4", "e7e5"] {
+            board.make_move(parse_uci_move(&board, mv).unwrap());
+        }
+        assert!(parse_uci_move(&board, bestmove).is_some());
+
+        // no move in a mated position
+        let mut out = vec![];
+        engine.handle("position fen 6Rk/5Q2/8/8/8/8/8/6K1 b - - 0 1", &mut out).unwrap();
+        engine.handle("go", &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "bestmove 0000\n");
+    }
+}
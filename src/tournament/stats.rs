@@ -0,0 +1,176 @@
+use std::fmt;
+
+use crate::board::GameResult;
+use crate::types::Color;
+
+/// Standard score of the two-sided 95% confidence interval
+const Z_95: f64 = 1.959_964;
+
+/// Wins, draws and losses of the first engine of a match
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Tally {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Counts a game in which the first engine played `color`
+    pub fn add(&mut self, result: GameResult, color: Color) {
+        match result {
+            GameResult::Win(winner) if winner == color => self.wins += 1,
+            GameResult::Win(_) => self.losses += 1,
+            GameResult::Draw => self.draws += 1,
+        }
+    }
+
+    /// The share of the points the first engine scored, from 0 to 1
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// Variance of the points of a single game
+    fn variance(&self) -> f64 {
+        let score = self.score();
+        let squares = self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2);
+        squares / self.games() as f64
+    }
+
+    /// The Elo difference between the first engine and the second, and the half width of its 95%
+    /// confidence interval. Infinite when one engine scored every point, or the margin is
+    /// unknown.
+    pub fn elo(&self) -> (f64, f64) {
+        if self.games() == 0 {
+            return (0.0, f64::INFINITY)
+        }
+        let score = self.score();
+        let margin = Z_95 * (self.variance() / self.games() as f64).sqrt();
+        let low = elo_difference((score - margin).max(0.0));
+        let high = elo_difference((score + margin).min(1.0));
+        // one bound is infinite if one engine scored every point
+        let margin = if low.is_finite() && high.is_finite() { (high - low) / 2.0 } else { f64::INFINITY };
+        (elo_difference(score), margin)
+    }
+}
+
+impl fmt::Display for Tally {
+    /// `W - L - D [score]`, as the score of a match is usually given
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} - {} - {} [{:.3}]", self.wins, self.losses, self.draws, self.score())
+    }
+}
+
+/// The Elo difference which makes `score` the expected score
+pub fn elo_difference(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// The expected score of a player `elo` points stronger than the opponent
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// What a sequential probability ratio test concludes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtDecision {
+    /// The first engine is no more than `elo0` stronger
+    AcceptH0,
+    /// The first engine is at least `elo1` stronger
+    AcceptH1,
+    /// More games are needed
+    Continue,
+}
+
+/// A sequential probability ratio test of whether the first engine of a match is `elo0` or
+/// `elo1` Elo stronger than the second, stopping the match as soon as the results tell the two
+/// apart with error rates `alpha` (accepting H1 wrongly) and `beta` (accepting H0 wrongly).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    /// A test with the usual 5% error rates
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self { elo0, elo1, alpha: 0.05, beta: 0.05 }
+    }
+
+    /// The log-likelihood ratio below which H0 is accepted, and above which H1 is
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    /// Log-likelihood ratio of H1 against H0, in the normal approximation of the generalized
+    /// SPRT over the trinomial results (as fishtest computes it)
+    pub fn llr(&self, tally: &Tally) -> f64 {
+        if tally.games() == 0 {
+            return 0.0
+        }
+        let variance = tally.variance();
+        if variance == 0.0 {
+            // every game had the same result, which tells nothing yet about the spread
+            return 0.0
+        }
+        let (score0, score1) = (expected_score(self.elo0), expected_score(self.elo1));
+        tally.games() as f64 * (score1 - score0) * (2.0 * tally.score() - score0 - score1) / (2.0 * variance)
+    }
+
+    pub fn decision(&self, tally: &Tally) -> SprtDecision {
+        let llr = self.llr(tally);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtDecision::AcceptH1
+        } else if llr <= lower {
+            SprtDecision::AcceptH0
+        } else {
+            SprtDecision::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elo() {
+        let tally = Tally { wins: 60, draws: 20, losses: 20 };
+        assert_eq!(tally.to_string(), "60 - 20 - 20 [0.700]");
+        let (elo, margin) = tally.elo();
+        assert!((elo - 147.2).abs() < 0.1, "{}", elo);
+        assert!((margin - 66.1).abs() < 0.1, "{}", margin);
+        assert_eq!(Tally { wins: 5, draws: 10, losses: 5 }.elo().0, 0.0);
+        assert_eq!(Tally { wins: 3, draws: 0, losses: 0 }.elo(), (f64::INFINITY, f64::INFINITY));
+        let mut tally = Tally::default();
+        tally.add(GameResult::Win(Color::Black), Color::Black);
+        tally.add(GameResult::Win(Color::Black), Color::White);
+        tally.add(GameResult::Draw, Color::White);
+        assert_eq!(tally, Tally { wins: 1, draws: 1, losses: 1 });
+        assert!((expected_score(elo_difference(0.3)) - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sprt() {
+        let sprt = Sprt::new(0.0, 10.0);
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 0.001 && (upper - 2.944).abs() < 0.001);
+        assert_eq!(sprt.decision(&Tally::default()), SprtDecision::Continue);
+        // a clearly stronger engine, an equal one, and too few games to tell
+        assert_eq!(sprt.decision(&Tally { wins: 600, draws: 200, losses: 200 }), SprtDecision::AcceptH1);
+        assert_eq!(sprt.decision(&Tally { wins: 3000, draws: 4000, losses: 3000 }), SprtDecision::AcceptH0);
+        assert_eq!(sprt.decision(&Tally { wins: 12, draws: 20, losses: 10 }), SprtDecision::Continue);
+        assert!(sprt.llr(&Tally { wins: 12, draws: 20, losses: 10 }) > 0.0);
+    }
+}
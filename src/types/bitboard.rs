@@ -1,6 +1,6 @@
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Add, Sub, Mul};
 
-use super::{square, File, Rank, Square};
+use super::{File, Rank, Square};
 
 
 /// 64-bit unsigned. Each bit indicates a square's occupancy
@@ -18,7 +18,7 @@ impl Bitboard {
         self.0.count_ones() as usize
     }
     pub const fn rank(rank: Rank) -> Self {
-        Self(255 << (rank as u8))
+        Self(255 << (rank as u8 * 8))
     }
 
     pub fn universal() -> Self {
@@ -53,32 +53,9 @@ impl Bitboard {
         self.0 &= !(1 << square as u64);
     }
 
+    /// Debugging helper for printing a bitboard
     pub fn pretty_print(&self) {
-        let mut cur_square = Square::A1;
-        let mut lines: [String; 8] = [(); 8].map(|_| String::new());
-        let mut current_line = 0;
-
-        let cloned = self.clone();
-
-        while cur_square <= Square::H8 {
-            let is_occupied: bool = !(cloned & Bitboard::from(cur_square)).is_empty();
-            let new_char: String = match is_occupied {
-                true => "X",
-                false => "-",
-            }.to_owned();
-
-            lines[current_line] = lines[current_line].to_owned() + &new_char;
-            if cur_square.file() == File::H {
-                current_line += 1;
-            }
-            cur_square = cur_square.shift(1)
-        }
-        lines.reverse();
-        println!("{}", lines
-            .map(|l| l.split("").collect::<Vec<&str>>().join(" "))
-            .join("\n")
-        );
-
+        println!("{}", crate::render::BoardRenderer::new().render_bitboard(*self));
     }
 }
 
@@ -99,7 +76,7 @@ impl From<File> for Bitboard {
 
 impl From<Rank> for Bitboard {
     fn from(rank: Rank) -> Self {
-        Self(255 << rank.index())
+        Self::rank(rank)
     }
 }
 
@@ -188,3 +165,16 @@ impl Not for Bitboard {
         Self(!self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank() {
+        assert_eq!(Bitboard::rank(Rank::R1), Bitboard(0xff));
+        assert_eq!(Bitboard::rank(Rank::R3), Bitboard(0xff << 16));
+        assert_eq!(Bitboard::from(Rank::R8), Bitboard(0xff << 56));
+        assert!(!(Bitboard::rank(Rank::R4) & Bitboard::from(Square::H4)).is_empty());
+    }
+}
@@ -1,45 +1,50 @@
 use std::ops::Index;
 
-use super::{Move, MoveType, Square};
+use super::{Color, MoveType, Square};
 
 pub trait CastlingKind {
     /// The mask of the catling kind
     const MASK: u8;
-    /// The path mask = squares that must be empty to legally castle
-    const ROOK_START_SQUARE: Square;
-    /// The check squares are the squares that must not be in check for the castling to occur
-    const CHECK_SQUARES: [Square; 3];
-    // The move associated with such a castling kind.
-    // const CASTLING_MOVE
-    const CASTLING_MOVE: Move;
+    const COLOR: Color;
+    /// Where the king ends up. In Chess960 too, the king always lands on the g or c file.
+    const KING_TARGET: Square;
+    /// Where the rook ends up, on the f or d file
+    const ROOK_TARGET: Square;
+    const MOVE_TYPE: MoveType;
 }
 
 macro_rules! impl_castling_kind {
-    ($($kind: ident => $mask:expr, $start_square:expr, $through_square:expr, $target_square: expr, $rook_square: expr, $move_type: expr,)*) => {
+    ($($kind: ident => $mask:expr, $color:expr, $king_target:expr, $rook_target:expr, $move_type: expr,)*) => {
         $(
             pub struct $kind;
 
             impl CastlingKind for $kind {
                 const MASK: u8 = $mask;
-                const ROOK_START_SQUARE: Square = $rook_square;
-                const CHECK_SQUARES: [Square; 3] = [$start_square, $through_square, $target_square];
-                const CASTLING_MOVE: Move = Move::new($start_square, $target_square, $move_type);
+                const COLOR: Color = $color;
+                const KING_TARGET: Square = $king_target;
+                const ROOK_TARGET: Square = $rook_target;
+                const MOVE_TYPE: MoveType = $move_type;
             }
         )*
     };
 }
 
 impl_castling_kind! {
-    WhiteKingside => 1, Square::E1, Square::F1, Square::G1, Square::H1, MoveType::KingsideCastle,
-    WhiteQueenside => 2, Square::E1, Square::D1, Square::C1, Square::A1, MoveType::QueensideCastle,
-    BlackKingside => 4, Square::E8, Square::F8, Square::G8, Square::H8, MoveType::KingsideCastle,
-    BlackQueenside => 8, Square::E8, Square::D8, Square::C8, Square::A8, MoveType::QueensideCastle,
+    WhiteKingside => 1, Color::White, Square::G1, Square::F1, MoveType::KingsideCastle,
+    WhiteQueenside => 2, Color::White, Square::C1, Square::D1, MoveType::QueensideCastle,
+    BlackKingside => 4, Color::Black, Square::G8, Square::F8, MoveType::KingsideCastle,
+    BlackQueenside => 8, Color::Black, Square::C8, Square::D8, MoveType::QueensideCastle,
 }
 
-#[derive(Copy, Clone, Default)]
-#[repr(transparent)]
+#[derive(Copy, Clone)]
 pub struct Castling {
-    raw: u8
+    raw: u8,
+    /// The file of the rook each right castles with, indexed like the bits of `raw`. In standard
+    /// chess these are the h and a files, but in Chess960 they can be any file on either side of
+    /// the king.
+    rook_files: [u8; 4],
+    /// The file each king starts on, white first
+    king_files: [u8; 2],
 }
 
 impl Castling {
@@ -50,43 +55,87 @@ impl Castling {
     /// fourth bit: 8 black queenside castling.
     /// So, 1001 would mean white kingside castling and black queenside castling rights are still
     /// intact
-    /// So, when a square is moved from a given square, the rights update
-    const UPDATES: [u8; Square::NUM] = [
-        13, 15, 15, 15, 12, 15, 15, 14,
-        15, 15, 15, 15, 15, 15, 15, 15,
-        15, 15, 15, 15, 15, 15, 15, 15,
-        15, 15, 15, 15, 15, 15, 15, 15,
-        15, 15, 15, 15, 15, 15, 15, 15,
-        15, 15, 15, 15, 15, 15, 15, 15,
-        15, 15, 15, 15, 15, 15, 15, 15,
-        7, 15, 15, 15, 3, 15, 15, 11,
-    ];
+    /// Each right also remembers the file of its rook, so that Chess960 positions, where the king
+    /// and rooks start anywhere on the back rank, castle the same way.
+    const fn right(color: Color, kingside: bool) -> usize {
+        (color as usize) * 2 + !kingside as usize
+    }
+
+    /// Grants the right to castle with the rook on `rook_file`, towards the h file if it is right
+    /// of the king
+    pub fn add(&mut self, color: Color, king_file: u8, rook_file: u8) {
+        let right = Self::right(color, rook_file > king_file);
+        self.raw |= 1 << right;
+        self.rook_files[right] = rook_file;
+        self.king_files[color as usize] = king_file;
+    }
 
     /// This updates the castling rights for a given move.
     /// It is important to include the target square because if a rook is captured, the player
-    /// should not be allowed to castle to that side.
+    /// should not be allowed to castle to that side. Moving the king loses both rights.
     pub fn update(&mut self, start: Square, target: Square) {
-        self.raw &= Self::UPDATES[start] & Self::UPDATES[target];
+        for right in 0..4 {
+            if self.raw & 1 << right == 0 {
+                continue
+            }
+            let rank = if right < 2 { 0 } else { 7 };
+            let rook = Square::from_rank_file(rank, self.rook_files[right]);
+            let king = Square::from_rank_file(rank, self.king_files[right / 2]);
+            if start == king || start == rook || target == rook {
+                self.raw &= !(1 << right);
+            }
+        }
+    }
+
+    /// No castling is allowed for either side
+    pub const fn is_empty(self) -> bool {
+        self.raw == 0
     }
 
     pub const fn is_allowed<Kind: CastlingKind>(self) -> bool {
         self.raw & Kind::MASK != 0
     }
-}
 
-impl From<&str> for Castling {
-    fn from(value: &str) -> Self {
-        let mut castling = Self::default();
-        for c in value.chars() {
-            castling.raw |= match c {
-                'K' => WhiteKingside::MASK,
-                'Q' => WhiteQueenside::MASK,
-                'k' => BlackKingside::MASK,
-                'q' => BlackQueenside::MASK,
-                _ => continue,
-            };
+    /// The square of the king, while it may castle
+    pub const fn king_square<Kind: CastlingKind>(self) -> Square {
+        let (rank, color) = if Kind::MASK < 4 { (0, 0) } else { (7, 1) };
+        Square::from_rank_file(rank, self.king_files[color])
+    }
+
+    /// The square of the rook, while it may castle
+    pub const fn rook_square<Kind: CastlingKind>(self) -> Square {
+        let rank = if Kind::MASK < 4 { 0 } else { 7 };
+        Square::from_rank_file(rank, self.rook_files[Kind::MASK.trailing_zeros() as usize])
+    }
+
+    /// The rights in a FEN castling field. Rooks which are not in the corner are written by file
+    /// (X-FEN); with `shredder` every right is (Shredder-FEN, `HAha`).
+    pub fn to_fen(self, shredder: bool) -> String {
+        let mut fen = String::new();
+        for (right, letter) in ['K', 'Q', 'k', 'q'].into_iter().enumerate() {
+            if self.raw & 1 << right == 0 {
+                continue
+            }
+            let rook_file = self.rook_files[right];
+            let outermost = if right % 2 == 0 { 7 } else { 0 };
+            let by_file = shredder || rook_file != outermost;
+            fen.push(match (by_file, right < 2) {
+                (false, _) => letter,
+                (true, true) => (b'A' + rook_file) as char,
+                (true, false) => (b'a' + rook_file) as char,
+            });
         }
-        castling
+        if fen.is_empty() {
+            fen.push('-');
+        }
+        fen
+    }
+}
+
+impl Default for Castling {
+    /// No rights, with the pieces where they start in standard chess
+    fn default() -> Self {
+        Self { raw: 0, rook_files: [7, 0, 7, 0], king_files: [4, 4] }
     }
 }
 
@@ -1,4 +1,4 @@
-use std::ops::{Index, IndexMut, Not};
+use std::{fmt, ops::{Index, IndexMut, Not}};
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum Color {
@@ -14,12 +14,13 @@ impl Color {
     }
 }
 
-impl ToString for Color {
-    fn to_string(&self) -> String {
-        match self {
-            Color::White => String::from("White"),
-            Color::Black => String::from("Black"),
-        }
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Color::White => "White",
+            Color::Black => "Black",
+        };
+        write!(f, "{}", s)
     }
 }
 
@@ -1,11 +1,11 @@
-pub mod bitboard;
-pub mod square;
-pub mod color;
-pub mod piece;
-pub mod castling;
-pub mod rankfile;
-pub mod moves;
-pub mod movelist;
+mod bitboard;
+mod square;
+mod color;
+mod piece;
+mod castling;
+mod rankfile;
+mod moves;
+mod movelist;
 
 pub use bitboard::*;
 pub use square::*;
@@ -16,5 +16,6 @@ pub use rankfile::*;
 pub use moves::*;
 pub use movelist::*;
 
-/// Per chess programming wiki, max moves in a position is 218
-pub const MAX_MOVES: usize = 218;
+/// Per chess programming wiki, max moves in a position is 218. Crazyhouse drops can add up to
+/// five moves for every empty square on top of the moves on the board, so there is room for more.
+pub const MAX_MOVES: usize = 512;
@@ -9,6 +9,11 @@ pub struct MoveList {
 }
 
 impl MoveList {
+    /// The moves in the list, without the unused space after them
+    pub fn as_slice(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+
     pub fn push(&mut self, m: Move) {
         self.moves[self.len] = m;
         self.len += 1;
@@ -18,6 +23,18 @@ impl MoveList {
         self.push(Move::new(start, target, move_type))
     }
 
+    /// Keeps only the moves for which `keep` is true, in the same order
+    pub fn retain(&mut self, mut keep: impl FnMut(Move) -> bool) {
+        let mut len = 0;
+        for i in 0..self.len {
+            if keep(self.moves[i]) {
+                self.moves[len] = self.moves[i];
+                len += 1;
+            }
+        }
+        self.len = len;
+    }
+
     pub fn add_many(&mut self, start: Square, targets: Bitboard, move_type: MoveType) {
         for t in targets {
             self.add(start, t, move_type)
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use super::{Piece, Square};
 
 #[derive(Clone, Copy, PartialEq, Default)]
@@ -20,15 +22,15 @@ impl FullMove {
 }
 
 
-/// A move is essentially represented as a u16.
-/// The first 6 bits represent the initial square
-/// The last 6 bits represent the target square
-/// The last 4 bits represent the MoveType
-#[derive(Clone, Copy, PartialEq, Default)]
-pub struct Move(pub u16);
+/// A move is essentially represented as a u32.
+/// The first 6 bits represent the initial square (for a drop, the piece dropped)
+/// The next 6 bits represent the target square
+/// The next 5 bits represent the MoveType
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Move(pub u32);
 
 /// Basic kinds of moves
-/// encoded in 4 bits (promotion, capture, special 1, special 2)
+/// encoded in 5 bits (drop, promotion, capture, special 1, special 2)
 /// See Chess Programming Wiki article on [Encoding Moves](https://www.chessprogramming.org/Encoding_Moves#From-To_Based)
 #[derive(Clone, Copy, PartialEq)]
 pub enum MoveType {
@@ -51,16 +53,28 @@ pub enum MoveType {
     PromotionCaptureToBishop = 0b1101,
     PromotionCaptureToRook = 0b1110,
     PromotionCaptureToQueen = 0b1111,
+    /// a piece from the pocket put on an empty square, as in Crazyhouse. The four bits above
+    /// were all taken, hence the fifth.
+    Drop = 0b1_0000,
+    // promotions to king, which only Antichess allows. They also use the fifth bit, and keep the
+    // promotion and capture bits of the other promotions.
+    PromotionToKing = 0b1_1000,
+    PromotionCaptureToKing = 0b1_1100,
 }
 
 impl Move {
-    pub const START_MASK: u16 = 0b0000_0000_0011_1111;
-    pub const TARGET_MASK: u16 = 0b0000_1111_1100_0000;
+    pub const START_MASK: u32 = 0b0000_0000_0011_1111;
+    pub const TARGET_MASK: u32 = 0b0000_1111_1100_0000;
+    pub const TYPE_MASK: u32 = 0b1_1111_0000_0000_0000;
     pub const fn new(start: Square, target: Square, move_type: MoveType) -> Self {
         Self(
-            start as u16 | (target as u16) << 6 | (move_type as u16) << 12
+            start as u32 | (target as u32) << 6 | (move_type as u32) << 12
         )
     }
+    /// Puts a piece from the pocket on `target`
+    pub const fn new_drop(piece: Piece, target: Square) -> Self {
+        Self(piece as u32 | (target as u32) << 6 | (MoveType::Drop as u32) << 12)
+    }
     pub const fn start(&self) -> Square {
         Square::new((self.0 & Self::START_MASK) as u8)
     }
@@ -68,7 +82,17 @@ impl Move {
         Square::new(((self.0 & Self::TARGET_MASK) >> 6) as u8)
     }
     pub const fn kind(&self) -> MoveType {
-        unsafe { std::mem::transmute((self.0 >> 12) as u8) }
+        unsafe { std::mem::transmute(((self.0 & Self::TYPE_MASK) >> 12) as u8) }
+    }
+    pub const fn is_drop(&self) -> bool {
+        matches!(self.kind(), MoveType::Drop)
+    }
+    /// The piece a drop puts on the board, `Piece::None` for any other move
+    pub const fn drop_piece(&self) -> Piece {
+        match self.is_drop() {
+            true => Piece::new((self.0 & Self::START_MASK) as usize),
+            false => Piece::None,
+        }
     }
     pub const fn is_en_passant(&self) -> bool {
        matches!(self.kind(), MoveType::EnPassant)
@@ -90,6 +114,8 @@ impl Move {
             || matches!(self.kind(), MoveType::PromotionCaptureToBishop)
             || matches!(self.kind(), MoveType::PromotionCaptureToRook)
             || matches!(self.kind(), MoveType::PromotionCaptureToQueen)
+            || matches!(self.kind(), MoveType::PromotionToKing)
+            || matches!(self.kind(), MoveType::PromotionCaptureToKing)
     }
     pub const fn promo_piece(&self) -> Piece {
         match self.kind() {
@@ -101,7 +127,68 @@ impl Move {
             MoveType::PromotionCaptureToBishop => Piece::Bishop,
             MoveType::PromotionCaptureToRook => Piece::Rook,
             MoveType::PromotionCaptureToQueen => Piece::Queen,
+            MoveType::PromotionToKing => Piece::King,
+            MoveType::PromotionCaptureToKing => Piece::King,
             _ => Piece::None,
         }
     }
+    /// Where the king and the rook of a castling move end up. Castling moves are encoded as the
+    /// king taking its own rook, since in Chess960 the king may already stand on its target.
+    pub const fn castling_targets(&self) -> (Square, Square) {
+        let rank = self.start() as u8 / 8;
+        match self.kind() {
+            MoveType::KingsideCastle => (Square::from_rank_file(rank, 6), Square::from_rank_file(rank, 5)),
+            _ => (Square::from_rank_file(rank, 2), Square::from_rank_file(rank, 3)),
+        }
+    }
+    /// The move in UCI notation. Under Chess960 castling is written as the king taking its rook,
+    /// otherwise as the king's two-square move.
+    pub fn to_uci(self, chess960: bool) -> String {
+        if self.is_castling() && chess960 {
+            return format!("{}{}", self.start(), self.target())
+        }
+        self.to_string()
+    }
+}
+
+/// Formats a move in UCI (long algebraic) notation, e.g. `e2e4`, `e1g1`, `e7e8q` or the drop `N@f3`
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_drop() {
+            let piece = match self.drop_piece() {
+                Piece::Knight => "N",
+                Piece::Bishop => "B",
+                Piece::Rook => "R",
+                Piece::Queen => "Q",
+                _ => "P",
+            };
+            return write!(f, "{}@{}", piece, self.target())
+        }
+        let promotion = match self.promo_piece() {
+            Piece::Knight => "n",
+            Piece::Bishop => "b",
+            Piece::Rook => "r",
+            Piece::Queen => "q",
+            Piece::King => "k",
+            _ => "",
+        };
+        let target = if self.is_castling() { self.castling_targets().0 } else { self.target() };
+        write!(f, "{}{}{}", self.start(), target, promotion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_bit() {
+        let promotion = |kind| Move::new(Square::E7, Square::E8, kind);
+        assert!(!promotion(MoveType::PromotionToKing).is_capture());
+        assert!(promotion(MoveType::PromotionCaptureToKing).is_capture());
+        assert!(promotion(MoveType::PromotionToKing).is_promotion());
+        assert!(promotion(MoveType::PromotionCaptureToKing).promo_piece() == Piece::King);
+        assert!(!Move::new_drop(Piece::Knight, Square::F3).is_capture());
+        assert_eq!(promotion(MoveType::PromotionToKing).to_string(), "e7e8k");
+    }
 }
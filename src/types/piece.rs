@@ -1,4 +1,4 @@
-use std::ops::{Index, IndexMut};
+use std::{fmt, ops::{Index, IndexMut}};
 
 #[derive(Clone, Copy, PartialEq, Default)]
 pub enum Piece {
@@ -36,17 +36,18 @@ impl TryFrom<char> for Piece {
     }
 }
 
-impl ToString for Piece {
-    fn to_string(&self) -> String {
-        match self {
-            Self::King => String::from("King"),
-            Self::Queen => String::from("Queen"),
-            Self::Rook => String::from("Rook"),
-            Self::Bishop => String::from("Bishop"),
-            Self::Knight => String::from("Knight"),
-            Self::Pawn => String::from("Pawn"),
-            Self::None => String::from("NONE"),
-        }
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::King => "King",
+            Self::Queen => "Queen",
+            Self::Rook => "Rook",
+            Self::Bishop => "Bishop",
+            Self::Knight => "Knight",
+            Self::Pawn => "Pawn",
+            Self::None => "NONE",
+        };
+        write!(f, "{}", s)
     }
 }
 
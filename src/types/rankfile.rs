@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(PartialEq, Eq, Ord, PartialOrd)]
 pub enum Rank { R1, R2, R3, R4, R5, R6, R7, R8 }
 
@@ -20,8 +22,8 @@ impl Rank {
     }
 }
 
-impl ToString for Rank {
-    fn to_string(&self) -> String {
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             Rank::R1 => "1",
             Rank::R2 => "2",
@@ -32,7 +34,7 @@ impl ToString for Rank {
             Rank::R7 => "7",
             Rank::R8 => "8",
         };
-        s.to_string()
+        write!(f, "{}", s)
     }
 }
 
@@ -53,8 +55,8 @@ impl File {
     }
 }
 
-impl ToString for File {
-    fn to_string(&self) -> String {
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             File::A => "a",
             File::B => "b",
@@ -65,7 +67,7 @@ impl ToString for File {
             File::G => "g",
             File::H => "h",
         };
-        s.to_string()
+        write!(f, "{}", s)
     }
 }
 
@@ -1,4 +1,4 @@
-use std::{cmp::max, ops::{BitXor, Index, IndexMut}};
+use std::{cmp::max, fmt, ops::{BitXor, Index, IndexMut}};
 
 use super::{File, Rank};
 
@@ -66,13 +66,12 @@ pub fn distance(from: &Square, to: &Square) -> usize {
     )
 }
 
-impl ToString for Square {
-    fn to_string(&self) -> String {
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if *self == Square::None {
-            return String::from("Null Square")
+            return write!(f, "Null Square")
         }
-        self.file().to_string();
-        format!("{}{}", self.file().to_string(), self.rank().to_string())
+        write!(f, "{}{}", self.file(), self.rank())
     }
 }
 
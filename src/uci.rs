@@ -1,4 +1,8 @@
-use crate::{board::Board, types::{FullMove, Move, MoveType, Piece}};
+use crate::{board::Board, types::{Color, FullMove, Move, MoveType, Piece, Square}};
+
+mod protocol;
+
+pub use protocol::*;
 
 pub struct AlgebraicMove {
     pub full_move: FullMove,
@@ -16,20 +20,119 @@ impl AlgebraicMove {
 }
 
 pub fn move_to_full(mv: Move, board: &Board) -> FullMove {
+    if mv.is_drop() {
+        return FullMove::new(mv.drop_piece(), Piece::None, mv)
+    }
     let piece = board.piece_on(mv.start());
-    let captured = match mv.is_en_passant() {
-        true => Piece::Pawn,
-        false => board.piece_on(mv.target()),
+    let captured = if mv.is_en_passant() {
+        Piece::Pawn
+    } else if mv.is_castling() {
+        // the king "takes" its own rook
+        Piece::None
+    } else {
+        board.piece_on(mv.target())
     };
     FullMove::new(piece, captured, mv)
 }
 
+/// Finds the legal move matching a move in UCI notation (e.g. `e2e4`, `e7e8q`). Castling may be
+/// written either as the king's two-square move (`e1g1`) or, as in Chess960, as the king taking
+/// its rook (`e1h1`).
+pub fn parse_uci_move(board: &Board, s: &str) -> Option<Move> {
+    board.legal_moves().as_slice()
+        .iter()
+        .copied()
+        .find(|mv| mv.to_string() == s || mv.to_uci(true) == s)
+}
+
+/// Finds the legal move matching a move in standard algebraic notation (e.g. `Nf3`, `exd5`,
+/// `Rad1`, `O-O`, `e8=Q`, or the Crazyhouse drops `N@f3` and `@e4`). Check marks and annotations
+/// like `+`, `#` or `!?` are ignored.
+pub fn parse_san(board: &Board, s: &str) -> Option<Move> {
+    let san = s.trim_end_matches(['+', '#', '!', '?']);
+    let mut moves = board.legal_moves().as_slice().iter().copied();
+    match san {
+        "O-O" | "0-0" => return moves.find(|mv| mv.kind() == MoveType::KingsideCastle),
+        "O-O-O" | "0-0-0" => return moves.find(|mv| mv.kind() == MoveType::QueensideCastle),
+        _ => {}
+    }
+    if let Some((piece, target)) = san.split_once('@') {
+        let piece = match piece {
+            "" => Piece::Pawn,
+            _ => Piece::try_from(piece.chars().next()?).ok()?,
+        };
+        let target = Square::try_from(target).ok()?;
+        return moves.find(|mv| mv.drop_piece() == piece && mv.target() == target)
+    }
+    let (piece, rest) = match san.chars().next()? {
+        c @ ('N' | 'B' | 'R' | 'Q' | 'K') => (Piece::try_from(c).ok()?, &san[1..]),
+        _ => (Piece::Pawn, san),
+    };
+    // a promotion is written `e8=Q`, and sometimes `e8Q`
+    let (rest, promotion) = match rest.char_indices().last()? {
+        (i, c @ ('N' | 'B' | 'R' | 'Q' | 'K')) if piece == Piece::Pawn => {
+            (rest[..i].trim_end_matches('='), Piece::try_from(c).ok()?)
+        }
+        _ => (rest, Piece::None),
+    };
+    let target = Square::try_from(rest.get(rest.len().checked_sub(2)?..)?).ok()?;
+    // whatever comes before the target square tells apart pieces which can reach it
+    let hints = rest[..rest.len() - 2].replace('x', "");
+    let mut matching = moves.filter(|mv| {
+        !mv.is_castling()
+            && mv.target() == target
+            && board.piece_on(mv.start()) == piece
+            && mv.promo_piece() == promotion
+            && hints.chars().all(|hint| match hint {
+                'a'..='h' => mv.start() as u8 & 7 == hint as u8 - b'a',
+                '1'..='8' => mv.start() as u8 / 8 == hint as u8 - b'1',
+                _ => false,
+            })
+    });
+    let mv = matching.next()?;
+    // an ambiguous move matches nothing
+    matching.next().is_none().then_some(mv)
+}
+
+/// Writes a legal move in standard algebraic notation, with `+` or `#` when it gives check or
+/// mate. `None` if the move is not legal in the position.
+pub fn move_to_san(board: &Board, mv: Move) -> Option<String> {
+    let mut san = collect_algebraic_moves(board).into_iter().find(|m| m.full_move.inner_move == mv)?.algebraic;
+    let mut after = board.clone();
+    after.make_move(mv);
+    if after.is_checkmate() {
+        san.push('#');
+    } else if after.is_check() {
+        san.push('+');
+    }
+    Some(san)
+}
+
+/// Writes a line of moves played from `board` in standard algebraic notation, numbered as in
+/// `12. Nf3 Nc6` or `12... Nc6 13. Nf3`. Stops at the first move which is not legal.
+pub fn line_to_san(board: &Board, line: &[Move]) -> String {
+    let mut board = board.clone();
+    let mut words = vec![];
+    for (i, &mv) in line.iter().enumerate() {
+        let Some(san) = move_to_san(&board, mv) else { break };
+        let number = board.fullmove_number();
+        match board.side_to_move {
+            Color::White => words.push(format!("{}.", number)),
+            _ if i == 0 => words.push(format!("{}...", number)),
+            _ => {}
+        }
+        words.push(san);
+        board.make_move(mv);
+    }
+    words.join(" ")
+}
+
 /// Take a board and return a list of full, algebraic moves.
 pub fn collect_algebraic_moves(board: &Board) -> Vec<AlgebraicMove> {
     let mut full_moves_vec: Vec<FullMove> = vec![];
-    let total_moves = board.legal_moves.len;
+    let total_moves = board.legal_moves().len;
     for i in 0..total_moves {
-        let lm = board.legal_moves.moves[i];
+        let lm = board.legal_moves().moves[i];
         full_moves_vec.push(move_to_full(lm, board));
     }
 
@@ -40,7 +143,18 @@ pub fn collect_algebraic_moves(board: &Board) -> Vec<AlgebraicMove> {
 
         let kind = fm.inner_move.kind();
         // let's handle castles first
-        if kind == MoveType::KingsideCastle {
+        if kind == MoveType::Drop {
+            // pawn drops are written without the piece, like pawn moves
+            let piece_segment = match fm.piece {
+                Piece::Knight => "N",
+                Piece::Bishop => "B",
+                Piece::Rook => "R",
+                Piece::Queen => "Q",
+                _ => "",
+            };
+            alg_moves_vec.push(AlgebraicMove::new(&fm, format!("{}@{}", piece_segment, fm.inner_move.target())));
+            continue
+        } else if kind == MoveType::KingsideCastle {
             alg_moves_vec.push(AlgebraicMove::new(&fm, String::from("O-O")));
             continue
         } else if kind == MoveType::QueensideCastle {
@@ -74,6 +188,8 @@ pub fn collect_algebraic_moves(board: &Board) -> Vec<AlgebraicMove> {
                             MoveType::PromotionCaptureToQueen => String::from("Q"),
                             MoveType::PromotionCaptureToBishop => String::from("B"),
                             MoveType::PromotionCaptureToKnight => String::from("N"),
+                            MoveType::PromotionToKing => String::from("K"),
+                            MoveType::PromotionCaptureToKing => String::from("K"),
                             _ => String::from(""),
                         })
                     } else {
@@ -108,9 +224,9 @@ pub fn collect_algebraic_moves(board: &Board) -> Vec<AlgebraicMove> {
 
                 let same_piece_and_target_moves_vec = full_moves_vec
                     .iter()
-                    .filter(|m| m.piece == piece)
+                    .filter(|m| m.piece == piece && !m.inner_move.is_drop())
                     .filter(|m| m.inner_move.target() == target)
-                    .map(|m| m.clone())
+                    .copied()
                     .collect::<Vec<FullMove>>();
 
                 // TODO: Capture location verification
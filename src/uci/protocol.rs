@@ -0,0 +1,827 @@
+//! The UCI protocol loop. See the [UCI specification](https://www.wbec-ridderkerk.nl/html/UCIProtocol.html).
+
+use std::{
+    io::{self, BufRead, Write},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    board::{Board, FenParseErr, Variant, START_FEN},
+    book::Book,
+    eval::nnue::Network,
+    search::{
+        pruning::SearchOptions,
+        time::{TimeLimit, TimeManager, WallClock},
+        tt::{TranspositionTable, DEFAULT_HASH_MB},
+        mate_in, Search, SearchLimits, SearchReport, SearchResult,
+    },
+    tablebase::{DtmTables, Tablebases, MAX_PIECES},
+    tools::{bench, DEFAULT_BENCH_DEPTH},
+    types::{Color, Move},
+};
+
+use super::parse_uci_move;
+
+pub(crate) const ENGINE_NAME: &str = "Mascagni";
+const ENGINE_AUTHOR: &str = "mikekanter";
+
+/// Where the engine writes its responses. Clones share the same writer, so the search thread can
+/// report while the main thread keeps reading commands.
+#[derive(Clone)]
+pub struct Output(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl Output {
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(writer))))
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    pub fn line(&self, line: &str) {
+        let mut out = self.0.lock().unwrap();
+        writeln!(out, "{}", line).unwrap();
+        out.flush().unwrap();
+    }
+}
+
+/// A writer whose contents can be read back, for the tests of the UCI and XBoard loops
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Buffer {
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+/// The parameters of a `go` command
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct GoParams {
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    /// Search for a mate in this many moves
+    pub mate: Option<u32>,
+    pub infinite: bool,
+    /// Think on the opponent's time, assuming they play the move we expect
+    pub ponder: bool,
+}
+
+impl GoParams {
+    pub fn parse(args: &[&str]) -> Self {
+        let mut params = Self::default();
+        let mut i = 0;
+        while i < args.len() {
+            let value = args.get(i + 1).copied().unwrap_or("");
+            let millis = || value.parse::<i64>().ok().map(|ms| Duration::from_millis(ms.max(0) as u64));
+            let mut consumed_value = true;
+            match args[i] {
+                "wtime" => params.wtime = millis(),
+                "btime" => params.btime = millis(),
+                "winc" => params.winc = millis(),
+                "binc" => params.binc = millis(),
+                "movetime" => params.movetime = millis(),
+                "movestogo" => params.movestogo = value.parse().ok(),
+                "depth" => params.depth = value.parse().ok(),
+                "nodes" => params.nodes = value.parse().ok(),
+                "mate" => params.mate = value.parse().ok(),
+                "infinite" => {
+                    params.infinite = true;
+                    consumed_value = false;
+                }
+                "ponder" => {
+                    params.ponder = true;
+                    consumed_value = false;
+                }
+                _ => consumed_value = false,
+            }
+            i += if consumed_value { 2 } else { 1 };
+        }
+        params
+    }
+
+    /// The time we have for this move, from the point of view of `side`
+    pub fn time_limit(&self, side: Color) -> TimeLimit {
+        if self.infinite {
+            return TimeLimit::Infinite
+        }
+        if let Some(movetime) = self.movetime {
+            return TimeLimit::MoveTime(movetime)
+        }
+        let (remaining, increment) = match side {
+            Color::White => (self.wtime, self.winc),
+            Color::Black => (self.btime, self.binc),
+        };
+        match remaining {
+            Some(remaining) => TimeLimit::Clock {
+                remaining,
+                increment: increment.unwrap_or_default(),
+                moves_to_go: self.movestogo,
+            },
+            None => TimeLimit::Infinite,
+        }
+    }
+
+    pub fn search_limits(&self) -> SearchLimits {
+        SearchLimits {
+            depth: self.depth,
+            nodes: self.nodes,
+            mate: self.mate.map(|moves| moves as usize),
+        }
+    }
+}
+
+/// Formats a finished iteration as an `info` line, writing castling as Chess960 does if
+/// `chess960` is set
+pub fn info_line(report: &SearchReport, chess960: bool) -> String {
+    let pv: Vec<String> = report.pv.iter().map(|mv| mv.to_uci(chess960)).collect();
+    let score = match mate_in(report.score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", report.score),
+    };
+    format!(
+        "info multipv {} depth {} seldepth {} score {} nodes {} nps {} time {} pv {}",
+        report.multipv,
+        report.depth,
+        report.seldepth,
+        score,
+        report.nodes,
+        report.nps(),
+        report.time.as_millis(),
+        pv.join(" "),
+    )
+}
+
+/// The reply we expect to our best move: the second move of the principal variation, or the move
+/// stored in the transposition table when the principal variation stops at our move
+fn ponder_move(board: &Board, result: &SearchResult, tt: &TranspositionTable) -> Option<Move> {
+    if let Some(&mv) = result.pv.get(1) {
+        return Some(mv)
+    }
+    let mut board = board.clone();
+    board.make_move(result.best_move?);
+    let mv = tt.probe(board.hash())?.mv?;
+    board.legal_moves().as_slice().contains(&mv).then_some(mv)
+}
+
+/// Most lines the `MultiPV` option accepts (no position has more legal moves)
+const MAX_MULTIPV: usize = crate::types::MAX_MOVES;
+const MAX_HASH_MB: usize = 65536;
+const MAX_THREADS: usize = 256;
+
+/// Names of the UCI options which switch search techniques on and off
+const SEARCH_TOGGLES: [&str; 6] = [
+    "NullMovePruning",
+    "LateMoveReductions",
+    "ReverseFutilityPruning",
+    "Razoring",
+    "LateMovePruning",
+    "CheckExtensions",
+];
+
+/// The flag of `options` that the option `name` switches
+fn search_toggle<'a>(options: &'a mut SearchOptions, name: &str) -> Option<&'a mut bool> {
+    let index = SEARCH_TOGGLES.iter().position(|n| n.eq_ignore_ascii_case(name))?;
+    Some(match index {
+        0 => &mut options.null_move,
+        1 => &mut options.late_move_reductions,
+        2 => &mut options.reverse_futility,
+        3 => &mut options.razoring,
+        4 => &mut options.late_move_pruning,
+        _ => &mut options.check_extensions,
+    })
+}
+
+pub struct UciEngine {
+    board: Board,
+    out: Output,
+    move_overhead: Duration,
+    multipv: usize,
+    search_options: SearchOptions,
+    /// Kept between searches, and only cleared by `ucinewgame`
+    tt: Arc<TranspositionTable>,
+    threads: usize,
+    /// The `Ponder` option: the GUI may let us think on the opponent's time
+    ponder: bool,
+    /// The `UCI_Chess960` option: castling moves are sent and expected as the king taking its rook
+    chess960: bool,
+    /// The variant set with `UCI_Variant`, which `position` and `ucinewgame` play
+    variant: Variant,
+    stop: Arc<AtomicBool>,
+    /// Set by `go ponder`, cleared by `ponderhit`
+    pondering: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<()>>,
+    /// The `OwnBook` option: play moves from the book loaded with `BookFile`
+    own_book: bool,
+    book: Option<Arc<Book>>,
+    /// State of the generator which picks among the book moves
+    random: u64,
+    /// Opened from the directories of the `SyzygyPath` option
+    tablebases: Option<Arc<Tablebases>>,
+    /// The `SyzygyProbeLimit` option: most pieces of a position looked up in the tablebases
+    tb_probe_limit: usize,
+    /// Loaded from the directory of the `DtmPath` option
+    dtm_tables: Option<Arc<DtmTables>>,
+    /// Loaded from the `EvalFile` option, the embedded network until then. Without one, the
+    /// search counts material.
+    network: Option<Arc<Network>>,
+}
+
+impl UciEngine {
+    pub fn new(out: Output) -> Self {
+        let mut engine = Self {
+            board: Board::new(START_FEN.to_string()).unwrap(),
+            out,
+            move_overhead: Duration::from_millis(10),
+            multipv: 1,
+            search_options: SearchOptions::default(),
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            threads: 1,
+            ponder: false,
+            chess960: false,
+            variant: Variant::Standard,
+            stop: Arc::default(),
+            pondering: Arc::default(),
+            search_thread: None,
+            own_book: false,
+            book: None,
+            // any odd number will do as a seed
+            random: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64) | 1,
+            tablebases: None,
+            tb_probe_limit: MAX_PIECES,
+            dtm_tables: None,
+            network: None,
+        };
+        engine.set_network(Network::embedded().map(Arc::new));
+        engine
+    }
+
+    /// A board of the current variant, evaluated by the current network
+    fn new_board(&self, fen: String) -> Result<Board, FenParseErr> {
+        let mut board = Board::new_variant(fen, self.variant)?;
+        if let Some(network) = &self.network {
+            board.set_network(network.clone());
+        }
+        Ok(board)
+    }
+
+    /// Evaluates with `network` from now on, or counts material without one
+    fn set_network(&mut self, network: Option<Arc<Network>>) {
+        match &network {
+            Some(network) => self.board.set_network(network.clone()),
+            None => self.board.remove_network(),
+        }
+        self.network = network;
+    }
+
+    /// Reads commands until `quit` or the end of the input
+    pub fn run<R: BufRead>(&mut self, input: R) {
+        for line in input.lines() {
+            let Ok(line) = line else { break };
+            if !self.handle(&line) {
+                break
+            }
+        }
+        self.stop_search();
+    }
+
+    /// Handles one command. Returns false when the engine should quit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first().copied() {
+            Some("uci") => {
+                self.out.line(&format!("id name {}", ENGINE_NAME));
+                self.out.line(&format!("id author {}", ENGINE_AUTHOR));
+                self.out.line("option name Move Overhead type spin default 10 min 0 max 5000");
+                self.out.line(&format!("option name MultiPV type spin default 1 min 1 max {}", MAX_MULTIPV));
+                self.out.line(&format!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH_MB, MAX_HASH_MB));
+                self.out.line(&format!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS));
+                self.out.line("option name Ponder type check default false");
+                self.out.line("option name UCI_Chess960 type check default false");
+                let variants: Vec<String> = Variant::ALL.iter().map(|variant| format!("var {}", variant)).collect();
+                self.out.line(&format!("option name UCI_Variant type combo default chess {}", variants.join(" ")));
+                self.out.line("option name OwnBook type check default false");
+                self.out.line("option name BookFile type string default <empty>");
+                self.out.line("option name SyzygyPath type string default <empty>");
+                self.out.line(&format!("option name SyzygyProbeLimit type spin default {} min 0 max {}", MAX_PIECES, MAX_PIECES));
+                self.out.line("option name DtmPath type string default <empty>");
+                self.out.line("option name EvalFile type string default <embedded>");
+                for name in SEARCH_TOGGLES {
+                    self.out.line(&format!("option name {} type check default true", name));
+                }
+                self.out.line("uciok");
+            }
+            Some("isready") => self.out.line("readyok"),
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.board = self.new_board(self.variant.start_fen().to_string()).unwrap();
+                self.tt.clear();
+            }
+            Some("setoption") => self.set_option(&tokens[1..]),
+            Some("position") => {
+                self.stop_search();
+                self.set_position(&tokens[1..]);
+            }
+            Some("go") => {
+                self.stop_search();
+                self.go(GoParams::parse(&tokens[1..]));
+            }
+            Some("stop") => self.stop_search(),
+            // the opponent played the move we pondered on: the search goes on, but now on our time
+            Some("ponderhit") => self.pondering.store(false, Ordering::Relaxed),
+            Some("bench") => {
+                self.stop_search();
+                let depth = tokens.get(1).and_then(|d| d.parse().ok()).unwrap_or(DEFAULT_BENCH_DEPTH);
+                let result = bench(depth, self.search_options, self.network.clone());
+                for line in result.to_string().lines() {
+                    self.out.line(line);
+                }
+                self.out.line(&format!("{} nodes {} nps", result.nodes, result.nps()));
+            }
+            // not UCI, but handy when debugging: the current position as a FEN
+            Some("d") => self.out.line(&format!("Fen: {}", self.board.fen())),
+            Some("quit") => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// `setoption name <name> value <value>`. Option names may contain spaces.
+    fn set_option(&mut self, args: &[&str]) {
+        let value_index = args.iter().position(|t| *t == "value");
+        let name = args[1.min(args.len())..value_index.unwrap_or(args.len())].join(" ");
+        let value = value_index.map(|i| args[i + 1..].join(" ")).unwrap_or_default();
+        if name.eq_ignore_ascii_case("Move Overhead") {
+            if let Ok(ms) = value.parse::<u64>() {
+                self.move_overhead = Duration::from_millis(ms.min(5000));
+            }
+        } else if name.eq_ignore_ascii_case("MultiPV") {
+            if let Ok(lines) = value.parse::<usize>() {
+                self.multipv = lines.clamp(1, MAX_MULTIPV);
+            }
+        } else if name.eq_ignore_ascii_case("Hash") {
+            if let Ok(megabytes) = value.parse::<usize>() {
+                self.stop_search();
+                self.tt = Arc::new(TranspositionTable::new(megabytes.clamp(1, MAX_HASH_MB)));
+            }
+        } else if name.eq_ignore_ascii_case("Threads") {
+            if let Ok(threads) = value.parse::<usize>() {
+                self.threads = threads.clamp(1, MAX_THREADS);
+            }
+        } else if name.eq_ignore_ascii_case("Ponder") {
+            self.ponder = value.eq_ignore_ascii_case("true");
+        } else if name.eq_ignore_ascii_case("UCI_Chess960") {
+            self.chess960 = value.eq_ignore_ascii_case("true");
+        } else if name.eq_ignore_ascii_case("UCI_Variant") {
+            if let Ok(variant) = Variant::try_from(value.as_str()) {
+                self.variant = variant;
+                self.board = self.new_board(variant.start_fen().to_string()).unwrap();
+            }
+        } else if name.eq_ignore_ascii_case("OwnBook") {
+            self.own_book = value.eq_ignore_ascii_case("true");
+        } else if name.eq_ignore_ascii_case("BookFile") {
+            self.book = None;
+            if value.is_empty() || value == "<empty>" {
+                return
+            }
+            match Book::load(&value) {
+                Ok(book) => self.book = Some(Arc::new(book)),
+                Err(e) => self.out.line(&format!("info string {}", e)),
+            }
+        } else if name.eq_ignore_ascii_case("SyzygyPath") {
+            self.tablebases = None;
+            if value.is_empty() || value == "<empty>" {
+                return
+            }
+            let tablebases = Tablebases::open(&value);
+            self.out.line(&format!("info string Found {} tablebases", tablebases.len()));
+            if !tablebases.is_empty() {
+                self.tablebases = Some(Arc::new(tablebases));
+            }
+        } else if name.eq_ignore_ascii_case("DtmPath") {
+            self.dtm_tables = None;
+            if value.is_empty() || value == "<empty>" {
+                return
+            }
+            match DtmTables::open(&value) {
+                Ok(tables) => {
+                    self.out.line(&format!("info string Found {} distance-to-mate tables", tables.len()));
+                    if !tables.is_empty() {
+                        self.dtm_tables = Some(Arc::new(tables));
+                    }
+                }
+                Err(e) => self.out.line(&format!("info string Could not read {}: {}", value, e)),
+            }
+        } else if name.eq_ignore_ascii_case("EvalFile") {
+            self.stop_search();
+            if value.is_empty() || value == "<embedded>" {
+                self.set_network(Network::embedded().map(Arc::new));
+                return
+            }
+            match Network::load(&value) {
+                Ok(network) => self.set_network(Some(Arc::new(network))),
+                Err(e) => self.out.line(&format!("info string {}", e)),
+            }
+        } else if name.eq_ignore_ascii_case("SyzygyProbeLimit") {
+            if let Ok(pieces) = value.parse::<usize>() {
+                self.tb_probe_limit = pieces.min(MAX_PIECES);
+            }
+        } else if let Some(flag) = search_toggle(&mut self.search_options, &name) {
+            *flag = value.eq_ignore_ascii_case("true");
+        }
+    }
+
+    /// `position [startpos | fen <fen>] [moves <move>...]`
+    fn set_position(&mut self, args: &[&str]) {
+        let moves_index = args.iter().position(|t| *t == "moves").unwrap_or(args.len());
+        let fen = match args.first() {
+            Some(&"startpos") => self.variant.start_fen().to_string(),
+            Some(&"fen") => args[1..moves_index].join(" "),
+            _ => return,
+        };
+        let Ok(mut board) = self.new_board(fen) else { return };
+        for mv in args.iter().skip(moves_index + 1) {
+            match parse_uci_move(&board, mv) {
+                Some(mv) => board.make_move(mv),
+                None => break,
+            }
+        }
+        self.board = board;
+    }
+
+    fn go(&mut self, params: GoParams) {
+        // a book move is played at once, unless the GUI wants to see the engine think
+        // the books are for standard chess
+        if self.own_book && self.variant == Variant::Standard && !params.ponder && !params.infinite && params.mate.is_none() {
+            if let Some(mv) = self.book_move() {
+                self.out.line(&format!("bestmove {}", mv.to_uci(self.chess960)));
+                return
+            }
+        }
+        let limit = params.time_limit(self.board.side_to_move);
+        let mut time = TimeManager::new(limit, self.move_overhead, Box::new(WallClock::start()));
+        if self.ponder {
+            time = time.with_ponder_bonus();
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        self.pondering.store(params.ponder, Ordering::Relaxed);
+        if params.ponder {
+            time = time.with_pondering(self.pondering.clone());
+        }
+        let mut search = Search::new(self.board.clone(), params.search_limits(), time, self.stop.clone())
+            .with_options(self.search_options)
+            .with_multipv(self.multipv)
+            .with_tt(self.tt.clone())
+            .with_threads(self.threads);
+        if let Some(tablebases) = &self.tablebases {
+            search = search.with_tablebases(tablebases.clone(), self.tb_probe_limit);
+        }
+        if let Some(tables) = &self.dtm_tables {
+            search = search.with_dtm_tables(tables.clone());
+        }
+        let out = self.out.clone();
+        let board = self.board.clone();
+        let tt = self.tt.clone();
+        let stop = self.stop.clone();
+        let pondering = self.pondering.clone();
+        let chess960 = self.chess960;
+        self.search_thread = Some(thread::spawn(move || {
+            let result = search.run(|report| out.line(&info_line(report, chess960)));
+            // `bestmove` must not be sent while pondering, even if the search ran out of depth
+            while pondering.load(Ordering::Relaxed) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            match result.best_move {
+                Some(mv) => match ponder_move(&board, &result, &tt) {
+                    Some(ponder) => out.line(&format!("bestmove {} ponder {}", mv.to_uci(chess960), ponder.to_uci(chess960))),
+                    None => out.line(&format!("bestmove {}", mv.to_uci(chess960))),
+                },
+                None => out.line("bestmove 0000"),
+            }
+        }));
+    }
+
+    fn book_move(&mut self) -> Option<Move> {
+        let book = self.book.as_ref()?;
+        // xorshift, as for the Zobrist keys
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        book.pick(&self.board, self.random)
+    }
+
+    /// Waits for the running search (if any) to finish by itself
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            handle.join().unwrap();
+        }
+    }
+
+    /// Stops any running search and waits for it to print its `bestmove`
+    fn stop_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            self.pondering.store(false, Ordering::Relaxed);
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+
+    #[test]
+    fn parse_go() {
+        let params = GoParams::parse(&["wtime", "60000", "btime", "50000", "winc", "1000", "binc", "900", "movestogo", "20"]);
+        assert_eq!(params.wtime, Some(Duration::from_millis(60_000)));
+        assert_eq!(params.binc, Some(Duration::from_millis(900)));
+        assert_eq!(params.movestogo, Some(20));
+        assert_eq!(params.time_limit(Color::Black), TimeLimit::Clock {
+            remaining: Duration::from_millis(50_000),
+            increment: Duration::from_millis(900),
+            moves_to_go: Some(20),
+        });
+        assert_eq!(GoParams::parse(&["infinite"]).time_limit(Color::White), TimeLimit::Infinite);
+        assert_eq!(GoParams::parse(&["depth", "3"]).depth, Some(3));
+        assert!(GoParams::parse(&["ponder", "wtime", "100"]).ponder);
+        assert_eq!(GoParams::parse(&["mate", "4"]).search_limits().mate, Some(4));
+    }
+
+    #[test]
+    fn search_toggles() {
+        let mut engine = UciEngine::new(Output::new(Buffer::default()));
+        engine.handle("setoption name NullMovePruning value false");
+        engine.handle("setoption name razoring value false");
+        assert!(!engine.search_options.null_move);
+        assert!(!engine.search_options.razoring);
+        assert!(engine.search_options.late_move_reductions);
+        engine.handle("setoption name NullMovePruning value true");
+        assert!(engine.search_options.null_move);
+    }
+
+    #[test]
+    fn multipv_info_lines() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        for line in ["setoption name MultiPV value 3", "position startpos", "go depth 2"] {
+            engine.handle(line);
+        }
+        engine.wait();
+        let output = buffer.contents();
+        for k in 1..=3 {
+            assert!(output.contains(&format!("info multipv {} depth 2", k)));
+        }
+        assert!(!output.contains("info multipv 4"));
+    }
+
+    #[test]
+    fn go_depth_prints_bestmove() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        for line in ["uci", "position startpos moves e2e4 e7e5", "go depth 2"] {
+            engine.handle(line);
+        }
+        engine.wait();
+        let output = buffer.contents();
+        assert!(output.contains("uciok"));
+        assert!(output.contains("info multipv 1 depth 2"));
+        assert!(output.contains("bestmove "));
+    }
+
+    /// Waits until the engine has written `text`, failing the test if it takes more than ten
+    /// seconds
+    fn wait_for(buffer: &Buffer, text: &str) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !buffer.contents().contains(text) {
+            if std::time::Instant::now() > deadline {
+                panic!("timed out waiting for {:?} in:\n{}", text, buffer.contents());
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn ponderhit_releases_bestmove() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        for line in ["setoption name Ponder value true", "position startpos moves e2e4", "go ponder depth 3 wtime 1000 btime 1000"] {
+            engine.handle(line);
+        }
+        wait_for(&buffer, "info multipv 1 depth 3");
+        // the search is done, but the opponent has not moved yet
+        thread::sleep(Duration::from_millis(20));
+        assert!(!buffer.contents().contains("bestmove"));
+        engine.handle("ponderhit");
+        engine.wait();
+        let output = buffer.contents();
+        let bestmove = output.lines().find(|line| line.starts_with("bestmove")).unwrap();
+        let tokens: Vec<&str> = bestmove.split_whitespace().collect();
+        assert_eq!(tokens.len(), 4, "{}", bestmove);
+        assert_eq!(tokens[2], "ponder");
+    }
+
+    #[test]
+    fn stop_while_pondering() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        for line in ["position startpos", "go ponder movetime 10"] {
+            engine.handle(line);
+        }
+        wait_for(&buffer, "info multipv 1 depth 1");
+        // the move time would be long gone, but it only counts after a ponderhit
+        thread::sleep(Duration::from_millis(30));
+        assert!(!buffer.contents().contains("bestmove"));
+        engine.handle("stop");
+        assert!(buffer.contents().contains("bestmove"));
+    }
+
+    #[test]
+    fn go_mate() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        for line in ["position fen r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1", "go mate 2"] {
+            engine.handle(line);
+        }
+        engine.wait();
+        let output = buffer.contents();
+        assert!(output.contains("score mate 2 "), "{}", output);
+        assert!(output.contains("bestmove d5f6"));
+        // the side being mated sees a negative mate score
+        let line = info_line(&SearchReport {
+            depth: 2,
+            seldepth: 2,
+            multipv: 1,
+            score: -MATE + 2,
+            nodes: 1,
+            time: Duration::ZERO,
+            pv: vec![],
+        }, false);
+        assert!(line.contains("score mate -1 "));
+    }
+
+    #[test]
+    fn threads_option() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        for line in ["setoption name Threads value 3", "setoption name Hash value 1", "position startpos", "go depth 3"] {
+            engine.handle(line);
+        }
+        engine.wait();
+        assert_eq!(engine.threads, 3);
+        let output = buffer.contents();
+        assert!(output.contains("info multipv 1 depth 3"));
+        assert!(output.contains("bestmove "));
+    }
+
+    #[test]
+    fn chess960_castling() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        engine.handle("setoption name UCI_Chess960 value true");
+        engine.handle("position fen 4k3/8/8/8/8/8/8/6KR w H - 0 1 moves g1h1");
+        assert!(engine.board.piece_on(crate::types::Square::F1) == crate::types::Piece::Rook);
+        assert!(engine.chess960);
+        engine.handle("d");
+        assert!(buffer.contents().contains("Fen: 4k3/8/8/8/8/8/8/5RK1 b - - 1 1"));
+
+        engine.handle("position startpos moves e2e4 c7c5 g1f3");
+        engine.handle("d");
+        assert!(buffer.contents().contains("Fen: rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"));
+    }
+
+    #[test]
+    fn variants() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        engine.handle("uci");
+        assert!(buffer.contents().contains("option name UCI_Variant type combo default chess var chess var kingofthehill var 3check var racingkings var antichess"));
+        engine.handle("setoption name UCI_Variant value racingkings");
+        engine.handle("position startpos");
+        assert_eq!(engine.board.legal_moves().len, 21);
+        engine.handle("setoption name UCI_Variant value 3check");
+        engine.handle("position fen 4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2+0");
+        engine.handle("go depth 2");
+        engine.wait();
+        // the third check wins at once
+        assert!(buffer.contents().contains("bestmove a1a8"));
+
+        // in Antichess, the rook is given away to the king, which must take it
+        engine.handle("setoption name UCI_Variant value antichess");
+        engine.handle("position fen k7/8/8/8/8/8/8/1R6 w - - 0 1");
+        engine.handle("go depth 3");
+        engine.wait();
+        let contents = buffer.contents();
+        assert!(contents.contains("bestmove b1b7") || contents.contains("bestmove b1b8"));
+    }
+
+    #[test]
+    fn syzygy_path() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        let dir = std::env::temp_dir().join(format!("mascagni-no-tablebases-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        engine.handle(&format!("setoption name SyzygyPath value {}", dir.display()));
+        engine.handle("setoption name SyzygyProbeLimit value 9");
+        assert!(buffer.contents().contains("info string Found 0 tablebases"));
+        assert!(engine.tablebases.is_none());
+        assert_eq!(engine.tb_probe_limit, MAX_PIECES);
+
+        engine.handle(&format!("setoption name DtmPath value {}", dir.display()));
+        assert!(buffer.contents().contains("info string Found 0 distance-to-mate tables"));
+        assert!(engine.dtm_tables.is_none());
+    }
+
+    #[test]
+    fn bench_writes_to_the_output() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        engine.handle("bench 1");
+        let output = buffer.contents();
+        assert!(output.starts_with("Position  1/12: "));
+        assert_eq!(output.matches(" nodes  ").count(), crate::tools::BENCH_POSITIONS.len());
+        assert!(output.lines().last().unwrap().ends_with(" nps"));
+    }
+
+    #[test]
+    fn eval_file() {
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        assert_eq!(engine.board.nnue().is_some(), cfg!(feature = "embedded-net"));
+        let path = std::env::temp_dir().join(format!("mascagni-eval-file-{}.nnue", std::process::id()));
+        Network::material().save(&path).unwrap();
+        engine.handle(&format!("setoption name EvalFile value {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        // the position searched from now on, and the current one, are evaluated by the network
+        assert!(engine.board.nnue().is_some());
+        engine.handle("position startpos moves e2e4");
+        let network = engine.network.clone().unwrap();
+        assert!(Arc::ptr_eq(&engine.board.nnue().unwrap().network, &network));
+        engine.handle("go depth 2");
+        engine.wait();
+        assert!(buffer.contents().contains("bestmove "));
+
+        engine.handle("setoption name EvalFile value /nonexistent/net.nnue");
+        assert!(buffer.contents().contains("info string Could not read network"));
+        assert!(Arc::ptr_eq(engine.network.as_ref().unwrap(), &network));
+        engine.handle("setoption name EvalFile value <embedded>");
+        assert_eq!(engine.board.nnue().is_some(), cfg!(feature = "embedded-net"));
+    }
+
+    #[test]
+    fn own_book() {
+        let board = Board::new(START_FEN.to_string()).unwrap();
+        let e4 = parse_uci_move(&board, "e2e4").unwrap();
+        let entry = crate::book::BookEntry {
+            key: crate::book::polyglot_key(&board),
+            mv: crate::book::polyglot_move(e4),
+            weight: 1,
+            learn: 0,
+        };
+        let path = std::env::temp_dir().join(format!("mascagni-own-book-{}.bin", std::process::id()));
+        Book::from_entries(vec![entry]).save(&path).unwrap();
+
+        let buffer = Buffer::default();
+        let mut engine = UciEngine::new(Output::new(buffer.clone()));
+        let book_file = format!("setoption name BookFile value {}", path.display());
+        for line in [book_file.as_str(), "setoption name OwnBook value true", "position startpos", "go depth 5"] {
+            engine.handle(line);
+        }
+        engine.wait();
+        std::fs::remove_file(&path).unwrap();
+        let output = buffer.contents();
+        assert!(output.contains("bestmove e2e4"));
+        // straight from the book, without searching
+        assert!(!output.contains("info "));
+
+        // out of book, the engine searches
+        engine.handle("position startpos moves d2d4");
+        engine.handle("go depth 1");
+        engine.wait();
+        assert!(buffer.contents().contains("info multipv 1 depth 1"));
+        engine.handle("setoption name BookFile value /nonexistent/book.bin");
+        assert!(buffer.contents().contains("info string Could not read book"));
+    }
+}
@@ -0,0 +1,480 @@
+//! The XBoard protocol (CECP version 2), for the GUIs and tools which do not speak UCI. See the
+//! [engine interface](https://www.gnu.org/software/xboard/engine-intf.html).
+//!
+//! Unlike a UCI engine, an XBoard engine keeps the game itself: it plays one side, or neither in
+//! force mode, and moves by itself whenever that side is to move. Searches run on a thread of
+//! their own, with the same search and transposition table as UCI.
+
+use std::{
+    io::BufRead,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::board::{Board, FenParseErr, Variant};
+use crate::eval::nnue::Network;
+use crate::game::outcome_reason;
+use crate::search::{
+    mate_in,
+    time::{TimeLimit, TimeManager, WallClock},
+    tt::{TranspositionTable, DEFAULT_HASH_MB},
+    Search, SearchLimits, SearchReport,
+};
+use crate::types::{Color, Move};
+use crate::uci::{parse_san, parse_uci_move, Output, ENGINE_NAME};
+
+/// How long the engine thinks over a move when the GUI has set no time control
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(5);
+/// Kept off the clock for the time the GUI takes to pass our moves on
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+/// Score XBoard expects for a mate in 0, to which the number of moves is added
+const XBOARD_MATE: i32 = 100_000;
+
+/// The name XBoard gives a variant
+fn variant_name(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Standard => "normal",
+        Variant::Antichess => "giveaway",
+        _ => variant.name(),
+    }
+}
+
+/// A finished iteration as XBoard thinking output: `ply score time nodes pv`, with the time in
+/// centiseconds and mates scored as 100000 plus the number of moves
+pub fn thinking_line(report: &SearchReport) -> String {
+    let score = match mate_in(report.score) {
+        Some(moves) if moves > 0 => XBOARD_MATE + moves,
+        Some(moves) => -XBOARD_MATE + moves,
+        None => report.score,
+    };
+    let pv: Vec<String> = report.pv.iter().map(|mv| mv.to_string()).collect();
+    format!("{} {} {} {} {}", report.depth, score, report.time.as_millis() / 10, report.nodes, pv.join(" "))
+}
+
+/// The time controls set by `level`, `st`, `sd`, `time` and `otim`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct TimeControl {
+    /// Moves to play in each period of a `level` control, 0 if the time is for the whole game
+    moves_per_period: u32,
+    increment: Duration,
+    /// Fixed time per move, from `st`
+    move_time: Option<Duration>,
+    /// Deepest search, from `sd`
+    depth: Option<usize>,
+    /// The engine's clock
+    remaining: Option<Duration>,
+    /// The opponent's clock
+    opponent: Option<Duration>,
+}
+
+impl TimeControl {
+    /// `level MPS BASE INC`, the base in minutes or `minutes:seconds` and the increment in
+    /// seconds
+    fn set_level(&mut self, args: &[&str]) -> Option<()> {
+        let [moves, base, increment] = args else { return None };
+        let base = match base.split_once(':') {
+            Some((minutes, seconds)) => minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?,
+            None => base.parse::<u64>().ok()? * 60,
+        };
+        self.moves_per_period = moves.parse().ok()?;
+        self.increment = Duration::try_from_secs_f64(increment.parse().ok()?).ok()?;
+        self.move_time = None;
+        self.remaining = Some(Duration::from_secs(base));
+        Some(())
+    }
+
+    /// The time for the engine's move at `board`
+    fn limit(&self, board: &Board) -> TimeLimit {
+        if let Some(move_time) = self.move_time {
+            return TimeLimit::MoveTime(move_time)
+        }
+        match self.remaining {
+            Some(remaining) => {
+                // the moves still to play in this period, counting from the first move
+                let mps = self.moves_per_period;
+                let moves_to_go = (mps > 0).then(|| mps - (board.fullmove_number() as u32 - 1) % mps);
+                TimeLimit::Clock { remaining, increment: self.increment, moves_to_go }
+            }
+            None if self.depth.is_some() => TimeLimit::Infinite,
+            None => TimeLimit::MoveTime(DEFAULT_MOVE_TIME),
+        }
+    }
+}
+
+/// Centiseconds, as in `time` and `otim`
+fn centiseconds(value: Option<&&str>) -> Option<Duration> {
+    value?.parse::<u64>().ok().map(|cs| Duration::from_millis(cs * 10))
+}
+
+pub struct XBoardEngine {
+    board: Board,
+    variant: Variant,
+    out: Output,
+    /// The side the engine plays, `None` in force mode
+    engine_side: Option<Color>,
+    /// Whether to send thinking output
+    post: bool,
+    analyzing: bool,
+    time_control: TimeControl,
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    /// Set when the move of the running search is no longer wanted. The search thread checks it,
+    /// under the lock, before it sends its move.
+    cancelled: Arc<Mutex<bool>>,
+    /// Returns the move it sent, which the board has yet to play
+    search_thread: Option<JoinHandle<Option<Move>>>,
+    /// The embedded network, if any. Without one, the search counts material.
+    network: Option<Arc<Network>>,
+}
+
+impl XBoardEngine {
+    pub fn new(out: Output) -> Self {
+        let mut engine = Self {
+            board: Board::new(Variant::Standard.start_fen().to_string()).unwrap(),
+            variant: Variant::Standard,
+            out,
+            engine_side: Some(Color::Black),
+            post: false,
+            analyzing: false,
+            time_control: TimeControl::default(),
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            stop: Arc::default(),
+            cancelled: Arc::default(),
+            search_thread: None,
+            network: Network::embedded().map(Arc::new),
+        };
+        engine.board = engine.new_board(Variant::Standard.start_fen().to_string()).unwrap();
+        engine
+    }
+
+    /// A board of the current variant, evaluated by the network
+    fn new_board(&self, fen: String) -> Result<Board, FenParseErr> {
+        let mut board = Board::new_variant(fen, self.variant)?;
+        if let Some(network) = &self.network {
+            board.set_network(network.clone());
+        }
+        Ok(board)
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Reads commands until `quit` or the end of the input
+    pub fn run<R: BufRead>(&mut self, input: R) {
+        for line in input.lines() {
+            let Ok(line) = line else { break };
+            if !self.handle(&line) {
+                break
+            }
+        }
+        self.finish_search(true);
+    }
+
+    /// Handles one command. Returns false when the engine should quit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(&command) = tokens.first() else { return true };
+        let args = &tokens[1..];
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "computer" | "hard" | "easy" | "name" | "rating" | "ics" | "." => {}
+            "protover" => {
+                let variants: Vec<&str> = Variant::ALL.iter().map(|&variant| variant_name(variant)).collect();
+                self.out.line(&format!(
+                    "feature myname=\"{}\" setboard=1 usermove=1 time=1 ping=1 playother=1 analyze=1 colors=0 draw=0 \
+                     sigint=0 sigterm=0 reuse=1 variants=\"{}\" done=1",
+                    ENGINE_NAME,
+                    variants.join(",")
+                ));
+            }
+            "ping" => self.out.line(&format!("pong {}", args.first().unwrap_or(&""))),
+            "new" => {
+                self.finish_search(true);
+                self.variant = Variant::Standard;
+                self.board = self.new_board(Variant::Standard.start_fen().to_string()).unwrap();
+                self.engine_side = Some(Color::Black);
+                self.time_control.depth = None;
+                self.tt.clear();
+            }
+            "variant" => {
+                self.finish_search(true);
+                match args.first().map(|name| Variant::try_from(*name)) {
+                    Some(Ok(variant)) => {
+                        self.variant = variant;
+                        self.board = self.new_board(variant.start_fen().to_string()).unwrap();
+                    }
+                    _ => self.out.line(&format!("Error (unsupported variant): {}", args.join(" "))),
+                }
+            }
+            "setboard" => {
+                self.finish_search(true);
+                match self.new_board(args.join(" ")) {
+                    Ok(board) => self.board = board,
+                    Err(e) => self.out.line(&format!("tellusererror Illegal position: {}", e)),
+                }
+                self.restart_analysis();
+            }
+            "usermove" => {
+                self.finish_search(self.analyzing);
+                self.user_move(args.first().copied().unwrap_or(""));
+            }
+            "go" => {
+                self.finish_search(true);
+                self.engine_side = Some(self.board.side_to_move);
+                self.think();
+            }
+            "playother" => {
+                self.finish_search(true);
+                self.engine_side = Some(!self.board.side_to_move);
+            }
+            "force" | "result" => {
+                self.finish_search(true);
+                self.engine_side = None;
+            }
+            // move now
+            "?" => {
+                if !self.analyzing {
+                    self.stop.store(true, Ordering::Relaxed);
+                    self.finish_search(false);
+                }
+            }
+            "level" => {
+                if self.time_control.set_level(args).is_none() {
+                    self.out.line(&format!("Error (bad level): {}", line));
+                }
+            }
+            "st" => match args.first().and_then(|seconds| seconds.parse::<f64>().ok()) {
+                Some(seconds) if seconds > 0.0 => self.time_control.move_time = Some(Duration::from_secs_f64(seconds)),
+                _ => self.out.line(&format!("Error (bad time): {}", line)),
+            },
+            "sd" => self.time_control.depth = args.first().and_then(|depth| depth.parse().ok()),
+            "time" => self.time_control.remaining = centiseconds(args.first()).or(self.time_control.remaining),
+            "otim" => self.time_control.opponent = centiseconds(args.first()),
+            "undo" | "remove" => {
+                self.finish_search(true);
+                let plies = if command == "undo" { 1 } else { 2 };
+                for _ in 0..plies {
+                    self.board.undo_move();
+                }
+                self.restart_analysis();
+            }
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "analyze" => {
+                self.finish_search(true);
+                self.analyzing = true;
+                self.engine_side = None;
+                self.restart_analysis();
+            }
+            "exit" => {
+                self.finish_search(true);
+                self.analyzing = false;
+            }
+            "quit" => return false,
+            // without `usermove=1`, moves come on their own
+            _ if parse_uci_move(&self.board, command).is_some() => {
+                self.finish_search(self.analyzing);
+                self.user_move(command);
+            }
+            _ => self.out.line(&format!("Error (unknown command): {}", command)),
+        }
+        true
+    }
+
+    /// Plays the opponent's move, then answers it if it is the engine's turn
+    fn user_move(&mut self, text: &str) {
+        let Some(mv) = parse_uci_move(&self.board, text).or_else(|| parse_san(&self.board, text)) else {
+            self.out.line(&format!("Illegal move: {}", text));
+            return
+        };
+        self.board.make_move(mv);
+        if let Some(result) = self.board.outcome() {
+            self.out.line(&format!("{} {{{}}}", result, outcome_reason(&self.board, result)));
+        }
+        if self.analyzing {
+            self.restart_analysis();
+        } else {
+            self.think();
+        }
+    }
+
+    /// Starts a search for the engine's move if it is its turn and the game is not over
+    fn think(&mut self) {
+        if self.engine_side == Some(self.board.side_to_move) && self.board.outcome().is_none() {
+            self.start_search(false);
+        }
+    }
+
+    fn restart_analysis(&mut self) {
+        if self.analyzing && self.board.outcome().is_none() {
+            self.start_search(true);
+        }
+    }
+
+    /// Searches the position on another thread. A game search sends its move and plays it on a
+    /// copy of the board, to tell whether the game is over; `finish_search` plays it on ours.
+    fn start_search(&mut self, analysis: bool) {
+        let (limits, time) = if analysis {
+            (SearchLimits::default(), TimeManager::infinite())
+        } else {
+            let limit = self.time_control.limit(&self.board);
+            let limits = SearchLimits { depth: self.time_control.depth, ..Default::default() };
+            (limits, TimeManager::new(limit, MOVE_OVERHEAD, Box::new(WallClock::start())))
+        };
+        self.stop.store(false, Ordering::Relaxed);
+        *self.cancelled.lock().unwrap() = false;
+        let mut search = Search::new(self.board.clone(), limits, time, self.stop.clone()).with_tt(self.tt.clone());
+        let mut board = self.board.clone();
+        let out = self.out.clone();
+        let cancelled = self.cancelled.clone();
+        let post = self.post || analysis;
+        self.search_thread = Some(thread::spawn(move || {
+            let result = search.run(|report| {
+                if post {
+                    out.line(&thinking_line(report));
+                }
+            });
+            if analysis {
+                return None
+            }
+            let mv = result.best_move?;
+            let cancelled = cancelled.lock().unwrap();
+            if *cancelled {
+                return None
+            }
+            out.line(&format!("move {}", mv));
+            board.make_move(mv);
+            if let Some(result) = board.outcome() {
+                out.line(&format!("{} {{{}}}", result, outcome_reason(&board, result)));
+            }
+            Some(mv)
+        }));
+    }
+
+    /// Waits for the running search, stopping it first if `cancel` is set or if it is an
+    /// analysis, and plays the move it sent. A cancelled search sends no move, unless it already
+    /// had, in which case the GUI has seen it and the board must play it too.
+    fn finish_search(&mut self, cancel: bool) {
+        let Some(handle) = self.search_thread.take() else { return };
+        if cancel {
+            *self.cancelled.lock().unwrap() = true;
+        }
+        if cancel || self.analyzing {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        if let Some(mv) = handle.join().unwrap() {
+            self.board.make_move(mv);
+        }
+    }
+
+    /// Waits for the engine's move, if it is thinking over one
+    pub fn wait(&mut self) {
+        self.finish_search(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uci::Buffer;
+
+    fn engine() -> (XBoardEngine, Buffer) {
+        let buffer = Buffer::default();
+        (XBoardEngine::new(Output::new(buffer.clone())), buffer)
+    }
+
+    #[test]
+    fn features() {
+        let (mut engine, buffer) = engine();
+        for line in ["xboard", "protover 2", "accepted setboard", "ping 7"] {
+            engine.handle(line);
+        }
+        let output = buffer.contents();
+        assert!(output.starts_with("feature myname=\"Mascagni\" setboard=1 usermove=1"));
+        assert!(output.contains("variants=\"normal,kingofthehill,3check,racingkings,giveaway,crazyhouse,atomic\" done=1\n"));
+        assert!(output.ends_with("pong 7\n"));
+    }
+
+    #[test]
+    fn plays_a_game() {
+        let (mut engine, buffer) = engine();
+        for line in ["new", "sd 2", "post", "usermove e2e4"] {
+            engine.handle(line);
+        }
+        engine.wait();
+        let output = buffer.contents();
+        assert!(output.lines().next().unwrap().starts_with("1 "));
+        let reply = output.lines().find_map(|line| line.strip_prefix("move ")).unwrap();
+        assert!(engine.board().side_to_move == Color::White);
+        // taking back both moves, then playing on in force mode
+        engine.handle("remove");
+        assert_eq!(engine.board().fen(), Variant::Standard.start_fen());
+        for line in ["force", "usermove e2e4", "usermove e7e5", "usermove e2e5", "e1e2"] {
+            engine.handle(line);
+        }
+        assert_eq!(engine.board().fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 1 2");
+        assert!(buffer.contents().ends_with("Illegal move: e2e5\n"));
+        assert_eq!(buffer.contents().matches("move ").count(), 1, "{} was the only reply", reply);
+        engine.handle("undo");
+        assert_eq!(engine.board().fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+    }
+
+    #[test]
+    fn mates_and_results() {
+        let (mut engine, buffer) = engine();
+        for line in ["new", "setboard 6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "sd 3", "go"] {
+            engine.handle(line);
+        }
+        engine.wait();
+        assert!(buffer.contents().ends_with("move a1a8\n1-0 {White mates}\n"));
+        // the opponent's move can end the game too
+        for line in ["setboard 6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "force", "usermove a1a8"] {
+            engine.handle(line);
+        }
+        assert!(buffer.contents().ends_with("1-0 {White mates}\n1-0 {White mates}\n"));
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let (mut engine, buffer) = engine();
+        for line in ["new", "force", "usermove g1f3", "usermove g8f6", "usermove f3g1", "usermove f6g8"] {
+            engine.handle(line);
+        }
+        // the start position has come up twice, which is not yet a draw
+        assert!(!buffer.contents().contains("1/2-1/2"));
+        for line in ["usermove g1f3", "usermove g8f6", "usermove f3g1", "usermove f6g8"] {
+            engine.handle(line);
+        }
+        assert!(buffer.contents().ends_with("1/2-1/2 {Draw by repetition}\n"));
+    }
+
+    #[test]
+    fn analysis() {
+        let (mut engine, buffer) = engine();
+        for line in ["new", "analyze", "usermove e2e4"] {
+            engine.handle(line);
+        }
+        thread::sleep(Duration::from_millis(50));
+        engine.handle("exit");
+        assert!(!buffer.contents().contains("move "));
+        assert!(buffer.contents().lines().all(|line| line.split(' ').count() >= 4));
+        assert!(engine.board().side_to_move == Color::Black);
+    }
+
+    #[test]
+    fn time_controls() {
+        let board = Board::new(Variant::Standard.start_fen().to_string()).unwrap();
+        let mut time = TimeControl::default();
+        assert_eq!(time.limit(&board), TimeLimit::MoveTime(DEFAULT_MOVE_TIME));
+        time.set_level(&["40", "0:30", "1.5"]).unwrap();
+        assert_eq!(time.limit(&board), TimeLimit::Clock {
+            remaining: Duration::from_secs(30),
+            increment: Duration::from_millis(1500),
+            moves_to_go: Some(40),
+        });
+        assert!(time.set_level(&["40", "x", "0"]).is_none());
+        time.move_time = Some(Duration::from_secs(2));
+        assert_eq!(time.limit(&board), TimeLimit::MoveTime(Duration::from_secs(2)));
+    }
+}